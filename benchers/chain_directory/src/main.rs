@@ -0,0 +1,193 @@
+// Read latency of the server's chain directory while new chains are being
+// created, compared with the evmap the server used to use.
+// Each reader thread looks up random existing chains as fast as it can while
+// a single writer adds new ones, the way the ordering thread does.
+
+extern crate evmap;
+extern crate fuzzy_log_packets;
+extern crate fuzzy_log_server;
+
+extern crate structopt;
+#[macro_use]
+extern crate structopt_derive;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fuzzy_log_packets::order;
+use fuzzy_log_server::chain_directory;
+
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "chain_directory", about = "chain lookup latency under chain creation.")]
+struct Args {
+    #[structopt(short = "r", long = "readers", help = "number of reader threads.",
+        default_value = "4")]
+    readers: usize,
+
+    #[structopt(short = "i", long = "initial", help = "chains created before the readers start.",
+        default_value = "1000")]
+    initial_chains: u64,
+
+    #[structopt(short = "n", long = "new", help = "chains created while the readers run.",
+        default_value = "100000")]
+    new_chains: u64,
+
+    #[structopt(short = "s", long = "sleep", help = "microseconds between chain creations.",
+        default_value = "0")]
+    sleep_us: u64,
+}
+
+fn main() {
+    let args: Args = StructOpt::from_args();
+    assert!(args.initial_chains > 0, "readers need at least one chain to look up");
+    println!("#{:?}", args);
+    println!("#directory\tp50 ns\tp99 ns\tp99.9 ns\tp99.99 ns\tmax ns\tcreated/s");
+
+    let (read, write) = evmap::new();
+    run("evmap", &args, (write, read),
+        |write: &mut evmap::WriteHandle<u64, u64>, chain| {
+            write.insert(chain, chain);
+            write.refresh();
+        },
+        |read: &evmap::ReadHandle<u64, u64>, chain| {
+            read.get_and(&chain, |vals| vals[0]) == Some(chain)
+        },
+    );
+
+    run("chain_directory", &args, chain_directory::new(),
+        |write: &mut chain_directory::DirWriter<u64>, chain| write.insert(order::from(chain), chain),
+        |read: &chain_directory::DirReader<u64>, chain| {
+            read.get_and(&order::from(chain), |val| unsafe { *val.get() }) == Some(chain)
+        },
+    );
+}
+
+fn run<W, R>(
+    name: &str,
+    args: &Args,
+    (mut writer, reader): (W, R),
+    insert: fn(&mut W, u64),
+    lookup: fn(&R, u64) -> bool,
+)
+where W: Send + 'static, R: Clone + Send + 'static {
+    for chain in 1..args.initial_chains + 1 {
+        insert(&mut writer, chain)
+    }
+    let created = Arc::new(AtomicUsize::new(args.initial_chains as usize));
+    let done = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..args.readers).map(|n| {
+        let reader = reader.clone();
+        let (created, done) = (created.clone(), done.clone());
+        thread::spawn(move || {
+            let mut latencies = Histogram::new();
+            let mut rng = 0x2545_F491_4F6C_DD1D ^ (n as u64 + 1);
+            while !done.load(Ordering::Relaxed) {
+                let known = created.load(Ordering::Acquire) as u64;
+                rng ^= rng << 13;
+                rng ^= rng >> 7;
+                rng ^= rng << 17;
+                let chain = rng % known + 1;
+                let start = Instant::now();
+                let found = lookup(&reader, chain);
+                latencies.record(nanos(start.elapsed()));
+                assert!(found, "chain {} is missing", chain);
+            }
+            latencies
+        })
+    }).collect();
+    drop(reader);
+
+    let start = Instant::now();
+    let first_new = args.initial_chains + 1;
+    for chain in first_new..first_new + args.new_chains {
+        insert(&mut writer, chain);
+        created.store(chain as usize, Ordering::Release);
+        if args.sleep_us > 0 {
+            thread::sleep(Duration::new(
+                args.sleep_us / 1_000_000,
+                (args.sleep_us % 1_000_000) as u32 * 1000,
+            ))
+        }
+    }
+    let write_time = start.elapsed();
+    done.store(true, Ordering::Relaxed);
+
+    let mut latencies = Histogram::new();
+    for reader in readers {
+        latencies.merge(&reader.join().unwrap())
+    }
+    let write_s = write_time.as_secs() as f64 + write_time.subsec_nanos() as f64 * 1e-9;
+    println!("{}\t{}\t{}\t{}\t{}\t{}\t{:.0}",
+        name,
+        latencies.percentile(0.5),
+        latencies.percentile(0.99),
+        latencies.percentile(0.999),
+        latencies.percentile(0.9999),
+        latencies.max(),
+        args.new_chains as f64 / write_s,
+    );
+}
+
+fn nanos(d: Duration) -> u64 {
+    d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64
+}
+
+// nanoseconds, bucketed by power of two with 8 linear sub-buckets each
+// so every reported value is within 12.5% of the measured one
+struct Histogram {
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram { counts: vec![0; 64 * 8] }
+    }
+
+    fn record(&mut self, ns: u64) {
+        self.counts[bucket(ns)] += 1
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += *other
+        }
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        let total: u64 = self.counts.iter().sum();
+        let target = ((total as f64 * p).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return upper_bound(i)
+            }
+        }
+        0
+    }
+
+    fn max(&self) -> u64 {
+        self.counts.iter().rposition(|&c| c > 0).map(upper_bound).unwrap_or(0)
+    }
+}
+
+fn bucket(ns: u64) -> usize {
+    if ns < 8 {
+        return ns as usize
+    }
+    let msb = 63 - ns.leading_zeros() as usize;
+    let sub = ((ns >> (msb - 3)) & 7) as usize;
+    (msb - 2) * 8 + sub
+}
+
+fn upper_bound(bucket: usize) -> u64 {
+    if bucket < 8 {
+        return bucket as u64
+    }
+    let (msb, sub) = (bucket / 8 + 2, (bucket % 8) as u64);
+    ((9 + sub) << (msb - 3)) - 1
+}
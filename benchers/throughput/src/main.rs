@@ -104,6 +104,25 @@ fn main() {
             (@arg random_seed: -e --radom_seed +takes_value "Sets the random seed.")
         )
 
+        (@subcommand ssm =>
+            (about: "single-server multiappend throughput.")
+            (@arg server: -s +required +takes_value "Server addr.")
+            (@arg clients: -n --num_clients +takes_value "Number of clients to run.")
+            (@arg jobsize: -j --jobsize  +takes_value "Number of bytes per entry.")
+            (@arg num_writes: -w --num_writes +takes_value "Number writes to perform.")
+            (@arg write_window: -i --write_window +takes_value
+                "Window size for writes.")
+            (@arg multi_portion: -m +takes_value
+                "1/m writes will be multiappends, 0 for none, default 2")
+            (@arg history: --history +takes_value
+                "Record every append and read and write them to this file, see check_history.")
+        )
+
+        (@subcommand check_history =>
+            (about: "check a history recorded with --history against the log's guarantees.")
+            (@arg history: +required +takes_value "History file.")
+        )
+
         (@subcommand server =>
             (about: "run a fuzzy log server.")
             (@arg port: +required +takes_value "Port the server should listen on.")
@@ -117,6 +136,10 @@ fn main() {
                 "Address and port of the server that comes after this one in it's replication chain.")
             (@arg workers: -w --workers +takes_value
                 "Number of worker threads this server should use (default is <number of cores> - 2).")
+            (@arg max_batch: -b --max_batch +takes_value
+                "Most appends the server orders as one batch, 1 disables batching.")
+            (@arg ordering_threads: -o --ordering_threads +takes_value
+                "Number of threads the server's chains are split between (default is 1, replicated servers must use 1).")
         )
     );
 
@@ -247,12 +270,45 @@ fn main() {
 
         ///////////////////////////////////////
 
+        "ssm" => {
+            value_if!(let server; args, SocketAddr);
+            let server = server.unwrap_or_else(|| invalid_value!("", "server").exit());
+            value_or!(let clients; args, usize, 1);
+            value_or!(let jobsize; args, usize, 1);
+            value_or!(let num_writes; args, u32, 100_000);
+            value_or!(let write_window; args, u32, num_writes);
+            value_or!(let multi_portion; args, u32, 2);
+            let history = args.value_of("history").map(|h| h.to_string());
+            drop(help);
+            workloads::single_server_multi(
+                server,
+                clients,
+                jobsize,
+                num_writes,
+                write_window,
+                multi_portion,
+                history,
+            )
+        }
+
+        ///////////////////////////////////////
+
+        "check_history" => {
+            let history = args.value_of("history").unwrap().to_string();
+            drop(help);
+            workloads::check_history(&history)
+        }
+
+        ///////////////////////////////////////
+
         "server" => {
             let port = value_t!(args, "port", u16).unwrap_or_else(|e| e.exit());
             //FIXME max 1
             value_or!(let workers; args, usize, num_cpus::get() - 2);
             value_if!(let upstream; args, SocketAddr);
             value_if!(let downstream; args, IpAddr);
+            value_if!(let max_batch; args, usize);
+            value_or!(let ordering_threads; args, usize, 1);
             let group = args.value_of("group").map(|a| {
                 let mut a = a.split(':');
                 let next = a.next();
@@ -264,7 +320,7 @@ fn main() {
                 (server_num, group_size)
             });
 
-            servers::run(port, workers, upstream, downstream, group)
+            servers::run(port, workers, upstream, downstream, group, max_batch, ordering_threads)
         }
 
         ///////////////////////////////////////
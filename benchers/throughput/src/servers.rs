@@ -5,6 +5,7 @@ use std::sync::atomic::AtomicUsize;
 use mio;
 
 use fuzzy_log::servers2;
+use fuzzy_log::servers2::tcp::Limits;
 
 pub fn run(
     port: u16,
@@ -12,20 +13,21 @@ pub fn run(
     upstream: Option<SocketAddr>,
     downstream: Option<IpAddr>,
     group: Option<(u32, u32)>,
+    max_batch: Option<usize>,
+    ordering_threads: usize,
 ) -> ! {
     let a = AtomicUsize::new(0);
     let (server_num, group_size) = group.unwrap_or((0, 1));
-    let replicated = upstream.is_some() || downstream.is_some();
+    let mut limits = Limits::default();
+    if let Some(max_batch) = max_batch {
+        limits.max_batch = max_batch
+    }
     let ip_addr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
     let addr = SocketAddr::new(ip_addr, port);
     let acceptor = mio::tcp::TcpListener::bind(&addr);
     match acceptor {
-        Ok(accept) => if replicated {
-            servers2::tcp::run_with_replication(accept, server_num, group_size,
-                upstream, downstream, workers, &a)
-        } else {
-            servers2::tcp::run(accept, server_num, group_size, workers, &a)
-        },
+        Ok(accept) => servers2::tcp::run_sharded(accept, server_num, group_size,
+            upstream, downstream, workers, ordering_threads, None, limits, &a),
         Err(e) => panic!("Could not start server due to {}.", e),
     }
 }
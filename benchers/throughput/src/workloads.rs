@@ -1,12 +1,16 @@
 use fuzzy_log::async::fuzzy_log::log_handle::{LogHandle, GetRes};
+use fuzzy_log::history::{History, Recorder};
 use fuzzy_log::packets::SingletonBuilder;
 
 use rand::{SeedableRng, XorShiftRng as RandGen, Rng};
 use rand::distributions::Sample;
 use rand::distributions::range::Range as RandRange;
 
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::iter;
 use std::net::SocketAddr;
+use std::process::exit;
 use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 use std::time::Instant;
 use std::thread;
@@ -347,6 +351,141 @@ pub fn dependent_cost(
 
 ///////////////////////////////////////
 
+// Many clients writing to one server, every multi_portion'th append is a
+// multiappend to the client's own chain and the one after it.
+// Runs against a server started with --max_batch 1 for the unbatched numbers.
+pub fn single_server_multi(
+    server_addr: SocketAddr,
+    clients_to_run: usize,
+    jobsize: usize,
+    num_writes: u32,
+    write_window: u32,
+    multi_portion: u32,
+    history_file: Option<String>,
+) {
+    println!(
+        "# Starting {} single-server multiappend clients with jobsize {} for {} iterations, 1/{} multi, running against:\n#\t{:?}",
+        clients_to_run, jobsize, num_writes, multi_portion, server_addr,
+    );
+
+    static WRITERS_READY: AtomicUsize = ATOMIC_USIZE_INIT;
+
+    //recording every append slows the clients down, so only do it when asked
+    let history = history_file.as_ref().map(|_| History::new());
+
+    let start = Instant::now();
+    let joins: Vec<_> = (0..clients_to_run).map(|client_num| {
+        let writer = history.as_ref().map(|h| h.client(&[]));
+        let history = history.clone();
+        thread::spawn(move || {
+            let own_chain = (client_num as u32 + 1).into();
+            let next_chain = (((client_num + 1) % clients_to_run) as u32 + 1).into();
+            let chains = [own_chain, next_chain];
+            let mut handle = LogHandle::<[u8]>::new_tcp_log(
+                iter::once(server_addr), iter::once(own_chain));
+            let data = vec![0u8; jobsize];
+
+            WRITERS_READY.fetch_add(1, Ordering::SeqCst);
+            while WRITERS_READY.load(Ordering::SeqCst) < clients_to_run {
+                thread::yield_now()
+            }
+
+            trace!("client {} starting write.", client_num);
+
+            let write_start = Instant::now();
+            let mut sent = 0;
+            let mut current_writes = 0;
+            while sent < num_writes {
+                if current_writes < write_window {
+                    if multi_portion > 0 && sent % multi_portion == 0 {
+                        handle.async_multiappend(&chains[..], &*data, &[]);
+                    } else {
+                        handle.async_append(own_chain, &*data, &[]);
+                    }
+                    sent += 1;
+                    current_writes += 1;
+                }
+                current_writes -= flush_completed(&mut handle, writer.as_ref());
+            }
+            match writer {
+                None => { let _ = handle.wait_for_all_appends(); },
+                Some(ref writer) => while let Ok((id, locs)) = handle.wait_for_any_append() {
+                    writer.appended(id, &locs, &[]);
+                },
+            }
+            let write_time = write_start.elapsed();
+
+            trace!("client {} finished write.", client_num);
+
+            let write_s = write_time.as_secs() as f64 + (write_time.subsec_nanos() as f64 * 10.0f64.powi(-9));
+            let write_hz = num_writes as f64 / write_s;
+            println!("# client {:?} elapsed time for {} writes {:?}, {}s, {:.3} Hz",
+                client_num, sent, write_time, write_s, write_hz);
+
+            if let Some(history) = history {
+                let reader = history.client(&[own_chain]);
+                handle.snapshot(own_chain);
+                while reader.get_next(&mut handle).is_ok() {}
+            }
+            write_hz
+        })
+    }).collect();
+
+    let total_write_hz: f64 = joins.into_iter().map(|j| j.join().unwrap()).sum();
+    let end = start.elapsed();
+    println!("#elapsed time {}s", end.as_secs());
+    println!("#clients | jobsize | 1/multi | write Hz");
+    println!("{}\t{}\t{}\t{:.3}", clients_to_run, jobsize, multi_portion, total_write_hz);
+
+    if let (Some(history), Some(file)) = (history, history_file) {
+        let out = File::create(&file).and_then(|f| history.write_to(BufWriter::new(f)));
+        if let Err(e) = out {
+            println!("# could not write history to {}: {}", file, e);
+            exit(1)
+        }
+        println!("# history written to {}", file);
+    }
+}
+
+fn flush_completed(handle: &mut LogHandle<[u8]>, writer: Option<&Recorder>) -> u32 {
+    match writer {
+        None => handle.flush_completed_appends().unwrap() as u32,
+        Some(writer) => {
+            let mut flushed = 0;
+            while let Ok((id, locs)) = handle.try_wait_for_any_append() {
+                writer.appended(id, &locs, &[]);
+                flushed += 1;
+            }
+            flushed
+        },
+    }
+}
+
+///////////////////////////////////////
+
+pub fn check_history(file: &str) {
+    let history = File::open(file).and_then(|f| History::read_from(BufReader::new(f)));
+    let history = match history {
+        Ok(history) => history,
+        Err(e) => {
+            println!("could not read history {}: {}", file, e);
+            exit(1)
+        },
+    };
+    match history.check() {
+        Ok(()) => println!("{} records, no violations", history.records().len()),
+        Err(violations) => {
+            for violation in &violations {
+                println!("{}", violation)
+            }
+            println!("{} violations", violations.len());
+            exit(1)
+        },
+    }
+}
+
+///////////////////////////////////////
+
 fn packetsize_for_jobsize(jobsize: usize) -> usize {
     let data = vec![0u8; jobsize];
     SingletonBuilder(&data[..], &[]).clone_entry().entry_size()
@@ -0,0 +1,239 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use packets::{order, entry};
+
+pub const MAGIC: &'static [u8; 8] = b"FZLGARCH";
+pub const INDEX_MAGIC: &'static [u8; 8] = b"FZLGINDX";
+
+/// The newest archive version this crate can read and the one it writes.
+pub const VERSION: u32 = 1;
+
+const HEADER_LEN: u64 = 16;
+const TRAILER_LEN: u64 = 16;
+
+/// The location of one chain's entries in the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub chain: order,
+    pub offset: u64,
+    pub num_entries: u64,
+    pub first: entry,
+    pub last: entry,
+}
+
+pub struct ArchiveWriter<W> {
+    inner: W,
+    pos: u64,
+    index: Vec<Segment>,
+    current: Option<Segment>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(MAGIC)?;
+        inner.write_u32::<LittleEndian>(VERSION)?;
+        inner.write_u32::<LittleEndian>(0)?;
+        Ok(ArchiveWriter { inner, pos: HEADER_LEN, index: vec![], current: None })
+    }
+
+    /// Start the segment for `chain`, all following entries will be part of it.
+    /// Each chain may only have one segment.
+    pub fn start_chain(&mut self, chain: order) -> io::Result<()> {
+        if self.current.map_or(false, |s| s.chain == chain)
+            || self.index.iter().any(|s| s.chain == chain) {
+            return Err(invalid_input(format!("chain {:?} was already archived", chain)))
+        }
+        self.end_chain();
+        self.current = Some(Segment {
+            chain,
+            offset: self.pos,
+            num_entries: 0,
+            first: 0.into(),
+            last: 0.into(),
+        });
+        Ok(())
+    }
+
+    /// Entries must be written in increasing index order.
+    pub fn write_entry(&mut self, index: entry, bytes: &[u8]) -> io::Result<()> {
+        {
+            let segment = match self.current.as_mut() {
+                Some(segment) => segment,
+                None => return Err(invalid_input("no chain started".to_string())),
+            };
+            if segment.num_entries > 0 && index <= segment.last {
+                return Err(invalid_input(format!(
+                    "entry {:?} after {:?} in chain {:?}", index, segment.last, segment.chain
+                )))
+            }
+            if segment.num_entries == 0 {
+                segment.first = index;
+            }
+            segment.last = index;
+            segment.num_entries += 1;
+        }
+        self.inner.write_u64::<LittleEndian>(index.into())?;
+        self.inner.write_u32::<LittleEndian>(bytes.len() as u32)?;
+        self.inner.write_all(bytes)?;
+        self.pos += 12 + bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Write the index, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.end_chain();
+        let index_offset = self.pos;
+        self.inner.write_u32::<LittleEndian>(self.index.len() as u32)?;
+        for segment in &self.index {
+            self.inner.write_u64::<LittleEndian>(segment.chain.into())?;
+            self.inner.write_u64::<LittleEndian>(segment.offset)?;
+            self.inner.write_u64::<LittleEndian>(segment.num_entries)?;
+            self.inner.write_u64::<LittleEndian>(segment.first.into())?;
+            self.inner.write_u64::<LittleEndian>(segment.last.into())?;
+        }
+        self.inner.write_u64::<LittleEndian>(index_offset)?;
+        self.inner.write_all(INDEX_MAGIC)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn end_chain(&mut self) {
+        if let Some(segment) = self.current.take() {
+            self.index.push(segment)
+        }
+    }
+}
+
+pub struct ArchiveReader<R> {
+    inner: R,
+    version: u32,
+    index: Vec<Segment>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    pub fn open(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a fuzzylog archive".to_string()))
+        }
+        let version = inner.read_u32::<LittleEndian>()?;
+        if version > VERSION {
+            return Err(invalid_data(format!("unsupported archive version {}", version)))
+        }
+        let _reserved = inner.read_u32::<LittleEndian>()?;
+
+        let end = inner.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        let index_offset = inner.read_u64::<LittleEndian>()?;
+        inner.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC || index_offset < HEADER_LEN || index_offset > end {
+            return Err(invalid_data("missing archive index, was the export cut short?".to_string()))
+        }
+        inner.seek(SeekFrom::Start(index_offset))?;
+        let num_segments = inner.read_u32::<LittleEndian>()?;
+        let mut index = Vec::with_capacity(num_segments as usize);
+        for _ in 0..num_segments {
+            let chain = inner.read_u64::<LittleEndian>()?.into();
+            let offset = inner.read_u64::<LittleEndian>()?;
+            let num_entries = inner.read_u64::<LittleEndian>()?;
+            let first = inner.read_u64::<LittleEndian>()?.into();
+            let last = inner.read_u64::<LittleEndian>()?.into();
+            if offset < HEADER_LEN || offset > index_offset {
+                return Err(invalid_data(format!("bad offset for chain {:?}", chain)))
+            }
+            index.push(Segment { chain, offset, num_entries, first, last })
+        }
+        Ok(ArchiveReader { inner, version, index })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.index
+    }
+
+    /// Call `per_entry` with the index and bytes of every entry in `chain`, in order.
+    pub fn for_each_entry<F>(&mut self, chain: order, mut per_entry: F) -> io::Result<()>
+    where F: FnMut(entry, &[u8]) -> io::Result<()> {
+        let segment = match self.index.iter().find(|s| s.chain == chain) {
+            Some(segment) => *segment,
+            None => return Ok(()),
+        };
+        self.inner.seek(SeekFrom::Start(segment.offset))?;
+        let mut bytes = vec![];
+        for _ in 0..segment.num_entries {
+            let index = self.inner.read_u64::<LittleEndian>()?.into();
+            let len = self.inner.read_u32::<LittleEndian>()?;
+            bytes.clear();
+            bytes.resize(len as usize, 0);
+            self.inner.read_exact(&mut bytes)?;
+            per_entry(index, &bytes)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = ArchiveWriter::new(vec![]).unwrap();
+        writer.start_chain(3.into()).unwrap();
+        writer.write_entry(1.into(), &[1, 2, 3]).unwrap();
+        writer.write_entry(4.into(), &[]).unwrap();
+        writer.start_chain(7.into()).unwrap();
+        writer.write_entry(2.into(), &[9; 40]).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = ArchiveReader::open(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.version(), VERSION);
+        assert_eq!(reader.segments().len(), 2);
+        assert_eq!(reader.segments()[0].num_entries, 2);
+        assert_eq!(reader.segments()[0].first, 1.into());
+        assert_eq!(reader.segments()[0].last, 4.into());
+        let mut entries = vec![];
+        for &chain in &[7.into(), 3.into(), 100.into()] {
+            reader.for_each_entry(chain, |i, bytes| {
+                entries.push((chain, i, bytes.to_vec()));
+                Ok(())
+            }).unwrap();
+        }
+        assert_eq!(entries, vec![
+            (7.into(), 2.into(), vec![9; 40]),
+            (3.into(), 1.into(), vec![1, 2, 3]),
+            (3.into(), 4.into(), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn rejects_bad_archives() {
+        let mut writer = ArchiveWriter::new(vec![]).unwrap();
+        writer.start_chain(1.into()).unwrap();
+        writer.write_entry(2.into(), &[1]).unwrap();
+        assert!(writer.write_entry(2.into(), &[1]).is_err());
+        assert!(writer.start_chain(1.into()).is_err());
+        let mut bytes = writer.finish().unwrap();
+
+        let truncated = bytes[..bytes.len() - 4].to_vec();
+        assert!(ArchiveReader::open(Cursor::new(truncated)).is_err());
+        bytes[8] = VERSION as u8 + 1;
+        assert!(ArchiveReader::open(Cursor::new(bytes)).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpStream};
+
+use packets::order;
+use servers2::admin::{ChainInfo, Request, Response, MAX_DUMP_ENTRIES};
+
+use archive::ArchiveWriter;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportStats {
+    pub chains: u64,
+    pub entries: u64,
+}
+
+/// Archive the chains stored at the servers whose admin interfaces are at `admin_addrs`.
+/// For a replicated cluster only the heads need to be given.
+///
+/// Each chain is read up to the end it had when the export reached its server,
+/// appends made after that point are not included.
+/// If `colors` is `Some` only those chains are archived.
+pub fn export<W: Write>(admin_addrs: &[SocketAddr], colors: Option<&[order]>, out: W)
+-> io::Result<(W, ExportStats)> {
+    let mut connections = admin_addrs.iter().map(|&addr| Connection::new(addr))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut chains = vec![];
+    for (server, conn) in connections.iter_mut().enumerate() {
+        for info in conn.chains()? {
+            let wanted = colors.map_or(true, |colors| colors.contains(&info.chain));
+            if info.chain != order::from(0) && wanted {
+                chains.push((info, server))
+            }
+        }
+    }
+    chains.sort_by_key(|&(ref info, _)| info.chain);
+
+    let mut stats = ExportStats::default();
+    let mut writer = ArchiveWriter::new(out)?;
+    for (info, server) in chains {
+        writer.start_chain(info.chain)?;
+        stats.chains += 1;
+        stats.entries += connections[server].dump(&info, &mut writer)?;
+    }
+    Ok((writer.finish()?, stats))
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn new(addr: SocketAddr) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let _ = stream.set_nodelay(true);
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    fn request(&mut self, request: Request) -> io::Result<Response> {
+        request.write_to(&mut self.writer)?;
+        self.writer.flush()?;
+        match Response::read_from(&mut self.reader)? {
+            Response::Error(msg) => Err(io::Error::new(io::ErrorKind::Other, msg)),
+            response => Ok(response),
+        }
+    }
+
+    fn chains(&mut self) -> io::Result<Vec<ChainInfo>> {
+        match self.request(Request::ListChains)? {
+            Response::Chains(chains) => Ok(chains),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn dump<W: Write>(&mut self, info: &ChainInfo, writer: &mut ArchiveWriter<W>)
+    -> io::Result<u64> {
+        let mut written = 0;
+        let mut next = info.min_entry;
+        //a batch may be short, or empty, where the chain has unfilled slots,
+        //so step through the chain in batches until its tail
+        while next < info.next_entry {
+            let end = ::std::cmp::min(info.next_entry, next.saturating_add(MAX_DUMP_ENTRIES));
+            let entries = match self.request(Request::DumpChain{
+                chain: info.chain, start: next.into(), end: end.into(),
+            })? {
+                Response::Entries(entries) => entries,
+                other => return Err(unexpected(other)),
+            };
+            for (index, bytes) in entries {
+                writer.write_entry(index, &bytes)?;
+                written += 1;
+            }
+            next = end;
+        }
+        Ok(written)
+    }
+}
+
+fn unexpected(response: Response) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected response {:?}", response))
+}
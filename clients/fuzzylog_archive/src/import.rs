@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, Read, Seek};
+
+use fuzzy_log_client::{LogHandle, TryWaitRes};
+use packets::{order, EntryContents, OrderIndex, Uuid};
+
+use archive::ArchiveReader;
+
+/// One append to be replayed into the destination cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Append {
+    pub id: Uuid,
+    pub chains: Vec<order>,
+    pub depends_on: Vec<order>,
+    /// Dependencies in terms of the source cluster.
+    pub deps: Vec<OrderIndex>,
+    pub data: Vec<u8>,
+    /// Where the append was found in the archive.
+    pub old_locs: Vec<OrderIndex>,
+}
+
+#[derive(Default)]
+struct Group {
+    contents: Option<(Vec<order>, Vec<order>, Vec<OrderIndex>, Vec<u8>)>,
+    old_locs: Vec<OrderIndex>,
+    //number of events which must happen before this append can be replayed
+    waiting_on: usize,
+    dependents: Vec<Uuid>,
+}
+
+/// Order the appends in `archive` so that replaying them one at a time
+/// recreates the order of every archived chain.
+///
+/// A multiappend which was found in several chains is replayed once,
+/// after everything before it in each of those chains,
+/// and an append is only replayed after the archived entries it depends on.
+/// Colors which are not in the archive are dropped from the appends.
+pub fn plan<R: Read + Seek>(archive: &mut ArchiveReader<R>) -> io::Result<Vec<Append>> {
+    let archived: Vec<order> = archive.segments().iter().map(|s| s.chain).collect();
+    let mut groups: HashMap<Uuid, Group> = HashMap::new();
+    let mut queues: BTreeMap<order, VecDeque<Uuid>> = BTreeMap::new();
+    let mut owners: HashMap<OrderIndex, Uuid> = HashMap::new();
+    for &chain in &archived {
+        let mut queue = VecDeque::new();
+        archive.for_each_entry(chain, |index, bytes| {
+            let contents = match unsafe { EntryContents::try_ref(bytes) } {
+                Ok((contents, _)) => contents,
+                Err(..) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("malformed entry at {:?}", (chain, index)))),
+            };
+            let (id, contents) = match contents {
+                EntryContents::Single{id, loc, deps, data, ..} =>
+                    (id, Some((vec![loc.0], vec![], deps.to_vec(), data.to_vec()))),
+                EntryContents::Multi{id, locs, deps, data, ..} => {
+                    let (chains, depends_on) = split_locs(locs, &archived);
+                    (id, Some((chains, depends_on, deps.to_vec(), data.to_vec())))
+                },
+                EntryContents::Senti{id, ..} => (id, None),
+                //snapshots, GC markers and the like are not part of the log's contents
+                _ => return Ok(()),
+            };
+            let group = groups.entry(*id).or_insert_with(Group::default);
+            if group.contents.is_none() {
+                group.contents = contents;
+            }
+            group.old_locs.push(OrderIndex(chain, index));
+            owners.insert(OrderIndex(chain, index), *id);
+            queue.push_back(*id);
+            Ok(())
+        })?;
+        queues.insert(chain, queue);
+    }
+
+    for queue in queues.values() {
+        for (n, id) in queue.iter().enumerate() {
+            if n > 0 {
+                groups.get_mut(id).unwrap().waiting_on += 1;
+            }
+        }
+    }
+    let mut dependencies = vec![];
+    for (id, group) in groups.iter() {
+        if let Some((_, _, ref deps, _)) = group.contents {
+            for dep in deps {
+                match owners.get(dep) {
+                    Some(owner) if owner != id => dependencies.push((*owner, *id)),
+                    _ => {},
+                }
+            }
+        }
+    }
+    for (owner, id) in dependencies {
+        groups.get_mut(&id).unwrap().waiting_on += 1;
+        groups.get_mut(&owner).unwrap().dependents.push(id);
+    }
+
+    let mut ready: VecDeque<Uuid> = VecDeque::new();
+    for queue in queues.values() {
+        if let Some(id) = queue.front() {
+            if groups[id].waiting_on == 0 && !ready.contains(id) {
+                ready.push_back(*id)
+            }
+        }
+    }
+    let mut plan = Vec::with_capacity(groups.len());
+    let mut replayed = 0;
+    while let Some(id) = ready.pop_front() {
+        replayed += 1;
+        let (old_locs, dependents, contents) = {
+            let group = groups.get_mut(&id).unwrap();
+            (group.old_locs.clone(), group.dependents.clone(), group.contents.take())
+        };
+        let mut unblocked = dependents;
+        for &OrderIndex(chain, _) in &old_locs {
+            let queue = queues.get_mut(&chain).unwrap();
+            debug_assert_eq!(queue.front(), Some(&id));
+            queue.pop_front();
+            if let Some(&next) = queue.front() {
+                unblocked.push(next)
+            }
+        }
+        for next in unblocked {
+            let group = groups.get_mut(&next).unwrap();
+            group.waiting_on -= 1;
+            if group.waiting_on == 0 {
+                ready.push_back(next)
+            }
+        }
+        //an append whose data lives only in chains which were not archived
+        //has nothing to replay, but still orders the chains it was found in
+        match contents {
+            Some((chains, depends_on, deps, data)) => if !chains.is_empty() {
+                plan.push(Append { id, chains, depends_on, deps, data, old_locs })
+            },
+            None => {},
+        }
+    }
+    if replayed != groups.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("{} appends could not be ordered, the archive is inconsistent",
+                groups.len() - replayed)))
+    }
+    Ok(plan)
+}
+
+//A multiappend's locs are its own chains, then a 0 marker followed by
+//the chains it only adds a dependency to.
+fn split_locs(locs: &[OrderIndex], archived: &[order]) -> (Vec<order>, Vec<order>) {
+    let mut chains = vec![];
+    let mut depends_on = vec![];
+    let mut in_deps = false;
+    for &OrderIndex(o, _) in locs {
+        if o == order::from(0) {
+            in_deps = true;
+        } else if archived.contains(&o) {
+            if in_deps { depends_on.push(o) } else { chains.push(o) }
+        }
+    }
+    (chains, depends_on)
+}
+
+/// Replay `plan` through `log`, returning where each archived entry ended up.
+///
+/// Every append is waited on before the next is sent,
+/// so the destination chains are in the same order as the archived ones.
+/// Dependencies on entries which were not archived are dropped.
+pub fn import(log: &mut LogHandle<[u8]>, plan: &[Append])
+-> io::Result<HashMap<OrderIndex, OrderIndex>> {
+    let mut new_locs = HashMap::with_capacity(plan.len());
+    for append in plan {
+        let deps: Vec<_> = append.deps.iter().filter_map(|d| new_locs.get(d).cloned()).collect();
+        let id = if !append.depends_on.is_empty() {
+            log.async_dependent_multiappend(&append.chains, &append.depends_on, &append.data, &deps)
+        } else if append.chains.len() == 1 {
+            log.async_append(append.chains[0], &append.data, &deps)
+        } else {
+            log.async_multiappend(&append.chains, &append.data, &deps)
+        };
+        let locs = match log.wait_for_a_specific_append(id) {
+            Ok(locs) => locs,
+            Err(TryWaitRes::IoErr(kind, server)) => return Err(io::Error::new(kind,
+                format!("append of {} failed at server {}", append.id, server))),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::Other,
+                format!("append of {} failed: {:?}", append.id, e))),
+        };
+        for &OrderIndex(chain, index) in &append.old_locs {
+            if let Some(&new) = locs.iter().find(|l| l.0 == chain) {
+                new_locs.insert(OrderIndex(chain, index), new);
+            }
+        }
+    }
+    Ok(new_locs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use archive::ArchiveWriter;
+    use packets::{entry, EntryFlag};
+
+    fn single(id: &Uuid, loc: OrderIndex, deps: &[OrderIndex], data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        EntryContents::Single {
+            id,
+            flags: &EntryFlag::Nothing,
+            loc: &loc,
+            deps,
+            data,
+            timestamp: &0,
+        }.fill_vec(&mut bytes);
+        bytes
+    }
+
+    fn multi(id: &Uuid, locs: &[OrderIndex], data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        EntryContents::Multi {
+            id,
+            flags: &EntryFlag::Nothing,
+            lock: &0,
+            locs,
+            deps: &[],
+            data,
+        }.fill_vec(&mut bytes);
+        bytes
+    }
+
+    fn loc(o: u64, i: u64) -> OrderIndex {
+        OrderIndex(o.into(), entry::from(i))
+    }
+
+    #[test]
+    fn replays_multis_once_and_in_order() {
+        let (a, m, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let m_locs = [loc(1, 2), loc(2, 1), loc(0, 0), loc(9, 0)];
+        let mut writer = ArchiveWriter::new(vec![]).unwrap();
+        writer.start_chain(1.into()).unwrap();
+        writer.write_entry(1.into(), &single(&a, loc(1, 1), &[], b"a")).unwrap();
+        writer.write_entry(2.into(), &multi(&m, &m_locs, b"m")).unwrap();
+        writer.start_chain(2.into()).unwrap();
+        writer.write_entry(1.into(), &multi(&m, &m_locs, b"m")).unwrap();
+        //c depends on a later entry in another chain
+        writer.write_entry(2.into(), &single(&c, loc(2, 2), &[loc(3, 1)], b"c")).unwrap();
+        writer.start_chain(3.into()).unwrap();
+        writer.write_entry(1.into(), &single(&b, loc(3, 1), &[loc(1, 1)], b"b")).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut archive = ArchiveReader::open(Cursor::new(bytes)).unwrap();
+        let plan = plan(&mut archive).unwrap();
+        let ids: Vec<_> = plan.iter().map(|a| a.id).collect();
+        assert_eq!(ids.len(), 4);
+        let pos = |id| ids.iter().position(|&i| i == id).unwrap();
+        assert!(pos(a) < pos(m));
+        assert!(pos(a) < pos(b));
+        assert!(pos(b) < pos(c));
+        assert!(pos(m) < pos(c));
+
+        let m = &plan[pos(m)];
+        assert_eq!(m.chains, vec![1.into(), 2.into()]);
+        //chain 9 was not archived
+        assert!(m.depends_on.is_empty());
+        assert_eq!(m.old_locs, vec![loc(1, 2), loc(2, 1)]);
+        assert_eq!(m.data, b"m");
+        assert_eq!(plan[pos(c)].deps, vec![loc(3, 1)]);
+    }
+
+    #[test]
+    fn rejects_inconsistent_order() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let (a_locs, b_locs) = ([loc(1, 1), loc(2, 2)], [loc(1, 2), loc(2, 1)]);
+        let mut writer = ArchiveWriter::new(vec![]).unwrap();
+        writer.start_chain(1.into()).unwrap();
+        writer.write_entry(1.into(), &multi(&a, &a_locs, b"a")).unwrap();
+        writer.write_entry(2.into(), &multi(&b, &b_locs, b"b")).unwrap();
+        writer.start_chain(2.into()).unwrap();
+        writer.write_entry(1.into(), &multi(&b, &b_locs, b"b")).unwrap();
+        writer.write_entry(2.into(), &multi(&a, &a_locs, b"a")).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut archive = ArchiveReader::open(Cursor::new(bytes)).unwrap();
+        assert!(plan(&mut archive).is_err());
+    }
+}
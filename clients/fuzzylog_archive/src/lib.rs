@@ -0,0 +1,31 @@
+/*!
+Portable archives of FuzzyLog chains.
+
+An archive is laid out as
+
+    header:   "FZLGARCH" version: u32 reserved: u32
+    segments: for each chain, its entries in order
+              entry: index: u64 len: u32 bytes: [u8 | len]
+    index:    num_segments: u32
+              segment: chain: u64 offset: u64 num_entries: u64 first: u64 last: u64
+    trailer:  index_offset: u64 "FZLGINDX"
+
+with all integers little endian, and the entry bytes being the `EntryContents`
+exactly as stored by the server.
+
+`export` fills an archive from the admin interface of a running cluster,
+`import` replays one into another cluster through a regular client.
+Since the import re-appends every entry, the destination cluster may have
+a different number of servers than the source.
+*/
+
+extern crate byteorder;
+extern crate fuzzy_log_client;
+extern crate fuzzy_log_packets as packets;
+extern crate fuzzy_log_server as servers2;
+
+pub mod archive;
+pub mod export;
+pub mod import;
+
+pub use archive::{ArchiveReader, ArchiveWriter, Segment};
@@ -0,0 +1,118 @@
+extern crate fuzzy_log_client;
+extern crate fuzzylog_archive;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::net::SocketAddr;
+use std::process::exit;
+
+use fuzzy_log_client::{LogHandle, order};
+use fuzzylog_archive::{export, import, ArchiveReader};
+
+const USAGE: &'static str =
+"Usage:
+\tfuzzylog-archive export <archive> <admin addr>[,<admin addr>...] [<color>...]
+\tfuzzylog-archive import <archive> (-s | --servers) <addr>[,<addr>...]
+\tfuzzylog-archive import <archive> (-r | --replicated) <head addr>/<tail addr>[,...]
+\tfuzzylog-archive list <archive>
+
+export archives the given colors, or every color if none are given,
+from the servers whose admin interfaces are at <admin addr>.
+For a replicated cluster give the admin addresses of the heads.
+import appends the contents of an archive to a cluster,
+which need not have the same number of servers as the one it was exported from.";
+
+pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match (args.get(0).map(|s| &**s), args.len()) {
+        (Some("export"), n) if n >= 3 => {
+            let servers: Vec<_> = args[2].split(',').map(parse_addr).collect();
+            let colors: Vec<order> = args[3..].iter().map(|c| parse_num(c).into()).collect();
+            let colors = if colors.is_empty() { None } else { Some(&colors[..]) };
+            run_export(&args[1], &servers, colors)
+        },
+        (Some("import"), 4) => {
+            let log = match &*args[2] {
+                "-s" | "--servers" => {
+                    let servers: Vec<_> = args[3].split(',').map(parse_addr).collect();
+                    LogHandle::<[u8]>::unreplicated_with_servers(&servers).build()
+                },
+                "-r" | "--replicated" => {
+                    let servers: Vec<_> = args[3].split(',').map(parse_replica_pair).collect();
+                    LogHandle::<[u8]>::replicated_with_servers(&servers).build()
+                },
+                _ => usage(),
+            };
+            run_import(&args[1], log)
+        },
+        (Some("list"), 2) => list(&args[1]),
+        _ => usage(),
+    };
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        exit(1)
+    }
+}
+
+fn run_export(path: &str, servers: &[SocketAddr], colors: Option<&[order]>) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let (_, stats) = export::export(servers, colors, file)?;
+    println!("Exported {} entries from {} chains to {}.", stats.entries, stats.chains, path);
+    Ok(())
+}
+
+fn run_import(path: &str, mut log: LogHandle<[u8]>) -> io::Result<()> {
+    let mut archive = ArchiveReader::open(BufReader::new(File::open(path)?))?;
+    let plan = import::plan(&mut archive)?;
+    let locs = import::import(&mut log, &plan)?;
+    println!("Imported {} appends, {} entries, from {}.", plan.len(), locs.len(), path);
+    Ok(())
+}
+
+fn list(path: &str) -> io::Result<()> {
+    let archive = ArchiveReader::open(BufReader::new(File::open(path)?))?;
+    println!("version {}", archive.version());
+    println!("{:>10} {:>12} {:>12} {:>12}", "chain", "entries", "first", "last");
+    for s in archive.segments() {
+        println!("{:>10} {:>12} {:>12} {:>12}",
+            u64::from(s.chain), s.num_entries, u64::from(s.first), u64::from(s.last));
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    exit(1)
+}
+
+fn parse_addr(arg: &str) -> SocketAddr {
+    match arg.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Invalid address '{}': {}.", arg, e);
+            exit(1)
+        },
+    }
+}
+
+fn parse_replica_pair(arg: &str) -> (SocketAddr, SocketAddr) {
+    let mut split = arg.splitn(2, '/');
+    match (split.next(), split.next()) {
+        (Some(head), Some(tail)) => (parse_addr(head), parse_addr(tail)),
+        _ => {
+            eprintln!("Invalid replica pair '{}', expected <head>/<tail>.", arg);
+            exit(1)
+        },
+    }
+}
+
+fn parse_num(arg: &str) -> u64 {
+    match arg.parse() {
+        Ok(num) => num,
+        Err(e) => {
+            eprintln!("Invalid color '{}': {}.", arg, e);
+            exit(1)
+        },
+    }
+}
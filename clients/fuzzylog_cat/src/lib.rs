@@ -0,0 +1,217 @@
+/*!
+Dump the contents of FuzzyLog colors as JSON lines.
+
+Every event is written on its own line as
+
+    {"id":"<uuid>","locs":[[<color>,<index>],...],"deps":[[<color>,<index>],...],"payload":<payload>}
+
+and once a snapshot has been fully read a line
+
+    {"horizon":[[<color>,<index>],...]}
+
+is written containing the last index read from each color.
+How a payload is rendered is up to the `Decoder` the `Cat` is created with.
+*/
+
+extern crate fuzzy_log_client;
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use fuzzy_log_client::{Cut, LogHandle, GetRes, order, OrderIndex, Uuid};
+
+/// Renders the payload of an event.
+pub trait Decoder {
+    /// Returns the payload as a JSON value.
+    fn decode(&mut self, payload: &[u8]) -> String;
+}
+
+/// Renders payloads as a string of hex digits.
+pub struct Hex;
+
+/// Renders payloads as a string, replacing invalid UTF-8.
+pub struct Utf8;
+
+impl Decoder for Hex {
+    fn decode(&mut self, payload: &[u8]) -> String {
+        let mut out = String::with_capacity(payload.len() * 2 + 2);
+        out.push('"');
+        for b in payload {
+            let _ = write!(out, "{:02x}", b);
+        }
+        out.push('"');
+        out
+    }
+}
+
+impl Decoder for Utf8 {
+    fn decode(&mut self, payload: &[u8]) -> String {
+        let mut out = String::with_capacity(payload.len() + 2);
+        write_json_str(&mut out, &String::from_utf8_lossy(payload));
+        out
+    }
+}
+
+impl<F> Decoder for F
+where F: FnMut(&[u8]) -> String {
+    fn decode(&mut self, payload: &[u8]) -> String {
+        self(payload)
+    }
+}
+
+pub struct Cat<D> {
+    log: LogHandle<[u8]>,
+    decoder: D,
+    follow: Option<Duration>,
+    horizon: Cut,
+}
+
+impl<D> Cat<D>
+where D: Decoder {
+    /// `log` should be built with the colors to be dumped as its interesting chains.
+    pub fn new(log: LogHandle<[u8]>, decoder: D) -> Self {
+        Cat {
+            log,
+            decoder,
+            follow: None,
+            horizon: Default::default(),
+        }
+    }
+
+    /// Instead of returning once the log is read,
+    /// keep taking a new snapshot every `poll_interval`.
+    pub fn follow(self, poll_interval: Duration) -> Self {
+        Cat { follow: Some(poll_interval), .. self }
+    }
+
+    pub fn run<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            let mut write_err = None;
+            let res = {
+                let &mut Cat{ref mut log, ref mut decoder, ..} = self;
+                log.sync_events(|e| {
+                    if write_err.is_some() { return }
+                    line.clear();
+                    write_event(&mut line, e.id, e.inhabits, e.happens_after, e.data, decoder);
+                    if let Err(e) = writeln!(out, "{}", line) {
+                        write_err = Some(e)
+                    }
+                })
+            };
+            if let Some(e) = write_err {
+                return Err(e)
+            }
+            match res {
+                Ok(seen) => if !seen.is_empty() {
+                    self.horizon.merge(&seen);
+                    line.clear();
+                    write_horizon(&mut line, &self.horizon);
+                    writeln!(out, "{}", line)?;
+                    out.flush()?;
+                },
+                Err(GetRes::IoErr(kind, server)) =>
+                    return Err(io::Error::new(kind, format!("error at server {}", server))),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))),
+            }
+            match self.follow {
+                None => return Ok(()),
+                Some(interval) => thread::sleep(interval),
+            }
+        }
+    }
+}
+
+pub fn write_event<D: Decoder>(
+    out: &mut String,
+    id: &Uuid,
+    locs: &[OrderIndex],
+    deps: &[OrderIndex],
+    payload: &[u8],
+    decoder: &mut D,
+) {
+    let _ = write!(out, "{{\"id\":\"{}\",\"locs\":", id);
+    write_locs(out, locs);
+    out.push_str(",\"deps\":");
+    write_locs(out, deps);
+    out.push_str(",\"payload\":");
+    out.push_str(&decoder.decode(payload));
+    out.push('}');
+}
+
+pub fn write_horizon(out: &mut String, horizon: &Cut) {
+    out.push_str("{\"horizon\":");
+    write_locs(out, &horizon.locs());
+    out.push('}');
+}
+
+fn write_locs(out: &mut String, locs: &[OrderIndex]) {
+    out.push('[');
+    //color 0 only separates the parts of a multi-server append
+    let locs = locs.iter().filter(|&&OrderIndex(o, _)| o != order::from(0));
+    for (n, &OrderIndex(o, i)) in locs.enumerate() {
+        if n > 0 { out.push(',') }
+        let _ = write!(out, "[{},{}]", u64::from(o), u64::from(i));
+    }
+    out.push(']');
+}
+
+pub fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_line() {
+        let mut line = String::new();
+        write_event(
+            &mut line,
+            &Uuid::nil(),
+            &[OrderIndex(1.into(), 2.into()), OrderIndex(0.into(), 0.into()), OrderIndex(3.into(), 4.into())],
+            &[OrderIndex(5.into(), 6.into())],
+            &[0xde, 0xad, 0x01],
+            &mut Hex,
+        );
+        assert_eq!(line,
+            "{\"id\":\"00000000-0000-0000-0000-000000000000\",\"locs\":[[1,2],[3,4]],\
+            \"deps\":[[5,6]],\"payload\":\"dead01\"}");
+    }
+
+    #[test]
+    fn utf8_payload() {
+        assert_eq!(Utf8.decode(b"a\"b\n\x01"), "\"a\\\"b\\n\\u0001\"");
+    }
+
+    #[test]
+    fn custom_decoder() {
+        let mut len = |p: &[u8]| p.len().to_string();
+        assert_eq!(len.decode(&[1, 2, 3]), "3");
+    }
+
+    #[test]
+    fn horizon_line() {
+        let mut horizon = Cut::new();
+        horizon.observe(OrderIndex(7.into(), 3.into()));
+        horizon.observe(OrderIndex(2.into(), 10.into()));
+        let mut line = String::new();
+        write_horizon(&mut line, &horizon);
+        assert_eq!(line, "{\"horizon\":[[2,10],[7,3]]}");
+    }
+}
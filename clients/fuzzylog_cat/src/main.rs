@@ -0,0 +1,167 @@
+extern crate fuzzy_log_client;
+extern crate fuzzylog_cat;
+
+use std::env;
+use std::io::{self, BufWriter};
+use std::net::SocketAddr;
+use std::process::exit;
+use std::time::Duration;
+
+use fuzzy_log_client::{LogHandle, order};
+use fuzzylog_cat::{Cat, Decoder, Hex, Utf8};
+
+const USAGE: &'static str =
+"Usage:
+\tfuzzylog-cat (-s | --servers) <addr>[,<addr>...] [options] <color>...
+\tfuzzylog-cat (-r | --replicated) <head addr>/<tail addr>[,...] [options] <color>...
+
+Prints every event in the given colors as a line of JSON.
+
+Options:
+\t--payload (hex | utf8)    how payloads are printed, defaults to hex
+\t-f, --follow              keep printing new events as they are appended
+\t--follow-interval <ms>    how often --follow polls for new events,
+\t                          in milliseconds (default 100), implies --follow";
+
+enum Servers {
+    Unreplicated(Vec<SocketAddr>),
+    Replicated(Vec<(SocketAddr, SocketAddr)>),
+}
+
+enum Payload {
+    Hex,
+    Utf8,
+}
+
+struct Args {
+    servers: Servers,
+    colors: Vec<order>,
+    payload: Payload,
+    follow: Option<Duration>,
+}
+
+pub fn main() {
+    let Args{servers, colors, payload, follow} = parse_args();
+    let log = match servers {
+        Servers::Unreplicated(servers) =>
+            LogHandle::<[u8]>::unreplicated_with_servers(&servers),
+        Servers::Replicated(servers) =>
+            LogHandle::<[u8]>::replicated_with_servers(&servers),
+    }.chains(&colors).build();
+    let res = match payload {
+        Payload::Hex => cat(log, Hex, follow),
+        Payload::Utf8 => cat(log, Utf8, follow),
+    };
+    match res {
+        Ok(()) => {},
+        //the reader went away, e.g. `fuzzylog-cat ... | head`
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {},
+        Err(e) => {
+            eprintln!("Could not read log due to {}.", e);
+            exit(1)
+        },
+    }
+}
+
+fn cat<D: Decoder>(log: LogHandle<[u8]>, decoder: D, follow: Option<Duration>)
+-> io::Result<()> {
+    let mut cat = Cat::new(log, decoder);
+    if let Some(interval) = follow {
+        cat = cat.follow(interval);
+    }
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    cat.run(&mut out)
+}
+
+fn parse_args() -> Args {
+    let mut servers = None;
+    let mut colors = vec![];
+    let mut payload = Payload::Hex;
+    let mut follow = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-s" | "--servers" => {
+                let addrs = args.next().unwrap_or_else(|| missing(&arg));
+                servers = Some(Servers::Unreplicated(
+                    addrs.split(',').map(parse_addr).collect()
+                ));
+            },
+            "-r" | "--replicated" => {
+                let addrs = args.next().unwrap_or_else(|| missing(&arg));
+                servers = Some(Servers::Replicated(
+                    addrs.split(',').map(|pair| {
+                        let mut split = pair.splitn(2, '/');
+                        let head = split.next().map(parse_addr);
+                        let tail = split.next().map(parse_addr);
+                        match (head, tail) {
+                            (Some(head), Some(tail)) => (head, tail),
+                            _ => {
+                                eprintln!("Invalid replica pair '{}', expected <head>/<tail>.", pair);
+                                exit(1)
+                            },
+                        }
+                    }).collect()
+                ));
+            },
+            "--payload" => {
+                payload = match args.next().as_ref().map(|s| &**s) {
+                    Some("hex") => Payload::Hex,
+                    Some("utf8") => Payload::Utf8,
+                    Some(other) => {
+                        eprintln!("Unknown payload format '{}'.", other);
+                        exit(1)
+                    },
+                    None => missing(&arg),
+                };
+            },
+            "-f" | "--follow" => {
+                follow = follow.or(Some(Duration::from_millis(100)));
+            },
+            "--follow-interval" => {
+                let ms = args.next().unwrap_or_else(|| missing(&arg));
+                let ms = ms.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid interval '{}'.", ms);
+                    exit(1)
+                });
+                follow = Some(Duration::from_millis(ms));
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0)
+            },
+            color => match color.parse::<u64>() {
+                Ok(color) if color != 0 => colors.push(color.into()),
+                _ => {
+                    eprintln!("Invalid color '{}'.\n\n{}", color, USAGE);
+                    exit(1)
+                },
+            },
+        }
+    }
+    let servers = servers.unwrap_or_else(|| {
+        eprintln!("No servers given.\n\n{}", USAGE);
+        exit(1)
+    });
+    if colors.is_empty() {
+        eprintln!("No colors given.\n\n{}", USAGE);
+        exit(1)
+    }
+    Args{servers, colors, payload, follow}
+}
+
+fn missing(flag: &str) -> ! {
+    eprintln!("Missing argument for '{}'.\n\n{}", flag, USAGE);
+    exit(1)
+}
+
+fn parse_addr(arg: &str) -> SocketAddr {
+    match arg.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Invalid address '{}': {}.", arg, e);
+            exit(1)
+        },
+    }
+}
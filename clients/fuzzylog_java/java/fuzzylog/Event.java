@@ -0,0 +1,29 @@
+package fuzzylog;
+
+import java.util.Arrays;
+
+/** An event read from the log. */
+public final class Event {
+    /** The id of the append which wrote the event. */
+    public final WriteId id;
+    public final byte[] data;
+    /** The locations of the event, one per color it was appended to. */
+    public final Location[] inhabits;
+    /** The locations the event was explicitly appended after. */
+    public final Location[] happensAfter;
+
+    // constructed by the native library
+    Event(byte[] id, byte[] data, long[] inhabits, long[] happensAfter) {
+        this.id = new WriteId(id);
+        this.data = data;
+        this.inhabits = Location.fromPairs(inhabits);
+        this.happensAfter = Location.fromPairs(happensAfter);
+    }
+
+    @Override
+    public String toString() {
+        return "Event(data=" + Arrays.toString(data)
+            + ", inhabits=" + Arrays.toString(inhabits)
+            + ", happensAfter=" + Arrays.toString(happensAfter) + ")";
+    }
+}
@@ -0,0 +1,8 @@
+package fuzzylog;
+
+/** Thrown when a {@link LogHandle} operation fails. */
+public class FuzzyLogException extends RuntimeException {
+    public FuzzyLogException(String message) {
+        super(message);
+    }
+}
@@ -0,0 +1,8 @@
+package fuzzylog;
+
+/** Thrown when a blocking {@link LogHandle} call exceeds the handle's timeout. */
+public class FuzzyLogTimeoutException extends FuzzyLogException {
+    public FuzzyLogTimeoutException(String message) {
+        super(message);
+    }
+}
@@ -0,0 +1,46 @@
+package fuzzylog;
+
+/** A position in the log: an index within a color's chain. */
+public final class Location {
+    public final long color;
+    public final long index;
+
+    public Location(long color, long index) {
+        this.color = color;
+        this.index = index;
+    }
+
+    static Location[] fromPairs(long[] pairs) {
+        Location[] locs = new Location[pairs.length / 2];
+        for (int i = 0; i < locs.length; i++) {
+            locs[i] = new Location(pairs[2 * i], pairs[2 * i + 1]);
+        }
+        return locs;
+    }
+
+    static long[] toPairs(Location[] locs) {
+        long[] pairs = new long[locs.length * 2];
+        for (int i = 0; i < locs.length; i++) {
+            pairs[2 * i] = locs[i].color;
+            pairs[2 * i + 1] = locs[i].index;
+        }
+        return pairs;
+    }
+
+    @Override
+    public boolean equals(Object o) {
+        if (!(o instanceof Location)) return false;
+        Location other = (Location) o;
+        return color == other.color && index == other.index;
+    }
+
+    @Override
+    public int hashCode() {
+        return Long.hashCode(color) * 31 + Long.hashCode(index);
+    }
+
+    @Override
+    public String toString() {
+        return "(" + color + ", " + index + ")";
+    }
+}
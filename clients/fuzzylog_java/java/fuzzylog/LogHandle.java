@@ -0,0 +1,173 @@
+package fuzzylog;
+
+/**
+ * A connection to a fuzzy log.
+ *
+ * <p>A handle reads the colors it was constructed with; {@link #snapshot()}
+ * fetches the events currently in them, which are then returned one at a
+ * time by {@link #nextEvent()}. Appends may be sent asynchronously and
+ * waited for later by their {@link WriteId}.
+ *
+ * <p>Calls on a handle are serialized, so a handle may be shared between
+ * threads, though a thread blocked in one call blocks the others.
+ * Handles must be {@link #close closed} to release their connections.
+ *
+ * <p>The native library, {@code libfuzzylog_java}, must be on
+ * {@code java.library.path}.
+ */
+public final class LogHandle implements AutoCloseable {
+    static {
+        System.loadLibrary("fuzzylog_java");
+    }
+
+    private static final Location[] NO_DEPS = new Location[0];
+
+    private long ptr;
+
+    private LogHandle(long ptr) {
+        this.ptr = ptr;
+    }
+
+    /**
+     * Connect to unreplicated servers, e.g. {@code "127.0.0.1:13229"},
+     * reading {@code colors}.
+     */
+    public static LogHandle unreplicated(String[] servers, long... colors) {
+        return unreplicated(servers, colors, 0, false);
+    }
+
+    /**
+     * Connect to unreplicated servers reading {@code colors}.
+     * A positive {@code timeoutMillis} bounds every blocking call;
+     * with {@code readsMyWrites} snapshots include this handle's own appends.
+     */
+    public static LogHandle unreplicated(
+        String[] servers, long[] colors, long timeoutMillis, boolean readsMyWrites
+    ) {
+        return new LogHandle(nativeUnreplicated(servers, colors, timeoutMillis, readsMyWrites));
+    }
+
+    /**
+     * Connect to replicated servers, where {@code tails[i]} is the tail of
+     * the chain headed by {@code heads[i]}, reading {@code colors}.
+     */
+    public static LogHandle replicated(
+        String[] heads, String[] tails, long[] colors, long timeoutMillis, boolean readsMyWrites
+    ) {
+        return new LogHandle(nativeReplicated(heads, tails, colors, timeoutMillis, readsMyWrites));
+    }
+
+    /** Append {@code data} to {@code color}, waiting for it to be ack'd. */
+    public Location[] append(long color, byte[] data) {
+        return append(color, data, NO_DEPS);
+    }
+
+    /**
+     * Append {@code data} to {@code color} after {@code deps},
+     * waiting for it to be ack'd; returns its location.
+     */
+    public synchronized Location[] append(long color, byte[] data, Location... deps) {
+        return waitForSpecificAppend(asyncAppend(color, data, deps));
+    }
+
+    /**
+     * Atomically append {@code data} to every color in {@code colors}
+     * after {@code deps}, waiting for it to be ack'd; returns its locations.
+     */
+    public synchronized Location[] multiappend(long[] colors, byte[] data, Location... deps) {
+        return waitForSpecificAppend(asyncMultiappend(colors, data, deps));
+    }
+
+    /** Like {@link #append(long, byte[], Location...)} but does not wait. */
+    public WriteId asyncAppend(long color, byte[] data, Location... deps) {
+        return asyncMultiappend(new long[] { color }, data, deps);
+    }
+
+    /** Like {@link #multiappend} but does not wait. */
+    public synchronized WriteId asyncMultiappend(long[] colors, byte[] data, Location... deps) {
+        return new WriteId(nativeAsyncAppend(ptr(), colors, data, Location.toPairs(deps)));
+    }
+
+    /** Wait for the append {@code id} to be ack'd; returns its locations. */
+    public synchronized Location[] waitForSpecificAppend(WriteId id) {
+        return Location.fromPairs(nativeWaitForSpecificAppend(ptr(), id.raw()));
+    }
+
+    /**
+     * Wait for any outstanding append to be ack'd.
+     *
+     * @return the append's ack, or {@code null} if none are outstanding
+     */
+    public synchronized Ack waitForAnyAppend() {
+        return nativeWaitForAnyAppend(ptr());
+    }
+
+    /** Wait for every outstanding append to be ack'd. */
+    public synchronized void waitForAllAppends() {
+        nativeWaitForAllAppends(ptr());
+    }
+
+    /** Snapshot every color this handle reads. */
+    public synchronized void snapshot() {
+        nativeSnapshot(ptr(), null);
+    }
+
+    /** Snapshot {@code colors}, which this handle must read. */
+    public synchronized void snapshot(long... colors) {
+        nativeSnapshot(ptr(), colors);
+    }
+
+    /**
+     * Return the next event of the current snapshot.
+     *
+     * @return the event, or {@code null} once the snapshot has been read
+     */
+    public synchronized Event nextEvent() {
+        return nativeNextEvent(ptr());
+    }
+
+    @Override
+    public synchronized void close() {
+        nativeClose(ptr);
+        ptr = 0;
+    }
+
+    private long ptr() {
+        if (ptr == 0) {
+            throw new FuzzyLogException("the handle has been closed");
+        }
+        return ptr;
+    }
+
+    /** The ack of an append. */
+    public static final class Ack {
+        public final WriteId id;
+        public final Location[] locations;
+
+        // constructed by the native library
+        Ack(byte[] id, long[] locations) {
+            this.id = new WriteId(id);
+            this.locations = Location.fromPairs(locations);
+        }
+    }
+
+    private static native long nativeUnreplicated(
+        String[] servers, long[] colors, long timeoutMillis, boolean readsMyWrites);
+
+    private static native long nativeReplicated(
+        String[] heads, String[] tails, long[] colors, long timeoutMillis, boolean readsMyWrites);
+
+    private static native void nativeClose(long ptr);
+
+    private static native byte[] nativeAsyncAppend(long ptr, long[] colors, byte[] data, long[] deps);
+
+    private static native long[] nativeWaitForSpecificAppend(long ptr, byte[] id);
+
+    private static native Ack nativeWaitForAnyAppend(long ptr);
+
+    private static native void nativeWaitForAllAppends(long ptr);
+
+    private static native void nativeSnapshot(long ptr, long[] colors);
+
+    private static native Event nativeNextEvent(long ptr);
+}
@@ -0,0 +1,40 @@
+package fuzzylog;
+
+import java.util.Arrays;
+
+/** The id of an append, used to wait for its ack. */
+public final class WriteId {
+    private final byte[] bytes;
+
+    WriteId(byte[] bytes) {
+        this.bytes = bytes;
+    }
+
+    /** The 16 bytes of the id's UUID. */
+    public byte[] bytes() {
+        return bytes.clone();
+    }
+
+    byte[] raw() {
+        return bytes;
+    }
+
+    @Override
+    public boolean equals(Object o) {
+        return o instanceof WriteId && Arrays.equals(bytes, ((WriteId) o).bytes);
+    }
+
+    @Override
+    public int hashCode() {
+        return Arrays.hashCode(bytes);
+    }
+
+    @Override
+    public String toString() {
+        StringBuilder s = new StringBuilder();
+        for (byte b : bytes) {
+            s.append(String.format("%02x", b));
+        }
+        return s.toString();
+    }
+}
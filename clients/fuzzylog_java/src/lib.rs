@@ -0,0 +1,368 @@
+/*!
+
+JNI bindings for the fuzzy log client.
+
+This is the native half of the `fuzzylog.LogHandle` Java class (see
+`java/fuzzylog`). Each Java handle owns a boxed `LogHandle<[u8]>` whose
+address it stores in a `long`; the Java class serializes calls on a handle,
+so the natives here never see concurrent use of the same pointer.
+
+To keep the JNI surface small, locations cross the boundary as flat
+`long[]`s of `color, index` pairs, and write ids as 16 byte `byte[]`s;
+the Java side wraps them in `Location`s and `WriteId`s.
+Errors are thrown as `fuzzylog.FuzzyLogException`, or its subclass
+`fuzzylog.FuzzyLogTimeoutException` when a handle's timeout expires.
+
+*/
+
+extern crate fuzzy_log_client;
+extern crate jni;
+
+use std::net::SocketAddr;
+use std::ptr;
+use std::time::Duration;
+
+use fuzzy_log_client::{
+    GetRes,
+    LogBuilder,
+    LogHandle as Handle,
+    TryWaitRes,
+    OrderIndex,
+    Uuid,
+    order,
+};
+
+use jni::JNIEnv;
+use jni::objects::{JByteArray, JClass, JLongArray, JObjectArray, JString, JValue};
+use jni::sys::{jboolean, jbyteArray, jlong, jlongArray, jobject};
+
+const EXCEPTION: &str = "fuzzylog/FuzzyLogException";
+const TIMEOUT_EXCEPTION: &str = "fuzzylog/FuzzyLogTimeoutException";
+
+struct Error {
+    class: &'static str,
+    message: String,
+}
+
+type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    fn new<S: Into<String>>(message: S) -> Self {
+        Error { class: EXCEPTION, message: message.into() }
+    }
+
+    fn timeout<S: Into<String>>(message: S) -> Self {
+        Error { class: TIMEOUT_EXCEPTION, message: message.into() }
+    }
+}
+
+impl From<jni::errors::Error> for Error {
+    fn from(err: jni::errors::Error) -> Self {
+        Error::new(format!("JNI error: {}", err))
+    }
+}
+
+impl From<GetRes> for Error {
+    fn from(err: GetRes) -> Self {
+        match err {
+            GetRes::Timeout => Error::timeout(err.to_string()),
+            _ => Error::new(err.to_string()),
+        }
+    }
+}
+
+impl From<TryWaitRes> for Error {
+    fn from(err: TryWaitRes) -> Self {
+        match err {
+            TryWaitRes::Timeout => Error::timeout(err.to_string()),
+            _ => Error::new(err.to_string()),
+        }
+    }
+}
+
+/// Run `f`, throwing any error it returns and returning `on_err` in its place.
+/// If a Java exception is already pending, e.g. from a failed JNI call,
+/// it is left to propagate instead.
+fn or_throw<'local, T, F>(env: &mut JNIEnv<'local>, on_err: T, f: F) -> T
+where F: FnOnce(&mut JNIEnv<'local>) -> Result<T> {
+    match f(env) {
+        Ok(t) => t,
+        Err(e) => {
+            if !env.exception_check().unwrap_or(false) {
+                let _ = env.throw_new(e.class, e.message);
+            }
+            on_err
+        },
+    }
+}
+
+fn handle<'h>(ptr: jlong) -> Result<&'h mut Handle<[u8]>> {
+    if ptr == 0 {
+        return Err(Error::new("the handle has been closed"))
+    }
+    Ok(unsafe { &mut *(ptr as *mut Handle<[u8]>) })
+}
+
+fn longs(env: &mut JNIEnv, array: &JLongArray) -> Result<Vec<jlong>> {
+    if array.is_null() {
+        return Ok(vec![])
+    }
+    let len = env.get_array_length(array)?;
+    let mut longs = vec![0; len as usize];
+    env.get_long_array_region(array, 0, &mut longs)?;
+    Ok(longs)
+}
+
+fn to_chains(env: &mut JNIEnv, chains: &JLongArray) -> Result<Vec<order>> {
+    let chains = longs(env, chains)?;
+    if chains.iter().any(|&c| c <= 0) {
+        return Err(Error::new("colors must be positive, color 0 is reserved"))
+    }
+    Ok(chains.into_iter().map(|c| (c as u64).into()).collect())
+}
+
+fn from_locs(env: &mut JNIEnv, locs: &JLongArray) -> Result<Vec<OrderIndex>> {
+    let locs = longs(env, locs)?;
+    if locs.len() % 2 != 0 {
+        return Err(Error::new("locations are (color, index) pairs"))
+    }
+    Ok(locs.chunks(2).map(|l| (l[0] as u64, l[1] as u64).into()).collect())
+}
+
+fn to_locs<'local>(env: &mut JNIEnv<'local>, locs: &[OrderIndex])
+-> Result<JLongArray<'local>> {
+    let flat: Vec<jlong> = locs.iter()
+        .flat_map(|&OrderIndex(o, i)| {
+            let (o, i): (u64, u64) = (o.into(), i.into());
+            vec![o as jlong, i as jlong]
+        })
+        .collect();
+    let array = env.new_long_array(flat.len() as i32)?;
+    env.set_long_array_region(&array, 0, &flat)?;
+    Ok(array)
+}
+
+fn to_id(env: &mut JNIEnv, id: &JByteArray) -> Result<Uuid> {
+    if id.is_null() {
+        return Err(Error::new("the write id is null"))
+    }
+    let id = env.convert_byte_array(id)?;
+    Uuid::from_bytes(&id).map_err(|_| Error::new("a write id is 16 bytes"))
+}
+
+fn parse_addrs(env: &mut JNIEnv, addrs: &JObjectArray) -> Result<Vec<SocketAddr>> {
+    if addrs.is_null() {
+        return Err(Error::new("no servers given"))
+    }
+    let len = env.get_array_length(addrs)?;
+    let mut parsed = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let addr = JString::from(env.get_object_array_element(addrs, i)?);
+        if addr.is_null() {
+            return Err(Error::new("server addresses cannot be null"))
+        }
+        let addr: String = env.get_string(&addr)?.into();
+        let addr = addr.parse()
+            .map_err(|e| Error::new(format!("invalid address \"{}\": {}", addr, e)))?;
+        parsed.push(addr);
+    }
+    Ok(parsed)
+}
+
+fn build(
+    env: &mut JNIEnv,
+    builder: LogBuilder<[u8]>,
+    chains: &JLongArray,
+    timeout_ms: jlong,
+    reads_my_writes: jboolean,
+) -> Result<jlong> {
+    let mut builder = builder.chains(to_chains(env, chains)?);
+    if timeout_ms > 0 {
+        builder = builder.timeout(Duration::from_millis(timeout_ms as u64));
+    }
+    if reads_my_writes != 0 {
+        builder = builder.reads_my_writes();
+    }
+    let handle = builder.try_build()
+        .map_err(|e| Error::new(format!("could not connect: {}", e)))?;
+    Ok(Box::into_raw(Box::new(handle)) as jlong)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeUnreplicated<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    servers: JObjectArray<'local>,
+    chains: JLongArray<'local>,
+    timeout_ms: jlong,
+    reads_my_writes: jboolean,
+) -> jlong {
+    or_throw(&mut env, 0, |env| {
+        let servers = parse_addrs(env, &servers)?;
+        let builder = Handle::unreplicated_with_servers(servers);
+        build(env, builder, &chains, timeout_ms, reads_my_writes)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeReplicated<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    heads: JObjectArray<'local>,
+    tails: JObjectArray<'local>,
+    chains: JLongArray<'local>,
+    timeout_ms: jlong,
+    reads_my_writes: jboolean,
+) -> jlong {
+    or_throw(&mut env, 0, |env| {
+        let heads = parse_addrs(env, &heads)?;
+        let tails = parse_addrs(env, &tails)?;
+        if heads.len() != tails.len() {
+            return Err(Error::new("every head server needs a tail"))
+        }
+        let builder = Handle::replicated_with_servers(heads.into_iter().zip(tails));
+        build(env, builder, &chains, timeout_ms, reads_my_writes)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeClose<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+) {
+    if ptr != 0 {
+        unsafe { drop(Box::from_raw(ptr as *mut Handle<[u8]>)) }
+    }
+}
+
+/// Appends to a single chain use a regular append,
+/// while appends to multiple chains use a multiappend.
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeAsyncAppend<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+    chains: JLongArray<'local>,
+    data: JByteArray<'local>,
+    deps: JLongArray<'local>,
+) -> jbyteArray {
+    or_throw(&mut env, ptr::null_mut(), |env| {
+        let handle = handle(ptr)?;
+        let mut chains = to_chains(env, &chains)?;
+        chains.sort();
+        chains.dedup();
+        if data.is_null() {
+            return Err(Error::new("the data is null"))
+        }
+        let data = env.convert_byte_array(&data)?;
+        let deps = from_locs(env, &deps)?;
+        let id = match chains.len() {
+            0 => return Err(Error::new("need at least one chain")),
+            1 => handle.async_append(chains[0], &data[..], &deps),
+            _ => handle.async_multiappend(&chains, &data[..], &deps),
+        };
+        Ok(env.byte_array_from_slice(id.as_bytes())?.into_raw())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeWaitForSpecificAppend<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+    id: JByteArray<'local>,
+) -> jlongArray {
+    or_throw(&mut env, ptr::null_mut(), |env| {
+        let handle = handle(ptr)?;
+        let id = to_id(env, &id)?;
+        let locs = handle.wait_for_a_specific_append(id)?;
+        Ok(to_locs(env, &locs)?.into_raw())
+    })
+}
+
+/// Returns a `fuzzylog.LogHandle.Ack`, or `null` if no appends are outstanding.
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeWaitForAnyAppend<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+) -> jobject {
+    or_throw(&mut env, ptr::null_mut(), |env| {
+        let handle = handle(ptr)?;
+        let (id, locs) = match handle.wait_for_any_append() {
+            Ok(ack) => ack,
+            Err(TryWaitRes::NothingReady) => return Ok(ptr::null_mut()),
+            Err(e) => return Err(e.into()),
+        };
+        let id = env.byte_array_from_slice(id.as_bytes())?;
+        let locs = to_locs(env, &locs)?;
+        let ack = env.new_object(
+            "fuzzylog/LogHandle$Ack",
+            "([B[J)V",
+            &[JValue::Object(&id), JValue::Object(&locs)],
+        )?;
+        Ok(ack.into_raw())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeWaitForAllAppends<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+) {
+    or_throw(&mut env, (), |_| Ok(handle(ptr)?.wait_for_all_appends()?))
+}
+
+/// A `null` or empty `chains` snapshots every chain the handle reads.
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeSnapshot<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+    chains: JLongArray<'local>,
+) {
+    or_throw(&mut env, (), |env| {
+        let handle = handle(ptr)?;
+        let chains = to_chains(env, &chains)?;
+        if chains.is_empty() {
+            handle.take_snapshot()
+        } else {
+            handle.snapshot_colors(&chains)
+        }
+        Ok(())
+    })
+}
+
+/// Returns a `fuzzylog.Event`, or `null` once the snapshot has been read.
+#[no_mangle]
+pub extern "system" fn Java_fuzzylog_LogHandle_nativeNextEvent<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ptr: jlong,
+) -> jobject {
+    or_throw(&mut env, ptr::null_mut(), |env| {
+        let handle = handle(ptr)?;
+        let event = match handle.get_next_event() {
+            Ok(event) => event,
+            Err(GetRes::Done) => return Ok(ptr::null_mut()),
+            Err(e) => return Err(e.into()),
+        };
+        let id = env.byte_array_from_slice(event.id.as_bytes())?;
+        let data = env.byte_array_from_slice(event.data)?;
+        let inhabits = to_locs(env, event.inhabits)?;
+        let happens_after = to_locs(env, event.happens_after)?;
+        let event = env.new_object(
+            "fuzzylog/Event",
+            "([B[B[J[J)V",
+            &[
+                JValue::Object(&id),
+                JValue::Object(&data),
+                JValue::Object(&inhabits),
+                JValue::Object(&happens_after),
+            ],
+        )?;
+        Ok(event.into_raw())
+    })
+}
@@ -0,0 +1,149 @@
+package fuzzylog;
+
+import java.lang.reflect.Method;
+import java.lang.reflect.Modifier;
+import java.nio.charset.StandardCharsets;
+import java.util.ArrayList;
+import java.util.Arrays;
+import java.util.List;
+import java.util.concurrent.ThreadLocalRandom;
+
+/**
+ * Runs every static {@code test*} method against a running server at
+ * {@code $FUZZYLOG_SERVER}, or {@code 127.0.0.1:13229} by default.
+ */
+public final class LogHandleTest {
+    static final String SERVER = System.getenv().getOrDefault("FUZZYLOG_SERVER", "127.0.0.1:13229");
+
+    public static void main(String[] args) throws Exception {
+        int failed = 0;
+        for (Method test : LogHandleTest.class.getDeclaredMethods()) {
+            if (!test.getName().startsWith("test") || !Modifier.isStatic(test.getModifiers())) {
+                continue;
+            }
+            try {
+                test.invoke(null);
+                System.out.println(test.getName() + " ... ok");
+            } catch (java.lang.reflect.InvocationTargetException e) {
+                failed++;
+                System.out.println(test.getName() + " ... FAILED");
+                e.getCause().printStackTrace(System.out);
+            }
+        }
+        if (failed > 0) {
+            System.out.println(failed + " test(s) failed");
+            System.exit(1);
+        }
+    }
+
+    // the tests share one server, give each its own colors
+    static long[] freshColors(int n) {
+        long base = ThreadLocalRandom.current().nextLong(1_000_000, 1L << 40);
+        long[] colors = new long[n];
+        for (int i = 0; i < n; i++) {
+            colors[i] = base + i;
+        }
+        return colors;
+    }
+
+    static LogHandle handle(long[] colors) {
+        return LogHandle.unreplicated(new String[] { SERVER }, colors, 10_000, false);
+    }
+
+    static byte[] bytes(String s) {
+        return s.getBytes(StandardCharsets.UTF_8);
+    }
+
+    static List<Event> readAll(LogHandle log) {
+        List<Event> events = new ArrayList<>();
+        for (Event e = log.nextEvent(); e != null; e = log.nextEvent()) {
+            events.add(e);
+        }
+        return events;
+    }
+
+    static void check(boolean cond, String what) {
+        if (!cond) throw new AssertionError(what);
+    }
+
+    static void testAppendAndRead() {
+        long color = freshColors(1)[0];
+        try (LogHandle log = handle(new long[] { color })) {
+            check(Arrays.equals(log.append(color, bytes("a")), new Location[] { new Location(color, 1) }),
+                "first append is at index 1");
+            check(Arrays.equals(log.append(color, bytes("b")), new Location[] { new Location(color, 2) }),
+                "second append is at index 2");
+            log.snapshot();
+            List<Event> events = readAll(log);
+            check(events.size() == 2, "read both events");
+            check(Arrays.equals(events.get(0).data, bytes("a")), "read a");
+            check(Arrays.equals(events.get(1).data, bytes("b")), "read b");
+        }
+    }
+
+    static void testMultiappend() {
+        long[] colors = freshColors(2);
+        try (LogHandle log = handle(colors)) {
+            Location[] locs = log.multiappend(colors, bytes("both"));
+            check(locs.length == 2, "one location per color");
+            log.snapshot();
+            List<Event> events = readAll(log);
+            check(events.size() == 1, "a multiappend is read once");
+            Event event = events.get(0);
+            check(Arrays.equals(event.data, bytes("both")), "read the multiappend");
+            long[] inhabits = { event.inhabits[0].color, event.inhabits[1].color };
+            Arrays.sort(inhabits);
+            check(Arrays.equals(inhabits, colors), "the event inhabits both colors");
+        }
+    }
+
+    static void testAsyncAppend() {
+        long color = freshColors(1)[0];
+        try (LogHandle log = handle(new long[] { color })) {
+            WriteId first = log.asyncAppend(color, bytes("1"));
+            WriteId second = log.asyncAppend(color, bytes("2"));
+            check(first.bytes().length == 16, "ids are uuids");
+            LogHandle.Ack ack = log.waitForAnyAppend();
+            check(ack.id.equals(first) || ack.id.equals(second), "ack is for a sent append");
+            log.waitForAllAppends();
+            check(log.waitForAnyAppend() == null, "no appends outstanding");
+            log.snapshot(color);
+            List<Event> events = readAll(log);
+            check(events.size() == 2, "read both events");
+            check(events.get(0).id.equals(first), "first event has first id");
+            check(events.get(1).id.equals(second), "second event has second id");
+        }
+    }
+
+    static void testHappensAfter() {
+        long[] colors = freshColors(2);
+        try (LogHandle log = handle(colors)) {
+            Location[] dep = log.append(colors[0], bytes("cause"));
+            log.append(colors[1], bytes("effect"), dep);
+            log.snapshot(colors[1]);
+            List<Event> events = readAll(log);
+            Event last = events.get(events.size() - 1);
+            check(Arrays.equals(last.data, bytes("effect")), "read the effect last");
+            check(Arrays.equals(last.happensAfter, dep), "the effect happens after the cause");
+        }
+    }
+
+    static void testBadAddress() {
+        try {
+            LogHandle.unreplicated(new String[] { "not an address" }, 1).close();
+            throw new AssertionError("connected to a bad address");
+        } catch (FuzzyLogException expected) {
+        }
+    }
+
+    static void testClosedHandle() {
+        long color = freshColors(1)[0];
+        LogHandle log = handle(new long[] { color });
+        log.close();
+        try {
+            log.append(color, bytes("late"));
+            throw new AssertionError("appended to a closed handle");
+        } catch (FuzzyLogException expected) {
+        }
+    }
+}
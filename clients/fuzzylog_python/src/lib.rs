@@ -0,0 +1,304 @@
+/*!
+
+Python bindings for the fuzzy log client.
+
+The module exposes a single handle type, `fuzzylog.LogHandle`, which wraps a
+`LogHandle<[u8]>`: payloads are `bytes`, colors are `int`s, and locations
+are `(color, index)` tuples.
+
+```python
+import fuzzylog
+
+log = fuzzylog.LogHandle.unreplicated(["127.0.0.1:13229"], chains=[1, 2])
+log.append(1, b"hello")
+log.multiappend([1, 2], b"both")
+log.snapshot()
+for event in log:
+    print(event.data, event.inhabits, event.happens_after)
+```
+
+*/
+
+extern crate fuzzy_log_client;
+#[macro_use] extern crate pyo3;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use fuzzy_log_client::{
+    GetRes,
+    LogBuilder,
+    LogHandle as Handle,
+    TryWaitRes,
+    OrderIndex,
+    Uuid,
+    order,
+};
+
+use pyo3::exceptions::{PyException, PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+create_exception!(fuzzylog, FuzzyLogError, PyException);
+
+type Loc = (u64, u64);
+
+fn to_locs(locs: &[OrderIndex]) -> Vec<Loc> {
+    locs.iter().map(|&OrderIndex(o, i)| (o.into(), i.into())).collect()
+}
+
+fn from_locs(locs: &[Loc]) -> Vec<OrderIndex> {
+    locs.iter().map(|&l| l.into()).collect()
+}
+
+fn to_chains(chains: &[u64]) -> PyResult<Vec<order>> {
+    if chains.contains(&0) {
+        return Err(PyValueError::new_err("color 0 is reserved"))
+    }
+    Ok(chains.iter().map(|&c| c.into()).collect())
+}
+
+fn parse_addr(addr: &str) -> PyResult<SocketAddr> {
+    addr.parse()
+        .map_err(|e| PyValueError::new_err(format!("invalid address \"{}\": {}", addr, e)))
+}
+
+fn to_id(id: &[u8]) -> PyResult<Uuid> {
+    Uuid::from_bytes(id).map_err(|_| PyValueError::new_err("a write id is 16 bytes"))
+}
+
+fn id_bytes(py: Python, id: &Uuid) -> PyObject {
+    PyBytes::new(py, id.as_bytes()).into()
+}
+
+fn read_err(err: GetRes) -> PyErr {
+    match err {
+        GetRes::Timeout => PyTimeoutError::new_err(err.to_string()),
+        _ => FuzzyLogError::new_err(err.to_string()),
+    }
+}
+
+fn wait_err(err: TryWaitRes) -> PyErr {
+    match err {
+        TryWaitRes::Timeout => PyTimeoutError::new_err(err.to_string()),
+        _ => FuzzyLogError::new_err(err.to_string()),
+    }
+}
+
+/// An event read from the log.
+#[pyclass(module = "fuzzylog")]
+pub struct Event {
+    id: [u8; 16],
+    data: Vec<u8>,
+    /// The `(color, index)` locations of the event.
+    #[pyo3(get)]
+    inhabits: Vec<Loc>,
+    /// The locations the event was explicitly appended after.
+    #[pyo3(get)]
+    happens_after: Vec<Loc>,
+}
+
+#[pymethods]
+impl Event {
+    /// The id of the write which appended the event.
+    #[getter]
+    fn id<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.id)
+    }
+
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.data)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Event(data={:?}, inhabits={:?}, happens_after={:?})",
+            self.data, self.inhabits, self.happens_after,
+        )
+    }
+}
+
+/// A connection to a fuzzy log.
+///
+/// Iterating a handle returns the events of its snapshots,
+/// stopping once every snapshot has been read.
+/// Blocking calls release the GIL.
+#[pyclass(module = "fuzzylog")]
+pub struct LogHandle {
+    handle: Handle<[u8]>,
+}
+
+fn build(builder: LogBuilder<[u8]>, chains: Vec<u64>, timeout: Option<f64>, reads_my_writes: bool)
+-> PyResult<LogHandle> {
+    let mut builder = builder.chains(to_chains(&chains)?);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(Duration::from_millis((timeout * 1000.0) as u64));
+    }
+    if reads_my_writes {
+        builder = builder.reads_my_writes();
+    }
+    let handle = builder.try_build()
+        .map_err(|e| FuzzyLogError::new_err(format!("could not connect: {}", e)))?;
+    Ok(LogHandle { handle })
+}
+
+#[pymethods]
+impl LogHandle {
+    /// Connect to unreplicated servers at `servers`, e.g. `["127.0.0.1:13229"]`,
+    /// reading `chains`.
+    /// `timeout` (in seconds) bounds every blocking call.
+    #[staticmethod]
+    #[pyo3(signature = (servers, chains, timeout=None, reads_my_writes=false))]
+    fn unreplicated(
+        servers: Vec<&str>, chains: Vec<u64>, timeout: Option<f64>, reads_my_writes: bool
+    ) -> PyResult<Self> {
+        let servers = servers.into_iter().map(parse_addr).collect::<PyResult<Vec<_>>>()?;
+        build(Handle::unreplicated_with_servers(servers), chains, timeout, reads_my_writes)
+    }
+
+    /// Connect to replicated servers given as `(head, tail)` address pairs,
+    /// reading `chains`.
+    #[staticmethod]
+    #[pyo3(signature = (servers, chains, timeout=None, reads_my_writes=false))]
+    fn replicated(
+        servers: Vec<(&str, &str)>, chains: Vec<u64>, timeout: Option<f64>, reads_my_writes: bool
+    ) -> PyResult<Self> {
+        let servers = servers.into_iter()
+            .map(|(head, tail)| Ok((parse_addr(head)?, parse_addr(tail)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        build(Handle::replicated_with_servers(servers), chains, timeout, reads_my_writes)
+    }
+
+    /// Append `data` to `chain` after `deps`,
+    /// waiting for it to be ack'd; returns its locations.
+    #[pyo3(signature = (chain, data, deps=vec![]))]
+    fn append(&mut self, py: Python, chain: u64, data: &[u8], deps: Vec<Loc>)
+    -> PyResult<Vec<Loc>> {
+        let chain = to_chains(&[chain])?[0];
+        let id = self.handle.async_append(chain, data, &from_locs(&deps));
+        self.wait_for(py, id)
+    }
+
+    /// Atomically append `data` to every chain in `chains` after `deps`,
+    /// waiting for it to be ack'd; returns its locations.
+    #[pyo3(signature = (chains, data, deps=vec![]))]
+    fn multiappend(&mut self, py: Python, chains: Vec<u64>, data: &[u8], deps: Vec<Loc>)
+    -> PyResult<Vec<Loc>> {
+        let id = self.send_multiappend(chains, data, deps)?;
+        self.wait_for(py, id)
+    }
+
+    /// Like `append` but returns the write id without waiting.
+    #[pyo3(signature = (chain, data, deps=vec![]))]
+    fn async_append(&mut self, py: Python, chain: u64, data: &[u8], deps: Vec<Loc>)
+    -> PyResult<PyObject> {
+        let chain = to_chains(&[chain])?[0];
+        let id = self.handle.async_append(chain, data, &from_locs(&deps));
+        Ok(id_bytes(py, &id))
+    }
+
+    /// Like `multiappend` but returns the write id without waiting.
+    #[pyo3(signature = (chains, data, deps=vec![]))]
+    fn async_multiappend(&mut self, py: Python, chains: Vec<u64>, data: &[u8], deps: Vec<Loc>)
+    -> PyResult<PyObject> {
+        let id = self.send_multiappend(chains, data, deps)?;
+        Ok(id_bytes(py, &id))
+    }
+
+    /// Wait for the append `write_id` to be ack'd; returns its locations.
+    fn wait_for_a_specific_append(&mut self, py: Python, write_id: &[u8]) -> PyResult<Vec<Loc>> {
+        let id = to_id(write_id)?;
+        self.wait_for(py, id)
+    }
+
+    /// Wait for any outstanding append to be ack'd;
+    /// returns its `(write_id, locations)`, or `None` if none are outstanding.
+    fn wait_for_any_append(&mut self, py: Python) -> PyResult<Option<(PyObject, Vec<Loc>)>> {
+        let handle = &mut self.handle;
+        match py.allow_threads(|| handle.wait_for_any_append()) {
+            Ok((id, locs)) => Ok(Some((id_bytes(py, &id), to_locs(&locs)))),
+            Err(TryWaitRes::NothingReady) => Ok(None),
+            Err(e) => Err(wait_err(e)),
+        }
+    }
+
+    /// Wait for every outstanding append to be ack'd.
+    fn wait_for_all_appends(&mut self, py: Python) -> PyResult<()> {
+        let handle = &mut self.handle;
+        py.allow_threads(|| handle.wait_for_all_appends()).map_err(wait_err)
+    }
+
+    /// Take a snapshot of `chain`, or of every chain this handle reads
+    /// if none is given; the events are returned by iterating the handle.
+    #[pyo3(signature = (chain=None))]
+    fn snapshot(&mut self, chain: Option<u64>) -> PyResult<()> {
+        match chain {
+            None => self.handle.take_snapshot(),
+            Some(chain) => self.handle.snapshot(to_chains(&[chain])?[0]),
+        }
+        Ok(())
+    }
+
+    /// Take a snapshot of every chain this handle reads and return all of
+    /// its events.
+    fn sync(&mut self, py: Python) -> PyResult<Vec<Event>> {
+        self.handle.take_snapshot();
+        let mut events = vec![];
+        while let Some(event) = self.next_event(py)? {
+            events.push(event)
+        }
+        Ok(events)
+    }
+
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<Event>> {
+        self.next_event(py)
+    }
+}
+
+impl LogHandle {
+    fn send_multiappend(&mut self, chains: Vec<u64>, data: &[u8], deps: Vec<Loc>)
+    -> PyResult<Uuid> {
+        let mut chains = to_chains(&chains)?;
+        chains.sort();
+        chains.dedup();
+        let deps = from_locs(&deps);
+        match chains.len() {
+            0 => Err(PyValueError::new_err("need at least one chain")),
+            1 => Ok(self.handle.async_append(chains[0], data, &deps)),
+            _ => Ok(self.handle.async_multiappend(&chains, data, &deps)),
+        }
+    }
+
+    fn wait_for(&mut self, py: Python, id: Uuid) -> PyResult<Vec<Loc>> {
+        let handle = &mut self.handle;
+        let locs = py.allow_threads(|| handle.wait_for_a_specific_append(id));
+        locs.map(|locs| to_locs(&locs)).map_err(wait_err)
+    }
+
+    fn next_event(&mut self, py: Python) -> PyResult<Option<Event>> {
+        let handle = &mut self.handle;
+        py.allow_threads(|| match handle.get_next_event() {
+            Ok(e) => Ok(Some(Event {
+                id: *e.id.as_bytes(),
+                data: e.data.to_vec(),
+                inhabits: to_locs(e.inhabits),
+                happens_after: to_locs(e.happens_after),
+            })),
+            Err(GetRes::Done) => Ok(None),
+            Err(e) => Err(e),
+        }).map_err(read_err)
+    }
+}
+
+#[pymodule]
+fn fuzzylog(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<LogHandle>()?;
+    m.add_class::<Event>()?;
+    m.add("FuzzyLogError", py.get_type::<FuzzyLogError>())?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+import os
+import random
+import unittest
+
+import fuzzylog
+
+SERVER = os.environ.get("FUZZYLOG_SERVER", "127.0.0.1:13229")
+
+
+def fresh_chains(n):
+    # the tests share one server, give each its own colors
+    base = random.randrange(1_000_000, 2**40)
+    return [base + i for i in range(n)]
+
+
+class TestLogHandle(unittest.TestCase):
+    def handle(self, chains):
+        return fuzzylog.LogHandle.unreplicated([SERVER], chains=chains, timeout=10)
+
+    def test_append_and_read(self):
+        [chain] = fresh_chains(1)
+        log = self.handle([chain])
+        self.assertEqual(log.append(chain, b"a"), [(chain, 1)])
+        self.assertEqual(log.append(chain, b"b"), [(chain, 2)])
+        log.snapshot()
+        self.assertEqual([e.data for e in log], [b"a", b"b"])
+
+    def test_multiappend(self):
+        chains = fresh_chains(2)
+        log = self.handle(chains)
+        locs = log.multiappend(chains, b"both")
+        self.assertEqual(sorted(c for c, _ in locs), chains)
+        [event] = log.sync()
+        self.assertEqual(event.data, b"both")
+        self.assertEqual(sorted(c for c, _ in event.inhabits), chains)
+
+    def test_async_append(self):
+        [chain] = fresh_chains(1)
+        log = self.handle([chain])
+        first = log.async_append(chain, b"1")
+        second = log.async_append(chain, b"2")
+        self.assertEqual(len(first), 16)
+        log.wait_for_all_appends()
+        self.assertIsNone(log.wait_for_any_append())
+        self.assertEqual([e.id for e in log.sync()], [first, second])
+
+    def test_happens_after(self):
+        [a, b] = fresh_chains(2)
+        log = self.handle([a, b])
+        dep = log.append(a, b"cause")
+        log.append(b, b"effect", deps=dep)
+        log.snapshot(b)
+        events = list(log)
+        self.assertEqual(events[-1].data, b"effect")
+        self.assertEqual(events[-1].happens_after, dep)
+
+    def test_bad_address(self):
+        with self.assertRaises(ValueError):
+            fuzzylog.LogHandle.unreplicated(["not an address"], chains=[1])
+
+
+if __name__ == "__main__":
+    unittest.main()
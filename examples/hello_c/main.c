@@ -36,6 +36,10 @@ main(int argc, char *argv[argc])
 	ColorSpec my_color = {.local_chain = 1, .remote_chains = chains, .num_remote_chains = 3};
 
 	FLPtr log = new_fuzzylog_instance(servers, my_color, NULL);
+	if(log == NULL) {
+		printf("could not start client: %s\n", fuzzylog_last_error());
+		return 1;
+	}
 
 	/* An example of basic FuzzyLog usage. */
 	{
@@ -0,0 +1,361 @@
+//! Causal cuts through the log.
+//!
+//! A `Cut` records, per chain, the furthest entry which lies inside it.
+//! Since every chain is read in order, and an entry is only returned after
+//! everything it depends on, the set of entries below a cut is closed under
+//! happens-before whenever the cut was taken from what a handle has read.
+//! Syncs return the cut they read to, `ReadHandle::read_until_cut` reads up
+//! to one, and `ReadHandle::has_observed` tells if a handle is past one,
+//! which is enough to checkpoint a materialized view and later resume it
+//! somewhere else: a `Checkpoint` pairs the view's serialized state with the
+//! cut it was read to, it can be kept in a file or in a color of the log, and
+//! `LogHandle::restore` skips everything inside the cut so the view picks up
+//! where it left off instead of replaying its colors from the start.
+//!
+//! A handle's session token is also a cut, see `session`.
+
+use std::cmp::Ordering;
+use std::collections::hash_map;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use hash::HashMap;
+
+use packets::{entry, order, OrderIndex};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cut {
+    horizons: HashMap<order, entry>,
+}
+
+impl Cut {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.horizons.is_empty()
+    }
+
+    /// The furthest entry in `chain` inside this cut, 0 if none.
+    pub fn horizon(&self, chain: order) -> entry {
+        self.horizons.get(&chain).cloned().unwrap_or(entry::from(0))
+    }
+
+    pub fn observe(&mut self, OrderIndex(chain, index): OrderIndex) {
+        if chain == order::from(0) || index == entry::from(0) {
+            return
+        }
+        let horizon = self.horizons.entry(chain).or_insert(index);
+        if *horizon < index {
+            *horizon = index
+        }
+    }
+
+    pub fn observe_all(&mut self, locs: &[OrderIndex]) {
+        for &loc in locs {
+            self.observe(loc)
+        }
+    }
+
+    /// Extend this cut to include everything in `other`.
+    pub fn merge(&mut self, other: &Cut) {
+        for (&chain, &index) in other.horizons.iter() {
+            self.observe(OrderIndex(chain, index))
+        }
+    }
+
+    /// Does every entry inside `other` also lie inside this cut?
+    pub fn includes(&self, other: &Cut) -> bool {
+        other.horizons.iter().all(|(&chain, &index)| self.horizon(chain) >= index)
+    }
+
+    /// The per-chain horizons, in no particular order.
+    pub fn iter(&self) -> Iter {
+        Iter { inner: self.horizons.iter() }
+    }
+
+    /// The per-chain horizons, sorted by chain.
+    pub fn locs(&self) -> Vec<OrderIndex> {
+        let mut locs: Vec<_> = self.horizons.iter()
+            .map(|(&chain, &index)| OrderIndex(chain, index))
+            .collect();
+        locs.sort_unstable_by_key(|oi| u64::from(oi.0));
+        locs
+    }
+
+    /// A portable encoding for sending the cut to another process:
+    /// a little-endian (chain, index) pair of u64s per chain.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let locs = self.locs();
+        let mut bytes = Vec::with_capacity(locs.len() * 16);
+        for OrderIndex(chain, index) in locs {
+            put_u64(&mut bytes, chain.into());
+            put_u64(&mut bytes, index.into());
+        }
+        bytes
+    }
+
+    /// Decodes a cut written by `to_bytes`,
+    /// `None` if `bytes` is not a whole number of entries.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() % 16 != 0 {
+            return None
+        }
+        let mut cut = Cut::new();
+        for pair in bytes.chunks(16) {
+            let (chain, index) = pair.split_at(8);
+            cut.observe(OrderIndex(get_u64(chain).into(), get_u64(index).into()));
+        }
+        Some(cut)
+    }
+}
+
+/// Cuts are ordered by inclusion;
+/// two cuts each of which is ahead in some chain are incomparable.
+impl PartialOrd for Cut {
+    fn partial_cmp(&self, other: &Cut) -> Option<Ordering> {
+        match (self.includes(other), other.includes(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+}
+
+pub struct Iter<'c> {
+    inner: hash_map::Iter<'c, order, entry>,
+}
+
+impl<'c> Iterator for Iter<'c> {
+    type Item = (order, entry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(&chain, &index)| (chain, index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'c> IntoIterator for &'c Cut {
+    type Item = (order, entry);
+    type IntoIter = Iter<'c>;
+
+    fn into_iter(self) -> Iter<'c> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Cut {
+    type Item = (order, entry);
+    type IntoIter = hash_map::IntoIter<order, entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.horizons.into_iter()
+    }
+}
+
+impl From<HashMap<order, entry>> for Cut {
+    fn from(horizons: HashMap<order, entry>) -> Self {
+        let mut cut = Cut::new();
+        for (chain, index) in horizons {
+            cut.observe(OrderIndex(chain, index))
+        }
+        cut
+    }
+}
+
+impl From<Cut> for HashMap<order, entry> {
+    fn from(cut: Cut) -> Self {
+        cut.horizons
+    }
+}
+
+/// A view's serialized state along with the cut it was materialized from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    cut: Cut,
+    state: Vec<u8>,
+}
+
+impl Checkpoint {
+    pub fn new(cut: Cut, state: Vec<u8>) -> Self {
+        Checkpoint { cut, state }
+    }
+
+    pub fn cut(&self) -> &Cut {
+        &self.cut
+    }
+
+    pub fn state(&self) -> &[u8] {
+        &self.state
+    }
+
+    pub fn into_parts(self) -> (Cut, Vec<u8>) {
+        (self.cut, self.state)
+    }
+
+    /// The length of the encoded cut as a little-endian u64,
+    /// then the cut, then the state.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let cut = self.cut.to_bytes();
+        let mut bytes = Vec::with_capacity(8 + cut.len() + self.state.len());
+        put_u64(&mut bytes, cut.len() as u64);
+        bytes.extend_from_slice(&cut);
+        bytes.extend_from_slice(&self.state);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None
+        }
+        let (cut_len, rest) = bytes.split_at(8);
+        let cut_len = get_u64(cut_len);
+        if cut_len > rest.len() as u64 {
+            return None
+        }
+        let (cut, state) = rest.split_at(cut_len as usize);
+        Cut::from_bytes(cut).map(|cut| Checkpoint::new(cut, state.to_vec()))
+    }
+
+    /// Writes the checkpoint to `path`, replacing any older one only once the
+    /// new one is completely on disk.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&self.to_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = vec![];
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Checkpoint::from_bytes(&bytes).ok_or_else(||
+            io::Error::new(io::ErrorKind::InvalidData, "malformed checkpoint"))
+    }
+}
+
+fn put_u64(bytes: &mut Vec<u8>, n: u64) {
+    for i in 0..8 {
+        bytes.push((n >> (8 * i)) as u8)
+    }
+}
+
+fn get_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |n, &b| (n << 8) | b as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observe_keeps_max() {
+        let mut cut = Cut::new();
+        cut.observe_all(&[
+            OrderIndex(3.into(), 7.into()),
+            OrderIndex(0.into(), 0.into()),
+            OrderIndex(3.into(), 2.into()),
+            OrderIndex(5.into(), 1.into()),
+        ]);
+        assert_eq!(cut.horizon(3.into()), entry::from(7));
+        assert_eq!(cut.horizon(5.into()), entry::from(1));
+        assert_eq!(cut.horizon(0.into()), entry::from(0));
+        assert_eq!(cut.horizon(9.into()), entry::from(0));
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = Cut::new();
+        a.observe(OrderIndex(1.into(), 4.into()));
+        a.observe(OrderIndex(2.into(), 1.into()));
+        let mut b = Cut::new();
+        b.observe(OrderIndex(2.into(), 6.into()));
+        b.observe(OrderIndex(3.into(), 2.into()));
+        a.merge(&b);
+        assert_eq!(a.locs(), vec![
+            OrderIndex(1.into(), 4.into()),
+            OrderIndex(2.into(), 6.into()),
+            OrderIndex(3.into(), 2.into()),
+        ]);
+    }
+
+    #[test]
+    fn ordered_by_inclusion() {
+        let empty = Cut::new();
+        let mut a = Cut::new();
+        a.observe(OrderIndex(1.into(), 4.into()));
+        let mut b = a.clone();
+        b.observe(OrderIndex(2.into(), 1.into()));
+        let mut c = Cut::new();
+        c.observe(OrderIndex(1.into(), 2.into()));
+        c.observe(OrderIndex(2.into(), 3.into()));
+
+        assert!(empty <= a && a <= a && a < b);
+        assert!(b.includes(&a) && !a.includes(&b));
+        assert_eq!(b.partial_cmp(&c), None);
+        assert!(!b.includes(&c) && !c.includes(&b));
+        let mut d = b.clone();
+        d.merge(&c);
+        assert!(d > b && d > c);
+    }
+
+    #[test]
+    fn map_round_trip() {
+        let mut map = HashMap::default();
+        map.insert(order::from(1), entry::from(3));
+        map.insert(order::from(2), entry::from(0));
+        let cut = Cut::from(map);
+        assert_eq!(cut.locs(), vec![OrderIndex(1.into(), 3.into())]);
+        let map: HashMap<order, entry> = cut.into();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&order::from(1)], entry::from(3));
+    }
+
+    #[test]
+    fn iterates_horizons() {
+        let mut cut = Cut::new();
+        cut.observe(OrderIndex(1.into(), 3.into()));
+        cut.observe(OrderIndex(2.into(), 5.into()));
+        let mut seen: Vec<_> = cut.iter().collect();
+        seen.sort_by_key(|&(chain, _)| u64::from(chain));
+        assert_eq!(seen, vec![(1.into(), 3.into()), (2.into(), 5.into())]);
+        let mut owned: Vec<_> = cut.into_iter().collect();
+        owned.sort_by_key(|&(chain, _)| u64::from(chain));
+        assert_eq!(owned, seen);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut cut = Cut::new();
+        cut.observe(OrderIndex(4.into(), 9.into()));
+        let checkpoint = Checkpoint::new(cut, b"view state".to_vec());
+        let bytes = checkpoint.to_bytes();
+        assert_eq!(Checkpoint::from_bytes(&bytes), Some(checkpoint.clone()));
+        assert_eq!(Checkpoint::from_bytes(&bytes[..12]), None);
+
+        let empty = Checkpoint::new(Cut::new(), vec![]);
+        assert_eq!(Checkpoint::from_bytes(&empty.to_bytes()), Some(empty));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut cut = Cut::new();
+        assert_eq!(Cut::from_bytes(&cut.to_bytes()), Some(cut.clone()));
+        cut.observe(OrderIndex(1.into(), 0x1_0000_0001.into()));
+        cut.observe(OrderIndex(0xfeed.into(), 12.into()));
+        let bytes = cut.to_bytes();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(Cut::from_bytes(&bytes), Some(cut));
+        assert_eq!(Cut::from_bytes(&bytes[..20]), None);
+    }
+}
@@ -1,11 +1,14 @@
 
 use std::borrow::Borrow;
+use std::fmt;
 use std::io;
 use std::marker::PhantomData;
 use std::mem;
 use std::net::SocketAddr;
+use std::os::unix::io::RawFd;
 use std::thread;
 use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub use hash::HashMap;
 use hash::HashSet;
@@ -19,9 +22,14 @@ use fuzzy_log::{
     FinshedWriteQueue,
     FinshedWriteRecv,
 };
+use fuzzy_log_util::config::ClusterConfig;
 use fuzzy_log_util::socket_addr::Ipv4SocketAddr;
 use store;
 use fuzzy_log::FromClient::*;
+use fuzzy_log::cut::{Checkpoint, Cut};
+use fuzzy_log::session::SessionToken;
+use fuzzy_log::prefetch::{PrefetchConfig, PrefetchPolicy, PrefetchStats};
+use fuzzy_log::ready::{Notifying, ReadyNotifier};
 pub use packets::{
     order,
     entry,
@@ -57,6 +65,10 @@ pub struct ReadHandle<V: ?Sized> {
     curr_entry: Vec<u8>,
     num_errors: u64,
     last_dropped: Arc<()>,
+    timeout: Option<Duration>,
+    session: SessionToken,
+    prefetch_stats: PrefetchStats,
+    ready: ReadyNotifier,
 }
 
 pub struct WriteHandle<V: ?Sized> {
@@ -64,6 +76,9 @@ pub struct WriteHandle<V: ?Sized> {
     finished_writes: FinshedWriteRecv,
     num_async_writes: Option<usize>,
     num_errors: u64,
+    timeout: Option<Duration>,
+    session: SessionToken,
+    ready: ReadyNotifier,
 }
 
 pub struct AtomicWriteHandle<V: ?Sized> {
@@ -105,12 +120,43 @@ pub enum GetRes {
     Done,
     IoErr(io::ErrorKind, usize),
     AlreadyGCd(order, entry),
+    /// The deadline passed before the next event arrived.
+    /// The snapshot is still live, a later `get_next` will continue it.
+    Timeout,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TryWaitRes {
     NothingReady,
     IoErr(io::ErrorKind, usize),
+    /// The deadline passed before the append was acknowledged.
+    /// The append is still outstanding and can be waited on again.
+    Timeout,
+    /// The append was cancelled before it was given a place in the log.
+    Cancelled,
+}
+
+impl fmt::Display for GetRes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetRes::NothingReady => write!(f, "no events are ready"),
+            GetRes::Done => write!(f, "the sync is finished"),
+            GetRes::IoErr(kind, server) => write!(f, "{:?} error on server {}", kind, server),
+            GetRes::AlreadyGCd(o, e) => write!(f, "{:?} has already been trimmed", (o, e)),
+            GetRes::Timeout => write!(f, "timed out reading the log"),
+        }
+    }
+}
+
+impl fmt::Display for TryWaitRes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TryWaitRes::NothingReady => write!(f, "no appends are ready"),
+            TryWaitRes::IoErr(kind, server) => write!(f, "{:?} error on server {}", kind, server),
+            TryWaitRes::Timeout => write!(f, "timed out waiting for an append"),
+            TryWaitRes::Cancelled => write!(f, "the append was cancelled"),
+        }
+    }
 }
 
 pub struct Event<'e, V: 'e + ?Sized> {
@@ -195,6 +241,23 @@ where V: Storeable {
     }
 }
 
+impl LogHandle<[u8]> {
+
+    /// Store `checkpoint` as the newest entry in `color`.
+    pub fn append_checkpoint(&mut self, color: order, checkpoint: &Checkpoint)
+    -> Vec<OrderIndex> {
+        self.append(color, &checkpoint.to_bytes()[..], &[])
+    }
+
+    /// The last checkpoint stored in `color`, if any.
+    pub fn latest_checkpoint(&mut self, color: order)
+    -> Result<Option<Checkpoint>, GetRes> {
+        let mut latest = None;
+        self.sync_chain(color, |bytes, _, _| latest = Some(bytes.to_vec()))?;
+        Ok(latest.and_then(|bytes| Checkpoint::from_bytes(&bytes)))
+    }
+}
+
 #[derive(Debug)]
 pub struct LogBuilder<V: ?Sized> {
     servers: Servers,
@@ -204,6 +267,8 @@ pub struct LogBuilder<V: ?Sized> {
     id: Option<Ipv4SocketAddr>,
     ack_writes: bool,
     my_colors_chains: Option<Vec<order>>,
+    timeout: Option<Duration>,
+    prefetch: PrefetchConfig,
     _pd: PhantomData<Box<V>>,
 }
 
@@ -225,6 +290,8 @@ where V: Storeable {
             id: None,
             ack_writes: true,
             my_colors_chains: None,
+            timeout: None,
+            prefetch: Default::default(),
             _pd: PhantomData,
         }
     }
@@ -263,38 +330,70 @@ where V: Storeable {
         LogBuilder{ack_writes: false, ..self}
     }
 
+    /// The default deadline for the handle's blocking reads and append waits,
+    /// see `LogHandle::set_timeout`.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        LogBuilder{timeout: Some(timeout), ..self}
+    }
+
+    /// How far ahead of the reader colors are fetched,
+    /// see `fuzzy_log::prefetch` for the policies.
+    pub fn prefetch(mut self, policy: PrefetchPolicy) -> Self {
+        self.prefetch.default = policy;
+        self
+    }
+
+    /// Prefetch `color` differently than the rest,
+    /// e.g. a color which is only read to catch up can use a larger window.
+    pub fn prefetch_for(mut self, color: order, policy: PrefetchPolicy) -> Self {
+        self.prefetch.per_color.insert(color, policy);
+        self
+    }
+
     pub fn build(self) -> LogHandle<V> {
+        self.try_build().expect("could not start store.")
+    }
+
+    /// Like `build` but returns the error instead of panicking if the
+    /// connections to the servers cannot be established,
+    /// or if color 0 is among the chains of interest.
+    pub fn try_build(self) -> Result<LogHandle<V>, io::Error> {
         let LogBuilder {
-            servers, chains, reads_my_writes, fetch_boring_multis, ack_writes, id, my_colors_chains, _pd,
+            servers, chains, reads_my_writes, fetch_boring_multis, ack_writes, id, my_colors_chains,
+            timeout, prefetch, _pd,
         } = self;
 
+        if chains.contains(&0.into()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "Don't register interest in color 0."
+            ))
+        }
+
         let make_store = |client| {
             let to_store_m = Arc::new(Mutex::new(None));
             let tsm = to_store_m.clone();
             let _ = thread::spawn(move || {
-                match servers {
-                    Servers::Unreplicated(servers) => {
-                        let (mut store, to_store) =
-                            ::store::AsyncTcpStore::new_tcp(
-                                id.unwrap_or_else(Ipv4SocketAddr::random),
-                                servers.into_iter(),
-                                client,
-                            ).expect("could not start store.");
-                        *tsm.lock().unwrap() = Some(to_store);
-                        store.set_reads_my_writes(reads_my_writes);
-                        store.run();
-                    },
-                    Servers::Replicated(servers) => {
-                        let (mut store, to_store) =
-                            ::store::AsyncTcpStore::replicated_new_tcp(
-                                id.unwrap_or_else(Ipv4SocketAddr::random),
-                                servers.into_iter(),
-                                client,
-                            ).expect("could not start store.");
-                        *tsm.lock().unwrap() = Some(to_store);
+                let store = match servers {
+                    Servers::Unreplicated(servers) =>
+                        ::store::AsyncTcpStore::new_tcp(
+                            id.unwrap_or_else(Ipv4SocketAddr::random),
+                            servers.into_iter(),
+                            client,
+                        ),
+                    Servers::Replicated(servers) =>
+                        ::store::AsyncTcpStore::replicated_new_tcp(
+                            id.unwrap_or_else(Ipv4SocketAddr::random),
+                            servers.into_iter(),
+                            client,
+                        ),
+                };
+                match store {
+                    Ok((mut store, to_store)) => {
+                        *tsm.lock().unwrap() = Some(Ok(to_store));
                         store.set_reads_my_writes(reads_my_writes);
                         store.run();
                     },
+                    Err(e) => *tsm.lock().unwrap() = Some(Err(e)),
                 }
             });
             let to_store;
@@ -308,13 +407,16 @@ where V: Storeable {
             to_store
         };
 
-        LogHandle::build_with_store(
+        let mut handle = LogHandle::try_spawn_with_store(
             chains,
             fetch_boring_multis,
             ack_writes,
             my_colors_chains,
+            prefetch,
             make_store
-        )
+        )?;
+        handle.set_timeout(timeout);
+        Ok(handle)
     }
 
     pub fn build_handles(self) -> (ReadHandle<V>, AtomicWriteHandle<V>) {
@@ -390,6 +492,16 @@ where V: Storeable {
         LogBuilder::from_servers(Servers::Replicated(servers))
     }
 
+    /// A builder for the cluster described by `config`,
+    /// replicated if `config` lists tails.
+    pub fn with_config(config: &ClusterConfig) -> LogBuilder<V> {
+        if config.is_replicated() {
+            Self::replicated_with_servers(config.replicas())
+        } else {
+            Self::unreplicated_with_servers(&config.servers)
+        }
+    }
+
     pub fn build_with_store<C, F>(
         interesting_chains: C,
         fetch_boring_multis: bool,
@@ -399,18 +511,66 @@ where V: Storeable {
     ) -> Self
     where C: IntoIterator<Item=order>,
           F: FnOnce(mpsc::Sender<Message>) -> store::ToSelf {
+        Self::spawn_with_store(
+            interesting_chains,
+            fetch_boring_multis,
+            ack_writes,
+            my_colors_chains,
+            Default::default(),
+            store_builder,
+        )
+    }
+
+    fn spawn_with_store<C, F>(
+        interesting_chains: C,
+        fetch_boring_multis: bool,
+        ack_writes: bool,
+        my_colors_chains: Option<Vec<order>>,
+        prefetch: PrefetchConfig,
+        store_builder: F,
+    ) -> Self
+    where C: IntoIterator<Item=order>,
+          F: FnOnce(mpsc::Sender<Message>) -> store::ToSelf {
+        let spawned = Self::try_spawn_with_store(
+            interesting_chains,
+            fetch_boring_multis,
+            ack_writes,
+            my_colors_chains,
+            prefetch,
+            |to_log| Ok(store_builder(to_log)),
+        );
+        match spawned {
+            Ok(handle) => handle,
+            Err(e) => panic!("could not start store: {}", e),
+        }
+    }
+
+    fn try_spawn_with_store<C, F>(
+        interesting_chains: C,
+        fetch_boring_multis: bool,
+        ack_writes: bool,
+        my_colors_chains: Option<Vec<order>>,
+        prefetch: PrefetchConfig,
+        store_builder: F,
+    ) -> Result<Self, io::Error>
+    where C: IntoIterator<Item=order>,
+          F: FnOnce(mpsc::Sender<Message>) -> Result<store::ToSelf, io::Error> {
         let (to_log, from_outside) = mpsc::channel();
-        let to_store = store_builder(to_log.clone());
+        let to_store = store_builder(to_log.clone())?;
         let (ready_reads_s, ready_reads_r) = mpsc::channel();
         let interesting_chains: Vec<_> = interesting_chains
             .into_iter()
             .inspect(|c| assert!(c != &0.into(), "Don't register interest in color 0."))
             .collect();
         let (finished_writes_s, finished_writes_r) = mpsc::channel();
+        let ready = ReadyNotifier::default();
+        let ready_reads_s = Notifying::new(ready_reads_s, ready.clone());
+        let finished_writes_s = Notifying::new(finished_writes_s, ready.clone());
         thread::spawn(move || {
             let builder = ThreadLog::builder(to_store, from_outside, ready_reads_s)
                 .set_fetch_boring_multis(fetch_boring_multis)
-                .chains(interesting_chains);
+                .chains(interesting_chains)
+                .prefetch(prefetch);
             let builder = match my_colors_chains {
                 Some(my_colors_chains) => builder.my_colors_chains(my_colors_chains),
                 None => builder,
@@ -421,7 +581,7 @@ where V: Storeable {
             };
         });
 
-        LogHandle::new(to_log, ready_reads_r, finished_writes_r, ack_writes)
+        Ok(LogHandle::new_with_notifier(to_log, ready_reads_r, finished_writes_r, ack_writes, ready))
     }
 
     pub fn with_store<C, F>(
@@ -625,14 +785,36 @@ where V: Storeable {
         ready_reads: FinshedReadRecv,
         finished_writes: FinshedWriteRecv,
         ack_writes: bool,
+    ) -> Self {
+        Self::new_with_notifier(
+            to_log, ready_reads, finished_writes, ack_writes, Default::default()
+        )
+    }
+
+    fn new_with_notifier(
+        to_log: mpsc::Sender<Message>,
+        ready_reads: FinshedReadRecv,
+        finished_writes: FinshedWriteRecv,
+        ack_writes: bool,
+        ready: ReadyNotifier,
     ) -> Self {
         let last_dropped = Arc::new(());
         LogHandle {
-            read_handle: ReadHandle::new(to_log.clone(), ready_reads, last_dropped.clone()),
-            write_handle: WriteHandle::new(to_log, finished_writes, last_dropped, ack_writes),
+            read_handle: ReadHandle::new(
+                to_log.clone(), ready_reads, last_dropped.clone(), ready.clone()
+            ),
+            write_handle: WriteHandle::new(to_log, finished_writes, last_dropped, ack_writes, ready),
         }
     }
 
+    /// A file descriptor which becomes readable whenever a read or an append
+    /// ack is ready for this handle, for use with `try_get_next` and
+    /// `try_wait_for_any_append` from an event loop; see `fuzzy_log::ready`.
+    /// Only handles made with a `LogBuilder` ever signal it.
+    pub fn ready_fd(&self) -> io::Result<RawFd> {
+        self.read_handle.ready_fd()
+    }
+
     /// Take a snapshot of a supplied interesting color and start prefetching.
     pub fn snapshot(&mut self, chain: order) {
         self.read_handle.snapshot(chain)
@@ -648,6 +830,25 @@ where V: Storeable {
         self.read_handle.strong_snapshot(colors)
     }
 
+    /// Take a snapshot of a set of interesting colors which includes
+    /// at least everything `session` has seen in them.
+    pub fn snapshot_colors_after(&mut self, colors: &[order], session: &SessionToken) {
+        self.read_handle.snapshot_colors_after(colors, session)
+    }
+
+    /// Everything this handle has read, and every append of its
+    /// which has been acknowledged.
+    pub fn session_token(&self) -> SessionToken {
+        let mut session = self.read_handle.session_token();
+        session.merge(&self.write_handle.session_token());
+        session
+    }
+
+    /// See `ReadHandle::prefetch_stats`.
+    pub fn prefetch_stats(&self) -> PrefetchStats {
+        self.read_handle.prefetch_stats()
+    }
+
     /// Take a snapshot of all interesting colors and start prefetching.
     pub fn take_snapshot(&mut self) {
         self.read_handle.take_snapshot()
@@ -664,35 +865,68 @@ where V: Storeable {
         self.read_handle.get_next2()
     }
 
+    /// Like `get_next` but gives up with `GetRes::Timeout` after `timeout`.
+    pub fn get_next_timeout(&mut self, timeout: Duration)
+    -> Result<(&V, &[OrderIndex]), GetRes>
+    where V: UnStoreable {
+        self.read_handle.get_next_timeout(timeout)
+    }
+
+    pub fn get_next2_timeout(&mut self, timeout: Duration)
+    -> Result<(&V, &[OrderIndex], &Uuid), GetRes>
+    where V: UnStoreable {
+        self.read_handle.get_next2_timeout(timeout)
+    }
+
+    pub fn get_next_event(&mut self) -> Result<Event<V>, GetRes>
+    where V: UnStoreable {
+        self.read_handle.get_next_event()
+    }
+
     pub fn sync<F>(&mut self, per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: FnMut(&V, &[OrderIndex], &Uuid) {
         self.read_handle.sync(per_event)
     }
 
+    /// Like `sync` but gives up with `GetRes::Timeout` if the whole sync
+    /// has not finished after `timeout`.
+    pub fn sync_timeout<F>(&mut self, timeout: Duration, per_event: F)
+    -> Result<Cut, GetRes>
+    where V: UnStoreable, F: FnMut(&V, &[OrderIndex], &Uuid) {
+        self.read_handle.sync_timeout(timeout, per_event)
+    }
+
     pub fn sync_chain<F>(&mut self, chain: order, per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: FnMut(&V, &[OrderIndex], &Uuid) {
         self.read_handle.sync_chain(chain, per_event)
     }
 
     pub fn sync_events<F>(&mut self, per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: for<'e> FnMut(Event<'e, V>) {
         self.read_handle.sync_events(per_event)
     }
 
+    pub fn sync_events_timeout<F>(&mut self, timeout: Duration, per_event: F)
+    -> Result<Cut, GetRes>
+    where V: UnStoreable, F: for<'e> FnMut(Event<'e, V>) {
+        self.read_handle.sync_events_timeout(timeout, per_event)
+    }
+
     pub fn sync_events_for_chain<F>(&mut self, chain: order, per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: for<'e> FnMut(Event<'e, V>) {
         self.read_handle.sync_events_for_chain(chain, per_event)
     }
 
     pub fn sync_and<F>(&mut self, per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: for<'e> FnMut(&mut WriteHandle<V>, Event<'e, V>) {
         let write_handle = &mut self.write_handle;
-        self.read_handle.do_sync_with(write_handle, per_event)
+        let deadline = deadline_after(self.read_handle.timeout);
+        self.read_handle.do_sync_with(write_handle, deadline, per_event)
     }
 
     /// Returns an event if one is ready.
@@ -706,6 +940,11 @@ where V: Storeable {
         self.read_handle.try_get_next2()
     }
 
+    pub fn try_get_next_event(&mut self) -> Result<Event<V>, GetRes>
+    where V: UnStoreable {
+        self.read_handle.try_get_next_event()
+    }
+
     pub fn color_append(
         &mut self,
         data: &V,
@@ -856,17 +1095,7 @@ where V: Storeable {
         data: &V,
         inhabits: &mut [order],
     ) -> Uuid {
-        if inhabits.len() == 0 {
-            return Uuid::nil()
-        }
-
-        inhabits.sort();
-        assert!(
-            inhabits.binary_search(&order::from(0)).is_err(),
-            "color 0 should not be used;it is special cased for legacy reasons."
-        );
-
-        self.causal_color_append(data, inhabits, &mut [], &mut [])
+        self.write_handle.simple_async_append(data, inhabits)
     }
 
     //TODO add wait_and_snapshot(..)
@@ -926,21 +1155,48 @@ where V: Storeable {
 
 impl<V: ?Sized> LogHandle<V> {
 
+    /// Sets the default deadline for every blocking read, sync and append wait
+    /// made through this handle; `None`, the default, blocks forever.
+    /// The `_timeout` variants override it for a single call.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_handle.set_timeout(timeout);
+        self.write_handle.set_timeout(timeout);
+    }
+
     //FIXME better error checking is no waiting is possible
 
     pub fn wait_for_all_appends(&mut self) -> Result<(), TryWaitRes> {
         self.write_handle.wait_for_all_appends()
     }
 
+    pub fn wait_for_all_appends_timeout(&mut self, timeout: Duration)
+    -> Result<(), TryWaitRes> {
+        self.write_handle.wait_for_all_appends_timeout(timeout)
+    }
+
     pub fn wait_for_a_specific_append(&mut self, write_id: Uuid)
     -> Result<Vec<OrderIndex>, TryWaitRes> {
         self.write_handle.wait_for_a_specific_append(write_id)
     }
 
+    pub fn wait_for_a_specific_append_timeout(&mut self, write_id: Uuid, timeout: Duration)
+    -> Result<Vec<OrderIndex>, TryWaitRes> {
+        self.write_handle.wait_for_a_specific_append_timeout(write_id, timeout)
+    }
+
+    pub fn cancel(&mut self, write_id: Uuid) -> Result<Vec<OrderIndex>, TryWaitRes> {
+        self.write_handle.cancel(write_id)
+    }
+
     pub fn wait_for_any_append(&mut self) -> Result<(Uuid, Vec<OrderIndex>), TryWaitRes> {
         self.write_handle.wait_for_any_append()
     }
 
+    pub fn wait_for_any_append_timeout(&mut self, timeout: Duration)
+    -> Result<(Uuid, Vec<OrderIndex>), TryWaitRes> {
+        self.write_handle.wait_for_any_append_timeout(timeout)
+    }
+
     pub fn try_wait_for_any_append(&mut self)
     -> Result<(Uuid, Vec<OrderIndex>), TryWaitRes> {
         self.write_handle.try_wait_for_any_append()
@@ -958,9 +1214,38 @@ impl<V: ?Sized> LogHandle<V> {
         self.read_handle.fastforward(loc)
     }
 
+    pub fn read_until_cut(&mut self, cut: &Cut) {
+        self.read_handle.read_until_cut(cut)
+    }
+
+    pub fn fastforward_to_cut(&mut self, cut: &Cut) {
+        self.read_handle.fastforward_to_cut(cut)
+    }
+
+    /// Has this handle read everything inside `cut`?
+    pub fn has_observed(&self, cut: &Cut) -> bool {
+        self.read_handle.has_observed(cut)
+    }
+
     pub fn rewind(&mut self, loc: OrderIndex) {
         self.read_handle.rewind(loc)
     }
+
+    /// Pairs `state`, a view materialized from what this handle has read,
+    /// with the cut it was read up to.
+    pub fn checkpoint(&self, state: Vec<u8>) -> Checkpoint {
+        Checkpoint::new(self.read_handle.session_token(), state)
+    }
+
+    /// Resume reading from where `checkpoint` was taken;
+    /// nothing inside its cut will be returned again.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.read_handle.restore_cut(checkpoint.cut())
+    }
+
+    pub fn trim(&mut self, cut: &Cut) {
+        self.write_handle.trim(cut)
+    }
 }
 
 impl<V: ?Sized> ReadHandle<V> {
@@ -969,6 +1254,7 @@ impl<V: ?Sized> ReadHandle<V> {
         to_log: mpsc::Sender<Message>,
         ready_reads: FinshedReadRecv,
         last_dropped: Arc<()>,
+        ready: ReadyNotifier,
     ) -> Self {
         Self {
             _pd: Default::default(),
@@ -978,9 +1264,25 @@ impl<V: ?Sized> ReadHandle<V> {
             num_snapshots: 0,
             num_errors: 0,
             last_dropped,
+            timeout: None,
+            session: Default::default(),
+            prefetch_stats: Default::default(),
+            ready,
         }
     }
 
+    /// See `LogHandle::ready_fd`.
+    /// This is shared with the `WriteHandle` this was split from.
+    pub fn ready_fd(&self) -> io::Result<RawFd> {
+        self.ready.fd()
+    }
+
+    /// Sets the default deadline for `get_next` and the `sync` family;
+    /// `None` blocks forever.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+
     /// Take a snapshot of a supplied interesting color and start prefetching.
     pub fn snapshot(&mut self, chain: order) {
         self.num_snapshots = self.num_snapshots.saturating_add(1);
@@ -996,6 +1298,27 @@ impl<V: ?Sized> ReadHandle<V> {
         self.to_log.send(Message::FromClient(MultiSnapshotAndPrefetch(colors))).unwrap();
     }
 
+    /// Take a snapshot of a set of interesting colors and start prefetching,
+    /// reading each color at least as far as `session` has seen,
+    /// even if the servers report a shorter horizon.
+    pub fn snapshot_colors_after(&mut self, colors: &[order], session: &SessionToken) {
+        trace!("HANDLE send session snap {:?}.", colors);
+        let colors = colors.iter().map(|&c| OrderIndex(c, session.horizon(c))).collect();
+        self.num_snapshots = self.num_snapshots.saturating_add(1);
+        self.to_log.send(Message::FromClient(SessionSnapshotAndPrefetch(colors))).unwrap();
+    }
+
+    /// The locations this handle has read up to.
+    pub fn session_token(&self) -> SessionToken {
+        self.session.clone()
+    }
+
+    /// How many of the entries returned so far were already prefetched
+    /// when they were asked for.
+    pub fn prefetch_stats(&self) -> PrefetchStats {
+        self.prefetch_stats
+    }
+
     /// Take a linearizable snapshot of a set of interesting colors and start prefetching.
     pub fn strong_snapshot(&mut self, colors: &[order]) {
         trace!("HANDLE send snap {:?}.", colors);
@@ -1014,47 +1337,65 @@ impl<V: ?Sized> ReadHandle<V> {
     }
 
     pub fn sync<F>(&mut self, mut per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: FnMut(&V, &[OrderIndex], &Uuid) {
         self.take_snapshot();
-        self.do_sync_with(&mut (), |&mut (), e| per_event(e.data, e.inhabits, e.id))
+        let deadline = deadline_after(self.timeout);
+        self.do_sync_with(&mut (), deadline, |&mut (), e| per_event(e.data, e.inhabits, e.id))
+    }
+
+    /// Like `sync` but gives up with `GetRes::Timeout` if the whole sync
+    /// has not finished after `timeout`.
+    pub fn sync_timeout<F>(&mut self, timeout: Duration, mut per_event: F)
+    -> Result<Cut, GetRes>
+    where V: UnStoreable, F: FnMut(&V, &[OrderIndex], &Uuid) {
+        self.take_snapshot();
+        let deadline = deadline_after(Some(timeout));
+        self.do_sync_with(&mut (), deadline, |&mut (), e| per_event(e.data, e.inhabits, e.id))
     }
 
     pub fn sync_chain<F>(
         &mut self, chain: order, mut per_event: F
-    ) -> Result<HashMap<order, entry>, GetRes>
+    ) -> Result<Cut, GetRes>
     where V: UnStoreable, F: FnMut(&V, &[OrderIndex], &Uuid) {
         self.snapshot(chain);
-        self.do_sync_with(&mut (), |&mut (), e| per_event(e.data, e.inhabits, e.id))
+        let deadline = deadline_after(self.timeout);
+        self.do_sync_with(&mut (), deadline, |&mut (), e| per_event(e.data, e.inhabits, e.id))
     }
 
     pub fn sync_events<F>(&mut self, mut per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: for<'e> FnMut(Event<'e, V>) {
         self.take_snapshot();
-        self.do_sync_with(&mut (), |&mut (), e| per_event(e))
+        let deadline = deadline_after(self.timeout);
+        self.do_sync_with(&mut (), deadline, |&mut (), e| per_event(e))
+    }
+
+    pub fn sync_events_timeout<F>(&mut self, timeout: Duration, mut per_event: F)
+    -> Result<Cut, GetRes>
+    where V: UnStoreable, F: for<'e> FnMut(Event<'e, V>) {
+        self.take_snapshot();
+        let deadline = deadline_after(Some(timeout));
+        self.do_sync_with(&mut (), deadline, |&mut (), e| per_event(e))
     }
 
     pub fn sync_events_for_chain<F>(&mut self, chain: order, mut per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    -> Result<Cut, GetRes>
     where V: UnStoreable, F: for<'e> FnMut(Event<'e, V>) {
         self.snapshot(chain);
-        self.do_sync_with(&mut (), |&mut (), e| per_event(e))
+        let deadline = deadline_after(self.timeout);
+        self.do_sync_with(&mut (), deadline, |&mut (), e| per_event(e))
     }
 
-    fn do_sync_with<W, F>(&mut self, write_handle: &mut W, mut per_event: F)
-    -> Result<HashMap<order, entry>, GetRes>
+    fn do_sync_with<W, F>(
+        &mut self, write_handle: &mut W, deadline: Option<Instant>, mut per_event: F
+    ) -> Result<Cut, GetRes>
     where V: UnStoreable, F: for<'e> FnMut(&mut W, Event<'e, V>) {
-        let mut entries_seen = HashMap::default();
+        let mut entries_seen = Cut::new();
         loop {
-            match self.get_next_event() {
+            match self.get_next_event_before(deadline) {
                 Ok(e) => {
-                    for &OrderIndex(o, i) in e.inhabits {
-                        let last = entries_seen.entry(o).or_insert(i);
-                        if *last <= i {
-                            *last = i
-                        }
-                    }
+                    entries_seen.observe_all(e.inhabits);
                     per_event(write_handle, e);
                 },
                 Err(GetRes::Done) => return Ok(entries_seen),
@@ -1076,15 +1417,54 @@ impl<V: ?Sized> ReadHandle<V> {
     }
 
     pub fn get_next_event(&mut self) -> Result<Event<V>, GetRes>
+    where V: UnStoreable {
+        let deadline = deadline_after(self.timeout);
+        self.get_next_event_before(deadline)
+    }
+
+    /// Like `get_next` but gives up with `GetRes::Timeout` after `timeout`.
+    pub fn get_next_timeout(&mut self, timeout: Duration)
+    -> Result<(&V, &[OrderIndex]), GetRes>
+    where V: UnStoreable {
+        self.get_next2_timeout(timeout).map(|(v, l, _)| (v, l))
+    }
+
+    pub fn get_next2_timeout(&mut self, timeout: Duration)
+    -> Result<(&V, &[OrderIndex], &Uuid), GetRes>
+    where V: UnStoreable {
+        self.get_next_event_timeout(timeout).map(|e| (e.data, e.inhabits, e.id))
+    }
+
+    pub fn get_next_event_timeout(&mut self, timeout: Duration) -> Result<Event<V>, GetRes>
+    where V: UnStoreable {
+        self.get_next_event_before(deadline_after(Some(timeout)))
+    }
+
+    fn get_next_event_before(&mut self, deadline: Option<Instant>) -> Result<Event<V>, GetRes>
     where V: UnStoreable {
         if self.num_snapshots == 0 {
             trace!("HANDLE read with no snap.");
             return Err(GetRes::Done)
         }
 
+        let mut waited = None;
         'recv: loop {
-            //TODO use recv_timeout in real version
-            let read = self.ready_reads.recv().expect("no log");
+            let read = match self.ready_reads.try_recv() {
+                Ok(read) => read,
+                Err(..) => {
+                    let start = Instant::now();
+                    let read = recv_before(&self.ready_reads, deadline);
+                    let waited_so_far = waited.unwrap_or(Duration::from_secs(0));
+                    waited = Some(waited_so_far + start.elapsed());
+                    match read {
+                        Some(read) => read,
+                        None => {
+                            trace!("HANDLE read timed out.");
+                            return Err(GetRes::Timeout)
+                        },
+                    }
+                },
+            };
             let read = match read.map_err(|e| self.make_read_error(e)) {
                 Ok(v) => v,
                 //TODO Gc err
@@ -1108,7 +1488,15 @@ impl<V: ?Sized> ReadHandle<V> {
             }
         }
 
+        match waited {
+            None => self.prefetch_stats.hits += 1,
+            Some(waited) => {
+                self.prefetch_stats.misses += 1;
+                self.prefetch_stats.waited += waited;
+            },
+        }
         trace!("HANDLE got val.");
+        self.session.observe_all(bytes_as_entry(&self.curr_entry).locs());
         let e = bytes_as_entry(&self.curr_entry);
         Ok(Event{
             id: e.id(),
@@ -1162,7 +1550,9 @@ impl<V: ?Sized> ReadHandle<V> {
             }
         }
 
+        self.prefetch_stats.hits += 1;
         trace!("HANDLE got val.");
+        self.session.observe_all(bytes_as_entry(&self.curr_entry).locs());
         let e = bytes_as_entry(&self.curr_entry);
         Ok(Event{
             id: e.id(),
@@ -1192,6 +1582,39 @@ impl<V: ?Sized> ReadHandle<V> {
         self.to_log.send(Message::FromClient(Fastforward(loc))).unwrap();
     }
 
+    /// Read every chain in `cut` up to its horizon in the cut,
+    /// as if by `read_until` on each.
+    pub fn read_until_cut(&mut self, cut: &Cut) {
+        for loc in cut.locs() {
+            self.read_until(loc)
+        }
+    }
+
+    /// `fastforward` every chain in `cut` to its horizon in the cut.
+    pub fn fastforward_to_cut(&mut self, cut: &Cut) {
+        for loc in cut.locs() {
+            self.fastforward(loc)
+        }
+    }
+
+    /// Treat everything inside `cut` as already read,
+    /// e.g. to resume a view checkpointed at `cut`;
+    /// reads will only return the entries which come after it.
+    pub fn restore_cut(&mut self, cut: &Cut) {
+        let locs = cut.locs();
+        for &loc in &locs {
+            self.session.observe(loc)
+        }
+        self.to_log.send(Message::FromClient(RestoreCut(locs))).unwrap();
+    }
+
+    /// Has this handle read everything inside `cut`?
+    /// Only entries returned by reads or covered by `restore_cut` count,
+    /// not ones skipped by `fastforward`.
+    pub fn has_observed(&self, cut: &Cut) -> bool {
+        self.session.includes(cut)
+    }
+
     pub fn rewind(&mut self, loc: OrderIndex) {
         self.to_log.send(Message::FromClient(Rewind(loc))).unwrap();
     }
@@ -1218,6 +1641,30 @@ where V: Storeable {
         }
     }
 
+    /// Append `data` to every color in `inhabits`, as a multiappend if there
+    /// is more than one, without waiting for the append to finish.
+    pub fn simple_async_append(
+        &mut self,
+        data: &V,
+        inhabits: &mut [order],
+    ) -> Uuid {
+        if inhabits.len() == 0 {
+            return Uuid::nil()
+        }
+
+        inhabits.sort();
+        assert!(
+            inhabits.binary_search(&order::from(0)).is_err(),
+            "color 0 should not be used;it is special cased for legacy reasons."
+        );
+
+        if inhabits.len() == 1 {
+            self.async_append(inhabits[0], data, &[])
+        } else {
+            self.async_multiappend(&*inhabits, data, &[])
+        }
+    }
+
     pub fn append(&mut self, chain: order, data: &V, deps: &[OrderIndex])
     -> Vec<OrderIndex> {
         let id = self.async_append(chain, data, deps);
@@ -1290,16 +1737,47 @@ impl<V: ?Sized> WriteHandle<V> {
         finished_writes: FinshedWriteRecv,
         last_dropped: Arc<()>,
         ack_writes: bool,
-
+        ready: ReadyNotifier,
     ) -> Self {
         Self {
             handle: AtomicWriteHandle::new(to_log, last_dropped),
             finished_writes,
             num_async_writes: if ack_writes { Some(0) } else { None },
             num_errors: 0,
+            timeout: None,
+            session: Default::default(),
+            ready,
         }
     }
 
+    /// See `LogHandle::ready_fd`.
+    /// This is shared with the `ReadHandle` this was split from.
+    pub fn ready_fd(&self) -> io::Result<RawFd> {
+        self.ready.fd()
+    }
+
+    /// The locations of this handle's acknowledged appends.
+    pub fn session_token(&self) -> SessionToken {
+        self.session.clone()
+    }
+
+    /// Sets the default deadline for the `wait_for_*` family;
+    /// `None` blocks forever.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+
+    /// Let the servers free every entry inside `cut`.
+    /// Reads of those entries will no longer find them,
+    /// so only trim a cut once every reader has checkpointed past it.
+    pub fn trim(&mut self, cut: &Cut) {
+        let locs = cut.locs();
+        if locs.is_empty() {
+            return
+        }
+        self.handle.to_log.send(Message::FromClient(GarbageCollect(locs))).unwrap();
+    }
+
     fn atomize(self) -> AtomicWriteHandle<V> {
         self.handle
     }
@@ -1307,27 +1785,81 @@ impl<V: ?Sized> WriteHandle<V> {
     //FIXME better error checking is no waiting is possible
 
     pub fn wait_for_all_appends(&mut self) -> Result<(), TryWaitRes> {
+        let deadline = deadline_after(self.timeout);
+        self.wait_for_all_appends_before(deadline)
+    }
+
+    /// Like `wait_for_all_appends` but gives up with `TryWaitRes::Timeout`
+    /// if some append is still outstanding after `timeout`.
+    pub fn wait_for_all_appends_timeout(&mut self, timeout: Duration)
+    -> Result<(), TryWaitRes> {
+        self.wait_for_all_appends_before(deadline_after(Some(timeout)))
+    }
+
+    fn wait_for_all_appends_before(&mut self, deadline: Option<Instant>)
+    -> Result<(), TryWaitRes> {
         trace!("HANDLE waiting for {:?} appends", self.num_async_writes);
         for _ in 0..self.num_async_writes
             .expect("cannot wait for everything with multiple write handles") {
-            self.wait_for_any_append()?;
+            self.wait_for_any_append_before(deadline)?;
         }
         Ok(())
     }
 
     pub fn wait_for_a_specific_append(&mut self, write_id: Uuid)
+    -> Result<Vec<OrderIndex>, TryWaitRes> {
+        let deadline = deadline_after(self.timeout);
+        self.wait_for_a_specific_append_before(write_id, deadline)
+    }
+
+    /// Like `wait_for_a_specific_append` but gives up with `TryWaitRes::Timeout`
+    /// if `write_id` has not been acknowledged after `timeout`.
+    pub fn wait_for_a_specific_append_timeout(&mut self, write_id: Uuid, timeout: Duration)
+    -> Result<Vec<OrderIndex>, TryWaitRes> {
+        self.wait_for_a_specific_append_before(write_id, deadline_after(Some(timeout)))
+    }
+
+    fn wait_for_a_specific_append_before(&mut self, write_id: Uuid, deadline: Option<Instant>)
     -> Result<Vec<OrderIndex>, TryWaitRes> {
         for _ in 0..self.num_async_writes
             .expect("cannot wait for a specific append with multiple write handles") {
-            let (id, locs) = self.wait_for_any_append()?;
+            let (id, locs) = self.wait_for_any_append_before(deadline)?;
             if id == write_id {
+                if locs.is_empty() {
+                    return Err(TryWaitRes::Cancelled)
+                }
                 return Ok(locs)
             }
         }
         Err(TryWaitRes::NothingReady)
     }
 
+    /// Tries to take back the multiappend `write_id`.
+    /// If the servers have not yet agreed on where it goes it is removed
+    /// from every chain and this returns `Err(TryWaitRes::Cancelled)`,
+    /// otherwise it is too late and this waits for the append to finish
+    /// like `wait_for_a_specific_append`.
+    /// Once sent, singleton appends and multiappends to a single server
+    /// are never cancelled.
+    pub fn cancel(&mut self, write_id: Uuid) -> Result<Vec<OrderIndex>, TryWaitRes> {
+        self.handle.to_log.send(Message::FromClient(CancelAppend(write_id)))
+            .expect("cannot send");
+        self.wait_for_a_specific_append(write_id)
+    }
+
+    /// A cancelled append finishes with no locations.
     pub fn wait_for_any_append(&mut self) -> Result<(Uuid, Vec<OrderIndex>), TryWaitRes> {
+        let deadline = deadline_after(self.timeout);
+        self.wait_for_any_append_before(deadline)
+    }
+
+    pub fn wait_for_any_append_timeout(&mut self, timeout: Duration)
+    -> Result<(Uuid, Vec<OrderIndex>), TryWaitRes> {
+        self.wait_for_any_append_before(deadline_after(Some(timeout)))
+    }
+
+    fn wait_for_any_append_before(&mut self, deadline: Option<Instant>)
+    -> Result<(Uuid, Vec<OrderIndex>), TryWaitRes> {
         //FIXME need to know the number of lost writes so we don't freeze?
         match self.num_async_writes {
             Some(0) => return Err(TryWaitRes::NothingReady),
@@ -1335,10 +1867,17 @@ impl<V: ?Sized> WriteHandle<V> {
             Some(_) => {
                 //TODO return buffers here and cache them?
                 loop {
-                    let res = self.recv_write().unwrap();
+                    let res = match recv_before(&self.finished_writes, deadline) {
+                        Some(res) => res,
+                        None => {
+                            trace!("HANDLE append wait timed out.");
+                            return Err(TryWaitRes::Timeout)
+                        },
+                    };
                     match res {
                         Ok(write) => {
                             self.num_async_writes.as_mut().map(|n| *n -= 1);
+                            self.session.observe_all(&write.1);
                             return Ok(write)
                         },
                         Err(err) => if let Some(err) = self.to_wait_error(err) {
@@ -1363,8 +1902,9 @@ impl<V: ?Sized> WriteHandle<V> {
                             None => TryWaitRes::NothingReady,
                         }
                     ));
-                if ret.is_ok() {
+                if let Ok((_, ref locs)) = ret {
                     self.num_async_writes.as_mut().map(|n| *n -= 1);
+                    self.session.observe_all(locs);
                 }
                 //TODO return buffers here and cache them?
                 ret
@@ -1380,9 +1920,10 @@ impl<V: ?Sized> WriteHandle<V> {
                 let mut flushed = 0;
                 for res in self.finished_writes.try_iter() {
                     match res {
-                        Ok(..) => {
+                        Ok((_, locs)) => {
                             flushed += 1;
                             self.num_async_writes.as_mut().map(|n| *n -= 1);
+                            self.session.observe_all(&locs);
                         },
                         Err(fuzzy_log::Error{server, error_num, error}) =>
                             //TODO return incremental count
@@ -1408,12 +1949,24 @@ impl<V: ?Sized> WriteHandle<V> {
             None
         }
     }
+}
+
+fn deadline_after(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
 
-    fn recv_write(&mut self)
-    -> Result<Result<(Uuid, Vec<OrderIndex>), fuzzy_log::Error>, ::std::sync::mpsc::RecvError> {
-        self.finished_writes.recv().map(|res| res.map(|(id, locs)| {
-            (id, locs)
-        }))
+/// Blocks until `recv` yields, or returns `None` once `deadline` has passed.
+fn recv_before<T>(recv: &mpsc::Receiver<T>, deadline: Option<Instant>) -> Option<T> {
+    let deadline = match deadline {
+        None => return Some(recv.recv().expect("no log")),
+        Some(deadline) => deadline,
+    };
+    let now = Instant::now();
+    let wait = if deadline > now { deadline - now } else { Duration::from_secs(0) };
+    match recv.recv_timeout(wait) {
+        Ok(t) => Some(t),
+        Err(mpsc::RecvTimeoutError::Timeout) => None,
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("no log"),
     }
 }
 
@@ -6,6 +6,7 @@ use std::collections::hash_map;
 use std::io;
 use std::sync::mpsc;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use std::u64;
 
 use packets::*;
@@ -16,16 +17,31 @@ use self::FromClient::*;
 use hash::{HashMap, HashSet, UuidHashMap};
 
 use self::per_color::{PerColor, IsRead, ReadHandle, NextToFetch};
+use self::prefetch::{PrefetchConfig, Window};
 
 use store;
 
+pub mod cut;
 pub mod log_handle;
 mod per_color;
+pub mod prefetch;
 mod range_tree;
+pub mod ready;
+pub mod session;
 
 // const MAX_PREFETCH: u32 = 40;
 const MAX_PREFETCH: u32 = 40;
 
+//How long appends are held back after a server reports it is overloaded,
+//doubling for each overload until an append gets through
+const MIN_OVERLOAD_BACKOFF_MS: u64 = 1;
+const MAX_OVERLOAD_BACKOFF_MS: u64 = 256;
+
+//How long a multiappend can be missing from some of its chains after being found
+//in another, or an append can be outstanding, before we decide some writer died
+//between skeens phases and finish or abort its multiappend
+const MULTI_RECOVERY_TIMEOUT_MS: u64 = 2000;
+
 type ChainEntry = Rc<Vec<u8>>;
 
 pub struct ThreadLog<FinshedReadQueue, FinshedWriteQueue> {
@@ -58,10 +74,17 @@ pub struct ThreadLog<FinshedReadQueue, FinshedWriteQueue> {
     finished: bool,
 
     print_data: PrintData,
-    prefetch : u32,
+    prefetch: PrefetchConfig,
+    prefetch_windows: HashMap<order, Window>,
 
     last_seen_entries: HashMap<order, entry>,
     my_colors_chains: HashSet<order>,
+
+    throttled_until: Option<Instant>,
+    overload_backoff: Duration,
+    throttled_appends: VecDeque<Vec<u8>>,
+
+    last_stall_check: Instant,
 }
 
 pub struct ThreadLogBuilder<FinshedReadQueue, FinshedWriteQueue=()> {
@@ -77,6 +100,7 @@ pub struct ThreadLogBuilder<FinshedReadQueue, FinshedWriteQueue=()> {
     no_remote_style: NoRemoteStyle,
 
     my_colors_chains: Option<HashSet<order>>,
+    prefetch: PrefetchConfig,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -107,6 +131,7 @@ impl<FinshedReadQueue> ThreadLogBuilder<FinshedReadQueue, ()> {
             no_remote_style: NoRemoteStyle::NoConnection,
 
             my_colors_chains: None,
+            prefetch: Default::default(),
         }
     }
 }
@@ -130,6 +155,7 @@ impl<FinshedReadQueue, FinshedWriteQueue> ThreadLogBuilder<FinshedReadQueue, Fin
             fetch_boring_multis,
             no_remote_style,
             my_colors_chains,
+            prefetch,
         } = self;
         ThreadLogBuilder{
             to_store,
@@ -142,6 +168,7 @@ impl<FinshedReadQueue, FinshedWriteQueue> ThreadLogBuilder<FinshedReadQueue, Fin
             ack_writes: true,
             finished_writes: to,
             my_colors_chains,
+            prefetch,
         }
     }
 
@@ -177,6 +204,10 @@ impl<FinshedReadQueue, FinshedWriteQueue> ThreadLogBuilder<FinshedReadQueue, Fin
         ThreadLogBuilder{ my_colors_chains: Some(chains), .. builder }
     }
 
+    pub fn prefetch(self, prefetch: PrefetchConfig) -> Self {
+        ThreadLogBuilder{ prefetch, .. self}
+    }
+
     pub fn build(self) -> ThreadLog<FinshedReadQueue, FinshedWriteQueue>
     where
         FinshedReadQueue: OnRead,
@@ -192,6 +223,7 @@ impl<FinshedReadQueue, FinshedWriteQueue> ThreadLogBuilder<FinshedReadQueue, Fin
             fetch_boring_multis,
             no_remote_style,
             my_colors_chains,
+            prefetch,
         } = self;
         ThreadLog {
             to_store,
@@ -213,9 +245,14 @@ impl<FinshedReadQueue, FinshedWriteQueue> ThreadLogBuilder<FinshedReadQueue, Fin
             ack_writes,
             return_snapshots,
             no_remote_style,
-            prefetch: 1,
+            prefetch,
+            prefetch_windows: Default::default(),
             last_seen_entries: Default::default(),
             my_colors_chains: my_colors_chains.unwrap_or_default(),
+            throttled_until: None,
+            overload_backoff: Duration::from_millis(MIN_OVERLOAD_BACKOFF_MS),
+            throttled_appends: Default::default(),
+            last_stall_check: Instant::now(),
         }
     }
 }
@@ -223,6 +260,8 @@ impl<FinshedReadQueue, FinshedWriteQueue> ThreadLogBuilder<FinshedReadQueue, Fin
 pub type FinshedReadQueue = mpsc::Sender<Result<Vec<u8>, Error>>;
 pub type FinshedReadRecv = mpsc::Receiver<Result<Vec<u8>, Error>>;
 
+/// A write which was cancelled before it got a place in the log
+/// is finished with no locations.
 pub type FinshedWriteQueue = mpsc::Sender<Result<(Uuid, Vec<OrderIndex>), Error>>;
 pub type FinshedWriteRecv = mpsc::Receiver<Result<(Uuid, Vec<OrderIndex>), Error>>;
 
@@ -241,12 +280,14 @@ counters!{
         read_done: u64,
         ret: u64,
         shut: u64,
+        overloaded: u64,
     }
 }
 
 struct MultiSearchState {
     val: Vec<u8>,
     //pieces_remaining: usize,
+    last_recovery: Instant,
 }
 
 pub enum Message {
@@ -259,6 +300,7 @@ pub enum FromStore {
     WriteComplete(Uuid, Vec<OrderIndex>), //TODO
     ReadComplete(OrderIndex, Vec<u8>),
     IoError(io::ErrorKind, usize),
+    Overloaded(usize),
 }
 
 pub enum FromClient {
@@ -266,10 +308,17 @@ pub enum FromClient {
     SnapshotAndPrefetch(order),
     MultiSnapshotAndPrefetch(Vec<order>),
     StrongSnapshotAndPrefetch(Vec<OrderIndex>),
+    //a snapshot which reads at least to the given index in each chain
+    SessionSnapshotAndPrefetch(Vec<OrderIndex>),
     PerformAppend(Vec<u8>),
+    CancelAppend(Uuid),
+    //let the servers free each chain up to and including the given index
+    GarbageCollect(Vec<OrderIndex>),
     ReturnBuffer(Vec<u8>),
     ReadUntil(OrderIndex),
     Fastforward(OrderIndex),
+    //treat each chain up to and including the given index as already read
+    RestoreCut(Vec<OrderIndex>),
     Rewind(OrderIndex),
     StopAckingWrites,
     Shutdown,
@@ -328,12 +377,24 @@ where
 
     pub fn run(mut self) {
         // use std::thread;
-        use std::time::Duration;
         //FIXME remove
         //let mut num_msgs = 0;
         'recv: while !self.finished {
+            let timeout = match self.throttled_until {
+                None => Duration::from_secs(3),
+                Some(until) => {
+                    let now = Instant::now();
+                    if until > now { until - now } else { Duration::from_secs(0) }
+                },
+            };
+            let timeout =
+                std::cmp::min(timeout, Duration::from_millis(MULTI_RECOVERY_TIMEOUT_MS));
             //let msg = self.from_outside.recv().expect("outside is gone");
-            if let Ok(msg) = self.from_outside.recv_timeout(Duration::from_secs(3)) {
+            let msg = self.from_outside.recv_timeout(timeout);
+            self.end_throttle_if_due();
+            self.recover_stuck_multiappends();
+            self.check_stalled_if_due();
+            if let Ok(msg) = msg {
             //if let Ok(msg) = self.from_outside.recv() {
                 if !self.handle_message(msg) { break 'recv }
                 // num_msgs += 1;
@@ -387,6 +448,30 @@ where
                 }
                 true
             },
+            SessionSnapshotAndPrefetch(chains) => {
+                self.print_data.snap(1);
+                self.num_snapshots = self.num_snapshots.saturating_add(1);
+                trace!("FUZZY session snapshot {:?}: {:?}", chains, self.num_snapshots);
+                for OrderIndex(chain, floor) in chains {
+                    //the server we snapshot from may not have caught up to the session yet,
+                    //so we also read until the session's horizon as in ReadUntil
+                    if floor > entry::from(0) {
+                        let unblocked = {
+                            let pc = self.per_chains.entry(chain)
+                                .or_insert_with(|| PerColor::new(chain));
+                            pc.increment_outstanding_snapshots(&self.chains_currently_being_read);
+                            pc.give_new_snapshot(floor)
+                        };
+                        if let Some(val) = unblocked {
+                            let locs = self.return_entry(val);
+                            if let Some(locs) = locs { self.stop_blocking_on(locs) }
+                        }
+                    }
+                    self.fetch_snapshot(chain);
+                    self.prefetch(chain);
+                }
+                true
+            },
             StrongSnapshotAndPrefetch(chains) => {
                 self.print_data.snap(1);
                 self.num_snapshots = self.num_snapshots.saturating_add(1);
@@ -421,7 +506,49 @@ where
                     let layout = bytes_as_entry(&msg).layout();
                     assert!(layout == EntryLayout::Data || layout == EntryLayout::Multiput);
                 }
-                self.to_store.send(msg).expect("store hung up");
+                if self.throttled_until.is_some() {
+                    self.throttled_appends.push_back(msg)
+                } else {
+                    self.to_store.send(msg).expect("store hung up");
+                }
+                true
+            }
+            CancelAppend(id) => {
+                let throttled = self.throttled_appends.iter()
+                    .position(|msg| bytes_as_entry(msg).id() == &id);
+                match throttled {
+                    //still held back by the overload backoff, the servers never saw it
+                    Some(i) => {
+                        self.throttled_appends.remove(i);
+                        if self.ack_writes && self.finished_writes.send(Ok((id, vec![]))).is_err() {
+                            self.finished = true;
+                        }
+                    },
+                    None => {
+                        let cancel = EntryContents::Senti {
+                            id: &id,
+                            flags: &(EntryFlag::TakeLock | EntryFlag::NewMultiPut | EntryFlag::Unlock),
+                            data_bytes: &0,
+                            lock: &0,
+                            locs: &[],
+                            deps: &[],
+                        }.to_vec();
+                        self.to_store.send(cancel).expect("store hung up");
+                    },
+                }
+                true
+            }
+            GarbageCollect(locs) => {
+                //the servers keep everything from the index they are sent onwards
+                let locs: Vec<_> = locs.into_iter()
+                    .map(|OrderIndex(o, i)| OrderIndex(o, i + 1))
+                    .collect();
+                let gc = EntryContents::GC {
+                    id: &Uuid::new_v4(),
+                    flags: &EntryFlag::Nothing,
+                    locs: &locs,
+                }.to_vec();
+                self.to_store.send(gc).expect("store hung up");
                 true
             }
             ReturnBuffer(buffer) => {
@@ -451,6 +578,14 @@ where
                 pc.give_new_snapshot(loc.1);
                 true
             }
+            RestoreCut(locs) => {
+                for OrderIndex(o, i) in locs {
+                    let pc = self.per_chains.entry(o)
+                        .or_insert_with(|| PerColor::new(o));
+                    pc.fastforward_to(i);
+                }
+                true
+            }
             Rewind(loc) => {
                 let pc = self.per_chains.entry(loc.0)
                     .or_insert_with(|| PerColor::new(loc.0));
@@ -475,6 +610,9 @@ where
         match msg {
             WriteComplete(id, locs) => {
                 self.print_data.write_done(1);
+                if self.throttled_until.is_none() {
+                    self.overload_backoff = Duration::from_millis(MIN_OVERLOAD_BACKOFF_MS);
+                }
                 let check_color = !self.my_colors_chains.is_empty();
                 for &OrderIndex(o, i) in locs.iter() {
                     if check_color && self.my_colors_chains.contains(&o) {
@@ -502,10 +640,80 @@ where
                     self.finished = true;
                 }
             }
+            Overloaded(server) => {
+                self.print_data.overloaded(1);
+                trace!("FUZZY server {} overloaded, backing off {:?}",
+                    server, self.overload_backoff);
+                if self.throttled_until.is_none() {
+                    self.throttled_until = Some(Instant::now() + self.overload_backoff);
+                    let max = Duration::from_millis(MAX_OVERLOAD_BACKOFF_MS);
+                    self.overload_backoff = std::cmp::min(self.overload_backoff * 2, max);
+                }
+            }
         }
         true
     }
 
+    // Once the backoff is over the store resends the refused appends,
+    // followed by any which were made in the meantime.
+    fn end_throttle_if_due(&mut self) {
+        match self.throttled_until {
+            Some(until) if until <= Instant::now() => self.throttled_until = None,
+            _ => return,
+        }
+        self.to_store.send(store::RETRY_OVERLOADED.to_vec()).expect("store hung up");
+        for msg in self.throttled_appends.drain(..) {
+            self.to_store.send(msg).expect("store hung up");
+        }
+    }
+
+    // A writer which dies between skeens phases leaves its multiappend in some chains
+    // and blocks the rest, so the store takes over and finishes it for them.
+    // Once it has the append will be found in the remaining chains as usual.
+    fn recover_stuck_multiappends(&mut self) {
+        if self.blocked_multiappends.is_empty() {
+            return
+        }
+        let now = Instant::now();
+        let timeout = Duration::from_millis(MULTI_RECOVERY_TIMEOUT_MS);
+        for (id, multi) in self.blocked_multiappends.iter_mut() {
+            if now.duration_since(multi.last_recovery) < timeout {
+                continue
+            }
+            multi.last_recovery = now;
+            let packet = {
+                let e = bytes_as_entry(&multi.val);
+                //only multi-server appends go through skeens,
+                //and the max timestamp is needed to finish one
+                if !e.flag().contains(EntryFlag::TakeLock) || e.lock_num() == 0 {
+                    continue
+                }
+                trace!("FUZZY recovering {:?} stuck at {:?}", id, e.locs());
+                EntryContents::UpdateRecovery {
+                    old_recoverer: &Uuid::nil(),
+                    write_id: id,
+                    flags: &EntryFlag::Nothing,
+                    lock: &e.lock_num(),
+                    locs: e.locs(),
+                }.to_vec()
+            };
+            self.to_store.send(packet).expect("store hung up");
+        }
+    }
+
+    // A writer which dies after phase 1 but before sending any phase 2 leaves
+    // nothing to find, only a multiappend which blocks every chain it got to,
+    // so the store also looks for writes stuck behind one.
+    fn check_stalled_if_due(&mut self) {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(MULTI_RECOVERY_TIMEOUT_MS);
+        if now.duration_since(self.last_stall_check) < timeout {
+            return
+        }
+        self.last_stall_check = now;
+        self.to_store.send(store::CHECK_STALLED.to_vec()).expect("store hung up");
+    }
+
     fn make_error(&mut self, error: io::ErrorKind, server: usize) -> Error {
         let error_num = self.num_errors;
         self.num_errors += 1;
@@ -538,13 +746,12 @@ where
     fn prefetch(&mut self, chain: order) {
         //TODO allow new chains?
         //TODO how much to fetch
+        let max_prefetch = self.prefetch.window(&mut self.prefetch_windows, chain).size();
         let to_fetch = {
             let pc = &mut self.per_chains.get_mut(&chain).expect("boring server read");
             pc.increment_outstanding_snapshots(&self.chains_currently_being_read);
             let next = pc.next_range_to_fetch();
-            let max_prefetch = self.prefetch;
             // let max_prefetch = (MAX_PREFETCH).saturating_sub(self.blockers.len() as u64) + 1;
-            // let max_prefetch = MAX_PREFETCH;
             match next {
                 NextToFetch::None => None,
                 NextToFetch::AboveHorizon(low, high) => {
@@ -575,7 +782,8 @@ where
             }
         };
         if let Some((low, high)) = to_fetch {
-            self.fetch_next(chain, low, high)
+            self.fetch_next(chain, low, high);
+            self.prefetch.window(&mut self.prefetch_windows, chain).fetched()
         }
     }
 
@@ -586,6 +794,9 @@ where
             (e.kind(), *e.flag())
         };
         trace!("FUZZY handle read @ {:?}", read_loc);
+        if flag.contains(EntryFlag::ReadSuccess) {
+            self.prefetch_windows.get_mut(&read_loc.0).map(|w| w.received());
+        }
 
         match kind.layout() {
             EntryLayout::Snapshot => {
@@ -627,7 +838,7 @@ where
                 else {
                     //due to non-atomic snapshot
                     let unblocked = {
-                        let prefetch = &mut self.prefetch;
+                        let (prefetch, windows) = (&self.prefetch, &mut self.prefetch_windows);
 
                         self.per_chains.get_mut(&read_loc.0).and_then(|s| {
                             let e = bytes_as_entry(&msg);
@@ -636,7 +847,7 @@ where
                             let new_horizon = e.horizon().1;
                             let old_horizon = s.current_snap();
                             let needs_fetch = (u64::from(new_horizon)).saturating_sub(u64::from(old_horizon));
-                            *prefetch = (needs_fetch / 3 + (2 * *prefetch as u64) / 3) as u32;
+                            prefetch.window(windows, read_loc.0).saw_backlog(needs_fetch);
                             trace!("FUZZY try update horizon to {:?}", (read_loc.0, new_horizon));
                             s.give_new_snapshot(new_horizon)
                         })
@@ -1084,6 +1295,7 @@ where
             self.blocked_multiappends.insert(id, MultiSearchState {
                 val: msg,
                 //pieces_remaining: pieces_remaining
+                last_recovery: Instant::now(),
             });
 
             return MultiSearch::InProgress
@@ -1580,6 +1792,11 @@ impl AsyncStoreClient for mpsc::Sender<Message> {
         self.send(Message::FromStore(IoError(err.kind(), server)))
             .map(|_| ()).map_err(|_| ())
     }
+
+    fn on_overloaded(&mut self, server: usize) -> Result<bool, ()> {
+        self.send(Message::FromStore(Overloaded(server)))
+            .map(|_| true).map_err(|_| ())
+    }
 }
 
 pub trait OnRead {
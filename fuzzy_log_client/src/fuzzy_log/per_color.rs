@@ -246,6 +246,18 @@ impl PerColor {
         self.update_horizon(new_horizon)
     }
 
+    /// Treat every entry up to `index` as already returned,
+    /// as when a client resumes from a checkpoint which covers them.
+    pub fn fastforward_to(&mut self, index: entry) {
+        trace!("FUZZY fastforward {:?} to {:?}", self.chain, index);
+        self.read_status.set_below_as_returned(index);
+        if self.last_snapshot < index {
+            self.last_snapshot = index;
+            //whatever was waiting for the next snapshot is now behind us
+            self.blocked_on_new_snapshot = None
+        }
+    }
+
     pub fn rewind_to(&mut self, index: entry) {
         self.read_status.set_above_as_none(index)
     }
@@ -0,0 +1,247 @@
+//! How far ahead of the reader each color is fetched.
+//!
+//! After a snapshot, the log thread keeps a window of reads outstanding for
+//! every color it still has to read. A small window keeps interactive reads
+//! from queueing behind reads nobody is waiting for yet, while long catch-up
+//! reads want enough in flight to cover the round trip to the servers.
+//! By default, `PrefetchPolicy::Backlog`, the window follows how far behind
+//! each snapshot finds the reader, without bound, so catching up on a long
+//! backlog fetches much of it at once.
+//! `PrefetchPolicy::Fixed` pins the window, `PrefetchPolicy::Adaptive` starts
+//! it at `min` and doubles it each window of reads which came back faster than
+//! the last, backing off when the round trip grows, as it does once reads are
+//! queueing at the servers instead of being pipelined.
+//! Both can be set for all colors, or for individual ones, with
+//! `LogBuilder::prefetch` and `LogBuilder::prefetch_for`.
+
+use std::cmp::{max, min};
+use std::time::{Duration, Instant};
+
+use hash::HashMap;
+use packets::order;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchPolicy {
+    /// Keep up to this many reads outstanding per color.
+    Fixed(u32),
+    /// Size the window from the throughput and latency of past reads,
+    /// keeping it between `min` and `max`.
+    Adaptive { min: u32, max: u32 },
+    /// Move the window a third of the way towards the backlog each snapshot
+    /// finds, with no upper bound.
+    Backlog,
+}
+
+impl Default for PrefetchPolicy {
+    fn default() -> Self {
+        PrefetchPolicy::Backlog
+    }
+}
+
+/// The prefetch policy for every color.
+#[derive(Debug, Clone, Default)]
+pub struct PrefetchConfig {
+    pub default: PrefetchPolicy,
+    pub per_color: HashMap<order, PrefetchPolicy>,
+}
+
+impl PrefetchConfig {
+    pub fn policy(&self, chain: order) -> PrefetchPolicy {
+        self.per_color.get(&chain).cloned().unwrap_or(self.default)
+    }
+
+    /// The window for `chain` in `windows`, starting a new one if needed.
+    pub fn window<'w>(&self, windows: &'w mut HashMap<order, Window>, chain: order)
+    -> &'w mut Window {
+        windows.entry(chain).or_insert_with(|| Window::new(self.policy(chain)))
+    }
+}
+
+/// How well prefetching kept ahead of a `ReadHandle`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrefetchStats {
+    /// Entries which had already arrived when the handle asked for them.
+    pub hits: u64,
+    /// Entries the handle had to wait for.
+    pub misses: u64,
+    /// The total time spent waiting on misses.
+    pub waited: Duration,
+}
+
+impl PrefetchStats {
+    /// The portion of entries which did not need to be waited for.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 1.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+//the round trip has to grow by this factor over the best seen before we shrink
+const LATENCY_BACKOFF: u32 = 2;
+
+/// The prefetch window for a single color.
+#[derive(Debug)]
+pub struct Window {
+    policy: PrefetchPolicy,
+    size: u32,
+    // the reads in the current round, which lasts until a window's worth is back
+    round_start: Option<Instant>,
+    round_rtt: Option<Duration>,
+    received: u32,
+    best_rate: f64,
+    best_rtt: Option<Duration>,
+}
+
+impl Window {
+    pub fn new(policy: PrefetchPolicy) -> Self {
+        let size = match policy {
+            PrefetchPolicy::Fixed(size) => max(size, 1),
+            PrefetchPolicy::Adaptive { min, .. } => max(min, 1),
+            PrefetchPolicy::Backlog => 1,
+        };
+        Window {
+            policy,
+            size,
+            round_start: None,
+            round_rtt: None,
+            received: 0,
+            best_rate: 0.0,
+            best_rtt: None,
+        }
+    }
+
+    /// The most reads to have outstanding.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// A snapshot found `behind` entries past what was already read,
+    /// an adaptive window grows to fetch them in one round if it can.
+    pub fn saw_backlog(&mut self, behind: u64) {
+        let hint = behind / 3 + (2 * self.size as u64) / 3;
+        match self.policy {
+            PrefetchPolicy::Fixed(..) => (),
+            PrefetchPolicy::Adaptive { min: low, max: high } => {
+                let hint = min(hint, high as u64) as u32;
+                self.size = max(max(self.size, hint), max(low, 1));
+            },
+            PrefetchPolicy::Backlog => self.size = min(hint, u32::max_value() as u64) as u32,
+        }
+    }
+
+    pub fn fetched(&mut self) {
+        self.fetched_at(Instant::now())
+    }
+
+    pub fn received(&mut self) {
+        self.received_at(Instant::now())
+    }
+
+    fn fetched_at(&mut self, now: Instant) {
+        if let PrefetchPolicy::Adaptive { .. } = self.policy {
+            if self.round_start.is_none() {
+                self.round_start = Some(now);
+                self.round_rtt = None;
+                self.received = 0;
+            }
+        }
+    }
+
+    fn received_at(&mut self, now: Instant) {
+        let (low, high) = match self.policy {
+            PrefetchPolicy::Fixed(..) | PrefetchPolicy::Backlog => return,
+            PrefetchPolicy::Adaptive { min, max } => (min, max),
+        };
+        let start = match self.round_start {
+            Some(start) => start,
+            None => return,
+        };
+        let elapsed = now.duration_since(start);
+        if self.round_rtt.is_none() {
+            self.round_rtt = Some(elapsed);
+        }
+        self.received += 1;
+        if self.received < self.size {
+            return
+        }
+
+        self.round_start = None;
+        let rtt = self.round_rtt.unwrap();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        let rate = self.received as f64 / secs.max(1e-9);
+        let best_rtt = *self.best_rtt.get_or_insert(rtt);
+        if rtt > best_rtt * LATENCY_BACKOFF {
+            self.size = max(self.size / 2, max(low, 1));
+            self.best_rate = 0.0;
+        } else if rate > self.best_rate {
+            self.size = min(self.size.saturating_mul(2), high);
+            self.best_rate = rate;
+        }
+        if rtt < best_rtt {
+            self.best_rtt = Some(rtt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_window_does_not_move() {
+        let mut window = Window::new(PrefetchPolicy::Fixed(8));
+        window.saw_backlog(1000);
+        for _ in 0..20 {
+            window.fetched();
+            window.received();
+        }
+        assert_eq!(window.size(), 8);
+    }
+
+    #[test]
+    fn backlog_window_is_unbounded() {
+        let mut window = Window::new(PrefetchPolicy::default());
+        assert_eq!(window.size(), 1);
+        window.saw_backlog(30_000);
+        assert_eq!(window.size(), 10_000);
+        window.saw_backlog(30_000);
+        assert_eq!(window.size(), 16_666);
+        window.saw_backlog(0);
+        assert_eq!(window.size(), 11_110);
+    }
+
+    #[test]
+    fn adaptive_window_grows_to_max() {
+        let mut window = Window::new(PrefetchPolicy::Adaptive { min: 1, max: 16 });
+        assert_eq!(window.size(), 1);
+        let mut now = Instant::now();
+        for round in 0..5 {
+            window.fetched_at(now);
+            now += Duration::from_millis(2);
+            for _ in 0..window.size() {
+                window.received_at(now);
+            }
+            assert_eq!(window.size(), min(2 << round, 16), "round {}", round);
+        }
+    }
+
+    #[test]
+    fn adaptive_window_backs_off() {
+        let mut window = Window::new(PrefetchPolicy::Adaptive { min: 2, max: 64 });
+        window.saw_backlog(96);
+        assert_eq!(window.size(), 33);
+        let now = Instant::now();
+        window.fetched_at(now);
+        for _ in 0..33 {
+            window.received_at(now + Duration::from_millis(1));
+        }
+        let size = window.size();
+        window.fetched_at(now + Duration::from_millis(1));
+        for _ in 0..size {
+            window.received_at(now + Duration::from_millis(21));
+        }
+        assert_eq!(window.size(), size / 2);
+    }
+}
@@ -198,6 +198,36 @@ impl RangeTree {
         debug_assert!(self.tree_invariant(), "invariant failed @ {:#?}", self);
     }
 
+    pub fn set_below_as_returned(&mut self, high: entry) {
+        debug_assert!(self.tree_invariant(), "invariant failed @ {:#?}", self);
+        loop {
+            let (old_range, old_kind) = match self.inner.iter().next() {
+                Some((r, &k)) if r.first() <= high => (r.clone(), k),
+                _ => break,
+            };
+            let old_range = remove_from_map(&mut self.inner, old_range).0;
+            let (covered, rest) = if old_range.last() > high {
+                (Range::new(old_range.first(), high),
+                    Some(Range::new(high + 1, old_range.last())))
+            } else {
+                (old_range, None)
+            };
+            match old_kind {
+                Kind::SentToServer => self.num_outstanding -= covered.len(),
+                Kind::GottenFromServer => self.num_buffered -= covered.len(),
+                _ => {},
+            }
+            if let Some(rest) = rest {
+                self.inner.insert(rest, old_kind);
+            }
+        }
+        let (new_range, new_kind) = try_merge_with_next(
+            &mut self.inner, Range::new(0.into(), high), Kind::ReturnedToClient
+        );
+        self.inner.insert(new_range, new_kind);
+        debug_assert!(self.tree_invariant(), "invariant failed @ {:#?}", self);
+    }
+
     pub fn is_returned(&self, point: entry) -> bool {
         match self.inner.get(&Range::point(point)) {
             Some(&Kind::ReturnedToClient) => true,
@@ -510,4 +540,36 @@ mod test {
         println!("{:?}", tree);
         assert!(false);
     }
+
+    #[test]
+    fn returned_below() {
+        let inner = [
+            (Range::new(0.into(), 4.into()), Kind::ReturnedToClient),
+            (Range::new(5.into(), 5.into()), Kind::GottenFromServer),
+            (Range::new(6.into(), 9.into()), Kind::SentToServer),
+            (Range::new(10.into(), 10.into()), Kind::Skip),
+            (Range::new(11.into(), u64::MAX.into()), Kind::None),
+            ].iter().cloned().collect();
+        let mut tree = RangeTree {
+            inner: inner, num_outstanding: 4, num_buffered: 1
+        };
+        tree.set_below_as_returned(7.into());
+        assert_eq!(tree.num_buffered(), 0);
+        assert_eq!(tree.num_outstanding(), 2);
+        assert!(tree.is_returned(7.into()));
+        assert!(tree.next_return_is(8.into()));
+        assert!(tree.tree_invariant());
+
+        tree.set_below_as_returned(9.into());
+        assert_eq!(tree.num_outstanding(), 0);
+        assert!(tree.is_returned(10.into()));
+        assert!(tree.next_return_is(11.into()));
+        assert!(tree.tree_invariant());
+
+        let mut tree = RangeTree::new();
+        tree.set_below_as_returned(3.into());
+        assert!(tree.next_return_is(4.into()));
+        assert_eq!(tree.min_range_to_fetch(), (4, u64::MAX));
+        assert!(tree.tree_invariant());
+    }
 }
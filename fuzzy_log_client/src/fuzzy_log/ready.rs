@@ -0,0 +1,111 @@
+//! Readiness notification for handles driven by an external event loop.
+//!
+//! Normally a handle blocks in `get_next` or `wait_for_any_append` until the
+//! log thread hands it something. Handles which instead poll with
+//! `try_get_next` and `try_wait_for_any_append` can ask for a file descriptor
+//! with `LogHandle::ready_fd`, which the log thread makes readable every time
+//! it hands the handle a read or an append ack. The descriptor is an eventfd;
+//! reading from it resets it, so a poller should read it _before_ draining
+//! the handle, lest it miss a notification which arrives mid-drain.
+//! It starts out readable, as the handle may already have been handed
+//! something before the descriptor was asked for.
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, RwLock};
+
+use packets::{OrderIndex, Uuid};
+
+use super::{Error, OnRead, OnWrote};
+
+#[derive(Debug)]
+struct ReadyFd {
+    fd: RawFd,
+}
+
+impl ReadyFd {
+    #[cfg(target_os = "linux")]
+    fn new() -> io::Result<Self> {
+        //start readable, anything queued before now was never notified
+        let fd = unsafe { ::libc::eventfd(1, ::libc::EFD_NONBLOCK | ::libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error())
+        }
+        Ok(ReadyFd { fd })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new() -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Other, "ready fds require eventfd"))
+    }
+
+    fn notify(&self) {
+        let one = 1u64;
+        //NOTE a full counter is already readable, so EAGAIN can be ignored
+        let _ = unsafe {
+            ::libc::write(self.fd, &one as *const u64 as *const ::libc::c_void, 8)
+        };
+    }
+}
+
+impl Drop for ReadyFd {
+    fn drop(&mut self) {
+        unsafe { ::libc::close(self.fd); }
+    }
+}
+
+/// Shared between a handle and its log thread,
+/// the fd is only created once some handle asks for it.
+#[derive(Debug, Clone, Default)]
+pub struct ReadyNotifier {
+    fd: Arc<RwLock<Option<ReadyFd>>>,
+}
+
+impl ReadyNotifier {
+    pub fn fd(&self) -> io::Result<RawFd> {
+        let mut fd = self.fd.write().unwrap();
+        if fd.is_none() {
+            *fd = Some(ReadyFd::new()?);
+        }
+        Ok(fd.as_ref().unwrap().fd)
+    }
+
+    fn notify(&self) {
+        if let Some(ref fd) = *self.fd.read().unwrap() {
+            fd.notify()
+        }
+    }
+}
+
+/// A queue to a handle which notifies the handle's `ReadyNotifier`
+/// after every send.
+pub struct Notifying<Q> {
+    queue: Q,
+    notifier: ReadyNotifier,
+}
+
+impl<Q> Notifying<Q> {
+    pub fn new(queue: Q, notifier: ReadyNotifier) -> Self {
+        Notifying { queue, notifier }
+    }
+}
+
+impl<Q: OnRead> OnRead for Notifying<Q> {
+    type Error = Q::Error;
+
+    fn send(&mut self, res: Result<Vec<u8>, Error>) -> Result<(), Self::Error> {
+        let sent = self.queue.send(res);
+        self.notifier.notify();
+        sent
+    }
+}
+
+impl<Q: OnWrote> OnWrote for Notifying<Q> {
+    type Error = Q::Error;
+
+    fn send(&mut self, res: Result<(Uuid, Vec<OrderIndex>), Error>) -> Result<(), Self::Error> {
+        let sent = self.queue.send(res);
+        self.notifier.notify();
+        sent
+    }
+}
@@ -0,0 +1,10 @@
+//! Session guarantees which outlive a single handle.
+//!
+//! A `SessionToken` records, per chain, the furthest entry a handle has read
+//! or had acknowledged as written, which is exactly a `Cut`. Handing it to
+//! `ReadHandle::snapshot_colors_after` on another handle, possibly in another
+//! process, makes that handle read at least that far, so it sees everything
+//! the first one did. Tokens are sent between processes with
+//! `Cut::to_bytes` and `Cut::from_bytes`.
+
+pub use fuzzy_log::cut::Cut as SessionToken;
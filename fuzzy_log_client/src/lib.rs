@@ -3,13 +3,18 @@
 pub extern crate fuzzy_log_packets as packets;
 #[macro_use] extern crate fuzzy_log_util;
 
+extern crate libc;
 #[macro_use] extern crate log;
 pub extern crate mio;
 extern crate reactor;
 
 pub use fuzzy_log_util::hash;
+pub use fuzzy_log_util::config;
 
 pub use fuzzy_log::log_handle::*;
+pub use fuzzy_log::cut::{Checkpoint, Cut};
+pub use fuzzy_log::session::SessionToken;
+pub use fuzzy_log::prefetch::{PrefetchPolicy, PrefetchStats};
 
 pub mod fuzzy_log;
 pub mod colors;
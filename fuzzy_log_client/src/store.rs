@@ -14,7 +14,7 @@ use std::rc::Rc;
 use packets::*;
 use packets::buffer2::Buffer;
 
-use hash::{HashMap, HashSet, UuidHashMap};
+use hash::{HashMap, HashSet, UuidHashMap, UuidHashSet};
 //use servers2::spsc;
 use fuzzy_log_util::socket_addr::Ipv4SocketAddr;
 
@@ -32,6 +32,14 @@ pub trait AsyncStoreClient {
 
     fn on_io_error(&mut self, err: io::Error, server: usize) -> Result<(), ()>;
 
+    /// Called when `server` refused an append because it is overloaded.
+    /// Returns whether the client will later send `RETRY_OVERLOADED`
+    /// to have the refused appends resent, if not they are resent at once.
+    fn on_overloaded(&mut self, server: usize) -> Result<bool, ()> {
+        let _ = server;
+        Ok(false)
+    }
+
     //TODO fn should_shutdown(&mut self) -> bool { false }
 }
 
@@ -41,6 +49,13 @@ fn channel() -> (ToSelf, FromClient) {
     mio::channel::channel()
 }
 
+/// Sent to the store to resend the appends which servers refused as overloaded.
+pub const RETRY_OVERLOADED: &'static [u8] = &[0];
+
+/// Sent to the store periodically to look for writes stuck behind
+/// a multiappend whose writer died before finishing it.
+pub const CHECK_STALLED: &'static [u8] = &[1];
+
 pub struct AsyncTcpStore<C: AsyncStoreClient> {
     reactor: Reactor<PerStream, StoreInner<C>>,
 }
@@ -65,6 +80,12 @@ struct StoreInner<C: AsyncStoreClient> {
     receiver: Ipv4SocketAddr,
 
     pending_skeens2: VecDeque<SK2Send>,
+
+    overloaded: VecDeque<(Token, Vec<u8>)>,
+
+    recoveries: UuidHashMap<RecoveryState>,
+    pending_recovery: VecDeque<(usize, Vec<u8>)>,
+    outstanding_at_last_check: UuidHashSet,
 }
 
 counters!{
@@ -73,6 +94,7 @@ counters!{
         finished_recvs: u64,
         finished_sends: u64,
         being_sent: u64,
+        overloaded: u64,
     }
 }
 
@@ -124,6 +146,32 @@ struct SK2Send {
     is_snapshot: bool,
 }
 
+//A multiappend whose writer died between skeens phases,
+//leaving it in some chains and blocking the rest.
+//We take over as the recoverer of each of its chains and ask each for the
+//append's timestamp: chains still waiting for a max answer with their phase 1
+//timestamp, ones which got phase 2 with the max, ones which never saw it with 0.
+//A chain which answers 0 also fences the append off, should the writer turn
+//out to be merely slow its phase 1 there is dropped and answered with 0,
+//making the writer abort the append itself.
+//Once every chain has answered we finish the append with the max, or with the
+//largest phase 1 timestamp if every chain got phase 1, and abort it otherwise.
+#[derive(Debug)]
+struct RecoveryState {
+    locs: Vec<OrderIndex>,
+    //the max timestamp, if some chain already got phase 2
+    max_ts: u64,
+    largest_phase1: u64,
+    //the chains which are still waiting for phase 2
+    waiting: HashSet<order>,
+    //some chain never got phase 1 and never will, if no chain got phase 2 we abort
+    missing_somewhere: bool,
+    in_progress: HashSet<order>,
+    //who was already recovering a chain when we tried to take over,
+    //we replace them if the append is still stuck the next time we try
+    holders: HashMap<order, Uuid>,
+}
+
 //TODO rename to AsyncStore
 impl<C> AsyncTcpStore<C>
 where C: AsyncStoreClient {
@@ -220,7 +268,11 @@ where C: AsyncStoreClient {
 
             max_timestamp_seen: Default::default(),
             pending_skeens2: Default::default(),
+            overloaded: Default::default(),
             receiver: id,
+            recoveries: Default::default(),
+            pending_recovery: Default::default(),
+            outstanding_at_last_check: Default::default(),
 
             print_data: Default::default(),
         })?;
@@ -263,7 +315,7 @@ where C: AsyncStoreClient {
 impl<C> StoreInner<C>
 where C: AsyncStoreClient {
     fn handle_message(
-        &mut self, token: mio::Token, _io: &mut TcpWriter, mut packet: Buffer
+        &mut self, token: mio::Token, io: &mut TcpWriter, mut packet: Buffer
     ) {
         self.print_data.finished_recvs(1);
         //stay_awake = true;
@@ -272,6 +324,20 @@ where C: AsyncStoreClient {
             (c.kind(), *c.flag())
         };
         trace!("CLIENT got a {:?} from {:?}", kind, token);
+        if kind == EntryKind::UpdateRecovery || kind == EntryKind::CheckSkeens1 {
+            return self.handle_recovery_reply(&packet)
+        }
+        //GCs are fire-and-forget, the servers just echo them back
+        if kind.layout() == EntryLayout::GC {
+            trace!("CLIENT GC {:?} done @ {:?}", packet.contents().id(), token);
+            return
+        }
+        if packet.contents().is_multi_cancel()
+            || (flag.contains(EntryFlag::Skeens1Queued)
+                && !self.sent_writes.contains_key(packet.contents().id())) {
+            trace!("CLIENT ignoring reply for cancelled {:?}", packet.contents().id());
+            return
+        }
         if flag.contains(EntryFlag::ReadSuccess) {
             if !flag.contains(EntryFlag::Unlock)
                 || flag.contains(EntryFlag::NewMultiPut) {
@@ -285,6 +351,10 @@ where C: AsyncStoreClient {
             //A read that found an usused entry still contains useful data
             self.handle_completed_read(token, &packet, false);
         }
+        //A write without ReadSuccess was refused by an overloaded server
+        else if kind.layout().is_write() || kind.layout() == EntryLayout::Snapshot {
+            self.handle_overloaded(token, io, packet)
+        }
         //TODO use option instead
         else {
             unimplemented!()
@@ -294,6 +364,279 @@ where C: AsyncStoreClient {
 
     ////////////////////
 
+    fn handle_overloaded(&mut self, token: Token, io: &mut TcpWriter, packet: Buffer) {
+        let id = *packet.contents().id();
+        if !self.sent_writes.contains_key(&id) {
+            trace!("CLIENT refused write {:?} already finished", id);
+            return
+        }
+        trace!("CLIENT {:?} refused write {:?}", token, id);
+        self.print_data.overloaded(1);
+        match self.client.on_overloaded(token.0) {
+            Ok(true) => self.overloaded.push_back((token, packet.entry_slice().to_vec())),
+            Ok(false) => io.add_bytes_to_write(&[packet.entry_slice(), self.receiver.bytes()]),
+            Err(..) => self.finished = true,
+        }
+    }
+
+    fn resend_overloaded(&mut self, inner: &mut IoState<PerStream>) {
+        let receiver = self.receiver;
+        let sent_writes = &self.sent_writes;
+        for (token, msg) in self.overloaded.drain(..) {
+            //the write may have been cancelled while it was waiting
+            if !sent_writes.contains_key(bytes_as_entry(&msg).id()) {
+                continue
+            }
+            inner.mutate(token, |per_server| per_server.add_writes(&[&msg, receiver.bytes()]));
+        }
+    }
+
+    ////////////////////
+
+    //The client gives up on a multiappend with a phase 2 whose max timestamp is 0.
+    //It can only be dropped while we are still gathering phase 1 timestamps,
+    //once phase 2 has been sent the append will happen regardless.
+    fn cancel_multiappend(&mut self, inner: &mut IoState<PerStream>, msg: Vec<u8>) {
+        let id = *bytes_as_entry(&msg).id();
+        let buf = match self.sent_writes.remove(&id) {
+            Some(WriteState::Skeens1(buf, ..)) => buf,
+            Some(sent) => {
+                trace!("CLIENT too late to cancel {:?}", id);
+                self.sent_writes.insert(id, sent);
+                return
+            },
+            None => {
+                trace!("CLIENT too late to cancel {:?}", id);
+                return
+            },
+        };
+        let mut cancel = buf.borrow().clone();
+        slice_to_sentinel(&mut cancel);
+        let size = {
+            let mut e = bytes_as_entry_mut(&mut cancel);
+            e.flag_mut().insert(EntryFlag::Unlock | EntryFlag::NewMultiPut);
+            e.locs_mut().iter_mut()
+                .fold((), |_, &mut OrderIndex(_, ref mut i)| *i = entry::from(0));
+            *e.lock_mut() = 0;
+            e.as_ref().len()
+        };
+        cancel.truncate(size);
+        trace!("CLIENT cancelling {:?}", id);
+        let receiver = self.receiver;
+        for server in self.get_servers_for_multi(&cancel) {
+            inner.mutate(server.into(), |ps| ps.add_writes(&[&cancel, receiver.bytes()]));
+        }
+        if self.client.on_finished_write(id, vec![]).is_err() {
+            self.finished = true
+        }
+    }
+
+    ////////////////////
+
+    //Recovery starts either when the client finds a multiappend in some chains
+    //but not others long after it should have been in all of them,
+    //in which case the lock is its max timestamp and the missing chains have index 0,
+    //or when a server reports that a multiappend stuck in phase 1 is blocking our writes,
+    //in which case we know none of its timestamps and check all of its chains.
+    fn start_recovery(&mut self, msg: &[u8]) {
+        let (write_id, lock, locs, is_report) = {
+            let e = bytes_as_entry(msg);
+            let is_report = e.flag().contains(EntryFlag::Skeens1Queued);
+            (*e.id(), e.lock_num(), e.locs().to_vec(), is_report)
+        };
+        //our own appends are only slow, not abandoned
+        if self.sent_writes.contains_key(&write_id) {
+            return
+        }
+        let max_ts = if is_report { 0 } else { lock };
+        let to_check: Vec<order> = locs.iter()
+            .filter(|&&OrderIndex(o, i)|
+                o != order::from(0) && (is_report || i == entry::from(0)))
+            .map(|&OrderIndex(o, _)| o)
+            .collect();
+        if to_check.is_empty() || (!is_report && max_ts == 0) {
+            return
+        }
+        let num_chain_servers = self.num_chain_servers;
+        let recovery = self.recoveries.entry(write_id).or_insert_with(|| RecoveryState {
+            locs: locs.iter()
+                .map(|&OrderIndex(o, _)| OrderIndex(o, entry::from(0)))
+                .collect(),
+            max_ts: 0,
+            largest_phase1: 0,
+            waiting: Default::default(),
+            missing_somewhere: false,
+            in_progress: Default::default(),
+            holders: Default::default(),
+        });
+        if !recovery.in_progress.is_empty() {
+            trace!("CLIENT already recovering {:?}", write_id);
+            return
+        }
+        //things may have moved on since our last attempt, so start over
+        recovery.max_ts = max_ts;
+        recovery.largest_phase1 = 0;
+        recovery.waiting.clear();
+        recovery.missing_somewhere = false;
+        for chain in to_check {
+            let old_recoverer = recovery.holders.get(&chain).cloned()
+                .unwrap_or_else(Uuid::nil);
+            let update = EntryContents::UpdateRecovery {
+                old_recoverer: &old_recoverer,
+                write_id: &write_id,
+                flags: &EntryFlag::Nothing,
+                lock: &0,
+                locs: &[OrderIndex(chain, entry::from(0))],
+            }.to_vec();
+            recovery.in_progress.insert(chain);
+            self.pending_recovery.push_back((write_server_for_chain(chain, num_chain_servers), update));
+        }
+        trace!("CLIENT recovering {:?} in {:?}", write_id, recovery.in_progress);
+    }
+
+    fn handle_recovery_reply(&mut self, packet: &Buffer) {
+        let contents = packet.contents();
+        if contents.kind() == EntryKind::UpdateRecovery
+            && contents.flag().contains(EntryFlag::Skeens1Queued) {
+            return self.start_recovery(packet.entry_slice())
+        }
+        let (write_id, chain) = (*contents.id(), contents.locs()[0].0);
+        let me = self.receiver.to_uuid();
+        let server = self.write_server_for_chain(chain);
+        {
+            let recovery = match self.recoveries.get_mut(&write_id) {
+                Some(recovery) => recovery,
+                None => return,
+            };
+            let succeeded = contents.flag().contains(EntryFlag::ReadSuccess);
+            if contents.kind() == EntryKind::UpdateRecovery {
+                let (_, &holder) = contents.write_id_and_old_recoverer();
+                if succeeded || holder == me {
+                    recovery.holders.remove(&chain);
+                    let check = EntryContents::CheckSkeens1 {
+                        id: &write_id,
+                        flags: &EntryFlag::Nothing,
+                        data_bytes: &0,
+                        dependency_bytes: &0,
+                        loc: &OrderIndex(chain, entry::from(0)),
+                    }.to_vec();
+                    self.pending_recovery.push_back((server, check));
+                    return
+                }
+                trace!("CLIENT {:?} is already recovering {:?} in {:?}",
+                    holder, write_id, chain);
+                recovery.holders.insert(chain, holder);
+                recovery.in_progress.remove(&chain);
+            } else {
+                recovery.in_progress.remove(&chain);
+                let timestamp = u64::from(contents.locs()[0].1);
+                if succeeded {
+                    trace!("CLIENT {:?} waiting in {:?} @ {}", write_id, chain, timestamp);
+                    recovery.waiting.insert(chain);
+                    recovery.largest_phase1 = ::std::cmp::max(recovery.largest_phase1, timestamp);
+                } else if timestamp > 0 {
+                    trace!("CLIENT {:?} has max {} in {:?}", write_id, timestamp, chain);
+                    recovery.max_ts = timestamp;
+                } else {
+                    trace!("CLIENT {:?} never reached {:?}", write_id, chain);
+                    recovery.missing_somewhere = true;
+                }
+            }
+            if !recovery.in_progress.is_empty() || !recovery.holders.is_empty() {
+                return
+            }
+        }
+        let recovery = self.recoveries.remove(&write_id).unwrap();
+        self.finish_recovery(write_id, recovery)
+    }
+
+    //Every chain has answered, the chains still waiting get a phase 2 with
+    //the max timestamp if we know it, an abort if some chain fenced the append off,
+    //and otherwise the largest phase 1 timestamp, which is the max.
+    fn finish_recovery(&mut self, write_id: Uuid, recovery: RecoveryState) {
+        if recovery.waiting.is_empty() {
+            trace!("CLIENT {:?} no longer waiting anywhere", write_id);
+            return
+        }
+        let lock = if recovery.max_ts > 0 {
+            if recovery.largest_phase1 > recovery.max_ts {
+                warn!("CLIENT cannot recover {:?}, its timestamp {} is past the max {}",
+                    write_id, recovery.largest_phase1, recovery.max_ts);
+                return
+            }
+            recovery.max_ts
+        } else if recovery.missing_somewhere {
+            0
+        } else {
+            recovery.largest_phase1
+        };
+        if lock == 0 {
+            trace!("CLIENT aborting {:?} in {:?}", write_id, recovery.waiting);
+        } else {
+            trace!("CLIENT finishing {:?} @ {} in {:?}", write_id, lock, recovery.waiting);
+        }
+        let skeens2 = EntryContents::Senti {
+            id: &write_id,
+            flags: &(EntryFlag::TakeLock | EntryFlag::NewMultiPut | EntryFlag::Unlock),
+            data_bytes: &0,
+            lock: &lock,
+            locs: &recovery.locs,
+            deps: &[],
+        }.to_vec();
+        let servers: HashSet<usize> = recovery.waiting.iter()
+            .map(|&chain| self.write_server_for_chain(chain))
+            .collect();
+        for server in servers {
+            self.pending_recovery.push_back((server, skeens2.clone()))
+        }
+    }
+
+    //Sent by the client every so often, any write which has been outstanding since
+    //the last check may be stuck behind a multiappend whose writer died in phase 1,
+    //so we ask the chains it is waiting on what is at the front of their queues.
+    fn check_stalled(&mut self) {
+        let mut chains: HashSet<order> = Default::default();
+        {
+            let last_check = &self.outstanding_at_last_check;
+            for (id, write) in self.sent_writes.iter() {
+                if !last_check.contains(id) {
+                    continue
+                }
+                let mut add_chains = |buf: &[u8]| chains.extend(bytes_as_entry(buf).locs().iter()
+                    .map(|&OrderIndex(o, _)| o)
+                    .filter(|&o| o != order::from(0)));
+                match write {
+                    &WriteState::SingleServer(ref buf) => add_chains(buf),
+                    &WriteState::Skeens1(ref buf, ..)
+                    | &WriteState::Skeens2(ref buf, ..)
+                    | &WriteState::SnapshotSkeens1(ref buf, ..)
+                    | &WriteState::SnapshotSkeens2(ref buf, ..) => add_chains(&buf.borrow()),
+                    &WriteState::GC(..) => {},
+                }
+            }
+        }
+        self.outstanding_at_last_check = self.sent_writes.keys().cloned().collect();
+        for chain in chains {
+            trace!("CLIENT checking what is blocking {:?}", chain);
+            let check = EntryContents::CheckSkeens1 {
+                id: &Uuid::nil(),
+                flags: &EntryFlag::Nothing,
+                data_bytes: &0,
+                dependency_bytes: &0,
+                loc: &OrderIndex(chain, entry::from(0)),
+            }.to_vec();
+            let server = self.write_server_for_chain(chain);
+            self.pending_recovery.push_back((server, check))
+        }
+    }
+
+    fn send_recovery(&mut self, inner: &mut IoState<PerStream>, server: usize, msg: &[u8]) {
+        let receiver = self.receiver;
+        inner.mutate(server.into(), |ps| ps.add_writes(&[msg, receiver.bytes()]));
+    }
+
+    ////////////////////
+
     fn handle_completion(
         &mut self, token: Token, num_chain_servers: usize, packet: &mut Buffer
     ) {
@@ -570,14 +913,21 @@ where C: AsyncStoreClient {
                         && read_server_for_chain(oi.0, num_chain_servers, unreplicated) == token.0 {
                         assert!(ts[i] == 0,
                             "repeat timestamp {:?} in {:#?}", oi, e);
+                        //a timestamp of 0 means a recoverer fenced the append off
+                        //in this chain, after aborting it elsewhere
                         let t: entry = oi.1;
                         ts[i] = u64::from(t);
-                        assert!(ts[i] > 0,
-                            "bad timestamp {:?} in {:#?}", oi, e);
                     }
                 }
 
                 if finished_writes {
+                    let fenced = e.locs().iter().zip(ts.iter())
+                        .any(|(oi, &t)| oi.0 != order::from(0) && t == 0);
+                    if fenced {
+                        //so we abort it in the rest too
+                        warn!("CLIENT {:?} was aborted by a recoverer", e.id());
+                        return Some(0)
+                    }
                     let max_ts = ts.iter().cloned().max().unwrap();
                     for &OrderIndex(o, _) in e.locs() {
                         let mts = max_timestamp_seen.entry(o).or_insert(max_ts);
@@ -761,6 +1111,22 @@ where C: AsyncStoreClient {
             self.finished = true;
             return false
         }
+        if &*msg == RETRY_OVERLOADED {
+            self.resend_overloaded(inner);
+            return true
+        }
+        if &*msg == CHECK_STALLED {
+            self.check_stalled();
+            return true
+        }
+        if bytes_as_entry(&msg).kind() == EntryKind::UpdateRecovery {
+            self.start_recovery(&msg);
+            return true
+        }
+        if bytes_as_entry(&msg).is_multi_cancel() {
+            self.cancel_multiappend(inner, msg);
+            return true
+        }
         let new_msg_kind = bytes_as_entry(&msg).layout();
         match new_msg_kind {
             EntryLayout::Read => {
@@ -851,9 +1217,8 @@ where C: AsyncStoreClient {
             }
 
             EntryLayout::GC => {
-                //TODO be less lazy
-                // self.add_gc(msg)
-                unimplemented!()
+                self.add_gc(inner, msg);
+                true
             },
             r @ EntryLayout::Sentinel | r @ EntryLayout::Lock =>
                 panic!("Invalid send request {:?}", r),
@@ -895,6 +1260,14 @@ where C: AsyncStoreClient {
 
     //////////
 
+    fn add_gc(&mut self, inner: &mut IoState<PerStream>, msg: Vec<u8>) {
+        trace!("CLIENT GC {:?}", bytes_as_entry(&msg).locs());
+        let receiver = self.receiver;
+        for server in self.get_servers_for_multi(&msg) {
+            inner.mutate(server.into(), |ps| ps.add_writes(&[&msg, receiver.bytes()]));
+        }
+    }
+
     fn add_skeens1(&mut self, inner: &mut IoState<PerStream>, msg: Vec<u8>) {
         debug_assert_eq!(bytes_as_entry(&msg).len(), msg.len());
         let (msg, servers, remaining_servers, timestamps) = self.prep_skeens1(msg);
@@ -1285,6 +1658,12 @@ where C: AsyncStoreClient {
 
         }
         self.pending_skeens2 = pending_sk2;
+
+        let mut pending_recovery = mem::replace(&mut self.pending_recovery, VecDeque::new());
+        for (server, msg) in pending_recovery.drain(..) {
+            self.send_recovery(inner, server, &msg);
+        }
+        self.pending_recovery = pending_recovery;
     }
 }
 
@@ -344,6 +344,16 @@ impl<'a> Packet::Ref<'a> {
         }
     }
 
+    /// A skeens phase 2 with a max timestamp of 0,
+    /// which aborts a multiappend that has not yet been given its place.
+    pub fn is_multi_cancel(self) -> bool {
+        match self {
+            Packet::Ref::Senti{flags, lock, ..} =>
+                flags.contains(EntryFlag::Unlock) && *lock == 0,
+            _ => false,
+        }
+    }
+
     pub fn flag(self) -> &'a Flag {
         use self::Packet::Ref::*;
         match self {
@@ -0,0 +1,273 @@
+//! Requests for inspecting a running server.
+//!
+//! Admin requests are served on their own listener, separate from the client
+//! protocol, so that inspecting a server never interferes with normal traffic.
+//! Every request is a one byte tag followed by its arguments,
+//! every response is a one byte tag followed by a little-endian body.
+
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use packets::{order, entry, Uuid};
+use socket_addr::Ipv4SocketAddr;
+
+/// The most entries returned by a single `DumpChain`.
+pub const MAX_DUMP_ENTRIES: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    ListChains,
+    ChainInfo(order),
+    DumpChain{ chain: order, start: entry, end: entry },
+    StuckAppends,
+    Clients,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Chains(Vec<ChainInfo>),
+    Entries(Vec<(entry, Vec<u8>)>),
+    Stuck(Vec<StuckAppend>),
+    Clients(Vec<ClientInfo>),
+    Error(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChainInfo {
+    pub chain: order,
+    pub min_entry: u64,
+    pub next_entry: u64,
+    /// Number of multiappends which have a local timestamp but no max timestamp.
+    pub phase1: u64,
+    /// Number of appends with a max timestamp waiting to be flushed.
+    pub waiting_for_flush: u64,
+}
+
+/// A multiappend which is still in Skeens phase 1 on this server.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StuckAppend {
+    pub chain: order,
+    pub id: Uuid,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub id: Ipv4SocketAddr,
+    pub worker: usize,
+}
+
+const LIST_CHAINS: u8 = 0;
+const CHAIN_INFO: u8 = 1;
+const DUMP_CHAIN: u8 = 2;
+const STUCK_APPENDS: u8 = 3;
+const CLIENTS: u8 = 4;
+
+const CHAINS: u8 = 0;
+const ENTRIES: u8 = 1;
+const STUCK: u8 = 2;
+const CLIENT_LIST: u8 = 3;
+const ERROR: u8 = 4;
+
+impl Request {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            &Request::ListChains => w.write_u8(LIST_CHAINS),
+            &Request::ChainInfo(chain) => {
+                w.write_u8(CHAIN_INFO)?;
+                w.write_u64::<LittleEndian>(chain.into())
+            },
+            &Request::DumpChain{chain, start, end} => {
+                w.write_u8(DUMP_CHAIN)?;
+                w.write_u64::<LittleEndian>(chain.into())?;
+                w.write_u64::<LittleEndian>(start.into())?;
+                w.write_u64::<LittleEndian>(end.into())
+            },
+            &Request::StuckAppends => w.write_u8(STUCK_APPENDS),
+            &Request::Clients => w.write_u8(CLIENTS),
+        }
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let req = match r.read_u8()? {
+            LIST_CHAINS => Request::ListChains,
+            CHAIN_INFO => Request::ChainInfo(r.read_u64::<LittleEndian>()?.into()),
+            DUMP_CHAIN => {
+                let chain = r.read_u64::<LittleEndian>()?.into();
+                let start = r.read_u64::<LittleEndian>()?.into();
+                let end = r.read_u64::<LittleEndian>()?.into();
+                Request::DumpChain{chain, start, end}
+            },
+            STUCK_APPENDS => Request::StuckAppends,
+            CLIENTS => Request::Clients,
+            tag => return Err(invalid(format!("unknown admin request {}", tag))),
+        };
+        Ok(req)
+    }
+}
+
+impl Response {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            &Response::Chains(ref chains) => {
+                w.write_u8(CHAINS)?;
+                w.write_u32::<LittleEndian>(chains.len() as u32)?;
+                for info in chains {
+                    w.write_u64::<LittleEndian>(info.chain.into())?;
+                    w.write_u64::<LittleEndian>(info.min_entry)?;
+                    w.write_u64::<LittleEndian>(info.next_entry)?;
+                    w.write_u64::<LittleEndian>(info.phase1)?;
+                    w.write_u64::<LittleEndian>(info.waiting_for_flush)?;
+                }
+                Ok(())
+            },
+            &Response::Entries(ref entries) => {
+                w.write_u8(ENTRIES)?;
+                w.write_u32::<LittleEndian>(entries.len() as u32)?;
+                for &(index, ref bytes) in entries {
+                    w.write_u64::<LittleEndian>(index.into())?;
+                    w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+                    w.write_all(bytes)?;
+                }
+                Ok(())
+            },
+            &Response::Stuck(ref stuck) => {
+                w.write_u8(STUCK)?;
+                w.write_u32::<LittleEndian>(stuck.len() as u32)?;
+                for s in stuck {
+                    w.write_u64::<LittleEndian>(s.chain.into())?;
+                    w.write_all(s.id.as_bytes())?;
+                    w.write_u64::<LittleEndian>(s.timestamp)?;
+                }
+                Ok(())
+            },
+            &Response::Clients(ref clients) => {
+                w.write_u8(CLIENT_LIST)?;
+                w.write_u32::<LittleEndian>(clients.len() as u32)?;
+                for c in clients {
+                    w.write_all(c.id.bytes())?;
+                    w.write_u64::<LittleEndian>(c.worker as u64)?;
+                }
+                Ok(())
+            },
+            &Response::Error(ref msg) => {
+                w.write_u8(ERROR)?;
+                w.write_u32::<LittleEndian>(msg.len() as u32)?;
+                w.write_all(msg.as_bytes())
+            },
+        }
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let tag = r.read_u8()?;
+        let len = r.read_u32::<LittleEndian>()? as usize;
+        let res = match tag {
+            CHAINS => {
+                let mut chains = Vec::with_capacity(len);
+                for _ in 0..len {
+                    chains.push(ChainInfo {
+                        chain: r.read_u64::<LittleEndian>()?.into(),
+                        min_entry: r.read_u64::<LittleEndian>()?,
+                        next_entry: r.read_u64::<LittleEndian>()?,
+                        phase1: r.read_u64::<LittleEndian>()?,
+                        waiting_for_flush: r.read_u64::<LittleEndian>()?,
+                    })
+                }
+                Response::Chains(chains)
+            },
+            ENTRIES => {
+                let mut entries = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let index = r.read_u64::<LittleEndian>()?.into();
+                    let size = r.read_u32::<LittleEndian>()? as usize;
+                    let mut bytes = vec![0; size];
+                    r.read_exact(&mut bytes)?;
+                    entries.push((index, bytes))
+                }
+                Response::Entries(entries)
+            },
+            STUCK => {
+                let mut stuck = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let chain = r.read_u64::<LittleEndian>()?.into();
+                    let id = read_uuid(r)?;
+                    let timestamp = r.read_u64::<LittleEndian>()?;
+                    stuck.push(StuckAppend{chain, id, timestamp})
+                }
+                Response::Stuck(stuck)
+            },
+            CLIENT_LIST => {
+                let mut clients = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let mut id = [0; 16];
+                    r.read_exact(&mut id)?;
+                    let worker = r.read_u64::<LittleEndian>()? as usize;
+                    clients.push(ClientInfo{id: Ipv4SocketAddr::from_bytes(id), worker})
+                }
+                Response::Clients(clients)
+            },
+            ERROR => {
+                let mut msg = vec![0; len];
+                r.read_exact(&mut msg)?;
+                Response::Error(String::from_utf8_lossy(&msg).into_owned())
+            },
+            tag => return Err(invalid(format!("unknown admin response {}", tag))),
+        };
+        Ok(res)
+    }
+}
+
+fn read_uuid<R: Read>(r: &mut R) -> io::Result<Uuid> {
+    let mut id = [0; 16];
+    r.read_exact(&mut id)?;
+    Ok(Uuid::from_bytes(&id).unwrap())
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip_request(req: Request) {
+        let mut bytes = vec![];
+        req.write_to(&mut bytes).unwrap();
+        assert_eq!(Request::read_from(&mut &bytes[..]).unwrap(), req);
+    }
+
+    fn round_trip_response(res: Response) {
+        let mut bytes = vec![];
+        res.write_to(&mut bytes).unwrap();
+        assert_eq!(Response::read_from(&mut &bytes[..]).unwrap(), res);
+    }
+
+    #[test]
+    fn requests() {
+        round_trip_request(Request::ListChains);
+        round_trip_request(Request::ChainInfo(7.into()));
+        round_trip_request(Request::DumpChain{
+            chain: 3.into(), start: 1.into(), end: 100.into()
+        });
+        round_trip_request(Request::StuckAppends);
+        round_trip_request(Request::Clients);
+    }
+
+    #[test]
+    fn responses() {
+        round_trip_response(Response::Chains(vec![
+            ChainInfo{chain: 1.into(), min_entry: 0, next_entry: 12, phase1: 1, waiting_for_flush: 0},
+            ChainInfo{chain: 5.into(), min_entry: 3, next_entry: 4, phase1: 0, waiting_for_flush: 2},
+        ]));
+        round_trip_response(Response::Entries(vec![(1.into(), vec![1, 2, 3]), (2.into(), vec![])]));
+        round_trip_response(Response::Stuck(vec![
+            StuckAppend{chain: 2.into(), id: Uuid::new_v4(), timestamp: 33},
+        ]));
+        round_trip_response(Response::Clients(vec![
+            ClientInfo{id: Ipv4SocketAddr::random(), worker: 2},
+        ]));
+        round_trip_response(Response::Error("no such chain".to_string()));
+    }
+}
@@ -0,0 +1,287 @@
+//! The map from each chain to its `Chain`, written by an ordering thread and
+//! read by every worker.
+//!
+//! There is a single writer and chains are never removed, so the directory is
+//! an insert-only open addressing table of pointers to boxed entries.
+//! Lookups are a few atomic loads: readers never wait for the writer, and
+//! adding a chain never waits for readers.
+//! When the table fills up the writer publishes a copy twice the size,
+//! the old table may still be probed by readers so it is only freed by the
+//! next `synchronize`.
+//!
+//! Readers do announce which lookups are in flight, so that the writer can
+//! `synchronize` with them; GC uses this to wait out every read which may have
+//! seen a trie's old bounds before freeing the entries below the new ones.
+
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::{mem, ptr, thread};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+
+use packets::order;
+
+/// Create an empty directory, the reader can be cloned for each worker.
+pub fn new<V>() -> (DirWriter<V>, DirReader<V>) {
+    let table = Box::into_raw(Table::with_capacity(MIN_CAPACITY));
+    let inner = Arc::new(Inner {
+        table: AtomicPtr::new(table),
+        retired: Mutex::new(vec![]),
+        readers: Mutex::new(vec![]),
+        _values: PhantomData,
+    });
+    let writer = DirWriter { reader: DirReader::register(inner.clone()), len: 0 };
+    (writer, DirReader::register(inner))
+}
+
+const MIN_CAPACITY: usize = 64;
+
+pub struct DirWriter<V> {
+    reader: DirReader<V>,
+    len: usize,
+}
+
+pub struct DirReader<V> {
+    inner: Arc<Inner<V>>,
+    // odd while this reader is in a lookup
+    epoch: Arc<AtomicUsize>,
+}
+
+struct Inner<V> {
+    table: AtomicPtr<Table<V>>,
+    retired: Mutex<Vec<Box<Table<V>>>>,
+    readers: Mutex<Vec<Arc<AtomicUsize>>>,
+    // the entries are only reachable through raw pointers
+    _values: PhantomData<V>,
+}
+
+struct Table<V> {
+    slots: Box<[AtomicPtr<Entry<V>>]>,
+    shift: u32,
+}
+
+struct Entry<V> {
+    chain: order,
+    value: UnsafeCell<V>,
+}
+
+// every reader and the writer hand out references to the same values
+unsafe impl<V: Sync> Send for DirWriter<V> {}
+unsafe impl<V: Sync> Sync for DirWriter<V> {}
+unsafe impl<V: Sync> Send for DirReader<V> {}
+unsafe impl<V: Sync> Sync for DirReader<V> {}
+
+impl<V> DirWriter<V> {
+    /// Add `chain`, which must not already be in the directory.
+    pub fn insert(&mut self, chain: order, value: V) {
+        debug_assert!(self.get_and(&chain, |_| ()).is_none(), "{:?} inserted twice", chain);
+        let entry = Box::into_raw(Box::new(Entry { chain, value: UnsafeCell::new(value) }));
+        self.len += 1;
+        if self.len * 4 > self.table().slots.len() * 3 {
+            self.grow()
+        }
+        self.table().place(entry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Wait until every lookup which started before this call is done.
+    pub fn synchronize(&self) {
+        let inner = &self.reader.inner;
+        let retired = mem::replace(&mut *inner.retired.lock().unwrap(), vec![]);
+        atomic::fence(Ordering::SeqCst);
+        let in_flight: Vec<_> = inner.readers.lock().unwrap().iter()
+            .map(|epoch| (epoch.clone(), epoch.load(Ordering::SeqCst)))
+            .filter(|&(_, seen)| seen % 2 == 1)
+            .collect();
+        for (epoch, seen) in in_flight {
+            while epoch.load(Ordering::SeqCst) == seen {
+                thread::yield_now()
+            }
+        }
+        drop(retired)
+    }
+
+    fn grow(&mut self) {
+        let inner = &self.reader.inner;
+        let old = unsafe { Box::from_raw(inner.table.load(Ordering::Relaxed)) };
+        let new = Table::with_capacity(old.slots.len() * 2);
+        for slot in old.slots.iter() {
+            let entry = slot.load(Ordering::Relaxed);
+            if !entry.is_null() {
+                new.place(entry)
+            }
+        }
+        inner.table.store(Box::into_raw(new), Ordering::Release);
+        inner.retired.lock().unwrap().push(old);
+    }
+}
+
+impl<V> Deref for DirWriter<V> {
+    type Target = DirReader<V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.reader
+    }
+}
+
+impl<V> DirReader<V> {
+    fn register(inner: Arc<Inner<V>>) -> Self {
+        let epoch = Arc::new(AtomicUsize::new(0));
+        inner.readers.lock().unwrap().push(epoch.clone());
+        DirReader { inner, epoch }
+    }
+
+    pub fn get_and<F, R>(&self, chain: &order, f: F) -> Option<R>
+    where F: FnOnce(&UnsafeCell<V>) -> R {
+        let _reading = self.start_read();
+        self.table().find(*chain).map(|entry| f(&entry.value))
+    }
+
+    pub fn for_each<F>(&self, mut f: F)
+    where F: FnMut(&order, &UnsafeCell<V>) {
+        let _reading = self.start_read();
+        for slot in self.table().slots.iter() {
+            let entry = slot.load(Ordering::Acquire);
+            if !entry.is_null() {
+                let entry = unsafe { &*entry };
+                f(&entry.chain, &entry.value)
+            }
+        }
+    }
+
+    fn start_read<'s>(&'s self) -> Reading<'s> {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        Reading(&self.epoch)
+    }
+
+    fn table(&self) -> &Table<V> {
+        unsafe { &*self.inner.table.load(Ordering::Acquire) }
+    }
+}
+
+impl<V> Clone for DirReader<V> {
+    fn clone(&self) -> Self {
+        DirReader::register(self.inner.clone())
+    }
+}
+
+impl<V> Drop for DirReader<V> {
+    fn drop(&mut self) {
+        let mut readers = self.inner.readers.lock().unwrap();
+        if let Some(i) = readers.iter().position(|e| Arc::ptr_eq(e, &self.epoch)) {
+            readers.swap_remove(i);
+        }
+    }
+}
+
+// ends a lookup even if the reader's closure panics,
+// otherwise the writer would wait for it forever
+struct Reading<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for Reading<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<V> Table<V> {
+    fn with_capacity(capacity: usize) -> Box<Self> {
+        debug_assert!(capacity.is_power_of_two());
+        let slots: Vec<_> = (0..capacity).map(|_| AtomicPtr::new(ptr::null_mut())).collect();
+        Box::new(Table { slots: slots.into_boxed_slice(), shift: 64 - capacity.trailing_zeros() })
+    }
+
+    fn find(&self, chain: order) -> Option<&Entry<V>> {
+        let mask = self.slots.len() - 1;
+        let mut i = self.home(chain);
+        loop {
+            let entry = self.slots[i].load(Ordering::Acquire);
+            if entry.is_null() {
+                return None
+            }
+            let entry = unsafe { &*entry };
+            if entry.chain == chain {
+                return Some(entry)
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    // only the writer places entries, and the table is never full
+    fn place(&self, entry: *mut Entry<V>) {
+        let mask = self.slots.len() - 1;
+        let mut i = self.home(unsafe { (*entry).chain });
+        while !self.slots[i].load(Ordering::Relaxed).is_null() {
+            i = (i + 1) & mask;
+        }
+        self.slots[i].store(entry, Ordering::Release)
+    }
+
+    // fibonacci hashing, chains are usually small dense ranges
+    fn home(&self, chain: order) -> usize {
+        (u64::from(chain).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> self.shift) as usize
+    }
+}
+
+impl<V> Drop for Inner<V> {
+    fn drop(&mut self) {
+        // the retired tables only hold entries which are also in the current one
+        let table = unsafe { Box::from_raw(*self.table.get_mut()) };
+        for slot in table.slots.iter() {
+            let entry = slot.load(Ordering::Relaxed);
+            if !entry.is_null() {
+                unsafe { drop(Box::from_raw(entry)) }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn insert_and_grow() {
+        let (mut writer, reader) = new();
+        for i in 1..1000u64 {
+            writer.insert(i.into(), i * 2);
+            assert_eq!(reader.get_and(&i.into(), |v| unsafe { *v.get() }), Some(i * 2));
+        }
+        assert_eq!(writer.len(), 999);
+        for i in 1..1000u64 {
+            assert_eq!(reader.get_and(&i.into(), |v| unsafe { *v.get() }), Some(i * 2));
+        }
+        assert_eq!(reader.get_and(&1000.into(), |_| ()), None);
+        let mut sum = 0;
+        reader.for_each(|_, v| sum += unsafe { *v.get() });
+        assert_eq!(sum, (1..1000u64).map(|i| i * 2).sum::<u64>());
+    }
+
+    #[test]
+    fn synchronize_waits_for_lookups() {
+        let (mut writer, reader) = new();
+        writer.insert(1.into(), ());
+        let in_lookup = Arc::new(AtomicBool::new(false));
+        let done = Arc::new(AtomicBool::new(false));
+        let reading = {
+            let (in_lookup, done) = (in_lookup.clone(), done.clone());
+            thread::spawn(move || reader.get_and(&1.into(), |_| {
+                in_lookup.store(true, Ordering::SeqCst);
+                thread::sleep(::std::time::Duration::from_millis(50));
+                done.store(true, Ordering::SeqCst);
+            }))
+        };
+        while !in_lookup.load(Ordering::SeqCst) {
+            thread::yield_now()
+        }
+        writer.synchronize();
+        assert!(done.load(Ordering::SeqCst));
+        reading.join().unwrap();
+    }
+}
@@ -4,7 +4,6 @@
 
 extern crate byteorder;
 extern crate deque;
-extern crate evmap;
 extern crate lazycell;
 extern crate mio;
 extern crate uuid;
@@ -18,6 +17,8 @@ use fuzzy_log_packets::{buffer, storeables};
 
 use fuzzy_log_util::{hash, socket_addr, vec_deque_map};
 
+pub use fuzzy_log_util::config;
+
 //use std::collections::HashSet;
 use std::cell::UnsafeCell;
 use std::collections::VecDeque;
@@ -37,20 +38,19 @@ use skeens::{
 };
 use trie::{AppendSlot, Trie};
 
-use evmap::{WriteHandle, ReadHandle};
-
 use self::ToWorker::*;
 
 use packets::*;
 
-use self::trivial_eq_arc::TrivialEqArc;
 
 pub use self::worker_thread::{handle_to_worker2, ToSend};
+pub use self::shards::{ShardMap, ShardedAppend};
 
 use self::trie::ValEdge;
 
 use self::shared_slice::RcSlice;
 
+pub mod admin;
 pub mod tcp;
 // pub mod udp;
 
@@ -63,16 +63,19 @@ pub mod trie;
 pub mod byte_trie;
 
 pub mod trivial_eq_arc;
+pub mod chain_directory;
 
 mod ordering_thread;
+mod shards;
 pub mod worker_thread;
 pub mod shared_slice;
+pub mod sim;
 
 #[cfg(test)]
 mod tests;
 
-pub type ChainStore<T> = WriteHandle<order, TrivialEqArc<Chain<T>>>;
-pub type ChainReader<T> = ReadHandle<order, TrivialEqArc<Chain<T>>>;
+pub type ChainStore<T> = chain_directory::DirWriter<Chain<T>>;
+pub type ChainReader<T> = chain_directory::DirReader<Chain<T>>;
 
 pub struct ServerLog<T: Send + Sync + Copy, ToWorkers>
 where ToWorkers: DistributeToWorkers<T> {
@@ -80,6 +83,8 @@ where ToWorkers: DistributeToWorkers<T> {
     //TODO per chain locks...
     total_servers: u32,
     this_server_num: u32,
+    shards: ShardMap,
+    shard: usize,
     // seen_ids: hash::UuidHashSet,
     pub to_workers: ToWorkers, //spmc::Sender<ToWorker<T>>,
     _pd: PhantomData<T>,
@@ -88,8 +93,7 @@ where ToWorkers: DistributeToWorkers<T> {
 }
 
 pub fn new_chain_store_and_reader<T: Copy>() -> (ChainStore<T>, ChainReader<T>) {
-    let (read, write) = ::evmap::new();
-    (write, read)
+    chain_directory::new()
 }
 
 counters! {
@@ -116,8 +120,8 @@ impl<T: Send + Sync> DistributeToWorkers<T> for spmc::Sender<ToWorker<T>> {
     }
 }
 
-impl DistributeToWorkers<()> for VecDeque<ToWorker<()>> {
-    fn send_to_worker(&mut self, msg: ToWorker<()>) {
+impl<T: Send + Sync> DistributeToWorkers<T> for VecDeque<ToWorker<T>> {
+    fn send_to_worker(&mut self, msg: ToWorker<T>) {
         self.push_front(msg);
     }
 }
@@ -173,6 +177,9 @@ pub enum ToWorker<T: Send + Sync> {
 
     SingleServerSkeens1(SkeensMultiStorage, T),
 
+    // a shard is done with round 1 of an append spanning shards
+    ShardSkeens1(Arc<ShardedAppend>, T),
+
     //FIXME this is getting too big...
     //This is not racey b/c both this and DelayedSingle single get sent to the same thread
     SingleSkeens {
@@ -330,7 +337,7 @@ where T: Send + Sync {
             | &mut ContinueRecovery(_, ref mut t)
             | &mut EndRecovery(_, ref mut t) => f(t),
 
-            &mut SingleServerSkeens1(_, ref mut t) => f(t),
+            &mut SingleServerSkeens1(_, ref mut t) | &mut ShardSkeens1(_, ref mut t) => f(t),
 
             &mut ReturnBuffer(_, ref mut t) | &mut MultiFastPath(_, _, ref mut t) => f(t),
         }
@@ -350,7 +357,7 @@ where T: Copy + Send + Sync {
             &SingleSkeens {t, ..} | &DelayedSingle {t, .. } => t,
             &Skeens1SingleReplica {t, ..} => t,
             &ReturnBuffer(_, t) => t,
-            &SingleServerSkeens1(_, t) => t,
+            &SingleServerSkeens1(_, t) | &ShardSkeens1(_, t) => t,
             &SnapshotSkeens1{t, ..} | &SnapSkeensFinished{t, ..} => t,
 
             &Skeens1Replica {t, ..}
@@ -388,6 +395,8 @@ pub enum ToReplicate {
     GC(BufferSlice),
 
     TasRecoverer(BufferSlice, Box<(Uuid, Box<[OrderIndex]>)>),
+
+    CheckSkeens1(BufferSlice),
 }
 
 pub enum Recovery {
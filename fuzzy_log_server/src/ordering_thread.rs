@@ -87,11 +87,20 @@ impl<T: Copy> Chain<T> {
         &mut self, id: Uuid, max_timestamp: u64, chain: order, mut on_finish: F)
     where F: FnMut(FinishSkeens<T>) { //Ret val?
         use self::FinishSkeens::*;
-        let r = self.skeens.set_max_timestamp(id, max_timestamp);
+        //a max timestamp of 0 is the writer giving up on the append
+        let r = if max_timestamp == 0 {
+            self.skeens.cancel_multi_append(id)
+        } else {
+            self.skeens.set_max_timestamp(id, max_timestamp)
+        };
         match r {
             SkeensSetMaxRes::Ok => trace!("multi with ts {:?} must wait", max_timestamp),
-            SkeensSetMaxRes::Duplicate(_ts) => unimplemented!(),
-            SkeensSetMaxRes::NotWaiting => unimplemented!(),
+            //a client recovering an abandoned append may send phase 2
+            //to a chain which already got it, so these are harmless
+            SkeensSetMaxRes::Duplicate(ts) =>
+                trace!("duplicate skeens2 for {:?} already has {:?}", id, ts),
+            SkeensSetMaxRes::NotWaiting =>
+                trace!("skeens2 for {:?} which is not waiting", id),
             SkeensSetMaxRes::NeedsFlush => {
                 trace!("multi flush due to {:?}", max_timestamp);
                 let trie = &mut self.trie;
@@ -158,7 +167,7 @@ fn horizon_or_add_blank(trie: &mut Trie, chain: order) -> u64 {
 
 //SAFETY: log is single writer and Chain refs never escape this thread
 fn ensure_chain<T: Copy>(log: &mut ChainStore<T>, chain: order) -> &mut Chain<T> {
-    let c = log.get_and(&chain, |chains| unsafe { &mut *UnsafeCell::get(&chains[0]) });
+    let c = log.get_and(&chain, |c| unsafe { &mut *UnsafeCell::get(c) });
     if let Some(chain) = c {
         return chain
     }
@@ -168,18 +177,47 @@ fn ensure_chain<T: Copy>(log: &mut ChainStore<T>, chain: order) -> &mut Chain<T>
     unsafe {
         t.partial_append(1).write_byte(mem::transmute(EntryKind::Read));
     };
-    let contents = TrivialEqArc::new(Chain{ trie: t, skeens: SkeensState::new()});
-    log.insert(chain, contents);
-    log.refresh();
+    log.insert(chain, Chain{ trie: t, skeens: SkeensState::new()});
     get_chain_mut(log, chain).unwrap()
 }
 
+// chains looked up so far in the current batch
+type ChainCache<T> = hash::HashMap<order, *mut Chain<T>>;
+
+//SAFETY: as ensure_chain, in addition chains are never removed from the store
+//        and each has its own allocation, so the pointers stay valid across inserts
+fn cached_chain<'l, T: Copy>(log: &'l mut ChainStore<T>, cache: &mut ChainCache<T>, chain: order)
+-> &'l mut Chain<T> {
+    if let Some(&c) = cache.get(&chain) {
+        return unsafe { &mut *c }
+    }
+    let c = ensure_chain(log, chain) as *mut _;
+    cache.insert(chain, c);
+    unsafe { &mut *c }
+}
+
 fn get_chain_mut<T: Copy>(log: &mut ChainStore<T>, chain: order) -> Option<&mut Chain<T>> {
-    log.get_and(&chain, |chains| unsafe { &mut *UnsafeCell::get(&chains[0]) })
+    log.get_and(&chain, |c| unsafe { &mut *UnsafeCell::get(c) })
 }
 
 fn get_chain<T: Copy>(log: &ChainStore<T>, chain: order) -> Option<&Chain<T>> {
-    log.get_and(&chain, |chains| unsafe { &*UnsafeCell::get(&chains[0]) })
+    log.get_and(&chain, |c| unsafe { &*UnsafeCell::get(c) })
+}
+
+fn for_each_chain<T: Copy, F>(log: &ChainStore<T>, mut f: F)
+where F: FnMut(order, &Chain<T>) {
+    log.for_each(|&chain, c| f(chain, unsafe { &*UnsafeCell::get(c) }))
+}
+
+fn chain_info<T: Copy>(chain: order, c: &Chain<T>) -> admin::ChainInfo {
+    let bounds = c.trie.bounds();
+    admin::ChainInfo {
+        chain,
+        min_entry: bounds.start,
+        next_entry: bounds.end,
+        phase1: c.skeens.waiting_for_max().len() as u64,
+        waiting_for_flush: c.skeens.num_waiting_for_flush() as u64,
+    }
 }
 
 enum FinishSkeens<T> {
@@ -196,17 +234,34 @@ where ToWorkers: DistributeToWorkers<T> {
         total_servers: u32,
         to_workers: ToWorkers,
         chains: ChainStore<T>,
+    ) -> Self {
+        let shards = ShardMap::new(this_server_num, total_servers, 1);
+        Self::new_shard(this_server_num, total_servers, shards, 0, to_workers, chains)
+    }
+
+    /// The ordering thread for the chains in `shard`,
+    /// `chains` must only be used by this shard.
+    pub fn new_shard(
+        this_server_num: u32,
+        total_servers: u32,
+        shards: ShardMap,
+        shard: usize,
+        to_workers: ToWorkers,
+        chains: ChainStore<T>,
     ) -> Self {
         //TODO
         //assert!(this_server_num > 0);
         assert!(this_server_num <= total_servers,
             "this_server_num <= total_servers, {:?} <= {:?}",
             this_server_num, total_servers);
+        assert!(shard < shards.num_shards());
         ServerLog {
             log: chains,
             // seen_ids: Default::default(),
             this_server_num: this_server_num,
             total_servers: total_servers,
+            shards: shards,
+            shard: shard,
             to_workers: to_workers,
             _pd: PhantomData,
             print_data: Default::default(),
@@ -215,7 +270,7 @@ where ToWorkers: DistributeToWorkers<T> {
 
     #[cfg(feature = "print_stats")]
     pub fn print_stats(&self) {
-        println!("{:?}, {:?}", self.print_data, self.this_server_num);
+        println!("{:?}, {:?}, {:?}", self.print_data, self.this_server_num, self.shard);
     }
 
     //FIXME pass buffer-slice so we can read batches
@@ -357,6 +412,130 @@ where ToWorkers: DistributeToWorkers<T> {
 
     /////////////////////////////////////////////////
 
+    /// Handles a batch of requests in the order they were received.
+    /// Singles and single-server multiappends which do not have to wait on a
+    /// skeens round are written directly, with each chain looked up once for
+    /// the whole batch; everything else goes through `handle_op`.
+    pub fn handle_ops<I>(&mut self, ops: I)
+    where I: IntoIterator<Item=(BufferSlice, Troption<SkeensMultiStorage, Box<(RcSlice, RcSlice)>>, T)> {
+        let mut chains = ChainCache::default();
+        for (mut buffer, storage, t) in ops {
+            if !self.can_take_fast_path(&buffer, &mut chains) {
+                self.handle_op(buffer, storage, t);
+                continue
+            }
+
+            self.print_data.msgs_recvd(1);
+            let (layout, flag, chain) = {
+                let c = buffer.contents();
+                (c.kind().layout(), *c.flag(), c.locs()[0].0)
+            };
+            if layout == EntryLayout::Data {
+                trace!("SERVER {:?} Batched Single Append", self.this_server_num);
+                let server_num = self.this_server_num;
+                let slot = unsafe {
+                    cached_chain(&mut self.log, &mut chains, chain)
+                        .append_data(server_num, chain, &mut buffer)
+                        .extend_lifetime()
+                };
+                self.print_data.msgs_sent(1);
+                self.to_workers.send_to_worker(Write(buffer, slot, t))
+            } else {
+                trace!("SERVER {:?} Batched Multi Append", self.this_server_num);
+                let storage = storage.unwrap_left();
+                self.single_server_single_append_fast_path(flag, buffer, storage, &mut chains, t)
+            }
+        }
+    }
+
+    fn can_take_fast_path(&mut self, buffer: &BufferSlice, chains: &mut ChainCache<T>) -> bool {
+        let contents = buffer.contents();
+        let flag = *contents.flag();
+        if flag.contains(EntryFlag::DirectWrite) {
+            return false
+        }
+        match contents.kind().layout() {
+            EntryLayout::Data => {
+                let chain = cached_chain(&mut self.log, chains, contents.locs()[0].0);
+                !chain.skeens.need_single_at(contents.lock_num())
+            },
+            EntryLayout::Multiput | EntryLayout::Sentinel
+            if !flag.contains(EntryFlag::TakeLock) => {
+                for &OrderIndex(c, _) in contents.locs() {
+                    if c == order::from(0) {
+                        continue
+                    }
+                    if !self.stores_chain(c) {
+                        return false
+                    }
+                    let chain = cached_chain(&mut self.log, chains, c);
+                    if chain.needs_skeens_single() || chain.trie.is_locked() {
+                        return false
+                    }
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /////////////////////////////////////////////////
+
+    /// Round 1 of skeens for the chains in this shard of an append spanning
+    /// shards. The worker is told once the local timestamps are in the storage.
+    pub fn handle_shard_skeens1(&mut self, append: Arc<ShardedAppend>, t: T) {
+        self.print_data.msgs_recvd(1);
+        trace!("SERVER {:?} shard {:?} Round 1 {:?}", self.this_server_num, self.shard, append.id());
+        {
+            let storage = append.storage();
+            let id = *append.id();
+            let locs = append.locs();
+            let timestamps = &mut unsafe { storage.get_mut().0 }[..locs.len()];
+            let queue_indicies = &mut unsafe { storage.get_mut().1 }[..locs.len()];
+            let mut is_sentinel = false;
+            for i in 0..locs.len() {
+                let chain = locs[i].0;
+                if chain == order::from(0) {
+                    is_sentinel = true;
+                    continue
+                }
+                if !self.stores_chain(chain) {
+                    continue
+                }
+
+                let chain = self.ensure_chain(chain);
+                let (local_timestamp, num) = if append.is_snapshot() {
+                    chain.timestamp_for_snap(id, storage.clone(), t)
+                } else {
+                    let is_sentinel = append.distinguish_sentinels() && is_sentinel;
+                    chain.timestamp_for_multi(id, storage.clone(), is_sentinel, t)
+                }.unwrap();
+                timestamps[i] = local_timestamp;
+                queue_indicies[i] = num;
+            }
+        }
+        self.print_data.msgs_sent(1);
+        self.to_workers.send_to_worker(ShardSkeens1(append, t))
+    }
+
+    /// Round 2 of skeens for the chains in this shard of an append spanning
+    /// shards, once every shard has done round 1.
+    pub fn handle_shard_skeens2(&mut self, append: Arc<ShardedAppend>) {
+        self.print_data.msgs_recvd(1);
+        let id = *append.id();
+        let max_timestamp = append.max_timestamp();
+        trace!("SERVER {:?} shard {:?} Round 2 {:?} mts {:?}",
+            self.this_server_num, self.shard, id, max_timestamp);
+        for &OrderIndex(chain, _) in append.locs() {
+            if chain == order::from(0) || !self.stores_chain(chain) {
+                continue
+            }
+            self.finish_multi_on(id, max_timestamp, chain);
+        }
+    }
+
+    /////////////////////////////////////////////////
+
     fn handle_snapshot(
         &mut self,
         kind: EntryFlag::Flag,
@@ -488,12 +667,12 @@ where ToWorkers: DistributeToWorkers<T> {
         let (mut needs_lock, mut needs_skeens) = (false, false);
         {
             for &OrderIndex(c, _) in buffer.contents().locs() {
-                assert!(self.stores_chain(c));
                 //TODO
                 // assert!(c != order::from(0), "{:?}", buffer.contents().locs());
                 if c == order::from(0) {
                     continue
                 }
+                assert!(self.stores_chain(c));
 
                 let chain =  self.ensure_chain(c);
                 needs_skeens |= chain.needs_skeens_single();
@@ -503,7 +682,8 @@ where ToWorkers: DistributeToWorkers<T> {
         debug_assert!(!(needs_lock && needs_skeens));
         if !needs_lock && !needs_skeens {
             let storage = storage.unwrap_left();
-            self.single_server_single_append_fast_path(kind, buffer, storage, t)
+            let mut chains = ChainCache::default();
+            self.single_server_single_append_fast_path(kind, buffer, storage, &mut chains, t)
         } else {
             let storage = storage.unwrap_left();
             //FIXME this doesn't work, causes packet corruption?
@@ -519,6 +699,7 @@ where ToWorkers: DistributeToWorkers<T> {
         kind: EntryFlag::Flag,
         mut buffer: BufferSlice,
         storage: SkeensMultiStorage,
+        chains: &mut ChainCache<T>,
         t: T
     ) {
         unsafe {
@@ -540,11 +721,12 @@ where ToWorkers: DistributeToWorkers<T> {
 
                 if !is_sentinel {
                     let (index, ptr) =
-                        self.ensure_chain(*o).trie.prep_append(ValEdge::null());
+                        cached_chain(&mut self.log, chains, *o).trie.prep_append(ValEdge::null());
                     *i =  entry::from(index as u64);
                     pointers[j] = ptr;
                 } else {
-                    let horizon = horizon_or_add_blank(&mut self.ensure_chain(*o).trie, *o);
+                    let horizon =
+                        horizon_or_add_blank(&mut cached_chain(&mut self.log, chains, *o).trie, *o);
                     *i = entry::from(horizon as u64);
                 }
             }
@@ -619,9 +801,15 @@ where ToWorkers: DistributeToWorkers<T> {
         trace!("SERVER {:?} new-style multiput {:?}", self.this_server_num, kind);
         assert!(kind.contains(EntryFlag::TakeLock));
         if kind.contains(EntryFlag::Unlock) {
+            let is_cancel = buffer.contents().is_multi_cancel();
             self.new_multiappend_round2(kind, &mut buffer);
             self.print_data.msgs_sent(1);
-            self.to_workers.send_to_worker(ReturnBuffer(buffer, t))
+            if is_cancel {
+                //the replicas need to drop the append too
+                self.to_workers.send_to_worker(Reply(buffer, t))
+            } else {
+                self.to_workers.send_to_worker(ReturnBuffer(buffer, t))
+            }
         } else {
             let storage = storage.unwrap_left();
             self.new_multiappend_round1(kind, &mut buffer, &storage, false, t);
@@ -694,63 +882,72 @@ where ToWorkers: DistributeToWorkers<T> {
                 continue
             }
 
-            //let chain = self.ensure_trie(chain);
-            let chain = get_chain_mut(&mut self.log, chain_num)
-                .expect("cannot have skeens-2 as the first op on a chain");
-                /*.or_insert_with(|| {
-                let mut t = Trie::new();
-                t.append(&EntryContents::Data(&(), &[]).clone_entry());
-                Chain{ trie: t, skeens: SkeensState::new() }
-            });*/
-            let to_workers = &mut self.to_workers;
-            let print_data = &mut self.print_data;
-            chain.finish_multi(id, max_timestamp, chain_num,
-                |finished| match finished {
-                    FinishSkeens::Multi(index, trie_slot, storage, timestamp, t) => {
-                        trace!("server finish sk multi");
-                        print_data.msgs_sent(1);
-                        to_workers.send_to_worker(
-                            SkeensFinished {
-                                loc: OrderIndex(chain_num, (index as u64).into()),
-                                trie_slot: trie_slot,
-                                storage: storage,
-                                timestamp: timestamp,
-                                t: t
-                            }
-                        )
-                    },
-
-                    FinishSkeens::Snap(index, storage, timestamp, t) => {
-                        trace!("server finish sk snap");
-                        print_data.msgs_sent(1);
-                        to_workers.send_to_worker(
-                            SnapSkeensFinished {
-                                loc: OrderIndex(chain_num, (index as u64).into()),
-                                storage,
-                                timestamp,
-                                t,
-                            }
-                        )
-                    },
-
-                    FinishSkeens::Single(index, trie_slot, storage, timestamp, t) => {
-                        trace!("server finish sk single");
-                        print_data.msgs_sent(1);
-                        to_workers.send_to_worker(
-                            DelayedSingle {
-                                index: index,
-                                trie_slot: trie_slot,
-                                storage: storage,
-                                timestamp,
-                                t: t
-                            }
-                        )
-                    },
-                }
-            );
+            self.finish_multi_on(id, max_timestamp, chain_num);
         }
     }
 
+    // flush whatever `chain_num` can now that `id` has its max timestamp
+    fn finish_multi_on(&mut self, id: Uuid, max_timestamp: u64, chain_num: order) {
+        //let chain = self.ensure_trie(chain);
+        let chain = match get_chain_mut(&mut self.log, chain_num) {
+            Some(chain) => chain,
+            //a cancel may be for an append this chain never got
+            None if max_timestamp == 0 => return,
+            None => panic!("cannot have skeens-2 as the first op on a chain"),
+        };
+            /*.or_insert_with(|| {
+            let mut t = Trie::new();
+            t.append(&EntryContents::Data(&(), &[]).clone_entry());
+            Chain{ trie: t, skeens: SkeensState::new() }
+        });*/
+        let to_workers = &mut self.to_workers;
+        let print_data = &mut self.print_data;
+        chain.finish_multi(id, max_timestamp, chain_num,
+            |finished| match finished {
+                FinishSkeens::Multi(index, trie_slot, storage, timestamp, t) => {
+                    trace!("server finish sk multi");
+                    print_data.msgs_sent(1);
+                    to_workers.send_to_worker(
+                        SkeensFinished {
+                            loc: OrderIndex(chain_num, (index as u64).into()),
+                            trie_slot: trie_slot,
+                            storage: storage,
+                            timestamp: timestamp,
+                            t: t
+                        }
+                    )
+                },
+
+                FinishSkeens::Snap(index, storage, timestamp, t) => {
+                    trace!("server finish sk snap");
+                    print_data.msgs_sent(1);
+                    to_workers.send_to_worker(
+                        SnapSkeensFinished {
+                            loc: OrderIndex(chain_num, (index as u64).into()),
+                            storage,
+                            timestamp,
+                            t,
+                        }
+                    )
+                },
+
+                FinishSkeens::Single(index, trie_slot, storage, timestamp, t) => {
+                    trace!("server finish sk single");
+                    print_data.msgs_sent(1);
+                    to_workers.send_to_worker(
+                        DelayedSingle {
+                            index: index,
+                            trie_slot: trie_slot,
+                            storage: storage,
+                            timestamp,
+                            t: t
+                        }
+                    )
+                },
+            }
+        );
+    }
+
     //////////////////////
 
     fn handle_old_multiappend(
@@ -976,7 +1173,7 @@ where ToWorkers: DistributeToWorkers<T> {
     /////////////////////////////////////////////////
 
     fn stores_chain(&self, chain: order) -> bool {
-        chain % u64::from(self.total_servers) == u64::from(self.this_server_num).into()
+        self.shards.on_this_server(chain) && self.shards.shard_of(chain) == self.shard
     }
 
     //Safety: since this thread is the only one that mutates the map,
@@ -1122,7 +1319,9 @@ where ToWorkers: DistributeToWorkers<T> {
                 let max_timestamp = buffer.contents().lock_num();
                 trace!("SERVER {:?} replicate skeens2 max {:?}, {:?}",
                     self.this_server_num, max_timestamp, id);
-                assert!(max_timestamp > 0, "SERVER {}: replicate 0 max ts {:#?}", self.this_server_num, buffer.contents());
+                let is_cancel = buffer.contents().is_multi_cancel();
+                assert!(max_timestamp > 0 || is_cancel,
+                    "SERVER {}: replicate 0 max ts {:#?}", self.this_server_num, buffer.contents());
                 'sk2_rep: for &OrderIndex(o, i) in buffer.contents().locs() {
                     if o == order::from(0) || !self.stores_chain(o) { continue 'sk2_rep }
                     //let c = self.ensure_chain(chain);
@@ -1131,7 +1330,7 @@ where ToWorkers: DistributeToWorkers<T> {
                     let print_data = &mut self.print_data;
                     let index = u64::from(i);
                     let trie = &mut c.trie;
-                    c.skeens.replicate_round2(&id, max_timestamp, index, |rep| match rep {
+                    let on_replicated = |rep: ReplicatedSkeens<T>| match rep {
                         Multi{index, storage, max_timestamp, t} => {
                             trace!("SERVER finish sk multi rep ({:?}, {:?}, {})", o, index, max_timestamp);
                             let slot = unsafe { trie.prep_append_at(index) };
@@ -1187,10 +1386,19 @@ where ToWorkers: DistributeToWorkers<T> {
                                 }
                             )
                         }
-                    });
+                    };
+                    if is_cancel {
+                        c.skeens.cancel_replica(&id, on_replicated)
+                    } else {
+                        c.skeens.replicate_round2(&id, max_timestamp, index, on_replicated)
+                    }
                 }
                 trace!("SRVER {:?} skeens2 over", self.this_server_num);
-                self.to_workers.send_to_worker(ReturnBuffer(buffer, t))
+                if is_cancel {
+                    self.to_workers.send_to_worker(Reply(buffer, t))
+                } else {
+                    self.to_workers.send_to_worker(ReturnBuffer(buffer, t))
+                }
             }
 
             ToReplicate::Multi(buffer, mut storage) => {
@@ -1302,7 +1510,14 @@ where ToWorkers: DistributeToWorkers<T> {
             },
 
             ToReplicate::TasRecoverer(buffer, recoverer) => {
-                let (&write_id, _) = buffer.contents().write_id_and_old_recoverer();
+                let (&write_id, &holder) = buffer.contents().write_id_and_old_recoverer();
+                //the head refused the recoverer, pass its answer along
+                if !buffer.contents().flag().contains(EntryFlag::ReadSuccess) {
+                    self.print_data.msgs_sent(1);
+                    return self.to_workers.send_to_worker(
+                        ToWorker::DidntGetRecovery(buffer, holder, t)
+                    )
+                }
                 let index = buffer.contents().lock_num();
                 let chain = recoverer.1[0].0;
                 let res = self.ensure_chain(chain).skeens.replicate_recoverer(
@@ -1314,6 +1529,24 @@ where ToWorkers: DistributeToWorkers<T> {
                     Err(id) => ToWorker::DidntGetRecovery(buffer, id.unwrap_or_else(Uuid::nil), t),
                 })
             },
+
+            ToReplicate::CheckSkeens1(buffer) => {
+                //only the head checks, replicas pass on its answer,
+                //fencing off the append if the head said it never got here
+                let (still_there, id, OrderIndex(chain, time)) = {
+                    let c = buffer.contents();
+                    (c.flag().contains(EntryFlag::ReadSuccess), *c.id(), c.locs()[0])
+                };
+                if !still_there && id != Uuid::nil() && u64::from(time) == 0 {
+                    self.ensure_chain(chain).skeens.fence(id)
+                }
+                self.print_data.msgs_sent(1);
+                self.to_workers.send_to_worker(if still_there {
+                    ToWorker::ContinueRecovery(buffer, t)
+                } else {
+                    ToWorker::EndRecovery(buffer, t)
+                })
+            },
         }
     }
 
@@ -1325,8 +1558,7 @@ where ToWorkers: DistributeToWorkers<T> {
                 let i = u64::from(i);
                 get_chain_mut(&mut self.log, o).map(|c| c.trie.set_min(i));
             }
-            self.log.set_meta(());
-            self.log.refresh();
+            self.log.synchronize();
             for &OrderIndex(o, _) in locs {
                 get_chain_mut(&mut self.log, o).map(|c| c.trie.delete_free());
             }
@@ -1358,15 +1590,32 @@ where ToWorkers: DistributeToWorkers<T> {
                 })
             },
 
-            Recovery::CheckSkeens1(buffer) => {
+            Recovery::CheckSkeens1(mut buffer) => {
                 let (id, OrderIndex(chain, time)) = {
                     let c = buffer.contents();
                     (*c.id(), c.locs()[0])
                 };
                 let time = u64::from(time);
-                let still_there = get_chain(&self.log, chain)
-                    .map(|c| c.skeens.check_skeens1(id, time))
-                    .unwrap_or(false);
+                if id == Uuid::nil() {
+                    return self.report_blocker(buffer, chain, t)
+                }
+                let still_there = if time == 0 {
+                    //A recoverer which doesn't know the timestamp yet is asking for it,
+                    //we answer with the phase 1 timestamp if the append still needs a max,
+                    //otherwise with the max timestamp, or 0 if the append never got here,
+                    //in which case it is fenced off so it never will
+                    let status = self.ensure_chain(chain).skeens.recovery_status(id);
+                    let (still_there, time) = match status {
+                        Ok(ts) => (true, ts),
+                        Err(max) => (false, max.unwrap_or(0)),
+                    };
+                    buffer.contents_mut().locs_mut()[0].1 = entry::from(time);
+                    still_there
+                } else {
+                    get_chain(&self.log, chain)
+                        .map(|c| c.skeens.check_skeens1(id, time))
+                        .unwrap_or(false)
+                };
                 self.to_workers.send_to_worker(if still_there {
                     ToWorker::ContinueRecovery(buffer, t)
                 } else {
@@ -1375,4 +1624,82 @@ where ToWorkers: DistributeToWorkers<T> {
             },
         }
     }
+
+    //A client whose appends have stalled is asking what is holding up `chain`,
+    //if it is a multiappend still in phase 1 we answer with an UpdateRecovery
+    //naming it, its phase 1 timestamp, and the chains it spans,
+    //so the client can gather the rest of its timestamps and finish it
+    fn report_blocker(&mut self, mut buffer: BufferSlice, chain: order, t: T) {
+        let report = get_chain(&self.log, chain)
+            .and_then(|c| c.skeens.blocker())
+            .map(|(id, timestamp, storage)| {
+                let st0 = unsafe { storage.get().2 };
+                let locs: Vec<_> = bytes_as_entry(&*st0).locs().iter()
+                    .map(|&OrderIndex(o, _)| OrderIndex(o, entry::from(0)))
+                    .collect();
+                EntryContents::UpdateRecovery {
+                    old_recoverer: &Uuid::nil(),
+                    write_id: &id,
+                    flags: &(EntryFlag::ReadSuccess | EntryFlag::Skeens1Queued),
+                    lock: &timestamp,
+                    locs: &locs,
+                }.to_vec()
+            });
+        self.print_data.msgs_sent(1);
+        match report {
+            Some(report) => {
+                trace!("SERVER {:?} reporting blocker of {:?}", self.this_server_num, chain);
+                buffer.fill_from_entry_contents(bytes_as_entry(&report));
+                self.to_workers.send_to_worker(ToWorker::ContinueRecovery(buffer, t))
+            },
+            None => self.to_workers.send_to_worker(ToWorker::EndRecovery(buffer, t)),
+        }
+    }
+
+    //NOTE this runs on the ordering thread so the skeens state is never read concurrently
+    pub fn handle_admin(&self, request: admin::Request) -> admin::Response {
+        use std::cmp::{max, min};
+        use admin::{Request, Response, StuckAppend, MAX_DUMP_ENTRIES};
+
+        match request {
+            Request::ListChains => {
+                let mut chains = vec![];
+                for_each_chain(&self.log, |chain, c| chains.push(chain_info(chain, c)));
+                chains.sort_by_key(|c| c.chain);
+                Response::Chains(chains)
+            },
+
+            Request::ChainInfo(chain) => Response::Chains(
+                get_chain(&self.log, chain).map(|c| chain_info(chain, c)).into_iter().collect()
+            ),
+
+            Request::DumpChain{chain, start, end} => match get_chain(&self.log, chain) {
+                None => Response::Error(format!("chain {:?} is not on this server", chain)),
+                Some(c) => {
+                    let bounds = c.trie.bounds();
+                    let start = max(u64::from(start), max(bounds.start, 1));
+                    let end = min(u64::from(end), bounds.end);
+                    let end = min(end, start.saturating_add(MAX_DUMP_ENTRIES));
+                    let entries = (start..end).filter_map(|i|
+                        c.trie.atomic_get(i).map(|e| (i.into(), e.bytes().to_vec()))
+                    ).collect();
+                    Response::Entries(entries)
+                },
+            },
+
+            Request::StuckAppends => {
+                let mut stuck = vec![];
+                for_each_chain(&self.log, |chain, c| {
+                    let waiting = c.skeens.waiting_for_max().into_iter()
+                        .map(|(id, timestamp)| StuckAppend{chain, id, timestamp});
+                    stuck.extend(waiting)
+                });
+                stuck.sort_by_key(|s| (s.timestamp, s.chain));
+                Response::Stuck(stuck)
+            },
+
+            Request::Clients =>
+                Response::Error("clients are tracked by the acceptor, not the log".to_string()),
+        }
+    }
 }
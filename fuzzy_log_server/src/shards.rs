@@ -0,0 +1,185 @@
+//! Splitting a server's chains between several ordering threads.
+//!
+//! Each ordering thread, or shard, owns a disjoint set of the chains stored on
+//! the server, along with their tries and skeens queues, so appends to
+//! chains in different shards are ordered in parallel.
+//! Appends which touch more than one shard are ordered with the same two
+//! rounds of skeens used for appends spanning servers, except that the worker
+//! which received the append collects the round 1 timestamps instead of the
+//! client: every involved shard assigns local timestamps to its chains, and
+//! once the last one is done the worker either starts round 2 in each shard
+//! with the max timestamp, or, for appends the client is already running
+//! skeens for, replies with the timestamps as if from a single ordering thread.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use buffer::Buffer;
+use packets::{order, EntryFlag, OrderIndex, Uuid};
+
+use SkeensMultiStorage;
+
+/// Which shard owns each chain stored on a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardMap {
+    this_server: u32,
+    total_servers: u32,
+    num_shards: u32,
+}
+
+impl ShardMap {
+    pub fn new(this_server: u32, total_servers: u32, num_shards: u32) -> Self {
+        assert!(num_shards > 0, "a server needs at least one shard");
+        ShardMap { this_server, total_servers, num_shards }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.num_shards as usize
+    }
+
+    /// Is `chain` stored on this server at all?
+    pub fn on_this_server(&self, chain: order) -> bool {
+        chain % u64::from(self.total_servers) == u64::from(self.this_server).into()
+    }
+
+    pub fn shard_of(&self, chain: order) -> usize {
+        let chain: u64 = chain.into();
+        ((chain / u64::from(self.total_servers)) % u64::from(self.num_shards)) as usize
+    }
+
+    /// The shards which own a chain in `locs`, in order.
+    pub fn shards_of(&self, locs: &[OrderIndex]) -> Vec<usize> {
+        let mut shards: Vec<_> = locs.iter()
+            .filter(|&&OrderIndex(chain, _)|
+                chain != order::from(0) && self.on_this_server(chain))
+            .map(|&OrderIndex(chain, _)| self.shard_of(chain))
+            .collect();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+    }
+}
+
+/// A multiappend or snapshot being ordered by more than one shard.
+pub struct ShardedAppend {
+    id: Uuid,
+    flag: EntryFlag::Flag,
+    is_snapshot: bool,
+    locs: Box<[OrderIndex]>,
+    shards: Box<[usize]>,
+    round1_left: AtomicUsize,
+    state: Mutex<ShardedState>,
+}
+
+struct ShardedState {
+    storage: Option<SkeensMultiStorage>,
+    // only given back for appends spanning servers, which reply after round 1,
+    // the rest only need it if the append is refused before any shard gets it
+    buffer: Option<Buffer>,
+    max_timestamp: u64,
+}
+
+impl ShardedAppend {
+    pub fn new(
+        buffer: Buffer,
+        storage: SkeensMultiStorage,
+        shards: Vec<usize>
+    ) -> Self {
+        let (id, flag, is_snapshot, locs) = {
+            let e = buffer.contents();
+            (*e.id(), *e.flag(), e.kind().layout() == ::packets::EntryLayout::Snapshot,
+                e.locs().to_vec().into_boxed_slice())
+        };
+        let buffer = Some(buffer);
+        ShardedAppend {
+            id,
+            flag,
+            is_snapshot,
+            locs,
+            round1_left: AtomicUsize::new(shards.len()),
+            shards: shards.into_boxed_slice(),
+            state: Mutex::new(ShardedState { storage: Some(storage), buffer, max_timestamp: 0 }),
+        }
+    }
+
+    pub fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    pub fn locs(&self) -> &[OrderIndex] {
+        &self.locs
+    }
+
+    pub fn shards(&self) -> &[usize] {
+        &self.shards
+    }
+
+    pub fn is_snapshot(&self) -> bool {
+        self.is_snapshot
+    }
+
+    /// Does the client run skeens for this append itself?
+    pub fn spans_servers(&self) -> bool {
+        self.flag.contains(EntryFlag::TakeLock)
+    }
+
+    /// Single-server multiappends use the same sentinel handling as
+    /// `single_server_local_skeens`.
+    pub fn distinguish_sentinels(&self) -> bool {
+        !self.spans_servers()
+    }
+
+    pub fn storage(&self) -> SkeensMultiStorage {
+        self.state.lock().unwrap().storage.clone().expect("round 1 already finished")
+    }
+
+    /// Called once a shard is done with round 1,
+    /// returns `true` for the last one.
+    pub fn finish_round1(&self) -> bool {
+        self.round1_left.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    /// Once round 1 is done, give up the storage so the entry can be completed
+    /// as soon as the chains are done with it, along with the buffer if the
+    /// append spans servers.
+    /// Records the max timestamp for round 2.
+    pub fn take_storage(&self) -> (SkeensMultiStorage, Option<Buffer>) {
+        let mut state = self.state.lock().unwrap();
+        let storage = state.storage.take().expect("round 1 finished twice");
+        state.max_timestamp = unsafe { storage.get().0.iter().cloned().max().unwrap_or(0) };
+        let buffer = state.buffer.take();
+        (storage, if self.spans_servers() { buffer } else { None })
+    }
+
+    /// The append as the client sent it, for refusing it before any shard has it.
+    pub fn into_buffer(self) -> Buffer {
+        self.state.into_inner().unwrap().buffer.expect("round 1 already finished")
+    }
+
+    pub fn max_timestamp(&self) -> u64 {
+        self.state.lock().unwrap().max_timestamp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shard_of() {
+        let shards = ShardMap::new(1, 2, 3);
+        assert!(shards.on_this_server(3.into()) && !shards.on_this_server(4.into()));
+        assert_eq!(shards.shard_of(1.into()), 0);
+        assert_eq!(shards.shard_of(3.into()), 1);
+        assert_eq!(shards.shard_of(5.into()), 2);
+        assert_eq!(shards.shard_of(7.into()), 0);
+        let locs = [
+            OrderIndex(5.into(), 0.into()),
+            OrderIndex(0.into(), 0.into()),
+            OrderIndex(4.into(), 0.into()),
+            OrderIndex(7.into(), 0.into()),
+            OrderIndex(1.into(), 0.into()),
+        ];
+        assert_eq!(shards.shards_of(&locs), vec![0, 2]);
+    }
+}
@@ -0,0 +1,870 @@
+//! A deterministic simulation of the ordering and replication protocol.
+//!
+//! The simulator runs real `ServerLog`s, with the same worker code the TCP
+//! server uses, connected to simulated clients by an in-memory network.
+//! Every choice, which operation a client runs next, how long a message is
+//! delayed, whether it is dropped, when a node crashes, comes from a single
+//! seeded RNG, so any run can be replayed from its `Config` alone.
+//!
+//! Clients speak the same protocol as `AsyncTcpStore`: single appends go
+//! straight to the chain's server, multiappends touching one server take the
+//! fast path, and multiappends spanning servers run both rounds of skeens
+//! from the client. Reads go to the tail replica.
+//!
+//! Once the network is quiet the logs stored on every replica are read back
+//! and checked for
+//!  - duplicate or misplaced entries,
+//!  - multiappends whose locations on one server disagree,
+//!  - acknowledged appends missing from the locations they were acked at,
+//!  - chains which order their common multiappends differently,
+//!  - replicas which disagree with their upstream,
+//!  - reads which returned something other than what ended up in the log,
+//!  - and, if no faults were injected, operations which never finished.
+//!
+//! Messages on a link are delivered in the order they were sent, as TCP
+//! would; delays only reorder messages on different links.
+//! Only links to and from clients drop messages, a lost message between
+//! replicas would have closed the connection, crash the replica instead.
+//! Crashed nodes never recover, recovery is not simulated.
+
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use buffer::Buffer;
+use hash::{HashMap, HashSet};
+use packets::*;
+use worker_thread::{self, handle_to_worker2, ToSend};
+
+use {new_chain_store_and_reader, ServerLog, ToWorker};
+
+pub type ClientNum = usize;
+
+pub type SimLog = ServerLog<ClientNum, VecDeque<ToWorker<ClientNum>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub seed: u64,
+    pub chain_servers: usize,
+    /// The length of each server's replication chain, `1` for no replication.
+    pub replicas: usize,
+    pub clients: usize,
+    pub chains: u64,
+    pub ops_per_client: usize,
+    pub multiappend_percent: u32,
+    pub read_percent: u32,
+    /// The most time units a message can spend in flight.
+    pub max_delay: u64,
+    pub drop_percent: u32,
+    /// The chance each client crashes at some point during the run.
+    pub client_crash_percent: u32,
+    /// The number of replicas which crash at some point during the run.
+    pub server_crashes: usize,
+    /// Give up after this many events.
+    pub max_steps: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            seed: 0,
+            chain_servers: 2,
+            replicas: 1,
+            clients: 4,
+            chains: 6,
+            ops_per_client: 50,
+            multiappend_percent: 30,
+            read_percent: 20,
+            max_delay: 10,
+            drop_percent: 0,
+            client_crash_percent: 0,
+            server_crashes: 0,
+            max_steps: 1_000_000,
+        }
+    }
+}
+
+/// An invariant broken by a run, along with the seed which reproduces it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub seed: u64,
+    pub what: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seed {}: {}", self.seed, self.what)
+    }
+}
+
+/// An append a client received an acknowledgement for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ack {
+    pub client: ClientNum,
+    pub id: Uuid,
+    pub locs: Vec<OrderIndex>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    Client(ClientNum),
+    /// The `.1`th replica of chain server `.0`, replica `0` is the head.
+    Server(usize, usize),
+}
+
+/// Runs a simulation to completion and checks the resulting logs.
+pub fn run(config: Config) -> Result<(), Violation> {
+    let mut sim = Simulation::new(config);
+    sim.run();
+    sim.check()
+}
+
+pub struct Simulation {
+    config: Config,
+    rng: Rng,
+    now: u64,
+    next_seq: u64,
+    steps: u64,
+    events: BTreeMap<(u64, u64), Event>,
+    // when the last message on each link will be delivered
+    links: HashMap<(Node, Node), u64>,
+    servers: Vec<Vec<Server>>,
+    clients: Vec<Client>,
+    acked: Vec<Ack>,
+    observed: Vec<(OrderIndex, Uuid)>,
+    faults_injected: bool,
+}
+
+enum Event {
+    Start(ClientNum),
+    Deliver(Message),
+    Crash(Node),
+}
+
+struct Message {
+    from: Node,
+    to: Node,
+    bytes: Vec<u8>,
+    storage_loc: Option<u64>,
+    client: ClientNum,
+}
+
+struct Server {
+    log: SimLog,
+    crashed: bool,
+}
+
+#[derive(Default)]
+struct Client {
+    crashed: bool,
+    ops_left: usize,
+    pending: Option<Pending>,
+    max_timestamp_seen: HashMap<order, u64>,
+    next_read: HashMap<order, u64>,
+}
+
+enum Pending {
+    Append(Uuid),
+    /// A multiappend whose chains are all on one server.
+    FastMulti(Uuid),
+    /// Round 1 of a multiappend spanning servers.
+    Skeens1 { msg: Vec<u8>, waiting_for: Vec<usize>, timestamps: Vec<u64> },
+    /// Round 2, `locs` are filled in as each server finishes.
+    Skeens2 { id: Uuid, locs: Vec<OrderIndex>, waiting_for: Vec<usize> },
+    Read(OrderIndex),
+}
+
+impl Simulation {
+    pub fn new(config: Config) -> Self {
+        assert!(config.chain_servers > 0 && config.replicas > 0);
+        assert!(config.chains > 0);
+        let mut rng = Rng::new(config.seed);
+        let servers = (0..config.chain_servers).map(|k| {
+            (0..config.replicas).map(|_| {
+                let (store, _reader) = new_chain_store_and_reader();
+                let log = ServerLog::new(
+                    k as u32, config.chain_servers as u32, Default::default(), store);
+                Server { log: log, crashed: false }
+            }).collect()
+        }).collect();
+        let clients = (0..config.clients).map(|_| Client {
+            ops_left: config.ops_per_client,
+            ..Default::default()
+        }).collect();
+        let mut events = BTreeMap::new();
+        let mut next_seq = 0;
+        {
+            let mut schedule = |at, event| {
+                events.insert((at, next_seq), event);
+                next_seq += 1;
+            };
+            for c in 0..config.clients {
+                schedule(rng.below(config.max_delay + 1), Event::Start(c));
+            }
+            // roughly how long the run would take without faults
+            let horizon = config.ops_per_client as u64 * (config.max_delay + 1) * 4 + 1;
+            for c in 0..config.clients {
+                if rng.percent(config.client_crash_percent) {
+                    schedule(rng.below(horizon), Event::Crash(Node::Client(c)));
+                }
+            }
+            for _ in 0..config.server_crashes {
+                let k = rng.below(config.chain_servers as u64) as usize;
+                let r = rng.below(config.replicas as u64) as usize;
+                schedule(rng.below(horizon), Event::Crash(Node::Server(k, r)));
+            }
+        }
+        Simulation {
+            config: config,
+            rng: rng,
+            now: 0,
+            next_seq: next_seq,
+            steps: 0,
+            events: events,
+            links: Default::default(),
+            servers: servers,
+            clients: clients,
+            acked: vec![],
+            observed: vec![],
+            faults_injected: false,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn acked(&self) -> &[Ack] {
+        &self.acked
+    }
+
+    /// The reads clients completed, and the id they saw at each location.
+    pub fn observed(&self) -> &[(OrderIndex, Uuid)] {
+        &self.observed
+    }
+
+    /// Runs until there is nothing left to deliver or `max_steps` is reached.
+    pub fn run(&mut self) -> &mut Self {
+        while self.steps < self.config.max_steps && self.step() {}
+        self
+    }
+
+    /// Handles the next event, returns `false` if there are none left.
+    pub fn step(&mut self) -> bool {
+        let key = match self.events.keys().next().cloned() {
+            Some(key) => key,
+            None => return false,
+        };
+        let event = self.events.remove(&key).unwrap();
+        self.now = key.0;
+        self.steps += 1;
+        match event {
+            Event::Start(c) => self.start_op(c),
+            Event::Deliver(msg) => match msg.to {
+                Node::Client(c) => self.client_receive(c, msg),
+                Node::Server(k, r) => self.server_receive(k, r, msg),
+            },
+            Event::Crash(node) => self.crash(node),
+        }
+        true
+    }
+
+    fn schedule(&mut self, at: u64, event: Event) {
+        self.events.insert((at, self.next_seq), event);
+        self.next_seq += 1;
+    }
+
+    fn send(
+        &mut self,
+        from: Node,
+        to: Node,
+        bytes: Vec<u8>,
+        storage_loc: Option<u64>,
+        client: ClientNum,
+    ) {
+        let client_link = match (from, to) {
+            (Node::Client(..), _) | (_, Node::Client(..)) => true,
+            _ => false,
+        };
+        if client_link && self.rng.percent(self.config.drop_percent) {
+            trace!("SIM {} drop {:?} => {:?}", self.now, from, to);
+            self.faults_injected = true;
+            return
+        }
+        let earliest = self.now + 1 + self.rng.below(self.config.max_delay + 1);
+        let at = {
+            let last = self.links.entry((from, to)).or_insert(0);
+            *last = max(earliest, *last);
+            *last
+        };
+        let msg = Message {
+            from: from, to: to, bytes: bytes, storage_loc: storage_loc, client: client,
+        };
+        self.schedule(at, Event::Deliver(msg))
+    }
+
+    fn crash(&mut self, node: Node) {
+        trace!("SIM {} crash {:?}", self.now, node);
+        self.faults_injected = true;
+        match node {
+            Node::Client(c) => {
+                let client = &mut self.clients[c];
+                client.crashed = true;
+                client.pending = None;
+            },
+            Node::Server(k, r) => self.servers[k][r].crashed = true,
+        }
+    }
+
+    fn new_id(&mut self) -> Uuid {
+        let mut bytes = [0u8; 16];
+        LittleEndian::write_u64(&mut bytes[..8], self.rng.next_u64());
+        LittleEndian::write_u64(&mut bytes[8..], self.rng.next_u64());
+        Uuid::from_bytes(&bytes).unwrap()
+    }
+
+    fn random_chain(&mut self) -> order {
+        order::from(1 + self.rng.below(self.config.chains))
+    }
+
+    /////////////////////////////////////////////////
+
+    fn start_op(&mut self, c: ClientNum) {
+        if self.clients[c].crashed || self.clients[c].ops_left == 0 {
+            return
+        }
+        self.clients[c].ops_left -= 1;
+        let n = self.config.chain_servers;
+        let data = [c as u8];
+        let roll = self.rng.below(100) as u32;
+        let read_percent = self.config.read_percent;
+        let pending = if roll < read_percent {
+            let chain = self.random_chain();
+            let index = self.clients[c].next_read.get(&chain).cloned().unwrap_or(1);
+            let loc = OrderIndex(chain, entry::from(index));
+            let tail = self.config.replicas - 1;
+            let read = EntryContents::read(&loc).to_vec();
+            self.send(Node::Client(c), Node::Server(server_for(chain, n), tail), read, None, c);
+            Pending::Read(loc)
+        } else if roll < read_percent + self.config.multiappend_percent
+            && self.config.chains > 1 {
+            let num_locs = 2 + self.rng.below(min(self.config.chains, 3) - 1) as usize;
+            let mut locs = Vec::with_capacity(num_locs);
+            while locs.len() < num_locs {
+                let loc = OrderIndex(self.random_chain(), entry::from(0));
+                if !locs.contains(&loc) {
+                    locs.push(loc)
+                }
+            }
+            locs.sort();
+            let id = self.new_id();
+            let servers = servers_for(&locs, n);
+            if servers.len() == 1 {
+                let msg = EntryContents::Multi {
+                    id: &id,
+                    flags: &EntryFlag::Nothing,
+                    lock: &1,
+                    locs: &locs,
+                    deps: &[],
+                    data: &data,
+                }.to_vec();
+                self.send(Node::Client(c), Node::Server(servers[0], 0), msg, None, c);
+                Pending::FastMulti(id)
+            } else {
+                let msg = EntryContents::Multi {
+                    id: &id,
+                    flags: &(EntryFlag::TakeLock | EntryFlag::NewMultiPut),
+                    lock: &0,
+                    locs: &locs,
+                    deps: &[],
+                    data: &data,
+                }.to_vec();
+                for &s in &servers {
+                    self.send(Node::Client(c), Node::Server(s, 0), msg.clone(), None, c);
+                }
+                Pending::Skeens1 {
+                    msg: msg,
+                    waiting_for: servers,
+                    timestamps: vec![0; num_locs],
+                }
+            }
+        } else {
+            let chain = self.random_chain();
+            let id = self.new_id();
+            let timestamp = self.clients[c].max_timestamp_seen.get(&chain).cloned()
+                .unwrap_or(0);
+            let msg = EntryContents::Single {
+                id: &id,
+                flags: &EntryFlag::Nothing,
+                loc: &OrderIndex(chain, entry::from(0)),
+                deps: &[],
+                data: &data,
+                timestamp: &timestamp,
+            }.to_vec();
+            self.send(Node::Client(c), Node::Server(server_for(chain, n), 0), msg, None, c);
+            Pending::Append(id)
+        };
+        self.clients[c].pending = Some(pending);
+    }
+
+    fn client_receive(&mut self, c: ClientNum, msg: Message) {
+        let server = match msg.from {
+            Node::Server(k, _) => k,
+            Node::Client(..) => unreachable!("client to client message"),
+        };
+        if self.clients[c].crashed {
+            return
+        }
+        let pending = match self.clients[c].pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let n = self.config.chain_servers;
+        let (id, layout, flag, reply_locs) = {
+            let e = bytes_as_entry(&msg.bytes);
+            (*e.id(), e.kind().layout(), *e.flag(), e.locs().to_vec())
+        };
+        let still_pending = match pending {
+            Pending::Append(expected) => {
+                let done = id == expected && reply_locs[0].1 != entry::from(0);
+                if done {
+                    self.acked.push(Ack { client: c, id: id, locs: reply_locs });
+                    None
+                } else {
+                    Some(Pending::Append(expected))
+                }
+            },
+
+            Pending::FastMulti(expected) => {
+                let done = id == expected
+                    && reply_locs.iter().all(|l| l.1 != entry::from(0));
+                if done {
+                    self.acked.push(Ack { client: c, id: id, locs: reply_locs });
+                    None
+                } else {
+                    Some(Pending::FastMulti(expected))
+                }
+            },
+
+            Pending::Skeens1 { msg, mut waiting_for, mut timestamps } => {
+                let relevant = id == *bytes_as_entry(&msg).id()
+                    && flag.contains(EntryFlag::Skeens1Queued)
+                    && reply_locs.len() == timestamps.len()
+                    && waiting_for.contains(&server);
+                if relevant {
+                    waiting_for.retain(|&s| s != server);
+                    for (i, &OrderIndex(o, ts)) in reply_locs.iter().enumerate() {
+                        if o != order::from(0) && server_for(o, n) == server {
+                            timestamps[i] = u64::from(ts)
+                        }
+                    }
+                }
+                if waiting_for.is_empty() {
+                    Some(self.start_skeens2(c, msg, &timestamps))
+                } else {
+                    Some(Pending::Skeens1 {
+                        msg: msg, waiting_for: waiting_for, timestamps: timestamps,
+                    })
+                }
+            },
+
+            Pending::Skeens2 { id: expected, mut locs, mut waiting_for } => {
+                let relevant = id == expected
+                    && reply_locs.len() == locs.len()
+                    && waiting_for.contains(&server);
+                if relevant {
+                    for (i, &OrderIndex(o, index)) in reply_locs.iter().enumerate() {
+                        let on_server = o != order::from(0) && server_for(o, n) == server;
+                        if on_server && index != entry::from(0) {
+                            locs[i].1 = index
+                        }
+                    }
+                    let finished = locs.iter()
+                        .filter(|l| server_for(l.0, n) == server)
+                        .all(|l| l.1 != entry::from(0));
+                    if finished {
+                        waiting_for.retain(|&s| s != server)
+                    }
+                }
+                if waiting_for.is_empty() {
+                    self.acked.push(Ack { client: c, id: id, locs: locs });
+                    None
+                } else {
+                    Some(Pending::Skeens2 { id: expected, locs: locs, waiting_for: waiting_for })
+                }
+            },
+
+            Pending::Read(loc) => {
+                if layout == EntryLayout::Read && reply_locs[0] == loc {
+                    None
+                } else if layout != EntryLayout::Read && reply_locs.contains(&loc) {
+                    self.observed.push((loc, id));
+                    *self.clients[c].next_read.entry(loc.0).or_insert(1) += 1;
+                    None
+                } else {
+                    Some(Pending::Read(loc))
+                }
+            },
+        };
+        match still_pending {
+            Some(pending) => self.clients[c].pending = Some(pending),
+            None => {
+                let at = self.now + self.rng.below(self.config.max_delay + 1);
+                self.schedule(at, Event::Start(c))
+            },
+        }
+    }
+
+    /// Every server has queued a multiappend, start round 2 with the largest
+    /// timestamp any of them assigned.
+    fn start_skeens2(&mut self, c: ClientNum, mut msg: Vec<u8>, timestamps: &[u64]) -> Pending {
+        let n = self.config.chain_servers;
+        let max_ts = timestamps.iter().cloned().max().unwrap_or(0);
+        let id = *bytes_as_entry(&msg).id();
+        let servers = servers_for(bytes_as_entry(&msg).locs(), n);
+        for &OrderIndex(o, _) in bytes_as_entry(&msg).locs() {
+            let seen = self.clients[c].max_timestamp_seen.entry(o).or_insert(0);
+            *seen = max(*seen, max_ts);
+        }
+        slice_to_sentinel(&mut msg);
+        let size = {
+            let mut e = bytes_as_entry_mut(&mut msg);
+            e.flag_mut().insert(EntryFlag::Unlock | EntryFlag::NewMultiPut);
+            for loc in e.locs_mut().iter_mut() {
+                loc.1 = entry::from(0)
+            }
+            *e.lock_mut() = max_ts;
+            e.as_ref().len()
+        };
+        msg.truncate(size);
+        let locs = bytes_as_entry(&msg).locs().to_vec();
+        for &s in &servers {
+            self.send(Node::Client(c), Node::Server(s, 0), msg.clone(), None, c);
+        }
+        Pending::Skeens2 { id: id, locs: locs, waiting_for: servers }
+    }
+
+    fn server_receive(&mut self, k: usize, r: usize, msg: Message) {
+        let has_downstream = r + 1 < self.config.replicas;
+        let mut out = vec![];
+        {
+            let server = &mut self.servers[k][r];
+            if server.crashed {
+                return
+            }
+            let Message { bytes, storage_loc, client, .. } = msg;
+            let mut buffer = Buffer::wrap_vec(bytes);
+            let layout = buffer.contents().kind().layout();
+            match storage_loc {
+                Some(storage_loc) => {
+                    let to_replicate =
+                        worker_thread::to_replicate(buffer, storage_loc, Uuid::nil(), 0);
+                    server.log.handle_replication(to_replicate, client)
+                },
+                None if layout == EntryLayout::Read => {
+                    let reply = worker_thread::handle_read(&*server.log.log, &buffer, 0, |res|
+                        match res {
+                            Ok(bytes) => bytes.to_vec(),
+                            Err(contents) => contents.to_vec(),
+                        }
+                    );
+                    out.push((Node::Client(client), reply, None, client));
+                },
+                None => {
+                    let storage = worker_thread::append_storage(&mut buffer);
+                    server.log.handle_op(buffer, storage, client)
+                },
+            }
+            // the log pushes to the front, so pop from the back to keep the
+            // order a real worker would see
+            while let Some(to_worker) = server.log.to_workers.pop_back() {
+                let _ = handle_to_worker2(to_worker, 0, has_downstream, |to_send, _, client| {
+                    let (bytes, storage_loc) = match to_send {
+                        ToSend::Nothing => return,
+                        ToSend::Contents(contents) => (contents.to_vec(), 0),
+                        ToSend::OldContents(contents, loc) => (contents.to_vec(), loc),
+                        ToSend::Slice(slice) => (slice.to_vec(), 0),
+                        ToSend::StaticSlice(slice) => (slice.to_vec(), 0),
+                        ToSend::Read(slice) => (slice.to_vec(), 0),
+                        ToSend::OldReplication(slice, loc) => (slice.to_vec(), loc),
+                    };
+                    if has_downstream {
+                        out.push((Node::Server(k, r + 1), bytes, Some(storage_loc), client))
+                    } else {
+                        out.push((Node::Client(client), bytes, None, client))
+                    }
+                });
+            }
+        }
+        for (to, bytes, storage_loc, client) in out {
+            self.send(Node::Server(k, r), to, bytes, storage_loc, client)
+        }
+    }
+
+    /////////////////////////////////////////////////
+
+    /// Checks the final state of every replica against the history clients saw.
+    pub fn check(&self) -> Result<(), Violation> {
+        let violation = |what: String| Violation { seed: self.config.seed, what: what };
+        let n = self.config.chain_servers;
+        let chains: Vec<order> = (1..self.config.chains + 1).map(order::from).collect();
+        let logs: Logs = self.servers.iter().map(|replicas|
+            replicas.iter().map(|server|
+                chains.iter().map(|&c| (c, read_chain(&server.log, c))).collect()
+            ).collect()
+        ).collect();
+        for (k, replicas) in logs.iter().enumerate() {
+            for (r, replica) in replicas.iter().enumerate() {
+                for &chain in chains.iter().filter(|&&c| server_for(c, n) == k) {
+                    let mut seen = HashSet::default();
+                    for (i, e) in replica[&chain].iter().enumerate() {
+                        let loc = OrderIndex(chain, entry::from(i as u64 + 1));
+                        let &(id, ref locs) = match e.as_ref() {
+                            Some(e) => e,
+                            None => continue,
+                        };
+                        if !seen.insert(id) {
+                            return Err(violation(format!(
+                                "{:?} appears twice in {:?} on {:?}", id, chain, (k, r))))
+                        }
+                        if !locs.contains(&loc) {
+                            return Err(violation(format!(
+                                "{:?} at {:?} on {:?} has locs {:?}", id, loc, (k, r), locs)))
+                        }
+                        for &other in locs {
+                            let local = other.0 != order::from(0)
+                                && other.1 != entry::from(0)
+                                && server_for(other.0, n) == k;
+                            if local && id_at(&logs, k, r, other) != Some(id) {
+                                return Err(violation(format!(
+                                    "{:?} at {:?} on {:?} is missing from {:?}",
+                                    id, loc, (k, r), other)))
+                            }
+                        }
+                        if r > 0 && id_at(&logs, k, r - 1, loc) != Some(id) {
+                            return Err(violation(format!(
+                                "replica {:?} has {:?} at {:?}, upstream has {:?}",
+                                (k, r), id, loc, id_at(&logs, k, r - 1, loc))))
+                        }
+                    }
+                }
+            }
+        }
+
+        let tail = self.config.replicas - 1;
+        for ack in &self.acked {
+            for &loc in &ack.locs {
+                if loc.0 == order::from(0) {
+                    continue
+                }
+                let found = id_at(&logs, server_for(loc.0, n), tail, loc);
+                if found != Some(ack.id) {
+                    return Err(violation(format!(
+                        "{:?} acked at {:?} but the log has {:?}", ack.id, loc, found)))
+                }
+            }
+        }
+
+        for &(loc, id) in &self.observed {
+            let found = id_at(&logs, server_for(loc.0, n), tail, loc);
+            if found != Some(id) {
+                return Err(violation(format!(
+                    "{:?} read at {:?} but the log has {:?}", id, loc, found)))
+            }
+        }
+
+        let orders: Vec<(order, Vec<Uuid>)> = chains.iter().map(|&c| {
+            let log = &logs[server_for(c, n)][0][&c];
+            (c, log.iter().filter_map(|e| e.as_ref().map(|e| e.0)).collect())
+        }).collect();
+        for (i, &(c0, ref ids0)) in orders.iter().enumerate() {
+            let in0: HashSet<Uuid> = ids0.iter().cloned().collect();
+            for &(c1, ref ids1) in &orders[i + 1..] {
+                let in1: HashSet<Uuid> = ids1.iter().cloned().collect();
+                let common0: Vec<_> = ids0.iter().filter(|id| in1.contains(id)).collect();
+                let common1: Vec<_> = ids1.iter().filter(|id| in0.contains(id)).collect();
+                if common0 != common1 {
+                    return Err(violation(format!(
+                        "{:?} and {:?} disagree on order: {:?} vs {:?}",
+                        c0, c1, common0, common1)))
+                }
+            }
+        }
+
+        if !self.faults_injected {
+            if !self.events.is_empty() {
+                return Err(violation(format!(
+                    "still running after {} steps", self.steps)))
+            }
+            for (c, client) in self.clients.iter().enumerate() {
+                if client.ops_left > 0 || client.pending.is_some() {
+                    return Err(violation(format!(
+                        "client {} never finished, {} ops left, waiting on {:?}",
+                        c, client.ops_left, client.pending)))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Pending::Append(ref id) => write!(f, "Append({:?})", id),
+            Pending::FastMulti(ref id) => write!(f, "FastMulti({:?})", id),
+            Pending::Skeens1 { ref msg, ref waiting_for, .. } =>
+                write!(f, "Skeens1({:?}, {:?})", bytes_as_entry(msg).id(), waiting_for),
+            Pending::Skeens2 { ref id, ref waiting_for, .. } =>
+                write!(f, "Skeens2({:?}, {:?})", id, waiting_for),
+            Pending::Read(ref loc) => write!(f, "Read({:?})", loc),
+        }
+    }
+}
+
+fn server_for(chain: order, chain_servers: usize) -> usize {
+    (u64::from(chain) % chain_servers as u64) as usize
+}
+
+fn servers_for(locs: &[OrderIndex], chain_servers: usize) -> Vec<usize> {
+    let mut servers: Vec<_> = locs.iter()
+        .filter(|l| l.0 != order::from(0))
+        .map(|l| server_for(l.0, chain_servers))
+        .collect();
+    servers.sort();
+    servers.dedup();
+    servers
+}
+
+/// The id and locations of each entry in a chain, `None` for holes.
+type ChainLog = Vec<Option<(Uuid, Vec<OrderIndex>)>>;
+
+/// The chains stored on each replica of each chain server.
+type Logs = Vec<Vec<HashMap<order, ChainLog>>>;
+
+fn id_at(logs: &Logs, k: usize, r: usize, loc: OrderIndex) -> Option<Uuid> {
+    let index = u64::from(loc.1);
+    if index == 0 {
+        return None
+    }
+    logs[k][r].get(&loc.0)
+        .and_then(|log| log.get(index as usize - 1))
+        .and_then(|e| e.as_ref().map(|e| e.0))
+}
+
+fn read_chain(log: &SimLog, chain: order) -> ChainLog {
+    let mut entries = vec![];
+    loop {
+        let loc = OrderIndex(chain, entry::from(entries.len() as u64 + 1));
+        let mut buffer = Buffer::empty();
+        buffer.fill_from_entry_contents(EntryContents::read(&loc));
+        let read = worker_thread::handle_read(&*log.log, &buffer, 0, |res| match res {
+            Ok(bytes) => {
+                let e = bytes_as_entry(bytes);
+                Ok((*e.id(), e.locs().to_vec()))
+            },
+            Err(EntryContents::Read{ horizon, .. }) => Err(u64::from(horizon.1)),
+            Err(..) => unreachable!(),
+        });
+        match read {
+            Ok(e) => entries.push(Some(e)),
+            Err(horizon) if u64::from(loc.1) <= horizon => entries.push(None),
+            Err(..) => return entries,
+        }
+    }
+}
+
+/// xorshift64*, small and fast, and all a simulation needs.
+/// Also used by the fault injection in tests which want reproducible choices
+/// without pulling in `rand`.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn percent(&mut self, p: u32) -> bool {
+        self.below(100) < p as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_seeds(config: Config, seeds: ::std::ops::Range<u64>) {
+        for seed in seeds {
+            let config = Config { seed: seed, ..config.clone() };
+            if let Err(violation) = run(config) {
+                panic!("{}", violation)
+            }
+        }
+    }
+
+    #[test]
+    fn no_delay() {
+        run_seeds(Config { max_delay: 0, ..Default::default() }, 0..10)
+    }
+
+    #[test]
+    fn delays() {
+        run_seeds(Config::default(), 0..20)
+    }
+
+    #[test]
+    fn one_server() {
+        run_seeds(Config { chain_servers: 1, ..Default::default() }, 0..10)
+    }
+
+    #[test]
+    fn three_servers() {
+        run_seeds(Config { chain_servers: 3, chains: 9, ..Default::default() }, 0..10)
+    }
+
+    #[test]
+    fn replicated() {
+        run_seeds(Config { replicas: 3, ..Default::default() }, 0..10)
+    }
+
+    #[test]
+    fn faults() {
+        let config = Config {
+            replicas: 2,
+            drop_percent: 2,
+            client_crash_percent: 25,
+            server_crashes: 1,
+            ..Default::default()
+        };
+        run_seeds(config, 0..20)
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let config = Config { seed: 7, drop_percent: 1, ..Default::default() };
+        let mut first = Simulation::new(config.clone());
+        let mut second = Simulation::new(config);
+        first.run();
+        second.run();
+        assert!(!first.acked().is_empty());
+        assert_eq!(first.acked(), second.acked());
+        assert_eq!(first.observed(), second.observed());
+    }
+}
@@ -7,7 +7,7 @@ use std::cmp::Ordering::*;
 
 use uuid::Uuid;
 
-use hash::UuidHashMap;
+use hash::{UuidHashMap, UuidHashSet};
 
 use vec_deque_map::VecDequeMap;
 
@@ -23,6 +23,13 @@ pub struct SkeensState<T: Copy> {
     got_max_timestamp: BinaryHeap<GotMax<T>>,
     append_status: UuidHashMap<AppendStatus>,
     recovering: UuidHashMap<(u64, Box<(Uuid, Box<[OrderIndex]>)>)>,
+    //every multiappend this chain has flushed, and its max timestamp,
+    //so a recoverer can tell them apart from ones this chain never saw;
+    //like the entries themselves these are never forgotten
+    flushed: UuidHashMap<Time>,
+    //multiappends a recoverer was told this chain never saw,
+    //should their phase 1 show up after all it is dropped
+    fenced: UuidHashSet,
     // early_sk2: UuidHashMap<u64>,
 }
 
@@ -85,6 +92,8 @@ impl<T: Copy> SkeensState<T> {
             next_timestamp: 1,
             last_flush: 0,
             recovering: Default::default(),
+            flushed: Default::default(),
+            fenced: Default::default(),
             // early_sk2: Default::default(),
         }
     }
@@ -166,6 +175,10 @@ impl<T: Copy> SkeensState<T> {
             true
         });
 
+        if self.fenced.contains(&id) && !self.append_status.contains_key(&id) {
+            return self.add_fenced(id)
+        }
+
         let ret = match self.append_status.entry(id) {
             Occupied(o) => {
                 match *o.into_mut() {
@@ -225,6 +238,10 @@ impl<T: Copy> SkeensState<T> {
             true
         });
 
+        if self.fenced.contains(&id) && !self.append_status.contains_key(&id) {
+            return self.add_fenced(id)
+        }
+
         let ret = match self.append_status.entry(id) {
             Occupied(o) => {
                 match *o.into_mut() {
@@ -266,6 +283,18 @@ impl<T: Copy> SkeensState<T> {
         ret
     }
 
+    //A recoverer may already have aborted this append in its other chains,
+    //so it only takes up a place in the queue, and gets timestamp 0
+    //telling the writer to abort it in the rest
+    fn add_fenced(&mut self, id: Uuid) -> SkeensAppendRes {
+        trace!("SKEENS dropping fenced {:?}", id);
+        let node_num = self.phase1_queue.push_index();
+        let n = self.phase1_queue.push_back(WaitingForMax::Cancelled{node_num: node_num, id: id});
+        assert_eq!(node_num, n);
+        self.pop_decided_front();
+        SkeensAppendRes::NewAppend(0, node_num)
+    }
+
     //AKA skeens2
     pub fn set_max_timestamp(&mut self, sk2_id: Uuid, max_timestamp: u64)
     -> SkeensSetMaxRes {
@@ -311,11 +340,7 @@ impl<T: Copy> SkeensState<T> {
         };
 
         if let SkeensSetMaxRes::Ok = ret {
-            while self.phase1_queue.front().map(|v| v.has_max()).unwrap_or(false) {
-                let s = self.phase1_queue.pop_front().expect("must have front of queue");
-                let s = s.into_got_max();
-                self.got_max_timestamp.push(s);
-            }
+            self.pop_decided_front();
             //TODO flush queue?
             return if self.can_flush() {
                 SkeensSetMaxRes::NeedsFlush
@@ -336,6 +361,37 @@ impl<T: Copy> SkeensState<T> {
         ret
     }
 
+    /// Aborts a multiappend which has not yet gotten its max timestamp.
+    /// The append is left as a tombstone in the queue so the appends behind it
+    /// keep their place, and is dropped once it reaches the front.
+    pub fn cancel_multi_append(&mut self, id: Uuid) -> SkeensSetMaxRes {
+        let i = match self.append_status.get(&id) {
+            None => return SkeensSetMaxRes::NotWaiting,
+            Some(&AppendStatus::Phase2(t)) => return SkeensSetMaxRes::Duplicate(t),
+            Some(&AppendStatus::Singleton(i)) | Some(&AppendStatus::Phase1(i)) => i,
+        };
+        if let Timestamp::Phase2(t) = self.phase1_queue[i].multi_timestamp() {
+            return SkeensSetMaxRes::Duplicate(t)
+        }
+        self.append_status.remove(&id);
+        self.phase1_queue[i] = WaitingForMax::Cancelled{node_num: i, id: id};
+        self.pop_decided_front();
+        if self.can_flush() {
+            SkeensSetMaxRes::NeedsFlush
+        } else {
+            SkeensSetMaxRes::Ok
+        }
+    }
+
+    fn pop_decided_front(&mut self) {
+        while self.phase1_queue.front().map(|v| v.has_max() || v.is_cancelled()).unwrap_or(false) {
+            let s = self.phase1_queue.pop_front().expect("must have front of queue");
+            if s.is_cancelled() { continue }
+            let s = s.into_got_max();
+            self.got_max_timestamp.push(s);
+        }
+    }
+
     pub fn flush_got_max_timestamp<F>(&mut self, mut f: F)
     where F: FnMut(GotMax<T>) {
         while self.can_flush() {
@@ -350,6 +406,12 @@ impl<T: Copy> SkeensState<T> {
             let _old = self.append_status.remove(&id);
             self.last_flush = ::std::cmp::max(g.timestamp(), self.last_flush);
             debug_assert!(_old.is_some(), "no skeen for {:?}", id);
+            match g {
+                GotMax::Multi{..} | GotMax::Senti{..} => {
+                    self.flushed.insert(id, g.timestamp());
+                },
+                _ => {},
+            }
             f(g)
             // debug_assert_eq!(self.phase2_ids.len(), self.got_max_timestamp.len(),
             //     "{:?} != {:?} @ {:?}",
@@ -483,6 +545,11 @@ impl<T: Copy> SkeensState<T> {
             self.next_timestamp = timestamp + 1
         }
 
+        if self.fenced.contains(&id) && !self.append_status.contains_key(&id) {
+            self.replicate_fenced(id, node_num);
+            return true
+        }
+
         match self.append_status.entry(id) {
             Occupied(..) => false,
             Vacant(v) => {
@@ -531,6 +598,11 @@ impl<T: Copy> SkeensState<T> {
             self.next_timestamp = timestamp + 1
         }
 
+        if self.fenced.contains(&id) && !self.append_status.contains_key(&id) {
+            self.replicate_fenced(id, node_num);
+            return true
+        }
+
         match self.append_status.entry(id) {
             Occupied(..) => false,
             Vacant(v) => {
@@ -552,7 +624,24 @@ impl<T: Copy> SkeensState<T> {
         }
     }
 
-    pub fn replicate_round2<F>(&mut self, id: &Uuid, max_timestamp: u64, index: TrieIndex, mut f: F)
+    //the head dropped this append's phase 1 as well, see `add_fenced`
+    fn replicate_fenced(&mut self, id: Uuid, node_num: QueueIndex) {
+        let old = self.phase1_queue.insert(node_num, WaitingForMax::Cancelled{node_num: node_num, id: id});
+        assert!(old.is_none(), "replace skeens with fenced? {:?} @ {:?}", old, node_num);
+        while self.phase1_queue.front().map(|w| w.is_cancelled()).unwrap_or(false) {
+            self.phase1_queue.pop_front();
+        }
+    }
+
+    /// Replays the head telling a recoverer that `id` never reached this chain,
+    /// see `recovery_status`.
+    pub fn fence(&mut self, id: Uuid) {
+        if !self.append_status.contains_key(&id) && !self.flushed.contains_key(&id) {
+            self.fenced.insert(id);
+        }
+    }
+
+    pub fn replicate_round2<F>(&mut self, id: &Uuid, max_timestamp: u64, index: TrieIndex, f: F)
     where F: FnMut(ReplicatedSkeens<T>) {
         let offset = match self.append_status.get(&id) {
             Some(&AppendStatus::Phase1(offset)) | Some(&AppendStatus::Singleton(offset)) => {
@@ -584,8 +673,35 @@ impl<T: Copy> SkeensState<T> {
             return
         }
         // trace!("flush {:#?}", self);
-        while self.phase1_queue.front().map(|w| w.has_replication()).unwrap_or(false) {
+        self.flush_replicated(f)
+    }
+
+    /// Replays a cancel from the head,
+    /// flushing anything that was only waiting on the cancelled append.
+    pub fn cancel_replica<F>(&mut self, id: &Uuid, f: F)
+    where F: FnMut(ReplicatedSkeens<T>) {
+        let offset = match self.append_status.get(id) {
+            Some(&AppendStatus::Phase1(offset)) => offset,
+            _ => return,
+        };
+        match self.phase1_queue.get(offset).map(|w| w.multi_timestamp()) {
+            Some(Timestamp::Phase1(..)) => (),
+            _ => return,
+        }
+        self.append_status.remove(id);
+        self.phase1_queue[offset] = WaitingForMax::Cancelled{node_num: offset, id: *id};
+        if offset != self.phase1_queue.start_index() {
+            return
+        }
+        self.flush_replicated(f)
+    }
+
+    fn flush_replicated<F>(&mut self, mut f: F)
+    where F: FnMut(ReplicatedSkeens<T>) {
+        while self.phase1_queue.front()
+            .map(|w| w.has_replication() || w.is_cancelled()).unwrap_or(false) {
             let replica = self.phase1_queue.pop_front().expect("flushing nothing");
+            if replica.is_cancelled() { continue }
             //let old_start = self.phase1_queue.start_index() - 1;
             //assert!(replica.contains_node_num(old_start));
             //TODO self.last_flush = ::std::cmp::max(replica.timestamp(), self.last_flush);
@@ -613,6 +729,29 @@ impl<T: Copy> SkeensState<T> {
         && self.got_max_timestamp.is_empty()
     }
 
+    /// The ids and local timestamps of the appends which are still waiting
+    /// for their max timestamp.
+    pub fn waiting_for_max(&self) -> Vec<(Uuid, Time)> {
+        self.append_status.iter().filter_map(|(&id, status)| match status {
+            &AppendStatus::Phase1(i) => match self.phase1_queue.get(i) {
+                Some(w) => match w.multi_timestamp() {
+                    Timestamp::Phase1(t) => Some((id, t)),
+                    Timestamp::Phase2(..) => None,
+                },
+                None => None,
+            },
+            _ => None,
+        }).collect()
+    }
+
+    /// The number of appends which have their max timestamp,
+    /// but are still queued behind an earlier phase 1 append.
+    pub fn num_waiting_for_flush(&self) -> usize {
+        self.append_status.values()
+            .filter(|s| match s { &&AppendStatus::Phase2(..) => true, _ => false })
+            .count()
+    }
+
     pub fn tas_recoverer(
         &mut self,
         write_id: Uuid,
@@ -674,6 +813,47 @@ impl<T: Copy> SkeensState<T> {
         }
         false
     }
+
+    /// How far `write_id` has gotten in this chain,
+    /// for a client finishing an append whose writer has gone away.
+    /// `Ok` holds its phase 1 timestamp if it is still waiting for its max timestamp,
+    /// `Err(Some(max))` means it already has its max timestamp, flushed or not,
+    /// and `Err(None)` that it was never seen here.
+    /// Once a chain has answered `Err(None)` the append is fenced off:
+    /// should its phase 1 arrive later it is dropped, so the recoverer can
+    /// abort the append in its other chains without it ever landing in this one.
+    pub fn recovery_status(&mut self, write_id: Uuid) -> Result<Time, Option<Time>> {
+        match self.append_status.get(&write_id) {
+            Some(&AppendStatus::Phase1(i)) => match self.phase1_queue.get(i) {
+                Some(w) => match w.multi_timestamp() {
+                    Timestamp::Phase1(t) => Ok(t),
+                    Timestamp::Phase2(t) => Err(Some(t)),
+                },
+                None => Err(None),
+            },
+            Some(&AppendStatus::Phase2(t)) => Err(Some(t)),
+            Some(&AppendStatus::Singleton(..)) => Err(None),
+            None => match self.flushed.get(&write_id) {
+                Some(&t) => Err(Some(t)),
+                None => {
+                    self.fenced.insert(write_id);
+                    Err(None)
+                },
+            },
+        }
+    }
+
+    /// The multiappend at the front of the queue if it is still waiting for
+    /// its max timestamp, along with its phase 1 timestamp;
+    /// nothing else in the chain can be flushed until it gets one.
+    pub fn blocker(&self) -> Option<(Uuid, Time, &SkeensMultiStorage)> {
+        match self.phase1_queue.front() {
+            Some(&WaitingForMax::Multi{id, timestamp, ref storage, ..})
+            | Some(&WaitingForMax::Senti{id, timestamp, ref storage, ..}) =>
+                Some((id, timestamp, storage)),
+            _ => None,
+        }
+    }
 }
 
 pub enum ReplicatedSkeens<T> {
@@ -700,6 +880,8 @@ enum WaitingForMax<T> {
     ReplicatedSnap{max_timestamp: u64, index: TrieIndex, storage: SkeensMultiStorage, t: T, id: Uuid},
 
     ReplicatedSingle{max_timestamp: u64, index: TrieIndex, storage: ValEdge, t: T, id: Uuid},
+
+    Cancelled{node_num: u64, id: Uuid},
 }
 
 enum Timestamp {
@@ -731,6 +913,8 @@ impl<T> WaitingForMax<T> {
             },
 
             &SimpleSingle{..} | &Single{..} | &ReplicatedSingle{..} => true,
+
+            &Cancelled{node_num: n, ..} => n == node_num,
         }
     }
 
@@ -748,6 +932,8 @@ impl<T> WaitingForMax<T> {
             | &ReplicatedSnap{ref storage, ..} => unsafe {&**storage.get().2}.as_ptr(),
 
             &SimpleSingle{ref storage,..} | &Single{ref storage,..} | &ReplicatedSingle{ref storage,..} => storage.ptr(),
+
+            &Cancelled{..} => unreachable!(),
         }
     }
 
@@ -769,6 +955,8 @@ impl<T> WaitingForMax<T> {
 
             &mut Single{timestamp, ..} => return Err(timestamp),
 
+            &mut Cancelled{..} => unreachable!(),
+
             &mut Multi{timestamp, node_num, ref storage, t, id, ..} => {
                 debug_assert!(max_timestamp >= timestamp,
                     "max_timestamp >= timestamp {:?} >= {:?},",// @ {:?}, {:#?}",
@@ -858,7 +1046,14 @@ impl<T> WaitingForMax<T> {
             | &ReplicatedSnap{..}
             | &ReplicatedSingle{..} => true,
 
-            &Multi{..} | &Senti{..} | &Snap{..} => false,
+            &Multi{..} | &Senti{..} | &Snap{..} | &Cancelled{..} => false,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        match self {
+            &WaitingForMax::Cancelled{..} => true,
+            _ => false,
         }
     }
 
@@ -872,7 +1067,8 @@ impl<T> WaitingForMax<T> {
             | &mut ReplicatedMulti{..}
             | &mut ReplicatedSenti{..}
             | &mut ReplicatedSnap{..}
-            | &mut ReplicatedSingle{..} =>
+            | &mut ReplicatedSingle{..}
+            | &mut Cancelled{..} =>
                 unreachable!(),
 
             &mut Single{timestamp, storage, t, id, ..}
@@ -997,6 +1193,8 @@ impl<T> WaitingForMax<T> {
             | &ReplicatedSenti{max_timestamp, ..}
             | &ReplicatedSnap{max_timestamp, ..}
             | &ReplicatedSingle{max_timestamp, ..} => Timestamp::Phase2(max_timestamp),
+
+            &Cancelled{..} => unreachable!(),
         }
     }
 
@@ -1017,6 +1215,8 @@ impl<T> WaitingForMax<T> {
             | &ReplicatedSenti{id, ..}
             | &ReplicatedSnap{id, ..}
             | &ReplicatedSingle{id, ..} => id,
+
+            &Cancelled{id, ..} => id,
         }
     }
 }
@@ -1141,6 +1341,12 @@ impl<T> ::std::fmt::Debug for WaitingForMax<T> {
                     .field("storage", storage)
                     .finish()
             },
+            &WaitingForMax::Cancelled{ref node_num, ref id} => {
+                fmt.debug_struct("WaitingForMax::Cancelled")
+                    .field("id", id)
+                    .field("node_num", node_num)
+                    .finish()
+            },
         }
     }
 }
@@ -1224,6 +1430,9 @@ impl<T> GotMax<T> {
             | (&GotMax::Senti{..}, &WaitingForMax::Snap{..})
             | (&GotMax::Snap{..}, &WaitingForMax::Senti{..}) => false,
 
+            (_, &WaitingForMax::Cancelled{..}) =>
+                unreachable!("cancelled appends are popped once at the front"),
+
             //FIXME
             (_, &WaitingForMax::SimpleSingle{..})
             | (_, &WaitingForMax::Single{..})
@@ -1729,6 +1938,174 @@ mod test {
             Multi{timestamp: 122, id: id1, t: (), storage: s1}]);
     }
 
+    #[test]
+    fn multi_waiting_for_max() {
+        let id0 = Uuid::new_v4();
+        let s0 = multi_storage();
+        let id1 = Uuid::new_v4();
+        let s1 = multi_storage();
+        let mut skeen = SkeensState::new();
+        assert_eq!(skeen.waiting_for_max(), vec![]);
+        skeen.add_multi_append(id0, s0.clone(), false, ()).assert_new();
+        skeen.add_multi_append(id1, s1.clone(), false, ()).assert_new();
+        let mut waiting = skeen.waiting_for_max();
+        waiting.sort_by_key(|&(_, t)| t);
+        assert_eq!(waiting, vec![(id0, 1), (id1, 2)]);
+        let r = skeen.set_max_timestamp(id1, 122);
+        assert_eq!(r, SkeensSetMaxRes::Ok);
+        assert_eq!(skeen.waiting_for_max(), vec![(id0, 1)]);
+        assert_eq!(skeen.num_waiting_for_flush(), 1);
+    }
+
+    #[test]
+    fn multi_recovery_status() {
+        let id0 = Uuid::new_v4();
+        let id1 = Uuid::new_v4();
+        let mut skeen = SkeensState::new();
+        assert_eq!(skeen.recovery_status(Uuid::new_v4()), Err(None));
+        skeen.add_multi_append(id0, multi_storage(), false, ()).assert_new();
+        skeen.add_multi_append(id1, multi_storage(), false, ()).assert_new();
+        assert_eq!(skeen.recovery_status(id0), Ok(1));
+        assert_eq!(skeen.recovery_status(id1), Ok(2));
+        let r = skeen.set_max_timestamp(id1, 7);
+        assert_eq!(r, SkeensSetMaxRes::Ok);
+        assert_eq!(skeen.recovery_status(id1), Err(Some(7)));
+        let r = skeen.set_max_timestamp(id0, 5);
+        assert_eq!(r, SkeensSetMaxRes::NeedsFlush);
+        skeen.flush_got_max_timestamp(|_| {});
+        assert_eq!(skeen.recovery_status(id0), Err(Some(5)));
+        assert_eq!(skeen.recovery_status(id1), Err(Some(7)));
+        assert_eq!(skeen.set_max_timestamp(id0, 5), SkeensSetMaxRes::NotWaiting);
+    }
+
+    #[test]
+    fn multi_recovery_status_after_many_flushes() {
+        let id0 = Uuid::new_v4();
+        let mut skeen = SkeensState::new();
+        skeen.add_multi_append(id0, multi_storage(), false, ()).assert_new();
+        assert_eq!(skeen.set_max_timestamp(id0, 3), SkeensSetMaxRes::NeedsFlush);
+        skeen.flush_got_max_timestamp(|_| {});
+        for _ in 0..5000 {
+            let id = Uuid::new_v4();
+            skeen.add_multi_append(id, multi_storage(), false, ()).assert_new();
+            let max = skeen.next_timestamp;
+            assert_eq!(skeen.set_max_timestamp(id, max), SkeensSetMaxRes::NeedsFlush);
+            skeen.flush_got_max_timestamp(|_| {});
+        }
+        assert_eq!(skeen.recovery_status(id0), Err(Some(3)));
+    }
+
+    #[test]
+    fn multi_fenced() {
+        let id0 = Uuid::new_v4();
+        let id1 = Uuid::new_v4();
+        let s1 = multi_storage();
+        let mut skeen = SkeensState::new();
+        assert_eq!(skeen.recovery_status(id0), Err(None));
+        let r = skeen.add_multi_append(id0, multi_storage(), false, ());
+        assert_eq!(r, SkeensAppendRes::NewAppend(0, 0));
+        assert!(skeen.is_empty());
+        assert_eq!(skeen.set_max_timestamp(id0, 5), SkeensSetMaxRes::NotWaiting);
+        assert_eq!(skeen.recovery_status(id0), Err(None));
+        skeen.add_multi_append(id1, s1.clone(), false, ()).assert_new();
+        assert_eq!(skeen.set_max_timestamp(id1, 7), SkeensSetMaxRes::NeedsFlush);
+        let mut v = Vec::with_capacity(1);
+        skeen.flush_got_max_timestamp(|g| v.push(g));
+        assert_eq!(&*v, &[Multi{timestamp: 7, id: id1, t: (), storage: s1}]);
+        assert!(skeen.is_empty());
+    }
+
+    #[test]
+    fn multi_blocker() {
+        let id0 = Uuid::new_v4();
+        let id1 = Uuid::new_v4();
+        let mut skeen = SkeensState::new();
+        assert!(skeen.blocker().is_none());
+        skeen.add_multi_append(id0, multi_storage(), false, ()).assert_new();
+        skeen.add_multi_append(id1, multi_storage(), false, ()).assert_new();
+        assert_eq!(skeen.blocker().map(|(id, ts, _)| (id, ts)), Some((id0, 1)));
+        let r = skeen.set_max_timestamp(id1, 7);
+        assert_eq!(r, SkeensSetMaxRes::Ok);
+        assert_eq!(skeen.blocker().map(|(id, ts, _)| (id, ts)), Some((id0, 1)));
+        assert_eq!(skeen.cancel_multi_append(id0), SkeensSetMaxRes::NeedsFlush);
+        assert!(skeen.blocker().is_none());
+    }
+
+    #[test]
+    fn multi_cancel() {
+        let id0 = Uuid::new_v4();
+        let id1 = Uuid::new_v4();
+        let s1 = multi_storage();
+        let mut skeen = SkeensState::new();
+        skeen.add_multi_append(id0, multi_storage(), false, ()).assert_new();
+        skeen.add_multi_append(id1, s1.clone(), false, ()).assert_new();
+        let r = skeen.set_max_timestamp(id1, 7);
+        assert_eq!(r, SkeensSetMaxRes::Ok);
+        assert_eq!(skeen.cancel_multi_append(id1), SkeensSetMaxRes::Duplicate(7));
+        let r = skeen.cancel_multi_append(id0);
+        assert_eq!(r, SkeensSetMaxRes::NeedsFlush);
+        assert_eq!(skeen.recovery_status(id0), Err(None));
+        assert_eq!(skeen.cancel_multi_append(id0), SkeensSetMaxRes::NotWaiting);
+        let mut v = Vec::with_capacity(1);
+        skeen.flush_got_max_timestamp(|g| v.push(g));
+        assert_eq!(&*v, &[Multi{timestamp: 7, id: id1, t: (), storage: s1}]);
+        assert!(skeen.is_empty());
+    }
+
+    #[test]
+    fn multi_cancel_middle() {
+        let id0 = Uuid::new_v4();
+        let s0 = multi_storage();
+        let id1 = Uuid::new_v4();
+        let id2 = Uuid::new_v4();
+        let s2 = multi_storage();
+        let mut skeen = SkeensState::new();
+        skeen.add_multi_append(id0, s0.clone(), false, ()).assert_new();
+        skeen.add_multi_append(id1, multi_storage(), true, ()).assert_new();
+        skeen.add_multi_append(id2, s2.clone(), false, ()).assert_new();
+        assert_eq!(skeen.cancel_multi_append(id1), SkeensSetMaxRes::Ok);
+        assert_eq!(skeen.set_max_timestamp(id2, 9), SkeensSetMaxRes::Ok);
+        let mut v = Vec::with_capacity(2);
+        skeen.flush_got_max_timestamp(|g| v.push(g));
+        assert_eq!(&*v, &[]);
+        assert_eq!(skeen.set_max_timestamp(id0, 4), SkeensSetMaxRes::NeedsFlush);
+        skeen.flush_got_max_timestamp(|g| v.push(g));
+        assert_eq!(&*v,
+            &[Multi{timestamp: 4, id: id0, t: (), storage: s0},
+            Multi{timestamp: 9, id: id2, t: (), storage: s2}]);
+        assert!(skeen.is_empty());
+    }
+
+    #[test]
+    fn replica_cancel() {
+        let id0 = Uuid::new_v4();
+        let id1 = Uuid::new_v4();
+        let mut skeen = SkeensState::new();
+        assert!(skeen.replicate_multi_append_round1(1, 0, id0, multi_storage(), false, ()));
+        assert!(skeen.replicate_multi_append_round1(2, 1, id1, multi_storage(), false, ()));
+        let mut flushed = 0;
+        skeen.replicate_round2(&id1, 5, 0, |_| flushed += 1);
+        assert_eq!(flushed, 0);
+        skeen.cancel_replica(&id0, |_| flushed += 1);
+        assert_eq!(flushed, 1);
+        assert!(skeen.is_empty());
+    }
+
+    #[test]
+    fn replica_fenced() {
+        let id0 = Uuid::new_v4();
+        let id1 = Uuid::new_v4();
+        let mut skeen = SkeensState::new();
+        skeen.fence(id0);
+        assert!(skeen.replicate_multi_append_round1(1, 0, id0, multi_storage(), false, ()));
+        assert!(skeen.is_empty());
+        assert!(skeen.replicate_multi_append_round1(2, 1, id1, multi_storage(), false, ()));
+        let mut flushed = 0;
+        skeen.replicate_round2(&id1, 5, 0, |_| flushed += 1);
+        assert_eq!(flushed, 1);
+        assert!(skeen.is_empty());
+    }
+
     #[test]
     fn multi_rev() {
         let id0 = Uuid::new_v4();
@@ -0,0 +1,171 @@
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use admin::{ClientInfo, Request, Response};
+use hash::HashMap;
+use socket_addr::Ipv4SocketAddr;
+use ShardMap;
+
+use super::{ShutdownHandle, WorkerNum};
+use super::worker::ToLog;
+
+/// The clients which have connected to this server, and the worker which serves each one.
+pub type Clients = Arc<Mutex<HashMap<Ipv4SocketAddr, WorkerNum>>>;
+
+/// Serve admin requests on `listener` in the background.
+/// Requests about a single chain are forwarded to the ordering thread which
+/// owns it, requests about every chain are sent to all of them and the answers
+/// merged; the client list is answered directly from `clients`.
+/// Once `shutdown` is requested the returned thread stops accepting connections,
+/// closes the open ones and exits after their threads have.
+pub fn spawn<T>(
+    listener: TcpListener,
+    to_log: Vec<mpsc::SyncSender<ToLog<T>>>,
+    shards: ShardMap,
+    clients: Clients,
+    shutdown: ShutdownHandle,
+) -> Option<JoinHandle<()>>
+where T: 'static, ToLog<T>: Send {
+    //NOTE the listener is polled so the thread can notice a shutdown
+    if let Err(e) = listener.set_nonblocking(true) {
+        error!("admin listener error {}", e);
+        return None
+    }
+    //each connection removes itself once it closes
+    let connections: Arc<Mutex<HashMap<u64, (TcpStream, JoinHandle<()>)>>> = Default::default();
+    let thread = thread::spawn(move || {
+        let mut next_connection = 0;
+        while !shutdown.is_requested() {
+            match listener.accept() {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock =>
+                    thread::sleep(Duration::from_millis(10)),
+                Err(e) => error!("admin accept error {}", e),
+                Ok((stream, _)) => {
+                    let _ = stream.set_nonblocking(false);
+                    let to_close = match stream.try_clone() {
+                        Ok(to_close) => to_close,
+                        Err(e) => {
+                            error!("admin accept error {}", e);
+                            continue
+                        },
+                    };
+                    let id = next_connection;
+                    next_connection += 1;
+                    let to_log = to_log.clone();
+                    let clients = clients.clone();
+                    let remove_from = connections.clone();
+                    //NOTE held until the connection is inserted so it cannot remove itself first
+                    let mut open = connections.lock().unwrap();
+                    let connection = thread::spawn(move || {
+                        if let Err(e) = serve(stream, &to_log, shards, &clients) {
+                            debug!("admin connection closed due to {}", e)
+                        }
+                        remove_from.lock().unwrap().remove(&id);
+                    });
+                    open.insert(id, (to_close, connection));
+                },
+            }
+        }
+        let open: Vec<_> = connections.lock().unwrap().drain().map(|(_, c)| c).collect();
+        for &(ref stream, _) in &open {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        for (_, connection) in open {
+            let _ = connection.join();
+        }
+    });
+    Some(thread)
+}
+
+fn serve<T>(
+    stream: TcpStream,
+    to_log: &[mpsc::SyncSender<ToLog<T>>],
+    shards: ShardMap,
+    clients: &Clients,
+) -> io::Result<()> {
+    let _ = stream.set_nodelay(true);
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let request = match Request::read_from(&mut reader) {
+            Ok(request) => request,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        trace!("ADMIN {:?}", request);
+        let response = match request {
+            Request::Clients => {
+                let mut list: Vec<_> = clients.lock().unwrap().iter()
+                    .map(|(&id, &worker)| ClientInfo{id, worker})
+                    .collect();
+                list.sort_by_key(|c| c.worker);
+                Response::Clients(list)
+            },
+            Request::ChainInfo(chain) => {
+                let shard = shards.shard_of(chain);
+                match ask(&to_log[shard], Request::ChainInfo(chain)) {
+                    Some(response) => response,
+                    None => return Ok(()),
+                }
+            },
+            Request::DumpChain{chain, start, end} => {
+                let shard = shards.shard_of(chain);
+                match ask(&to_log[shard], Request::DumpChain{chain, start, end}) {
+                    Some(response) => response,
+                    None => return Ok(()),
+                }
+            },
+            request => {
+                let mut responses = Vec::with_capacity(to_log.len());
+                for to_log in to_log {
+                    match ask(to_log, request.clone()) {
+                        Some(response) => responses.push(response),
+                        None => return Ok(()),
+                    }
+                }
+                merge(responses)
+            },
+        };
+        response.write_to(&mut writer)?;
+        writer.flush()?;
+    }
+}
+
+/// Returns `None` if the ordering thread could not be reached.
+fn ask<T>(to_log: &mpsc::SyncSender<ToLog<T>>, request: Request) -> Option<Response> {
+    let (send, recv) = mpsc::channel();
+    if to_log.send(ToLog::Admin(request, send)).is_err() {
+        return None
+    }
+    let response = recv.recv()
+        .unwrap_or_else(|_| Response::Error("log thread is gone".to_string()));
+    Some(response)
+}
+
+/// Combine the answers of every ordering thread into the one a server with a
+/// single ordering thread would give.
+fn merge(responses: Vec<Response>) -> Response {
+    let mut responses = responses.into_iter();
+    let mut merged = match responses.next() {
+        Some(response) => response,
+        None => return Response::Error("server has no ordering threads".to_string()),
+    };
+    for response in responses {
+        match (&mut merged, response) {
+            (&mut Response::Chains(ref mut all), Response::Chains(c)) => all.extend(c),
+            (&mut Response::Stuck(ref mut all), Response::Stuck(s)) => all.extend(s),
+            (_, Response::Error(e)) => return Response::Error(e),
+            (&mut Response::Error(..), _) => {},
+            (_, other) => return Response::Error(format!("unexpected response {:?}", other)),
+        }
+    }
+    match merged {
+        Response::Chains(ref mut chains) => chains.sort_by_key(|c| c.chain),
+        Response::Stuck(ref mut stuck) => stuck.sort_by_key(|s| (s.timestamp, s.chain)),
+        _ => {},
+    }
+    merged
+}
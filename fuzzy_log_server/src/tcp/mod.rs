@@ -2,15 +2,18 @@ use std::collections::hash_map::Entry as HashEntry;
 use std::io::{self, Read, Write};
 use std::thread;
 use std::net::{IpAddr, SocketAddr};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
 // use std::time::Duration;
 
 // use prelude::*;
-use ::{spsc, ServerLog};
+use ::{spsc, ServerLog, ShardMap};
 use hash::HashMap;
 use socket_addr::Ipv4SocketAddr;
 
+pub use config::Limits;
+pub use self::shutdown::ShutdownHandle;
+
 use mio;
 use mio::tcp::*;
 
@@ -18,9 +21,11 @@ use self::worker::{Worker, DistToWorker, ToLog};
 
 // use packets::EntryContents;
 
+mod admin;
 mod worker;
 mod per_socket;
 mod socket_negotiate;
+mod shutdown;
 
 /*
   GC with parrallel readers plan:
@@ -128,6 +133,7 @@ mod socket_negotiate;
 const ACCEPT: mio::Token = mio::Token(0);
 const FROM_WORKERS: mio::Token = mio::Token(1);
 const DIST_FROM_LOG: mio::Token = mio::Token(2);
+const SHUTDOWN: mio::Token = mio::Token(3);
 
 //Worker tokens
 const FROM_DIST: mio::Token = mio::Token(0);
@@ -181,12 +187,120 @@ pub fn run_with_replication(
     num_workers: usize,
     ready: &AtomicUsize,
 ) -> ! {
+    run_with_admin(
+        acceptor,
+        this_server_num,
+        total_chain_servers,
+        prev_server,
+        next_server,
+        num_workers,
+        None,
+        ready,
+    )
+}
+
+/// Like `run_with_replication`, but also serves admin requests (see `::admin`)
+/// on `admin_acceptor` if one is provided.
+pub fn run_with_admin(
+    acceptor: TcpListener,
+    this_server_num: u32,
+    total_chain_servers: u32,
+    prev_server: Option<SocketAddr>,
+    next_server: Option<IpAddr>,
+    num_workers: usize,
+    admin_acceptor: Option<::std::net::TcpListener>,
+    ready: &AtomicUsize,
+) -> ! {
+    run_with_limits(
+        acceptor,
+        this_server_num,
+        total_chain_servers,
+        prev_server,
+        next_server,
+        num_workers,
+        admin_acceptor,
+        Limits::default(),
+        ready,
+    )
+}
+
+/// Like `run_with_admin`, but with flow control set by `limits`
+/// instead of the defaults.
+pub fn run_with_limits(
+    acceptor: TcpListener,
+    this_server_num: u32,
+    total_chain_servers: u32,
+    prev_server: Option<SocketAddr>,
+    next_server: Option<IpAddr>,
+    num_workers: usize,
+    admin_acceptor: Option<::std::net::TcpListener>,
+    limits: Limits,
+    ready: &AtomicUsize,
+) -> ! {
+    run_sharded(
+        acceptor,
+        this_server_num,
+        total_chain_servers,
+        prev_server,
+        next_server,
+        num_workers,
+        1,
+        admin_acceptor,
+        limits,
+        ready,
+    )
+}
+
+/// Like `run_with_limits`, but splits the chains stored on this server between
+/// `num_ordering_threads` ordering threads (see `::shards`).
+/// Replication is not sharded, so servers with replication must use one.
+pub fn run_sharded(
+    acceptor: TcpListener,
+    this_server_num: u32,
+    total_chain_servers: u32,
+    prev_server: Option<SocketAddr>,
+    next_server: Option<IpAddr>,
+    num_workers: usize,
+    num_ordering_threads: usize,
+    admin_acceptor: Option<::std::net::TcpListener>,
+    limits: Limits,
+    ready: &AtomicUsize,
+) -> ! {
+    run_until_shutdown(
+        acceptor,
+        this_server_num,
+        total_chain_servers,
+        prev_server,
+        next_server,
+        num_workers,
+        num_ordering_threads,
+        admin_acceptor,
+        limits,
+        &ShutdownHandle::new(),
+        ready,
+    );
+    unreachable!("SERVER {} stopped without being shut down", this_server_num)
+}
+
+/// Like `run_sharded`, but returns once `shutdown` is used
+/// (see `ShutdownHandle`).
+pub fn run_until_shutdown(
+    acceptor: TcpListener,
+    this_server_num: u32,
+    total_chain_servers: u32,
+    prev_server: Option<SocketAddr>,
+    next_server: Option<IpAddr>,
+    num_workers: usize,
+    num_ordering_threads: usize,
+    admin_acceptor: Option<::std::net::TcpListener>,
+    limits: Limits,
+    shutdown: &ShutdownHandle,
+    ready: &AtomicUsize,
+) {
     use std::cmp::max;
 
     //let (dist_to_workers, recv_from_dist) = spmc::channel();
     //let (log_to_workers, recv_from_log) = spmc::channel();
-    //TODO or sync channel?
-    let (workers_to_log, recv_from_workers) = mpsc::channel();
     let (workers_to_dist, dist_from_workers) = mio::channel::channel();
     if num_workers == 0 {
         warn!("SERVER {} started with 0 workers.", this_server_num);
@@ -202,12 +316,31 @@ pub fn run_with_replication(
 
     let num_workers = max(num_workers, 1);
 
+    assert!(is_unreplicated || num_ordering_threads <= 1,
+        "SERVER {} has replication, which needs a single ordering thread, not {}",
+        this_server_num, num_ordering_threads);
+    let num_shards = max(num_ordering_threads, 1);
+    let shards = ShardMap::new(this_server_num, total_chain_servers, num_shards as u32);
+    let (workers_to_log, recv_from_workers): (Vec<_>, Vec<_>) = (0..num_shards)
+        .map(|_| mpsc::sync_channel(max(limits.log_queue_len, 1)))
+        .unzip();
+    let (log_writers, log_readers): (Vec<_>, Vec<_>) = (0..num_shards)
+        .map(|_| ::new_chain_store_and_reader())
+        .unzip();
+
+    let clients: admin::Clients = Arc::new(Mutex::new(Default::default()));
+    let admin_thread = admin_acceptor.and_then(|admin_acceptor| admin::spawn(
+        admin_acceptor, workers_to_log.clone(), shards, clients.clone(), shutdown.clone()
+    ));
+
     let mut poll = mio::Poll::new().unwrap();
     poll.register(&acceptor,
         ACCEPT,
         mio::Ready::readable(),
         mio::PollOpt::level()
     ).expect("cannot start server poll");
+    let _shutdown_registration = shutdown.register(&poll, SHUTDOWN)
+        .expect("cannot start server poll");
     let mut events = mio::Events::with_capacity(1023);
 
     //let next_server_ip: Option<_> = Some(panic!());
@@ -274,33 +407,44 @@ pub fn run_with_replication(
     // drop(downstream_admin_socket);
     // drop(upstream_admin_socket);
 
-    let mut log_to_workers: Vec<_> = Vec::with_capacity(num_workers);
+    //indexed by shard then worker
+    let mut log_to_workers: Vec<Vec<_>> = (0..num_shards)
+        .map(|_| Vec::with_capacity(num_workers))
+        .collect();
     let mut dist_to_workers: Vec<_> = Vec::with_capacity(num_workers);
-    let (log_writer, log_reader) = ::new_chain_store_and_reader();
+    let mut worker_threads = Vec::with_capacity(num_workers);
     for n in 0..num_workers {
         //let from_dist = recv_from_dist.clone();
         let to_dist   = workers_to_dist.clone();
         //let from_log  = recv_from_log.clone();
         let to_log = workers_to_log.clone();
-        let (to_worker, from_log) = spsc::channel();
+        let from_log = log_to_workers.iter_mut().map(|to_workers| {
+            let (to_worker, from_log) = spsc::channel();
+            to_workers.push(to_worker);
+            from_log
+        }).collect();
         let (dist_to_worker, from_dist) = spsc::channel();
-        let log_reader = log_reader.clone();
-        thread::spawn(move ||
+        let log_reader = log_readers.clone();
+        let clients = clients.clone();
+        let worker_thread = thread::spawn(move ||
             Worker::new(
                 from_dist,
                 to_dist,
                 from_log,
                 to_log,
                 log_reader,
+                shards,
                 num_workers,
                 is_unreplicated,
                 prev_server.is_some(),
                 next_server.is_some(),
                 n,
+                limits.max_in_flight_per_client,
+                clients,
             ).run()
         );
-        log_to_workers.push(to_worker);
         dist_to_workers.push(dist_to_worker);
+        worker_threads.push(worker_thread);
     }
     assert_eq!(dist_to_workers.len(), num_workers);
 
@@ -312,43 +456,19 @@ pub fn run_with_replication(
         // mio::Ready::readable(),
         // mio::PollOpt::level()
     // ).expect("cannot pol from log on dist");
-    thread::spawn(move || {
-        let mut log = ServerLog::new(
-            this_server_num, total_chain_servers, log_to_workers, log_writer
-        );
-        #[cfg(not(feature = "print_stats"))]
-        for to_log in recv_from_workers.iter() {
-            match to_log {
-                ToLog::New(buffer, storage, st) => {
-                    // assert!(!is_replica);
-                    log.handle_op(buffer, storage, st)
-                },
-                ToLog::Replication(tr, st) => {
-                    // assert!(is_replica);
-                    log.handle_replication(tr, st)
-                },
-                ToLog::Recovery(r, st) => log.handle_recovery(r, st),
-            }
-        }
-        #[cfg(feature = "print_stats")]
-        loop {
-            use std::sync::mpsc::RecvTimeoutError;
-            let msg = recv_from_workers.recv_timeout(Duration::from_secs(10));
-            match msg {
-                Ok(ToLog::New(buffer, storage, st)) => {
-                    assert!(!is_replica);
-                    log.handle_op(buffer, storage, st)
-                },
-                Ok(ToLog::Replication(tr, st)) => {
-                    assert!(is_replica);
-                    log.handle_replication(tr, st)
-                },
-                Ok(ToLog::Recovery(r, st)) => log.handle_recovery(r, st),
-                Err(RecvTimeoutError::Timeout) => log.print_stats(),
-                Err(RecvTimeoutError::Disconnected) => panic!("log disconnected"),
-            }
-        }
-    });
+    let max_batch = max(limits.max_batch, 1);
+    let shard_parts = log_to_workers.into_iter()
+        .zip(log_writers)
+        .zip(recv_from_workers)
+        .enumerate();
+    let log_threads: Vec<_> = shard_parts.map(
+        |(shard, ((log_to_workers, log_writer), recv_from_workers))| thread::spawn(move || {
+            let log = ServerLog::new_shard(
+                this_server_num, total_chain_servers, shards, shard, log_to_workers, log_writer
+            );
+            run_log(log, recv_from_workers, max_batch)
+        })
+    ).collect();
 
     poll.register(&dist_from_workers,
         FROM_WORKERS,
@@ -384,7 +504,7 @@ pub fn run_with_replication(
 
     // let mut accepted = 0;
     trace!("SERVER start server loop");
-    loop {
+    'serve: loop {
         let _ = poll.poll(&mut events, None);
         for event in events.iter() {
             // println!("{:?} event {:?}", acceptor.local_addr(), event.token());
@@ -402,6 +522,7 @@ pub fn run_with_replication(
                                 let worker = worker_for_ip(id, num_workers as u64);
                                 let old = worker_for_client.insert(id, (worker, up_tok));
                                 assert!(old.is_none(), "Duplicate id {:?}", id);
+                                clients.lock().unwrap().insert(id, worker);
                                 // println!("SERVER accepting connection @ {:?}, {:?}", (_addr, id), (worker, up_tok));
                                 dist_to_workers[worker]
                                     .send(DistToWorker::NewClient(up_tok, upstream, down, id));
@@ -412,6 +533,8 @@ pub fn run_with_replication(
                     }
                 }
 
+                SHUTDOWN => break 'serve,
+
                 FROM_WORKERS => unreachable!(),
                 DIST_FROM_LOG => unreachable!(),
 
@@ -421,6 +544,7 @@ pub fn run_with_replication(
                         let worker = worker_for_ip(id, num_workers as u64);
                         let old = worker_for_client.insert(id, (worker, up_tok));
                         assert!(old.is_none(), "Duplicate id {:?}", id);
+                        clients.lock().unwrap().insert(id, worker);
                         // println!("SERVER accepting connection @ {:?} => {:?} ({:?} => {:?}), {:?}, {:?}",
                         //     upstream.local_addr(), upstream.peer_addr(),
                         //     down.as_ref().map(|&(_, ref d)| d.local_addr()),
@@ -435,6 +559,125 @@ pub fn run_with_replication(
             }
         }
     }
+
+    // Stop taking new connections, then let each worker finish what its
+    // clients have already sent. The ordering threads are only stopped once
+    // every worker is done since any worker may still need any shard.
+    trace!("SERVER {} shutting down", this_server_num);
+    let _ = poll.deregister(&acceptor);
+    drop(acceptor);
+    drop(negotiator);
+    for to_worker in &dist_to_workers {
+        to_worker.send(DistToWorker::Shutdown);
+    }
+    for worker in worker_threads {
+        let _ = worker.join();
+    }
+    for to_log in &workers_to_log {
+        let _ = to_log.send(ToLog::Stop);
+    }
+    for log in log_threads {
+        let _ = log.join();
+    }
+    if let Some(admin_thread) = admin_thread {
+        let _ = admin_thread.join();
+    }
+    trace!("SERVER {} shut down", this_server_num);
+    shutdown.finished()
+}
+
+type WorkerData = (usize, mio::Token, Ipv4SocketAddr);
+
+/// The loop run by each ordering thread.
+fn run_log(
+    mut log: ServerLog<WorkerData, Vec<spsc::Sender<::ToWorker<WorkerData>>>>,
+    recv_from_workers: mpsc::Receiver<ToLog<WorkerData>>,
+    max_batch: usize,
+) {
+    // new requests already waiting in the queue are ordered together,
+    // anything else flushes the batch first so the order is kept
+    #[cfg(not(feature = "print_stats"))]
+    {
+        let mut batch = Vec::with_capacity(max_batch);
+        let mut next = None;
+        loop {
+            let to_log = match next.take() {
+                Some(to_log) => to_log,
+                None => match recv_from_workers.recv() {
+                    Ok(to_log) => to_log,
+                    Err(..) => break,
+                },
+            };
+            match to_log {
+                ToLog::New(buffer, storage, st) => {
+                    // assert!(!is_replica);
+                    batch.push((buffer, storage, st));
+                    if batch.len() < max_batch {
+                        next = recv_from_workers.try_recv().ok();
+                        if next.is_some() {
+                            continue
+                        }
+                    }
+                    log.handle_ops(batch.drain(..))
+                },
+                ToLog::Replication(tr, st) => {
+                    // assert!(is_replica);
+                    log.handle_ops(batch.drain(..));
+                    log.handle_replication(tr, st)
+                },
+                ToLog::Recovery(r, st) => {
+                    log.handle_ops(batch.drain(..));
+                    log.handle_recovery(r, st)
+                },
+                ToLog::Admin(request, reply) => {
+                    log.handle_ops(batch.drain(..));
+                    let _ = reply.send(log.handle_admin(request));
+                },
+                ToLog::ShardSkeens1(append, st) => {
+                    log.handle_ops(batch.drain(..));
+                    log.handle_shard_skeens1(append, st)
+                },
+                ToLog::ShardSkeens2(append) => {
+                    log.handle_ops(batch.drain(..));
+                    log.handle_shard_skeens2(append)
+                },
+                ToLog::Drain(done) => {
+                    log.handle_ops(batch.drain(..));
+                    let _ = done.send(());
+                },
+                ToLog::Stop => {
+                    log.handle_ops(batch.drain(..));
+                    break
+                },
+            }
+        }
+    }
+    #[cfg(feature = "print_stats")]
+    loop {
+        use std::sync::mpsc::RecvTimeoutError;
+        use std::time::Duration;
+        let msg = recv_from_workers.recv_timeout(Duration::from_secs(10));
+        match msg {
+            Ok(ToLog::New(buffer, storage, st)) => {
+                // assert!(!is_replica);
+                log.handle_op(buffer, storage, st)
+            },
+            Ok(ToLog::Replication(tr, st)) => {
+                // assert!(is_replica);
+                log.handle_replication(tr, st)
+            },
+            Ok(ToLog::Recovery(r, st)) => log.handle_recovery(r, st),
+            Ok(ToLog::Admin(request, reply)) => {
+                let _ = reply.send(log.handle_admin(request));
+            },
+            Ok(ToLog::ShardSkeens1(append, st)) => log.handle_shard_skeens1(append, st),
+            Ok(ToLog::ShardSkeens2(append)) => log.handle_shard_skeens2(append),
+            Ok(ToLog::Drain(done)) => { let _ = done.send(()); },
+            Ok(ToLog::Stop) => break,
+            Err(RecvTimeoutError::Timeout) => log.print_stats(),
+            Err(RecvTimeoutError::Disconnected) => panic!("log disconnected"),
+        }
+    }
 }
 
 pub fn blocking_read<R: Read>(r: &mut R, mut buffer: &mut [u8]) -> io::Result<()> {
@@ -496,8 +739,9 @@ mod tests {
     use std::io::{Read, Write};
     use std::net::TcpStream;
 
-    use packets::{OrderIndex, EntryFlag, EntryContents, Uuid};
+    use packets::{OrderIndex, EntryFlag, EntryContents, EntryKind, Uuid};
     use packets::SingletonBuilder as Data;
+    use super::Limits;
 
     /*pub fn run(
         acceptor: TcpListener,
@@ -513,6 +757,12 @@ mod tests {
     const replicas_addr: &'static [&'static str] = &["0.0.0.0:13491", "0.0.0.0:13492"];
     static BASIC_SERVER_READY: AtomicUsize = ATOMIC_USIZE_INIT;
     static REPLICAS_READY: AtomicUsize = ATOMIC_USIZE_INIT;
+    #[allow(non_upper_case_globals)]
+    const sharded_addr: &'static str = "0.0.0.0:13493";
+    static SHARDED_READY: AtomicUsize = ATOMIC_USIZE_INIT;
+    #[allow(non_upper_case_globals)]
+    const overloaded_addr: &'static str = "0.0.0.0:13494";
+    static OVERLOADED_READY: AtomicUsize = ATOMIC_USIZE_INIT;
 
     #[test]
     fn test_write() {
@@ -839,6 +1089,312 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_skeens_recovery() {
+        let _ = env_logger::init();
+        trace!("TCP test skeens recovery");
+        start_servers(basic_addr, &BASIC_SERVER_READY);
+        trace!("TCP test skeens recovery start");
+        let mut writer = TcpStream::connect(&"127.0.0.1:13490").unwrap();
+        let mut recoverer = TcpStream::connect(&"127.0.0.1:13490").unwrap();
+        let mut other = TcpStream::connect(&"127.0.0.1:13490").unwrap();
+        let _ = writer.set_nodelay(true);
+        let _ = recoverer.set_nodelay(true);
+        let _ = other.set_nodelay(true);
+        let locs = [OrderIndex(11.into(), 0.into()), OrderIndex(12.into(), 0.into())];
+        let mut buffer = Buffer::empty();
+        let id = Uuid::new_v4();
+        buffer.fill_from_entry_contents(EntryContents::Multi{
+            id: &id,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock),
+            lock: &0,
+            locs: &locs,
+            deps: &[],
+            data: &[94, 49, 0xff],
+        });
+        writer.write_all(buffer.entry_slice()).unwrap();
+        writer.write_all(&[0; 6]).unwrap();
+        buffer[..].iter_mut().fold((), |_, i| *i = 0);
+        recv_packet(&mut buffer, &mut writer);
+        assert!(buffer.contents().flag().contains(EntryFlag::Skeens1Queued));
+        let timestamp = buffer.contents().locs()[0];
+        assert!(u64::from(timestamp.1) > 0);
+        let max_timestamp = buffer.contents().locs().iter()
+        .fold(0, |max_ts, &OrderIndex(_, i)|
+            ::std::cmp::max(max_ts, u64::from(i))
+        );
+
+        //the writer never sends phase 2, so someone else takes over
+        let update = EntryContents::UpdateRecovery{
+            old_recoverer: &Uuid::nil(),
+            write_id: &id,
+            flags: &EntryFlag::Nothing,
+            lock: &0,
+            locs: &locs[..1],
+        };
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(update.clone());
+        recoverer.write_all(buffer.entry_slice()).unwrap();
+        recoverer.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut recoverer);
+        assert!(buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+        assert_eq!(buffer.contents().lock_num(), 1);
+
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(update);
+        other.write_all(buffer.entry_slice()).unwrap();
+        other.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut other);
+        assert!(!buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+
+        let check = EntryContents::CheckSkeens1{
+            id: &id,
+            flags: &EntryFlag::Nothing,
+            data_bytes: &0,
+            dependency_bytes: &0,
+            loc: &locs[0],
+        };
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(check.clone());
+        recoverer.write_all(buffer.entry_slice()).unwrap();
+        recoverer.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut recoverer);
+        assert!(buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+        assert_eq!(buffer.contents().locs()[0], timestamp);
+
+        //sending phase 2 twice must be harmless
+        for _ in 0..2 {
+            buffer.clear_data();
+            buffer.fill_from_entry_contents(EntryContents::Senti{
+                id: &id,
+                flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock | EntryFlag::Unlock),
+                data_bytes: &0,
+                lock: &max_timestamp,
+                locs: &locs,
+                deps: &[],
+            });
+            recoverer.write_all(buffer.entry_slice()).unwrap();
+            recoverer.write_all(&[0; 6]).unwrap();
+        }
+
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut writer);
+        assert_eq!(buffer.contents(), EntryContents::Multi{
+            id: &id,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock | EntryFlag::ReadSuccess),
+            lock: &0,
+            locs: &[OrderIndex(11.into(), 1.into()), OrderIndex(12.into(), 1.into())],
+            deps: &[],
+            data: &[94, 49, 0xff],
+        });
+
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(check);
+        recoverer.write_all(buffer.entry_slice()).unwrap();
+        recoverer.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut recoverer);
+        assert!(!buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+        assert_eq!(buffer.contents().locs()[0], locs[0]);
+    }
+
+    #[test]
+    fn test_sharded_skeens() {
+        let _ = env_logger::init();
+        trace!("TCP test sharded skeens");
+        //chain c belongs to shard c % 3
+        start_sharded_server(sharded_addr, 3, Limits::default(), &SHARDED_READY);
+        trace!("TCP test sharded skeens start");
+        let mut stream = TcpStream::connect(&"127.0.0.1:13493").unwrap();
+        let _ = stream.set_nodelay(true);
+        let mut buffer = Buffer::empty();
+
+        let id = Uuid::new_v4();
+        let locs = [OrderIndex(3.into(), 0.into()), OrderIndex(4.into(), 0.into())];
+        buffer.fill_from_entry_contents(EntryContents::Multi{
+            id: &id,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock),
+            lock: &0,
+            locs: &locs,
+            deps: &[],
+            data: &[94, 49, 0xff],
+        });
+        stream.write_all(buffer.entry_slice()).unwrap();
+        stream.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut stream);
+        assert_eq!(buffer.contents().id(), &id);
+        assert!(buffer.contents().flag().contains(EntryFlag::Skeens1Queued));
+        let max_timestamp = buffer.contents().locs().iter()
+        .fold(0, |max_ts, &OrderIndex(_, i)|
+            ::std::cmp::max(max_ts, u64::from(i))
+        );
+        assert!(max_timestamp > 0);
+
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(EntryContents::Senti{
+            id: &id,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock | EntryFlag::Unlock),
+            data_bytes: &0,
+            lock: &max_timestamp,
+            locs: &locs,
+            deps: &[],
+        });
+        stream.write_all(buffer.entry_slice()).unwrap();
+        stream.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut stream);
+        assert_eq!(buffer.contents(), EntryContents::Multi{
+            id: &id,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock | EntryFlag::ReadSuccess),
+            lock: &0,
+            locs: &[OrderIndex(3.into(), 1.into()), OrderIndex(4.into(), 1.into())],
+            deps: &[],
+            data: &[94, 49, 0xff],
+        });
+
+        //a multiappend whose phase 2 never comes is found from either of its chains
+        let id2 = Uuid::new_v4();
+        let locs2 = [OrderIndex(7.into(), 0.into()), OrderIndex(8.into(), 0.into())];
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(EntryContents::Multi{
+            id: &id2,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock),
+            lock: &0,
+            locs: &locs2,
+            deps: &[],
+            data: &[7, 8],
+        });
+        stream.write_all(buffer.entry_slice()).unwrap();
+        stream.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut stream);
+        assert_eq!(buffer.contents().id(), &id2);
+        assert!(buffer.contents().flag().contains(EntryFlag::Skeens1Queued));
+
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(EntryContents::CheckSkeens1{
+            id: &Uuid::nil(),
+            flags: &EntryFlag::Nothing,
+            data_bytes: &0,
+            dependency_bytes: &0,
+            loc: &locs2[1],
+        });
+        stream.write_all(buffer.entry_slice()).unwrap();
+        stream.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut stream);
+        assert_eq!(buffer.contents().kind(), EntryKind::UpdateRecovery);
+        assert_eq!(buffer.contents().id(), &id2);
+        assert!(buffer.contents().flag().contains(EntryFlag::Skeens1Queued));
+        assert_eq!(buffer.contents().locs(), &locs2[..]);
+
+        //both shards handle the cancel, but the client only hears about it once
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(EntryContents::Senti{
+            id: &id2,
+            flags: &(EntryFlag::NewMultiPut | EntryFlag::TakeLock | EntryFlag::Unlock),
+            data_bytes: &0,
+            lock: &0,
+            locs: &locs2,
+            deps: &[],
+        });
+        stream.write_all(buffer.entry_slice()).unwrap();
+        stream.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut stream);
+        assert_eq!(buffer.contents().id(), &id2);
+        assert!(buffer.contents().is_multi_cancel());
+
+        buffer.clear_data();
+        buffer.fill_from_entry_contents(EntryContents::Read{
+            id: &Uuid::nil(),
+            flags: &EntryFlag::Nothing,
+            data_bytes: &0,
+            dependency_bytes: &0,
+            loc: &OrderIndex(3.into(), 1.into()),
+            horizon: &OrderIndex(0.into(), 0.into()),
+            min: &OrderIndex(0.into(), 0.into()),
+        });
+        stream.write_all(buffer.entry_slice()).unwrap();
+        stream.write_all(&[0; 6]).unwrap();
+        buffer.clear_data();
+        recv_packet(&mut buffer, &mut stream);
+        assert_eq!(buffer.contents().id(), &id);
+        assert!(buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+    }
+
+    #[test]
+    fn test_refuse_overloaded() {
+        let _ = env_logger::init();
+        trace!("TCP test refuse overloaded");
+        let limits = Limits { log_queue_len: 1, max_in_flight_per_client: 1, max_batch: 1 };
+        start_sharded_server(overloaded_addr, 1, limits, &OVERLOADED_READY);
+        trace!("TCP test refuse overloaded start");
+        let mut stream = TcpStream::connect(&"127.0.0.1:13494").unwrap();
+        let _ = stream.set_nodelay(true);
+        let mut buffer = Buffer::empty();
+
+        //sent all at once, so the worker has most of them before the log answers any
+        let mut appends = vec![];
+        for i in 0..50u32 {
+            Data(&i, &[]).fill_entry(&mut buffer);
+            buffer.contents_mut().locs_mut()[0] = OrderIndex(30.into(), 0.into());
+            appends.extend_from_slice(buffer.entry_slice());
+            appends.extend_from_slice(&[0; 6]);
+        }
+        stream.write_all(&appends).unwrap();
+        let mut refused = vec![];
+        for _ in 0..50 {
+            buffer.clear_data();
+            recv_packet(&mut buffer, &mut stream);
+            if !buffer.contents().flag().contains(EntryFlag::ReadSuccess) {
+                assert_eq!(buffer.contents().locs()[0], OrderIndex(30.into(), 0.into()));
+                refused.push(buffer.entry_slice().to_vec());
+            }
+        }
+        assert!(!refused.is_empty());
+
+        //one at a time the refused appends get through
+        for append in refused {
+            stream.write_all(&append).unwrap();
+            stream.write_all(&[0; 6]).unwrap();
+            buffer.clear_data();
+            recv_packet(&mut buffer, &mut stream);
+            assert!(buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+        }
+
+        let mut values = vec![];
+        for i in 1..52u64 {
+            buffer.clear_data();
+            buffer.fill_from_entry_contents(EntryContents::Read {
+                id: &Uuid::nil(),
+                flags: &EntryFlag::Nothing,
+                data_bytes: &0,
+                dependency_bytes: &0,
+                loc: &OrderIndex(30.into(), i.into()),
+                horizon: &OrderIndex(0.into(), 0.into()),
+                min: &OrderIndex(0.into(), 0.into()),
+            });
+            stream.write_all(buffer.entry_slice()).unwrap();
+            stream.write_all(&[0; 6]).unwrap();
+            buffer.clear_data();
+            recv_packet(&mut buffer, &mut stream);
+            if i == 51 {
+                assert!(!buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+                break
+            }
+            assert!(buffer.contents().flag().contains(EntryFlag::ReadSuccess));
+            let Data(&v, _) = buffer.contents().into_singleton_builder::<u32>();
+            values.push(v);
+        }
+        values.sort();
+        assert_eq!(values, (0..50).collect::<Vec<_>>());
+    }
+
     #[test]
     fn test_replicated_skeens_write() {
         let _ = env_logger::init();
@@ -1395,6 +1951,33 @@ mod tests {
 
         while server_ready.load(Ordering::Acquire) < addr_strs.len() {}
     }
+
+    fn start_sharded_server(
+        addr_str: &str,
+        num_ordering_threads: usize,
+        limits: Limits,
+        server_ready: &'static AtomicUsize,
+    ) {
+        use std::thread;
+
+        use mio;
+
+        trace!("starting sharded server @ {:?}", addr_str);
+        let addr = addr_str.parse().expect("invalid inet address");
+        let acceptor = mio::tcp::TcpListener::bind(&addr);
+        if let Ok(acceptor) = acceptor {
+            thread::spawn(move || {
+                trace!("starting server");
+                ::tcp::run_sharded(acceptor, 0, 1, None, None,
+                    1, num_ordering_threads, None, limits, server_ready)
+            });
+        }
+        else {
+            trace!("server already started @ {}", addr_str);
+        }
+
+        while server_ready.load(Ordering::Acquire) < 1 {}
+    }
 }
 
 fn get_next_token(token: &mut mio::Token) -> mio::Token {
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use mio;
+
+/// Stops a server started with `run_until_shutdown`.
+///
+/// Shutting down a server stops it from accepting new connections,
+/// lets the ordering threads finish every request the server has already
+/// received, sends the resulting acks and replication downstream, and then
+/// stops every server thread, closing the server's connections.
+///
+/// Requests which need more messages from a client to finish, such as the
+/// second round of a multi-server multiappend, are abandoned.
+/// A replica only replicates what it has received from upstream by the time
+/// it is shut down, so a replication chain should be shut down head first.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requested: AtomicBool,
+    wake_server: Mutex<Option<mio::SetReadiness>>,
+    finished: Mutex<bool>,
+    on_finish: Condvar,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start shutting the server down without waiting for it to finish.
+    pub fn request(&self) {
+        self.inner.requested.store(true, Ordering::SeqCst);
+        if let Some(ref wake) = *self.inner.wake_server.lock().unwrap() {
+            let _ = wake.set_readiness(mio::Ready::readable());
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Block until the server has finished shutting down.
+    pub fn wait(&self) {
+        let mut finished = self.inner.finished.lock().unwrap();
+        while !*finished {
+            finished = self.inner.on_finish.wait(finished).unwrap();
+        }
+    }
+
+    /// Shut the server down, returning once it has finished.
+    pub fn shutdown(&self) {
+        self.request();
+        self.wait()
+    }
+
+    /// Register the server's poll to be woken by `request`.
+    pub(super) fn register(&self, poll: &mio::Poll, token: mio::Token)
+    -> ::std::io::Result<mio::Registration> {
+        let (registration, wake) = mio::Registration::new2();
+        poll.register(&registration, token, mio::Ready::readable(), mio::PollOpt::level())?;
+        let mut wake_server = self.inner.wake_server.lock().unwrap();
+        //NOTE checked with the lock held so a concurrent `request` cannot be missed
+        if self.is_requested() {
+            let _ = wake.set_readiness(mio::Ready::readable());
+        }
+        *wake_server = Some(wake);
+        Ok(registration)
+    }
+
+    pub(super) fn finished(&self) {
+        *self.inner.finished.lock().unwrap() = true;
+        self.inner.on_finish.notify_all();
+    }
+}
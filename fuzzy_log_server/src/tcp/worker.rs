@@ -1,18 +1,20 @@
 #![allow(non_snake_case)]
 
 use std::collections::VecDeque;
-use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::mpsc::{self, TrySendError};
+use std::time::Duration;
 
 use ::{
-    spsc, worker_thread, ToReplicate, ToWorker,
+    admin, spsc, worker_thread, ToReplicate, ToWorker,
     DistributeToWorkers, Troption, Recovery, SkeensMultiStorage,
-    ToSend, ChainReader,
+    ToSend, ChainReader, ShardMap, ShardedAppend,
 };
 use shared_slice::RcSlice;
-use hash::HashMap;
+use hash::{HashMap, UuidHashSet};
 use socket_addr::Ipv4SocketAddr;
 
-use packets::{EntryLayout, OrderIndex, EntryFlag};
+use packets::{EntryKind, EntryLayout, EntryFlag, Uuid};
 
 use mio;
 use mio::tcp::*;
@@ -24,6 +26,7 @@ use byteorder::{ByteOrder, LittleEndian};
 //use super::{DistToWorker, WorkerToDist, ToLog, WorkerNum};
 use super::*;
 use super::per_socket::{PerSocket, PerStream};
+use super::admin::Clients;
 
 use reactor::*;
 
@@ -40,15 +43,24 @@ pub enum DistToWorker {
     NewClient(mio::Token, TcpStream, Option<(mio::Token, TcpStream)>, Ipv4SocketAddr),
     FenceOff(mio::Token, Buffer),
     FinishedFence(mio::Token, Buffer),
+    Shutdown,
 }
 
 pub enum ToLog<T> {
     //TODO test different layouts.
     New(Buffer, Troption<SkeensMultiStorage, Box<(RcSlice, RcSlice)>>, T),
     Replication(ToReplicate, T),
-
-    #[allow(dead_code)]
     Recovery(Recovery, T),
+
+    Admin(admin::Request, mpsc::Sender<admin::Response>),
+
+    ShardSkeens1(Arc<ShardedAppend>, T),
+    ShardSkeens2(Arc<ShardedAppend>),
+
+    /// Answered once everything sent to the log before it has been handled.
+    Drain(mpsc::Sender<()>),
+    /// Stops the ordering thread.
+    Stop,
 }
 
 pub struct Worker {
@@ -59,10 +71,15 @@ pub struct Worker {
 pub struct WorkerInner {
     from_dist: spsc::Receiver<DistToWorker>,
     to_dist: mio::channel::Sender<WorkerToDist>,
-    from_log: spsc::Receiver<ToWorker<(WorkerNum, mio::Token, Ipv4SocketAddr)>>,
-    to_log: mpsc::Sender<ToLog<(WorkerNum, mio::Token, Ipv4SocketAddr)>>,
-    log_reader: ChainReader<(WorkerNum, mio::Token, Ipv4SocketAddr)>,
+    //one of each per shard
+    from_log: Vec<spsc::Receiver<ToWorker<(WorkerNum, mio::Token, Ipv4SocketAddr)>>>,
+    to_log: Vec<mpsc::SyncSender<ToLog<(WorkerNum, mio::Token, Ipv4SocketAddr)>>>,
+    log_reader: Vec<ChainReader<(WorkerNum, mio::Token, Ipv4SocketAddr)>>,
+    shards: ShardMap,
     downstream_for_addr: HashMap<Ipv4SocketAddr, mio::Token>,
+    //the client each upstream belongs to, so it can be forgotten once closed
+    addr_for_upstream: HashMap<mio::Token, Ipv4SocketAddr>,
+    clients: Clients,
     worker_num: WorkerNum,
     num_workers: WorkerNum,
     poll: mio::Poll,
@@ -73,12 +90,30 @@ pub struct WorkerInner {
 
     remove_backpressure: VecDeque<mio::Token>,
 
+    //appends from each client which the log has not yet responded to,
+    //approximate since we don't know which responses are for appends
+    in_flight: HashMap<mio::Token, usize>,
+    max_in_flight_per_client: usize,
+
+    //requests without skeens which were sent to several shards,
+    //how many of them have yet to reply, and whether all that did succeeded
+    shard_replies: HashMap<(mio::Token, Uuid), (usize, bool)>,
+
+    //the last RECENT_APPENDS appends given to the log, oldest first,
+    //so a copy of one duplicated by the network is not appended again
+    recent_appends: UuidHashSet,
+    recent_append_order: VecDeque<Uuid>,
 
     next_token: usize,
 
+    //set once the server is shutting down, new requests are ignored
+    finished: bool,
+
     print_data: WorkerData,
 }
 
+const RECENT_APPENDS: usize = 1 << 12;
+
 counters! {
     struct WorkerData {
         from_dist_N: u64,
@@ -91,6 +126,8 @@ counters! {
         new_to_log: u64,
         to_log: u64,
         rep_to_log: u64,
+        overloaded: u64,
+        duplicate: u64,
     }
 }
 
@@ -118,12 +155,15 @@ impl Wakeable for WorkerInner {
             mio::PollOpt::level() //TODO or edge?
         ).expect("cannot pol from dist on worker");
 
-        poll.register(
-            &self.from_log,
-            FROM_LOG,
-            mio::Ready::readable(),
-            mio::PollOpt::level() //TODO or edge?
-        ).expect("cannot pol from log on worker");
+        //every shard shares the token, handle_from_log checks them all
+        for from_log in &self.from_log {
+            poll.register(
+                from_log,
+                FROM_LOG,
+                mio::Ready::readable(),
+                mio::PollOpt::level() //TODO or edge?
+            ).expect("cannot pol from log on worker");
+        }
     }
 
     fn needs_to_mark_as_staying_awake(&mut self, _: mio::Token) -> bool { false }
@@ -142,7 +182,7 @@ impl Handler<IoState<PerStream>> for WorkerInner {
     fn on_poll(&mut self, inner: &mut IoState<PerStream>, token: mio::Token)
     -> Result<(), Self::Error> {
         match token {
-            FROM_LOG => self.handle_from_log(inner),
+            FROM_LOG => { self.handle_from_log(inner); },
             FROM_DIST => self.handle_from_dist(inner),
             _ => {},
         }
@@ -153,11 +193,24 @@ impl Handler<IoState<PerStream>> for WorkerInner {
         false
     }
 
+    fn on_stream_removed(&mut self, token: mio::Token) {
+        self.in_flight.remove(&token);
+        if let Some(addr) = self.addr_for_upstream.remove(&token) {
+            trace!("WORKER {} client {:?} disconnected", self.worker_num, addr);
+            self.downstream_for_addr.remove(&addr);
+            self.clients.lock().unwrap().remove(&addr);
+        }
+    }
+
     fn after_work(&mut self, inner: &mut IoState<PerStream>) {
         for token in self.remove_backpressure.drain(..) {
             inner.mutate(token, |s| s.mark_as_not_backpressured());
         }
     }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
 }
 
 impl Worker {
@@ -165,14 +218,17 @@ impl Worker {
     pub fn new(
         from_dist: spsc::Receiver<DistToWorker>,
         to_dist: mio::channel::Sender<WorkerToDist>,
-        from_log: spsc::Receiver<ToWorker<(WorkerNum, mio::Token, Ipv4SocketAddr)>>,
-        to_log: mpsc::Sender<ToLog<(WorkerNum, mio::Token, Ipv4SocketAddr)>>,
-        log_reader: ChainReader<(WorkerNum, mio::Token, Ipv4SocketAddr)>,
+        from_log: Vec<spsc::Receiver<ToWorker<(WorkerNum, mio::Token, Ipv4SocketAddr)>>>,
+        to_log: Vec<mpsc::SyncSender<ToLog<(WorkerNum, mio::Token, Ipv4SocketAddr)>>>,
+        log_reader: Vec<ChainReader<(WorkerNum, mio::Token, Ipv4SocketAddr)>>,
+        shards: ShardMap,
         num_workers: usize,
         is_unreplicated: bool,
         has_upstream: bool,
         has_downstream: bool,
         worker_num: WorkerNum,
+        max_in_flight_per_client: usize,
+        clients: Clients,
     ) -> Self {
         let poll = mio::Poll::new().unwrap();
         let inner = WorkerInner {
@@ -181,7 +237,10 @@ impl Worker {
             from_log,
             to_log,
             log_reader,
+            shards,
             downstream_for_addr: HashMap::default(),
+            addr_for_upstream: HashMap::default(),
+            clients,
             worker_num,
             num_workers,
             poll,
@@ -192,7 +251,14 @@ impl Worker {
 
             next_token: FIRST_CLIENT_TOKEN.0,
 
+            finished: false,
+
             remove_backpressure: Default::default(),
+            in_flight: Default::default(),
+            max_in_flight_per_client,
+            shard_replies: Default::default(),
+            recent_appends: Default::default(),
+            recent_append_order: Default::default(),
 
             print_data: Default::default(),
         };
@@ -200,19 +266,37 @@ impl Worker {
         Self { reactor }
     }
 
-    pub fn run(mut self) -> ! {
+    /// Runs until the dist sends `DistToWorker::Shutdown`.
+    pub fn run(mut self) {
         self.reactor.run().unwrap();
-        panic!("should not be")
     }// end fn run
 }
 
 impl WorkerInner {
 
-    fn handle_from_log(&mut self, streams: &mut IoState<PerStream>) {
-        while let Some(log_work) = self.from_log.try_recv() {
+    /// Returns the number of messages handled.
+    fn handle_from_log(&mut self, streams: &mut IoState<PerStream>) -> usize {
+        (0..self.from_log.len()).map(|shard| self.handle_from_shard(shard, streams)).sum()
+    }
+
+    fn handle_from_shard(&mut self, shard: usize, streams: &mut IoState<PerStream>) -> usize {
+        let mut handled = 0;
+        while let Some(log_work) = self.from_log[shard].try_recv() {
+            handled += 1;
             self.print_data.from_log(1);
+            let mut log_work = match log_work {
+                ToWorker::ShardSkeens1(append, t) => match self.shard_skeens1_done(append, t) {
+                    Some(log_work) => log_work,
+                    None => continue,
+                },
+                log_work => log_work,
+            };
+            let is_last_reply = self.is_last_shard_reply(&mut log_work);
             let (_wk, recv_token, src_addr) = log_work.get_associated_data();
             debug_assert_eq!(_wk, self.worker_num);
+            if let Some(in_flight) = self.in_flight.get_mut(&recv_token) {
+                *in_flight = in_flight.saturating_sub(1)
+            }
             let continue_replication = self.has_downstream;
             let send_token = self.downstream_for_addr.get(&src_addr)
                 .cloned()
@@ -224,6 +308,9 @@ impl WorkerInner {
                     if head_ack {
                         unimplemented!()
                     }
+                    if !is_last_reply {
+                        return false
+                    }
                     if continue_replication {
                         // trace!("WORKER {} replicate {:?}", self.inner.worker_num, to_send);
                         self.send_downsteam(streams, send_token, src_addr, to_send)
@@ -243,6 +330,98 @@ impl WorkerInner {
             // buffer.map(|b| self.clients.get_mut(&recv_token).map(|c| c.return_buffer(b)));
             continue
         }
+        handled
+    }
+
+    // Every request this worker has sent to a shard has been handled once the
+    // shard answers a `Drain` sent after it. Handling the log's responses can
+    // send more to the log, e.g. the second round of a sharded multiappend,
+    // so keep going until the log has nothing more for this worker,
+    // then write out everything still buffered for each connection.
+    fn drain(&mut self, streams: &mut IoState<PerStream>) {
+        trace!("WORKER {} draining", self.worker_num);
+        self.finished = true;
+        loop {
+            let (drained, wait_for_drain) = mpsc::channel();
+            for to_log in &self.to_log {
+                to_log.send(ToLog::Drain(drained.clone())).expect("log gone")
+            }
+            drop(drained);
+            for () in wait_for_drain.iter() {}
+            if self.handle_from_log(streams) == 0 {
+                break
+            }
+        }
+        for stream in streams.streams_mut() {
+            if let Err(e) = stream.flush_writes(Duration::from_secs(5)) {
+                error!("WORKER {} could not flush connection: {}", self.worker_num, e)
+            }
+        }
+        trace!("WORKER {} drained", self.worker_num);
+    }
+
+    // Once every shard has timestamped an append spanning shards,
+    // either start round 2 or, if the client is running skeens across servers,
+    // reply with the timestamps the same way an unsharded server would.
+    fn shard_skeens1_done(
+        &mut self,
+        append: Arc<ShardedAppend>,
+        t: (WorkerNum, mio::Token, Ipv4SocketAddr),
+    ) -> Option<ToWorker<(WorkerNum, mio::Token, Ipv4SocketAddr)>> {
+        if !append.finish_round1() {
+            return None
+        }
+        let (storage, buffer) = append.take_storage();
+        match buffer {
+            None => {
+                drop(storage);
+                for &shard in append.shards() {
+                    self.print_data.to_log(1);
+                    self.to_log[shard].send(ToLog::ShardSkeens2(append.clone()))
+                        .expect("log gone")
+                }
+                None
+            },
+            Some(buffer) if append.is_snapshot() =>
+                Some(ToWorker::SnapshotSkeens1{ buffer, storage, t }),
+            Some(buffer) => Some(ToWorker::Skeens1{ buffer, storage, t }),
+        }
+    }
+
+    // Requests without skeens which span shards are handled by each of them,
+    // the client only hears back from the last one,
+    // which only succeeds if all of them did.
+    fn is_last_shard_reply(
+        &mut self,
+        log_work: &mut ToWorker<(WorkerNum, mio::Token, Ipv4SocketAddr)>,
+    ) -> bool {
+        if self.shard_replies.is_empty() {
+            return true
+        }
+        //replicated multiappends are marked successful when they are sent
+        let (buffer, t, is_reply) = match log_work {
+            &mut ToWorker::Reply(ref mut buffer, t) => (buffer, t, true),
+            &mut ToWorker::MultiReplica{ref mut buffer, t, ..} => (buffer, t, false),
+            _ => return true,
+        };
+        let key = (t.1, *buffer.contents().id());
+        let (left, succeeded) = match self.shard_replies.get_mut(&key) {
+            None => return true,
+            Some(&mut (ref mut left, ref mut succeeded)) => {
+                *left -= 1;
+                *succeeded &= !is_reply
+                    || buffer.contents().flag().contains(EntryFlag::ReadSuccess);
+                (*left, *succeeded)
+            },
+        };
+        if left > 0 {
+            return false
+        }
+        self.shard_replies.remove(&key);
+        if !succeeded {
+            buffer.contents_mut().flag_mut().remove(EntryFlag::ReadSuccess);
+        }
+        true
     }
 
     fn send_downsteam(
@@ -381,6 +560,7 @@ impl WorkerInner {
                         self.worker_num, (tok, client_addr));
                     let downstream_token = downstream_token.unwrap_or(upstream_token);
                     self.downstream_for_addr.insert(client_addr, downstream_token);
+                    self.addr_for_upstream.insert(upstream_token, client_addr);
                 },
 
                 Some(DistToWorker::FenceOff(_token, buffer)) => {
@@ -397,6 +577,8 @@ impl WorkerInner {
                     streams.wake(token);
                 },
 
+                Some(DistToWorker::Shutdown) => return self.drain(streams),
+
             }
         }
     }
@@ -409,6 +591,9 @@ impl WorkerInner {
         addr: Ipv4SocketAddr,
         storage_loc: Option<u64>,
     ) -> Result<(), ()> {
+        if self.finished {
+            return Ok(())
+        }
         match storage_loc {
             Some(storage_loc) =>
                 self.send_replication_to_log(token, msg, storage_loc, addr),
@@ -430,10 +615,31 @@ impl WorkerInner {
             let c = buffer.contents();
             (c.kind().clone(), c.flag().clone())
         };
+        //recovery packets have no layout
+        if k == EntryKind::UpdateRecovery || k == EntryKind::CheckSkeens1 {
+            return self.send_recovery_to_log(token, buffer, src_addr)
+        }
         let kind = k.layout();
+        let is_new_append = match kind {
+            EntryLayout::Data | EntryLayout::Multiput
+            | EntryLayout::Sentinel | EntryLayout::Snapshot =>
+                !f.contains(EntryFlag::Unlock) && !f.contains(EntryFlag::DirectWrite),
+            _ => false,
+        };
+        //the client already gets its reply from the first copy
+        if is_new_append && self.recent_appends.contains(buffer.contents().id()) {
+            trace!("WORKER {} duplicate append {:?}", self.worker_num, buffer.contents().id());
+            self.print_data.duplicate(1);
+            return
+        }
+        if is_new_append
+            && self.in_flight.get(&token).map_or(false, |&n| n >= self.max_in_flight_per_client) {
+            return self.refuse_overloaded(socket_state, buffer)
+        }
         let storage = match kind {
             EntryLayout::Read => {
-                worker_thread::handle_read(&self.log_reader, &buffer, worker_num, |to_send| {
+                let shard = self.shards.shard_of(buffer.contents().locs()[0].0);
+                worker_thread::handle_read(&self.log_reader[shard], &buffer, worker_num, |to_send| {
                     match to_send {
                         Ok(to_send) => socket_state.add_bytes_to_write(&[to_send]),
                         Err(to_send) => per_socket::add_contents(socket_state, to_send),
@@ -443,86 +649,193 @@ impl WorkerInner {
                 return
             },
 
-            EntryLayout::Multiput | EntryLayout::Sentinel => {
-                let (size, senti_size, num_locs, has_senti, is_unlock, is_direct_write) = {
+            //each shard places the entry in its own chains
+            EntryLayout::Multiput | EntryLayout::Sentinel
+            if f.contains(EntryFlag::DirectWrite) && !f.contains(EntryFlag::Unlock) => {
+                let (size, senti_size) = {
                     let e = buffer.contents();
-                    let locs = e.locs();
-                    let num_locs = locs.len();
-                    //FIXME
-                    let has_senti = locs.contains(&OrderIndex(0.into(), 0.into()))
-                        || !e.flag().contains(EntryFlag::TakeLock);
-                    (
-                        e.len(),
-                        e.sentinel_entry_size(),
-                        num_locs,
-                        has_senti,
-                        e.flag().contains(EntryFlag::Unlock),
-                        e.flag().contains(EntryFlag::DirectWrite),
-                    )
+                    (e.len(), e.sentinel_entry_size())
                 };
-                if is_unlock {
-                    Troption::None
-                } else if is_direct_write {
+                let t = (worker_num, token, src_addr);
+                let shards = self.shards_for(&buffer);
+                self.expect_shard_replies(&buffer, shards.len(), t);
+                for shard in shards {
                     let storage = {
                         let m = RcSlice::with_len(size);
                         let s = RcSlice::with_len(senti_size);
                         Box::new((m, s))
                     };
-                    let t = (worker_num, token, src_addr);
-                    let to_send = ToLog::Replication(ToReplicate::Multi(buffer, storage), t);
+                    let to_send = ToLog::Replication(ToReplicate::Multi(buffer.clone(), storage), t);
                     self.print_data.to_log(1);
                     //self.waiting_for_log += 1;
-                    return self.to_log.send(to_send).expect("log gone")
-                } else if f.contains(EntryFlag::NewMultiPut) || !f.contains(EntryFlag::TakeLock) {
-                    let senti_size = if has_senti { Some(senti_size) } else { None };
-                    let mut storage = SkeensMultiStorage::new(num_locs, size, senti_size);
-                    //FIXME is this the bug?
-                    if !f.contains(EntryFlag::TakeLock) {
-                        storage.fill_from(&mut buffer)
-                    }
-                    Troption::Left(storage)
-                } else {
-                    let m = RcSlice::with_len(size);
-                    let s = RcSlice::with_len(senti_size);
-                    Troption::Right(Box::new((m, s)))
+                    self.to_log[shard].send(to_send).expect("log gone")
                 }
+                return
             },
 
-            EntryLayout::Snapshot => {
-                let (size, num_locs, is_unlock) = {
-                    let e = buffer.contents();
-                    let locs = e.locs();
-                    (e.len(), locs.len(), e.flag().contains(EntryFlag::Unlock))
-                };
-                if is_unlock {
-                    Troption::None
-                } else {
-                    let mut storage = SkeensMultiStorage::new(num_locs, size, None);
-                    storage.fill_from(&mut buffer);
-                    Troption::Left(storage)
-                }
-
-            }
-
-            EntryLayout::GC => {
-                //TODO send downstream first?
-                Troption::None
-            },
             EntryLayout::Data if f.contains(EntryFlag::DirectWrite) => {
                 let t = (worker_num, token, src_addr);
+                let shard = self.shard_of_first_chain(&buffer);
                 let tr = ToReplicate::Data(buffer, ::std::u64::MAX);
                 let to_send = ToLog::Replication(tr, t);
                 self.print_data.to_log(1);
                 //self.waiting_for_log += 1;
-                return self.to_log.send(to_send).expect("log gone")
+                return self.to_log[shard].send(to_send).expect("log gone")
             }
-            _ => Troption::None,
+            _ => worker_thread::append_storage(&mut buffer),
         };
         self.print_data.new_to_log(1);
         self.print_data.to_log(1);
         //self.waiting_for_log += 1;
-        let to_send = ToLog::New(buffer, storage, (worker_num, token, src_addr));
-        self.to_log.send(to_send).expect("log gone")
+        let t = (worker_num, token, src_addr);
+        let shard = match self.shards.num_shards() {
+            1 => 0,
+            _ => {
+                let mut shards = self.shards.shards_of(buffer.contents().locs());
+                if shards.len() > 1 {
+                    return self.send_to_shards(socket_state, buffer, storage, shards, t)
+                }
+                shards.pop().unwrap_or(0)
+            },
+        };
+        let id = *buffer.contents().id();
+        let to_send = ToLog::New(buffer, storage, t);
+        if !is_new_append {
+            return self.to_log[shard].send(to_send).expect("log gone")
+        }
+        //we only refuse appends that haven't been seen by the log yet,
+        //everything else needs to get through for the appends in progress to finish
+        match self.to_log[shard].try_send(to_send) {
+            Ok(()) => self.admitted(token, id),
+            Err(TrySendError::Full(ToLog::New(buffer, ..))) =>
+                self.refuse_overloaded(socket_state, buffer),
+            Err(TrySendError::Full(..)) => unreachable!(),
+            Err(TrySendError::Disconnected(..)) => panic!("log gone"),
+        }
+    }
+
+    // Each shard only handles its own chains, so requests touching several
+    // shards go to all of them; new multiappends and snapshots also need the
+    // shards to agree on a timestamp, see `::shards`.
+    fn send_to_shards(
+        &mut self,
+        socket_state: &mut TcpWriter,
+        buffer: Buffer,
+        storage: Troption<SkeensMultiStorage, Box<(RcSlice, RcSlice)>>,
+        shards: Vec<usize>,
+        t: (WorkerNum, mio::Token, Ipv4SocketAddr),
+    ) {
+        trace!("WORKER {} send to shards {:?}", self.worker_num, shards);
+        match storage {
+            //like any other new append this is refused if the log is overloaded,
+            //but once the first shard has it the rest must get it too
+            Troption::Left(storage) => {
+                let id = *buffer.contents().id();
+                let append = Arc::new(ShardedAppend::new(buffer, storage, shards));
+                let first = append.shards()[0];
+                match self.to_log[first].try_send(ToLog::ShardSkeens1(append.clone(), t)) {
+                    Ok(()) => self.admitted(t.1, id),
+                    Err(TrySendError::Full(refused)) => {
+                        drop(refused);
+                        let append = Arc::try_unwrap(append).ok().expect("append was not sent");
+                        return self.refuse_overloaded(socket_state, append.into_buffer())
+                    },
+                    Err(TrySendError::Disconnected(..)) => panic!("log gone"),
+                }
+                for &shard in &append.shards()[1..] {
+                    self.to_log[shard].send(ToLog::ShardSkeens1(append.clone(), t))
+                        .expect("log gone")
+                }
+            },
+            Troption::None => {
+                self.expect_shard_replies(&buffer, shards.len(), t);
+                for &shard in &shards[1..] {
+                    self.to_log[shard].send(ToLog::New(buffer.clone(), Troption::None, t))
+                        .expect("log gone")
+                }
+                self.to_log[shards[0]].send(ToLog::New(buffer, Troption::None, t))
+                    .expect("log gone")
+            },
+            //old-style multiappends are ordered by the lock server rather than skeens,
+            //and each shard would need to fill in its part of the same entry,
+            //so they are failed the way a failed unlock is, without ReadSuccess
+            Troption::Right(..) => {
+                error!("WORKER {} old-style multiappend {:?} cannot span shards",
+                    self.worker_num, buffer.contents().id());
+                socket_state.add_bytes_to_write(&[buffer.entry_slice()]);
+            },
+        }
+    }
+
+    fn admitted(&mut self, token: mio::Token, id: Uuid) {
+        *self.in_flight.entry(token).or_insert(0) += 1;
+        if self.recent_append_order.len() >= RECENT_APPENDS {
+            let oldest = self.recent_append_order.pop_front().unwrap();
+            self.recent_appends.remove(&oldest);
+        }
+        self.recent_appends.insert(id);
+        self.recent_append_order.push_back(id);
+    }
+
+    //see `is_last_shard_reply`
+    fn expect_shard_replies(
+        &mut self,
+        buffer: &Buffer,
+        num_shards: usize,
+        t: (WorkerNum, mio::Token, Ipv4SocketAddr),
+    ) {
+        if num_shards > 1 {
+            self.shard_replies.insert((t.1, *buffer.contents().id()), (num_shards, true));
+        }
+    }
+
+    fn shards_for(&self, buffer: &Buffer) -> Vec<usize> {
+        match self.shards.num_shards() {
+            1 => vec![0],
+            _ => {
+                let shards = self.shards.shards_of(buffer.contents().locs());
+                if shards.is_empty() { vec![0] } else { shards }
+            },
+        }
+    }
+
+    //recovery requests and direct writes of single entries are about one chain,
+    //the first in their locs
+    fn shard_of_first_chain(&self, buffer: &Buffer) -> usize {
+        match self.shards.num_shards() {
+            1 => 0,
+            _ => self.shards.shard_of(buffer.contents().locs()[0].0),
+        }
+    }
+
+    // An append is refused by sending it back to the client as-is;
+    // since it lacks ReadSuccess the client knows to retry it later.
+    fn refuse_overloaded(&mut self, socket_state: &mut TcpWriter, mut buffer: Buffer) {
+        trace!("WORKER {} overloaded, refusing append", self.worker_num);
+        self.print_data.overloaded(1);
+        //appends which do not need skeens are marked successful when their storage is made
+        buffer.contents_mut().flag_mut().remove(EntryFlag::ReadSuccess);
+        socket_state.add_bytes_to_write(&[buffer.entry_slice()]);
+    }
+
+    fn send_recovery_to_log(
+        &mut self,
+        token: mio::Token,
+        buffer: Buffer,
+        src_addr: Ipv4SocketAddr,
+    ) {
+        let shard = self.shard_of_first_chain(&buffer);
+        let recovery = if buffer.contents().kind() == EntryKind::UpdateRecovery {
+            trace!("WORKER {} recoverer for {:?}", self.worker_num, buffer.contents().id());
+            let locs = buffer.contents().locs().to_vec().into_boxed_slice();
+            Recovery::TasRecoverer(buffer, Box::new((src_addr.to_uuid(), locs)))
+        } else {
+            trace!("WORKER {} check skeens1 {:?}", self.worker_num, buffer.contents().id());
+            Recovery::CheckSkeens1(buffer)
+        };
+        self.print_data.to_log(1);
+        let to_send = ToLog::Recovery(recovery, (self.worker_num, token, src_addr));
+        self.to_log[shard].send(to_send).expect("log gone")
     }
 
     fn send_replication_to_log(
@@ -532,93 +845,15 @@ impl WorkerInner {
         storage_addr: u64,
         src_addr: Ipv4SocketAddr,
     ) {
-        use packets::EntryKind;
         let worker_num = self.worker_num;
         trace!("WORKER {} send replica to log", self.worker_num);
-        let kind = buffer.contents().kind();
-        let to_send = match kind {
-            EntryKind::Data => {
-                trace!("WORKER {} replicate Data", self.worker_num);
-                ToReplicate::Data(buffer, storage_addr)
-            },
-            EntryKind::Lock => {
-                trace!("WORKER {} replicate Unlock", self.worker_num);
-                ToReplicate::UnLock(buffer)
-            },
-            //TODO
-            EntryKind::Multiput | EntryKind::Sentinel => {
-                trace!("WORKER {} replicate Multi/Senti", self.worker_num);
-                let (size, senti_size) = {
-                    let e = buffer.contents();
-                    (e.len(), e.sentinel_entry_size())
-                };
-                let storage = {
-                    let m = RcSlice::with_len(size);
-                    let s = RcSlice::with_len(senti_size);
-                    Box::new((m, s))
-                };
-                ToReplicate::Multi(buffer, storage)
-            },
-            EntryKind::SingleToReplica => {
-                trace!("WORKER {} replicate single skeens 1", self.worker_num);
-                ToReplicate::SingleSkeens1(buffer, storage_addr)
-            }
-            EntryKind::MultiputToReplica => {
-                trace!("WORKER {} replicate multi skeens 1", self.worker_num);
-                let (size, senti_size, num_locs, has_senti) = {
-                    let e = buffer.contents();
-                    let locs = e.locs();
-                    let num_locs = locs.len();
-                    //FIXME
-                    let has_senti = locs.contains(&OrderIndex(0.into(), 0.into()))
-                        || !e.flag().contains(EntryFlag::TakeLock);
-                    (e.non_replicated_len(), e.sentinel_entry_size(), num_locs, has_senti)
-                };
-                let senti_size = if has_senti { Some(senti_size) } else { None };
-                let storage = SkeensMultiStorage::new(num_locs, size, senti_size);
-                ToReplicate::Skeens1(buffer, storage)
-            }
-            EntryKind::SentinelToReplica => {
-                trace!("WORKER {} replicate senti skeens 1", self.worker_num);
-                let (size, senti_size, num_locs, has_senti) = {
-                    let e = buffer.contents();
-                    let locs = e.locs();
-                    let num_locs = locs.len();
-                    //FIXME
-                    let has_senti = locs.contains(&OrderIndex(0.into(), 0.into()))
-                        || !e.flag().contains(EntryFlag::TakeLock);
-                    (e.non_replicated_len(), e.sentinel_entry_size(), num_locs, has_senti)
-                };
-                let senti_size = if has_senti { Some(senti_size) } else { None };
-                let storage = SkeensMultiStorage::new(num_locs, size, senti_size);
-                ToReplicate::Skeens1(buffer, storage)
-            }
-            EntryKind::Skeens2ToReplica => {
-                trace!("WORKER {} replicate skeens 2", self.worker_num);
-                ToReplicate::Skeens2(buffer)
-            }
-            EntryKind::SnapshotToReplica => {
-                let (size, num_locs) = {
-                    let e = buffer.contents();
-                    let locs = e.locs();
-                    (e.len(), locs.len())
-                };
-                let mut storage = SkeensMultiStorage::new(num_locs, size, None);
-                let mut buffer = buffer;
-                storage.fill_from(&mut buffer);
-                ToReplicate::SnapshotSkeens1(buffer, storage)
-            }
-            EntryKind::GC => {
-                //TODO send downstream first?
-                ToReplicate::GC(buffer)
-            },
-            e => unreachable!("{:?}", e),
-        };
+        let to_send =
+            worker_thread::to_replicate(buffer, storage_addr, src_addr.to_uuid(), worker_num);
         self.print_data.rep_to_log(1);
         self.print_data.to_log(1);
         //self.waiting_for_log += 1;
         let to_send = ToLog::Replication(to_send, (worker_num, token, src_addr));
-        self.to_log.send(to_send).expect("log gone 2");
+        self.to_log[0].send(to_send).expect("log gone 2");
     }
 
     pub fn end_backpressure(&mut self, token: mio::Token) {
@@ -103,6 +103,21 @@ fn handle_op(
     buffer
 }
 
+fn handle_ops(
+    server: &mut ServerLog<(), VecDeque<ToWorker<()>>>,
+    ops: Vec<(BufferSlice, Troption<SkeensMultiStorage, Box<(RcSlice, RcSlice)>>)>,
+) -> usize {
+    server.handle_ops(ops.into_iter().map(|(buffer, storage)| (buffer, storage, ())));
+    let mut returned = 0;
+    while let Some(msg) = server.to_workers.pop_front() {
+        let (b, _) = handle_to_worker2(msg, 0, false, |_, _, _| {});
+        if b.is_some() {
+            returned += 1
+        }
+    }
+    returned
+}
+
 #[test]
 fn read_empty() {
     let mut server = new_log();
@@ -600,3 +615,106 @@ fn single_after_partial_skeens() {
         }
     });
 }
+
+#[test]
+fn batched_appends() {
+    let _ = env_logger::init();
+    let mut server = new_log();
+    let (s0, m, s1, s2) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let multi = multi_append_buffer(
+        &m,
+        &[OrderIndex(2.into(), 0.into()), OrderIndex(4.into(), 0.into())],
+        false,
+    );
+    let storage = make_storage(&multi);
+    let returned = handle_ops(&mut server, vec![
+        (singe_append_buffer(&s0, 2.into()), Troption::None),
+        (multi, Troption::Left(storage)),
+        (singe_append_buffer(&s1, 4.into()), Troption::None),
+        (singe_append_buffer(&s2, 2.into()), Troption::None),
+    ]);
+    assert_eq!(returned, 4);
+
+    let expected = [
+        (OrderIndex(2.into(), 1.into()), s0),
+        (OrderIndex(2.into(), 2.into()), m),
+        (OrderIndex(2.into(), 3.into()), s2),
+        (OrderIndex(4.into(), 1.into()), m),
+        (OrderIndex(4.into(), 2.into()), s1),
+    ];
+    for &(loc, wid) in &expected {
+        read_from_log(&server, loc, &mut |res| {
+            match res {
+                Err(e) => panic!("bad return @ {:?}: {:#?}", loc, e),
+                Ok(bytes) => unsafe {
+                    let (e, _) = EntryContents::try_ref(bytes).unwrap();
+                    assert_eq!(e.id(), &wid, "wrong entry @ {:?}: {:#?}", loc, e);
+                    assert!(e.locs().contains(&loc), "wrong locs @ {:?}: {:#?}", loc, e);
+                },
+            }
+        });
+    }
+}
+
+#[test]
+fn sharded_multi_append() {
+    use std::sync::Arc;
+
+    let _ = env_logger::init();
+    let shards = ShardMap::new(0, 2, 2);
+    let mut logs: Vec<ServerLog<(), VecDeque<ToWorker<()>>>> = (0..2).map(|shard| {
+        let (store, _reader) = ::new_chain_store_and_reader();
+        ServerLog::new_shard(0, 2, shards, shard, Default::default(), store)
+    }).collect();
+    let wid = Uuid::new_v4();
+    let locs = [OrderIndex(2.into(), 0.into()), OrderIndex(4.into(), 0.into())];
+    assert_eq!(shards.shards_of(&locs), vec![0, 1]);
+    let buffer = multi_append_buffer(&wid, &locs, false);
+    let storage = make_storage(&buffer);
+    let append = Arc::new(ShardedAppend::new(buffer, storage, shards.shards_of(&locs)));
+
+    // round 1, collected by the worker
+    for log in &mut logs {
+        log.handle_shard_skeens1(append.clone(), ());
+        match log.to_workers.pop_front() {
+            Some(ToWorker::ShardSkeens1(..)) => {},
+            _ => panic!("round 1 did not finish"),
+        }
+        assert!(log.to_workers.is_empty());
+    }
+    assert!(!append.finish_round1());
+    assert!(append.finish_round1());
+    let (storage, buffer) = append.take_storage();
+    assert!(buffer.is_none());
+    drop(storage);
+
+    // round 2
+    let mut returned = 0;
+    for log in &mut logs {
+        log.handle_shard_skeens2(append.clone());
+        while let Some(msg) = log.to_workers.pop_front() {
+            let (b, _) = handle_to_worker2(msg, 0, false, |_, _, _| {});
+            if b.is_some() {
+                returned += 1
+            }
+        }
+    }
+    assert_eq!(returned, 1);
+
+    for (&loc, log) in [OrderIndex(4.into(), 1.into()), OrderIndex(2.into(), 1.into())]
+        .iter().zip(&logs) {
+        read_from_log(log, loc, &mut |res| {
+            match res {
+                Err(e) => panic!("bad return @ {:?}: {:#?}", loc, e),
+                Ok(bytes) => unsafe {
+                    let (e, _) = EntryContents::try_ref(bytes).unwrap();
+                    assert_eq!(e.id(), &wid, "wrong entry @ {:?}: {:#?}", loc, e);
+                    assert_eq!(
+                        e.locs(),
+                        &[OrderIndex(2.into(), 1.into()), OrderIndex(4.into(), 1.into())]
+                    );
+                },
+            }
+        });
+    }
+}
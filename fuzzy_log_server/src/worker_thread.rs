@@ -292,6 +292,9 @@ where SendFn: for<'a> FnMut(ToSend<'a>, bool, T) -> U {
             (None, u)
         },
 
+        //the worker waits for every shard before replying
+        ShardSkeens1(..) => unreachable!("round 1 across shards must be finished by the worker"),
+
         Skeens1{mut buffer, storage, t} => unsafe {
             let (ts, indicies, st0, _st1) = storage.get_mut();
             trace!("WORKER {} finish skeens1 {:?}", worker_num, ts);
@@ -562,7 +565,11 @@ where SendFn: for<'a> FnMut(ToSend<'a>, bool, T) -> U {
             (Some(buffer), u)
         },
 
-        ToWorker::EndRecovery(buffer, t) => {
+        ToWorker::EndRecovery(mut buffer, t) => {
+            {
+                let mut e = buffer.contents_mut();
+                e.flag_mut().remove(EntryFlag::ReadSuccess);
+            }
             let u = if continue_replication {
                 send(ToSend::Slice(buffer.entry_slice()), false, t)
             } else {
@@ -651,8 +658,8 @@ pub fn handle_read<U, V: Send + Sync + Copy, SendFn>(
 where SendFn: for<'a> FnMut(Result<&'a [u8], EntryContents<'a>>) -> U {
     let OrderIndex(chain, index) = buffer.contents().locs()[0];
     debug_assert!(index > entry::from(0)); //TODO return error on index < GC
-    let res = chains.get_and(&chain, |logs| {
-        let log = unsafe {&*UnsafeCell::get(&logs[0])};
+    let res = chains.get_and(&chain, |log| {
+        let log = unsafe {&*UnsafeCell::get(log)};
         match log.trie.atomic_get(u64::from(index)) {
             Some(packet) => {
                 trace!("WORKER {:?} read occupied entry {:?} {:?}",
@@ -688,3 +695,147 @@ where SendFn: for<'a> FnMut(Result<&'a [u8], EntryContents<'a>>) -> U {
         }
     }
 }
+
+/// The storage the ordering thread needs for a new append from a client,
+/// allocated on the worker so the ordering thread does not have to.
+/// Appends which `DirectWrite` are replicated as-is and do not use this.
+pub fn append_storage(buffer: &mut Buffer)
+-> Troption<SkeensMultiStorage, Box<(RcSlice, RcSlice)>> {
+    let (layout, f) = {
+        let c = buffer.contents();
+        (c.kind().layout(), *c.flag())
+    };
+    match layout {
+        EntryLayout::Multiput | EntryLayout::Sentinel => {
+            let (size, senti_size, num_locs, has_senti) = {
+                let e = buffer.contents();
+                let locs = e.locs();
+                let num_locs = locs.len();
+                //FIXME
+                let has_senti = locs.contains(&OrderIndex(0.into(), 0.into()))
+                    || !e.flag().contains(EntryFlag::TakeLock);
+                (e.len(), e.sentinel_entry_size(), num_locs, has_senti)
+            };
+            if f.contains(EntryFlag::Unlock) {
+                Troption::None
+            } else if f.contains(EntryFlag::NewMultiPut) || !f.contains(EntryFlag::TakeLock) {
+                let senti_size = if has_senti { Some(senti_size) } else { None };
+                let mut storage = SkeensMultiStorage::new(num_locs, size, senti_size);
+                //FIXME is this the bug?
+                if !f.contains(EntryFlag::TakeLock) {
+                    storage.fill_from(buffer)
+                }
+                Troption::Left(storage)
+            } else {
+                let m = RcSlice::with_len(size);
+                let s = RcSlice::with_len(senti_size);
+                Troption::Right(Box::new((m, s)))
+            }
+        },
+
+        EntryLayout::Snapshot => {
+            let (size, num_locs) = {
+                let e = buffer.contents();
+                (e.len(), e.locs().len())
+            };
+            if f.contains(EntryFlag::Unlock) {
+                Troption::None
+            } else {
+                let mut storage = SkeensMultiStorage::new(num_locs, size, None);
+                storage.fill_from(buffer);
+                Troption::Left(storage)
+            }
+        },
+
+        _ => Troption::None,
+    }
+}
+
+/// What the ordering thread of a replica needs to replicate a packet from
+/// upstream, `src` is the client which originally sent it.
+pub fn to_replicate(buffer: Buffer, storage_addr: u64, src: Uuid, worker_num: usize)
+-> ToReplicate {
+    let kind = buffer.contents().kind();
+    match kind {
+        EntryKind::Data => {
+            trace!("WORKER {} replicate Data", worker_num);
+            ToReplicate::Data(buffer, storage_addr)
+        },
+        EntryKind::Lock => {
+            trace!("WORKER {} replicate Unlock", worker_num);
+            ToReplicate::UnLock(buffer)
+        },
+        //cancels are forwarded by the head as-is
+        EntryKind::Sentinel if buffer.contents().is_multi_cancel() => {
+            trace!("WORKER {} replicate skeens cancel", worker_num);
+            ToReplicate::Skeens2(buffer)
+        },
+        //TODO
+        EntryKind::Multiput | EntryKind::Sentinel => {
+            trace!("WORKER {} replicate Multi/Senti", worker_num);
+            let (size, senti_size) = {
+                let e = buffer.contents();
+                (e.len(), e.sentinel_entry_size())
+            };
+            let storage = {
+                let m = RcSlice::with_len(size);
+                let s = RcSlice::with_len(senti_size);
+                Box::new((m, s))
+            };
+            ToReplicate::Multi(buffer, storage)
+        },
+        EntryKind::SingleToReplica => {
+            trace!("WORKER {} replicate single skeens 1", worker_num);
+            ToReplicate::SingleSkeens1(buffer, storage_addr)
+        }
+        EntryKind::MultiputToReplica | EntryKind::SentinelToReplica => {
+            trace!("WORKER {} replicate multi skeens 1", worker_num);
+            let (size, senti_size, num_locs, has_senti) = {
+                let e = buffer.contents();
+                let locs = e.locs();
+                let num_locs = locs.len();
+                //FIXME
+                let has_senti = locs.contains(&OrderIndex(0.into(), 0.into()))
+                    || !e.flag().contains(EntryFlag::TakeLock);
+                (e.non_replicated_len(), e.sentinel_entry_size(), num_locs, has_senti)
+            };
+            let senti_size = if has_senti { Some(senti_size) } else { None };
+            let storage = SkeensMultiStorage::new(num_locs, size, senti_size);
+            ToReplicate::Skeens1(buffer, storage)
+        }
+        EntryKind::Skeens2ToReplica => {
+            trace!("WORKER {} replicate skeens 2", worker_num);
+            ToReplicate::Skeens2(buffer)
+        }
+        EntryKind::SnapshotToReplica => {
+            let (size, num_locs) = {
+                let e = buffer.contents();
+                let locs = e.locs();
+                (e.len(), locs.len())
+            };
+            let mut storage = SkeensMultiStorage::new(num_locs, size, None);
+            let mut buffer = buffer;
+            storage.fill_from(&mut buffer);
+            ToReplicate::SnapshotSkeens1(buffer, storage)
+        }
+        EntryKind::GC => {
+            //TODO send downstream first?
+            ToReplicate::GC(buffer)
+        },
+        //the head's report of what is blocking a chain is forwarded as-is
+        EntryKind::UpdateRecovery if buffer.contents().flag().contains(EntryFlag::Skeens1Queued) => {
+            trace!("WORKER {} replicate blocker report", worker_num);
+            ToReplicate::CheckSkeens1(buffer)
+        },
+        EntryKind::UpdateRecovery => {
+            trace!("WORKER {} replicate recoverer", worker_num);
+            let locs = buffer.contents().locs().to_vec().into_boxed_slice();
+            ToReplicate::TasRecoverer(buffer, Box::new((src, locs)))
+        },
+        EntryKind::CheckSkeens1 => {
+            trace!("WORKER {} replicate check skeens1", worker_num);
+            ToReplicate::CheckSkeens1(buffer)
+        },
+        e => unreachable!("{:?}", e),
+    }
+}
@@ -0,0 +1,502 @@
+//! The FuzzyLog configuration file.
+//!
+//! A single TOML file describes both the cluster, which is all a client needs,
+//! and optionally the settings for one server process:
+//!
+//! ```toml
+//! [cluster]
+//! servers = ["10.0.0.1:13289", "10.0.0.2:13289"]
+//! # only for replicated clusters, one tail for each head in `servers`
+//! tails = ["10.0.0.3:13289", "10.0.0.4:13289"]
+//!
+//! [server]
+//! listen = "0.0.0.0:13289"
+//! server_num = 0
+//! group_size = 2
+//! upstream = "10.0.0.5:13289"
+//! downstream = "10.0.0.3"
+//! workers = 4
+//! # replicated servers, with an upstream or downstream, must use 1
+//! ordering_threads = 1
+//! admin = "0.0.0.0:13290"
+//! metrics = "0.0.0.0:9100"
+//! storage_dir = "/var/lib/fuzzylog"
+//!
+//! [server.gc]
+//! policy = "keep_last"
+//! entries = 1000000
+//!
+//! [server.limits]
+//! log_queue_len = 16384
+//! max_in_flight_per_client = 1024
+//! max_batch = 64
+//! ```
+//!
+//! The older top-level `DELOS_CHAIN_SERVERS`, `DELOS_CHAIN_SERVERS_TAILS` and
+//! `DELOS_LOCK_SERVER` keys, each a whitespace separated list of addresses,
+//! are still accepted in place of the `[cluster]` table.
+//!
+//! Unknown keys are rejected rather than ignored.
+//! `metrics`, `storage_dir` and `[server.gc]` are parsed and checked, but the
+//! server does not act on them yet: it keeps entries in memory, only collects
+//! garbage when asked to, and exports no metrics.
+
+use std::{error, fmt, io};
+use std::fs::File;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use toml::{self, Value};
+
+type Table = ::std::collections::BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    pub cluster: ClusterConfig,
+    pub server: Option<ServerConfig>,
+}
+
+/// The servers which make up a FuzzyLog deployment.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClusterConfig {
+    /// The chain servers, or the heads of their replication chains.
+    pub servers: Vec<SocketAddr>,
+    /// The tails of the replication chains, empty if the cluster is unreplicated.
+    pub tails: Vec<SocketAddr>,
+    pub lock_server: Option<SocketAddr>,
+}
+
+impl ClusterConfig {
+    pub fn is_replicated(&self) -> bool {
+        !self.tails.is_empty()
+    }
+
+    /// The (head, tail) of each replication chain.
+    pub fn replicas(&self) -> Vec<(SocketAddr, SocketAddr)> {
+        self.servers.iter().cloned().zip(self.tails.iter().cloned()).collect()
+    }
+}
+
+/// The settings for a single server process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    pub server_num: u32,
+    pub group_size: u32,
+    /// The server before this one in its replication chain.
+    pub upstream: Option<SocketAddr>,
+    /// The server after this one in its replication chain.
+    pub downstream: Option<IpAddr>,
+    /// `None` to pick based on the number of cores.
+    pub workers: Option<usize>,
+    /// How many threads the chains on this server are split between.
+    /// Replication is not sharded, so a replicated server must use one.
+    pub ordering_threads: usize,
+    pub admin: Option<SocketAddr>,
+    pub metrics: Option<SocketAddr>,
+    pub storage_dir: Option<PathBuf>,
+    pub gc: GcPolicy,
+    pub limits: Limits,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: SocketAddr::from(([0, 0, 0, 0], 13289)),
+            server_num: 0,
+            group_size: 1,
+            upstream: None,
+            downstream: None,
+            workers: None,
+            ordering_threads: 1,
+            admin: None,
+            metrics: None,
+            storage_dir: None,
+            gc: GcPolicy::Never,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn is_replicated(&self) -> bool {
+        self.upstream.is_some() || self.downstream.is_some()
+    }
+
+    /// Checks the settings which cannot be used together,
+    /// for configs which were changed after being parsed.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.group_size == 0 || self.server_num >= self.group_size {
+            return Err(ConfigError::Invalid(format!(
+                "server.server_num {} must be less than server.group_size {}",
+                self.server_num, self.group_size
+            )))
+        }
+        if self.workers == Some(0) {
+            return Err(ConfigError::Invalid("server.workers must be at least 1".to_string()))
+        }
+        if self.ordering_threads == 0 {
+            return Err(ConfigError::Invalid(
+                "server.ordering_threads must be at least 1".to_string()
+            ))
+        }
+        if self.ordering_threads > 1 && self.is_replicated() {
+            return Err(ConfigError::Invalid(format!(
+                "server.ordering_threads is {}, but a replicated server can only use 1",
+                self.ordering_threads
+            )))
+        }
+        if let Some(metrics) = self.metrics {
+            if metrics == self.listen || Some(metrics) == self.admin {
+                return Err(ConfigError::Invalid(format!(
+                    "server.metrics {} is already used by server.listen or server.admin", metrics
+                )))
+            }
+        }
+        if self.storage_dir.as_ref().is_some_and(|dir| dir.as_os_str().is_empty()) {
+            return Err(ConfigError::Invalid("server.storage_dir is empty".to_string()))
+        }
+        if self.gc == GcPolicy::KeepLast(0) {
+            return Err(ConfigError::Invalid(
+                "server.gc.entries must be at least 1".to_string()
+            ))
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcPolicy {
+    Never,
+    /// Keep only the most recent `n` entries of each chain.
+    KeepLast(u64),
+}
+
+/// Flow control for a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Bound on the requests waiting for the ordering thread.
+    /// Once it is full new appends are refused as overloaded,
+    /// while everything else waits for space.
+    pub log_queue_len: usize,
+    /// Appends from a single client which may be waiting for the ordering thread
+    /// before new ones are refused as overloaded.
+    pub max_in_flight_per_client: usize,
+    /// Most requests the ordering thread takes from its queue to handle as
+    /// one batch, 1 handles each request on its own.
+    pub max_batch: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            log_queue_len: 1 << 14,
+            max_in_flight_per_client: 1 << 10,
+            max_batch: 64,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(ref e) => write!(f, "invalid config syntax: {}", e),
+            ConfigError::Invalid(ref e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Io(..) => "could not read config",
+            ConfigError::Parse(..) => "invalid config syntax",
+            ConfigError::Invalid(..) => "invalid config",
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl Config {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        contents.parse()
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        let mut parser = toml::Parser::new(s);
+        let mut vals = match parser.parse() {
+            Some(vals) => vals,
+            None => {
+                let errors: Vec<_> = parser.errors.iter().map(|e| {
+                    let (line, col) = parser.to_linecol(e.lo);
+                    format!("{} at {}:{}", e.desc, line + 1, col + 1)
+                }).collect();
+                return Err(ConfigError::Parse(errors.join(", ")))
+            },
+        };
+
+        let mut cluster = match vals.remove("cluster") {
+            None => ClusterConfig::default(),
+            Some(Value::Table(mut table)) => {
+                let cluster = ClusterConfig {
+                    servers: take_addrs(&mut table, "cluster.servers")?,
+                    tails: take_addrs(&mut table, "cluster.tails")?,
+                    lock_server: take_parsed(&mut table, "cluster.lock_server")?,
+                };
+                no_unknown_keys(&table, "cluster")?;
+                cluster
+            },
+            Some(other) => return Err(wrong_type("cluster", "a table", &other)),
+        };
+        if let Some(servers) = take_legacy_addrs(&mut vals, "DELOS_CHAIN_SERVERS")? {
+            cluster.servers = servers
+        }
+        if let Some(tails) = take_legacy_addrs(&mut vals, "DELOS_CHAIN_SERVERS_TAILS")? {
+            cluster.tails = tails
+        }
+        if let Some(mut lock) = take_legacy_addrs(&mut vals, "DELOS_LOCK_SERVER")? {
+            cluster.lock_server = lock.pop()
+        }
+        if cluster.is_replicated() && cluster.tails.len() != cluster.servers.len() {
+            return Err(ConfigError::Invalid(format!(
+                "{} heads but {} tails, each replication chain needs both",
+                cluster.servers.len(), cluster.tails.len()
+            )))
+        }
+
+        let server = match vals.remove("server") {
+            None => None,
+            Some(Value::Table(table)) => Some(parse_server(table)?),
+            Some(other) => return Err(wrong_type("server", "a table", &other)),
+        };
+        no_unknown_keys(&vals, "the top level")?;
+        Ok(Config { cluster, server })
+    }
+}
+
+fn parse_server(mut table: Table) -> Result<ServerConfig, ConfigError> {
+    let default = ServerConfig::default();
+    let gc = match table.remove("gc") {
+        None => default.gc,
+        Some(Value::Table(mut gc)) => {
+            let policy: Option<String> = take_parsed(&mut gc, "server.gc.policy")?;
+            let entries: Option<u64> = take_int(&mut gc, "server.gc.entries")?;
+            no_unknown_keys(&gc, "server.gc")?;
+            match (policy.as_deref(), entries) {
+                (None, None) | (Some("never"), None) => GcPolicy::Never,
+                (Some("keep_last"), Some(entries)) => GcPolicy::KeepLast(entries),
+                (Some("keep_last"), None) => return Err(ConfigError::Invalid(
+                    "server.gc.policy 'keep_last' needs server.gc.entries".to_string()
+                )),
+                (_, Some(..)) => return Err(ConfigError::Invalid(
+                    "server.gc.entries is only used with policy 'keep_last'".to_string()
+                )),
+                (Some(other), None) => return Err(ConfigError::Invalid(format!(
+                    "unknown server.gc.policy '{}', expected 'never' or 'keep_last'", other
+                ))),
+            }
+        },
+        Some(other) => return Err(wrong_type("server.gc", "a table", &other)),
+    };
+    let limits = match table.remove("limits") {
+        None => default.limits,
+        Some(Value::Table(mut limits)) => {
+            let l = Limits {
+                log_queue_len: take_int(&mut limits, "server.limits.log_queue_len")?
+                    .unwrap_or(default.limits.log_queue_len),
+                max_in_flight_per_client:
+                    take_int(&mut limits, "server.limits.max_in_flight_per_client")?
+                    .unwrap_or(default.limits.max_in_flight_per_client),
+                max_batch: take_int(&mut limits, "server.limits.max_batch")?
+                    .unwrap_or(default.limits.max_batch),
+            };
+            no_unknown_keys(&limits, "server.limits")?;
+            l
+        },
+        Some(other) => return Err(wrong_type("server.limits", "a table", &other)),
+    };
+    let server = ServerConfig {
+        listen: take_parsed(&mut table, "server.listen")?.unwrap_or(default.listen),
+        server_num: take_int(&mut table, "server.server_num")?.unwrap_or(default.server_num),
+        group_size: take_int(&mut table, "server.group_size")?.unwrap_or(default.group_size),
+        upstream: take_parsed(&mut table, "server.upstream")?,
+        downstream: take_parsed(&mut table, "server.downstream")?,
+        workers: take_int(&mut table, "server.workers")?,
+        ordering_threads: take_int(&mut table, "server.ordering_threads")?
+            .unwrap_or(default.ordering_threads),
+        admin: take_parsed(&mut table, "server.admin")?,
+        metrics: take_parsed(&mut table, "server.metrics")?,
+        storage_dir: take_parsed::<String>(&mut table, "server.storage_dir")?.map(PathBuf::from),
+        gc,
+        limits,
+    };
+    no_unknown_keys(&table, "server")?;
+    server.validate()?;
+    Ok(server)
+}
+
+//`path` is the dotted path of the key, its last part is the key within `table`
+fn key(path: &str) -> &str {
+    path.rsplit('.').next().unwrap()
+}
+
+fn take_parsed<T>(table: &mut Table, path: &str) -> Result<Option<T>, ConfigError>
+where T: FromStr, T::Err: fmt::Display {
+    match table.remove(key(path)) {
+        None => Ok(None),
+        Some(Value::String(s)) => s.parse().map(Some).map_err(|e| ConfigError::Invalid(
+            format!("{} '{}': {}", path, s, e)
+        )),
+        Some(other) => Err(wrong_type(path, "a string", &other)),
+    }
+}
+
+fn take_int<T>(table: &mut Table, path: &str) -> Result<Option<T>, ConfigError>
+where T: FromStr {
+    match table.remove(key(path)) {
+        None => Ok(None),
+        Some(Value::Integer(i)) => i.to_string().parse().map(Some).map_err(|_|
+            ConfigError::Invalid(format!("{} {} is out of range", path, i))
+        ),
+        Some(other) => Err(wrong_type(path, "an integer", &other)),
+    }
+}
+
+fn take_addrs(table: &mut Table, path: &str) -> Result<Vec<SocketAddr>, ConfigError> {
+    match table.remove(key(path)) {
+        None => Ok(vec![]),
+        Some(Value::Array(addrs)) => addrs.into_iter().map(|addr| match addr {
+            Value::String(s) => s.parse().map_err(|e| ConfigError::Invalid(
+                format!("{} '{}': {}", path, s, e)
+            )),
+            other => Err(wrong_type(path, "an array of strings", &other)),
+        }).collect(),
+        Some(other) => Err(wrong_type(path, "an array of strings", &other)),
+    }
+}
+
+fn take_legacy_addrs(table: &mut Table, key: &str)
+-> Result<Option<Vec<SocketAddr>>, ConfigError> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => s.split_whitespace().map(|addr|
+            addr.parse().map_err(|e| ConfigError::Invalid(format!("{} '{}': {}", key, addr, e)))
+        ).collect::<Result<_, _>>().map(Some),
+        Some(other) => Err(wrong_type(key, "a string", &other)),
+    }
+}
+
+fn no_unknown_keys(table: &Table, section: &str) -> Result<(), ConfigError> {
+    match table.keys().next() {
+        None => Ok(()),
+        Some(k) => Err(ConfigError::Invalid(format!("unknown key '{}' in {}", k, section))),
+    }
+}
+
+fn wrong_type(path: &str, expected: &str, found: &Value) -> ConfigError {
+    ConfigError::Invalid(format!("{} must be {}, found {}", path, expected, found.type_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_config() {
+        let config: Config = r#"
+            [cluster]
+            servers = ["10.0.0.1:13289", "10.0.0.2:13289"]
+            tails = ["10.0.0.3:13289", "10.0.0.4:13289"]
+
+            [server]
+            listen = "0.0.0.0:4000"
+            server_num = 1
+            group_size = 2
+            upstream = "10.0.0.5:13289"
+            downstream = "10.0.0.3"
+            workers = 4
+            ordering_threads = 1
+            admin = "0.0.0.0:4001"
+            storage_dir = "/tmp/fuzzylog"
+
+            [server.gc]
+            policy = "keep_last"
+            entries = 100
+
+            [server.limits]
+            max_in_flight_per_client = 7
+            max_batch = 1
+        "#.parse().unwrap();
+        assert!(config.cluster.is_replicated());
+        assert_eq!(config.cluster.replicas()[1],
+            ("10.0.0.2:13289".parse().unwrap(), "10.0.0.4:13289".parse().unwrap()));
+        let server = config.server.unwrap();
+        assert_eq!(server.listen, "0.0.0.0:4000".parse().unwrap());
+        assert_eq!((server.server_num, server.group_size), (1, 2));
+        assert_eq!(server.downstream, Some("10.0.0.3".parse().unwrap()));
+        assert_eq!(server.workers, Some(4));
+        assert_eq!(server.ordering_threads, 1);
+        assert_eq!(server.admin, Some("0.0.0.0:4001".parse().unwrap()));
+        assert_eq!(server.metrics, None);
+        assert_eq!(server.storage_dir, Some(PathBuf::from("/tmp/fuzzylog")));
+        assert_eq!(server.gc, GcPolicy::KeepLast(100));
+        assert_eq!(server.limits.max_in_flight_per_client, 7);
+        assert_eq!(server.limits.log_queue_len, Limits::default().log_queue_len);
+        assert_eq!(server.limits.max_batch, 1);
+    }
+
+    #[test]
+    fn legacy_keys() {
+        let config: Config = r#"
+            DELOS_CHAIN_SERVERS = "127.0.0.1:3333 127.0.0.1:3334"
+            DELOS_LOCK_SERVER = "127.0.0.1:3332"
+        "#.parse().unwrap();
+        assert_eq!(config.cluster.servers.len(), 2);
+        assert!(!config.cluster.is_replicated());
+        assert_eq!(config.cluster.lock_server, Some("127.0.0.1:3332".parse().unwrap()));
+        assert!(config.server.is_none());
+    }
+
+    #[test]
+    fn rejects_bad_configs() {
+        let bad = [
+            "[cluster]\nservers = [\"1.2.3.4:5\"]\ntails = []\nextra = 1",
+            "[cluster]\nservers = [\"1.2.3.4:5\"]\ntails = [\"1.2.3.4:6\", \"1.2.3.4:7\"]",
+            "[cluster]\nservers = [\"not an addr\"]",
+            "[server]\nserver_num = 2\ngroup_size = 2",
+            "[server]\nworkers = \"4\"",
+            "[server]\ndownstream = \"10.0.0.3\"\nordering_threads = 2",
+            "[server.gc]\npolicy = \"keep_last\"",
+            "[server.gc]\npolicy = \"keep_last\"\nentries = 0",
+            "[server.gc]\npolicy = \"sometimes\"",
+            "[server]\nmetrics = \"not an addr\"",
+            "[server]\nadmin = \"0.0.0.0:9100\"\nmetrics = \"0.0.0.0:9100\"",
+            "[server]\nstorage_dir = \"\"",
+            "[server",
+        ];
+        for config in bad.iter() {
+            assert!(config.parse::<Config>().is_err(), "{}", config);
+        }
+    }
+}
@@ -1,9 +1,11 @@
 
 extern crate byteorder;
+extern crate toml;
 pub extern crate uuid;
 
 pub use hash_deque_map as vec_deque_map;
 
+pub mod config;
 pub mod counter_macro;
 pub mod hash;
 pub mod socket_addr;
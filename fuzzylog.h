@@ -9,8 +9,52 @@
 #include <stdlib.h>
 #include <stdbool.h>
 
+/*
+ * The result of a fallible FuzzyLog call.
+ * Whenever a function returns something other than `FUZZYLOG_OK`
+ * (or its documented failure value) a description of what went wrong
+ * can be retrieved with `fuzzylog_last_error`.
+ */
+typedef enum {
+    FUZZYLOG_OK = 0,
+    /*
+     * A NULL handle, malformed address, or otherwise unusable argument.
+     */
+    FUZZYLOG_INVALID_ARGUMENT = 1,
+    /*
+     * Communication with a server failed.
+     */
+    FUZZYLOG_IO_ERROR = 2,
+    /*
+     * The handle's timeout passed before the operation finished.
+     */
+    FUZZYLOG_TIMEOUT = 3,
+    /*
+     * The append was cancelled before it was given a place in the log.
+     */
+    FUZZYLOG_CANCELLED = 4,
+    /*
+     * The requested entries have been garbage collected by the servers.
+     */
+    FUZZYLOG_TRIMMED = 5,
+    /*
+     * Nothing is ready yet, try again once the event fd is readable.
+     * Not an error, `fuzzylog_last_error` is not set.
+     */
+    FUZZYLOG_NOTHING_READY = 6,
+    /*
+     * Every event in the current sync has been returned.
+     * Not an error, `fuzzylog_last_error` is not set.
+     */
+    FUZZYLOG_DONE = 7,
+} FuzzyLogStatus;
+
 typedef struct DAG DAG;
 
+typedef struct FuzzyLogReader FuzzyLogReader;
+
+typedef struct FuzzyLogWriter FuzzyLogWriter;
+
 typedef struct HashMap_order__entry HashMap_order__entry;
 
 typedef HashMap_order__entry SnapBody;
@@ -19,6 +63,10 @@ typedef SnapBody *SnapId;
 
 typedef DAG *FLPtr;
 
+typedef FuzzyLogReader *FLReader;
+
+typedef FuzzyLogWriter *FLWriter;
+
 /*
  * A `ColorSpec` describes the layout of a color.
  *
@@ -59,6 +107,23 @@ typedef struct {
     char **tail_ips;
 } ServerSpec;
 
+/*
+ * The two halves of a client handle split by `fuzzylog_split`.
+ *
+ * Neither a `FLPtr`, nor a `FLReader`, nor a `FLWriter` may be used by
+ * more than one thread at a time. However, the reader and the writer of
+ * a split handle are independent: one thread may read with the reader
+ * while another appends with the writer.
+ * A handle may be moved between threads between calls.
+ */
+typedef struct {
+    FLReader reader;
+    FLWriter writer;
+} FuzzyLogReaderAndWriter;
+
+/*
+ * Free a SnapId returned by a sync. Deleting NULL does nothing.
+ */
 void delete_snap_id(SnapId snap);
 
 /*
@@ -74,14 +139,49 @@ void delete_snap_id(SnapId snap);
  * `local_color` will be read from these colors.
  * num_colors: the number of colors in `colors`
  */
-int32_t fuzzylog_append(FLPtr handle,
-                        const char *data,
-                        uintptr_t data_size,
-                        const ColorSpec *colors,
-                        uintptr_t num_colors);
+FuzzyLogStatus fuzzylog_append(FLPtr handle,
+                               const char *data,
+                               uintptr_t data_size,
+                               const ColorSpec *colors,
+                               uintptr_t num_colors);
 
+/*
+ * Close a client handle. Closing NULL does nothing.
+ */
 void fuzzylog_close(FLPtr handle);
 
+/*
+ * A description of the last error that occurred in a FuzzyLog call made
+ * on this thread, or NULL if no call on this thread has failed.
+ * The string is owned by the FuzzyLog and remains valid until the next
+ * failing call on this thread.
+ */
+const char *fuzzylog_last_error(void);
+
+/*
+ * Close the reader half of a split handle. Closing NULL does nothing.
+ */
+void fuzzylog_reader_close(FLReader reader);
+
+/*
+ * Like `fuzzylog_sync` but for the reader half of a split handle.
+ */
+SnapId fuzzylog_reader_sync(FLReader reader,
+                            void (*callback)(void*, const char*, uintptr_t),
+                            void *callback_state);
+
+/*
+ * Split a client handle into a reader, which syncs the handle's colors,
+ * and a writer, which performs the handle's appends, so that each can
+ * be used from its own thread.
+ * This consumes `handle`, which must no longer be used.
+ * The reader and writer are closed independently, the connection to the
+ * servers is closed along with the last of them.
+ *
+ * returns NULL for both halves if `handle` is NULL.
+ */
+FuzzyLogReaderAndWriter fuzzylog_split(FLPtr handle);
+
 /*
  * Sync a local view with the FuzzyLog.
  *
@@ -92,12 +192,37 @@ void fuzzylog_close(FLPtr handle);
  * the events `data_size`
  * callback_state: a pointer passed as the first argument to callback.
  * May be `NULL`.
+ *
+ * returns NULL if the sync failed.
  */
 SnapId fuzzylog_sync(FLPtr handle,
                      void (*callback)(void*, const char*, uintptr_t),
                      void *callback_state);
 
-void fuzzylog_trim(FLPtr handle, SnapId snap);
+/*
+ * Let the servers free every entry up to a snapshot returned by a sync.
+ * The freed entries can no longer be read by any handle.
+ */
+FuzzyLogStatus fuzzylog_trim(FLPtr handle, SnapId snap);
+
+/*
+ * Like `fuzzylog_append` but for the writer half of a split handle.
+ */
+FuzzyLogStatus fuzzylog_writer_append(FLWriter writer,
+                                      const char *data,
+                                      uintptr_t data_size,
+                                      const ColorSpec *colors,
+                                      uintptr_t num_colors);
+
+/*
+ * Close the writer half of a split handle. Closing NULL does nothing.
+ */
+void fuzzylog_writer_close(FLWriter writer);
+
+/*
+ * Like `fuzzylog_trim` but for the writer half of a split handle.
+ */
+FuzzyLogStatus fuzzylog_writer_trim(FLWriter writer, SnapId snap);
 
 /*
  * Start a new FuzzyLog client instance, and connect it so the supplied
@@ -109,6 +234,10 @@ void fuzzylog_trim(FLPtr handle, SnapId snap);
  * snap: a SnapId that the client should start `sync`ing from,
  * or NULL if the client should start from the beginning of its
  * color
+ *
+ * returns NULL if the client could not be started.
+ * The handle may only be used by one thread at a time,
+ * use `fuzzylog_split` to read and append from different threads.
  */
 FLPtr new_fuzzylog_instance(ServerSpec servers, ColorSpec color, SnapId snap);
 
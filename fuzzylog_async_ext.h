@@ -37,6 +37,8 @@ typedef struct {
  * colors: the colors the new node should inhabit. Note that only
  * `local_color` will be read from these colors.
  * num_colors: the number of colors in `colors`
+ *
+ * returns WriteId{0} if the append could not be sent.
  */
 WriteId fuzzylog_async_append(FLPtr handle,
                               const char *data,
@@ -44,8 +46,48 @@ WriteId fuzzylog_async_append(FLPtr handle,
                               const ColorSpec *colors,
                               uintptr_t num_colors);
 
+/*
+ * A file descriptor which becomes readable whenever an event or an
+ * append ack is ready for `handle`, for use with epoll or libuv.
+ * Once it is readable, first read 8 bytes from it to reset it, then
+ * drain `fuzzylog_try_next_event` and `fuzzylog_try_wait_for_any_append`
+ * until neither has anything ready.
+ * It starts out readable, so results which arrived before it was
+ * asked for are not missed.
+ * The descriptor is owned by the handle, and closed by `fuzzylog_close`,
+ * every call returns the same one.
+ *
+ * returns -1 if the descriptor could not be created.
+ */
+int fuzzylog_event_fd(FLPtr handle);
+
 bool fuzzylog_event_inhabits_chain(FuzzyLogEvent event, uint64_t chain);
 
+/*
+ * Like `fuzzylog_start_sync` but for the reader half of a split handle.
+ */
+FuzzyLogStatus fuzzylog_reader_start_sync(FLReader reader);
+
+/*
+ * Like `fuzzylog_sync_events` but for the reader half of a split handle.
+ */
+SnapId fuzzylog_reader_sync_events(FLReader reader,
+                                   void (*callback)(void*, FuzzyLogEvent),
+                                   void *callback_state);
+
+/*
+ * Like `fuzzylog_try_next_event` but for the reader half of a split
+ * handle.
+ */
+FuzzyLogStatus fuzzylog_reader_try_next_event(FLReader reader,
+                                              FuzzyLogEvent *event);
+
+/*
+ * Start syncing a local view with the FuzzyLog without waiting for it.
+ * The events are then returned by `fuzzylog_try_next_event`.
+ */
+FuzzyLogStatus fuzzylog_start_sync(FLPtr handle);
+
 /*
  * Sync a local view with the FuzzyLog.
  *
@@ -56,11 +98,24 @@ bool fuzzylog_event_inhabits_chain(FuzzyLogEvent event, uint64_t chain);
  * the events `data_size`
  * callback_state: a pointer passed as the first argument to callback.
  * May be `NULL`.
+ *
+ * returns NULL if the sync failed.
  */
 SnapId fuzzylog_sync_events(FLPtr handle,
                             void (*callback)(void*, FuzzyLogEvent),
                             void *callback_state);
 
+/*
+ * Get the next event of a sync started with `fuzzylog_start_sync`
+ * without blocking.
+ * The event's contents remain valid until the next read on `handle`.
+ *
+ * returns FUZZYLOG_OK and fills in `event` if an event was ready,
+ * FUZZYLOG_NOTHING_READY if it was not yet,
+ * and FUZZYLOG_DONE once the sync has returned every event.
+ */
+FuzzyLogStatus fuzzylog_try_next_event(FLPtr handle, FuzzyLogEvent *event);
+
 /*
  * Check if any append written by this client has been ack'd by the server
  * return WriteId{0} if no such append exists.
@@ -70,16 +125,56 @@ WriteId fuzzylog_try_wait_for_any_append(FLPtr handle);
 /*
  * Wait for a specific append sent by this client to be ack'd by the server.
  */
-void fuzzylog_wait_for_a_specific_append(FLPtr handle, WriteId write_id);
+FuzzyLogStatus fuzzylog_wait_for_a_specific_append(FLPtr handle,
+                                                   WriteId write_id);
 
 /*
  * Wait for all outstanding appends to be ack'd by the server.
  */
-void fuzzylog_wait_for_all_appends(FLPtr handle);
+FuzzyLogStatus fuzzylog_wait_for_all_appends(FLPtr handle);
 
 /*
  * Wait for any append sent by this client to be ack'd by the server.
+ * return WriteId{0} if no such append exists or the wait failed.
  */
 WriteId fuzzylog_wait_for_any_append(FLPtr handle);
 
+/*
+ * Like `fuzzylog_async_append` but for the writer half of a split handle.
+ *
+ * returns WriteId{0} if the append could not be sent.
+ */
+WriteId fuzzylog_writer_async_append(FLWriter writer,
+                                     const char *data,
+                                     uintptr_t data_size,
+                                     const ColorSpec *colors,
+                                     uintptr_t num_colors);
+
+/*
+ * Like `fuzzylog_try_wait_for_any_append` but for the writer half of a
+ * split handle.
+ * return WriteId{0} if no such append exists.
+ */
+WriteId fuzzylog_writer_try_wait_for_any_append(FLWriter writer);
+
+/*
+ * Like `fuzzylog_wait_for_a_specific_append` but for the writer half of
+ * a split handle.
+ */
+FuzzyLogStatus fuzzylog_writer_wait_for_a_specific_append(FLWriter writer,
+                                                          WriteId write_id);
+
+/*
+ * Like `fuzzylog_wait_for_all_appends` but for the writer half of a
+ * split handle.
+ */
+FuzzyLogStatus fuzzylog_writer_wait_for_all_appends(FLWriter writer);
+
+/*
+ * Like `fuzzylog_wait_for_any_append` but for the writer half of a
+ * split handle.
+ * return WriteId{0} if no such append exists or the wait failed.
+ */
+WriteId fuzzylog_writer_wait_for_any_append(FLWriter writer);
+
 #endif /* FuzzyLog_C_async_ext_h */
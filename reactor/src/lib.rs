@@ -10,7 +10,7 @@ use std::collections::VecDeque;
 use std::collections::hash_map::Entry as HashEntry;
 use std::{io, mem};
 use std::io::{Read, Write};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use fuzzy_log_packets::EntryContents;
 use fuzzy_log_packets::double_buffer::DoubleBuffer;
@@ -38,8 +38,14 @@ pub trait Handler<Inner>: Wakeable {
 
     fn on_error(&mut self, error: Self::Error, poll: &mut mio::Poll) -> ShouldRemove;
 
+    /// Called once the stream at `token` has been removed after an error.
+    fn on_stream_removed(&mut self, _token: mio::Token) {}
+
     //FIXME should be own trait
     fn after_work(&mut self, _inner: &mut Inner) {}
+
+    /// Once the reactor's inner handler is finished `Reactor::run` returns.
+    fn is_finished(&self) -> bool { false }
 }
 
 ///////////////////////////////////////
@@ -109,6 +115,10 @@ where
         // let mut events = mio::Events::with_capacity(1024);
         let mut running = VecDeque::new();
         loop {
+            if self.inner.is_finished() {
+                return Ok(())
+            }
+
             self.io_state.poll.poll(&mut self.events, None)?;
 
             self.handle_new_events()?;
@@ -128,6 +138,7 @@ where
                             if let Err(e) = error {
                                 if o.get_mut().on_error(e, &mut self.io_state.poll) {
                                     o.remove();
+                                    self.inner.on_stream_removed(token);
                                     continue
                                 }
                             }
@@ -179,6 +190,7 @@ where
                     if let Err(e) = error {
                         if o.get_mut().on_error(e, &mut self.io_state.poll) {
                             o.remove();
+                            self.inner.on_stream_removed(token);
                             continue
                         }
                     }
@@ -213,6 +225,10 @@ where
 }
 
 impl<PerStream> IoState<PerStream> {
+    pub fn streams_mut(&mut self) -> ::std::collections::hash_map::ValuesMut<mio::Token, PerStream> {
+        self.streams.values_mut()
+    }
+
     pub fn get_for(&self, token: mio::Token) -> Option<&PerStream> {
         self.streams.get(&token)
     }
@@ -466,6 +482,11 @@ TcpHandler<PacketReader, PacketHandler, WriteHandler> {
         self.io.add_bytes_to_write(bytes)
     }
 
+    /// See `TcpIo::flush`.
+    pub fn flush_writes(&mut self, timeout: Duration) -> Result<(), io::Error> {
+        self.io.flush(timeout)
+    }
+
     pub fn add_contents(&mut self, contents: EntryContents, extra: &[&[u8]]) {
         self.io.add_contents_to_write(contents, extra)
     }
@@ -735,6 +756,22 @@ impl TcpIo {
         Ok(wrote)
     }
 
+    /// Write everything buffered so far, blocking until it is written or
+    /// until `timeout` passes without the socket accepting any bytes.
+    pub fn flush(&mut self, timeout: Duration) -> Result<(), io::Error> {
+        let mut last_progress = Instant::now();
+        while !self.write_buffer.is_empty() {
+            if self.write()? > 0 {
+                last_progress = Instant::now();
+            } else if last_progress.elapsed() > timeout {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "could not flush writes"))
+            } else {
+                ::std::thread::yield_now()
+            }
+        }
+        Ok(())
+    }
+
     ///////////////////////////////////
 
     pub fn needs_to_mark_as_staying_awake(&mut self) -> bool {
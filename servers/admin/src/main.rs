@@ -0,0 +1,187 @@
+extern crate fuzzy_log_packets as packets;
+extern crate fuzzy_log_server as servers2;
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::exit;
+
+use packets::{bytes_as_entry, order};
+use servers2::admin::{ChainInfo, Request, Response, MAX_DUMP_ENTRIES};
+
+const USAGE: &'static str =
+"Usage:
+\tfuzzylog-admin <admin addr> chains
+\tfuzzylog-admin <admin addr> chain <chain>
+\tfuzzylog-admin <admin addr> dump <chain> [<first entry> [<last entry>]]
+\tfuzzylog-admin <admin addr> stuck
+\tfuzzylog-admin <admin addr> clients
+\tfuzzylog-admin lag <head admin addr> <tail admin addr>
+
+<admin addr> is the address given to a server with '--admin'.";
+
+pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        usage()
+    }
+    let res = if args[0] == "lag" {
+        if args.len() != 3 { usage() }
+        lag(parse_addr(&args[1]), parse_addr(&args[2]))
+    } else {
+        let addr = parse_addr(&args[0]);
+        match (&*args[1], args.len()) {
+            ("chains", 2) => chains(addr, Request::ListChains),
+            ("chain", 3) => chains(addr, Request::ChainInfo(parse_num(&args[2]).into())),
+            ("dump", 3...5) => {
+                let chain = parse_num(&args[2]).into();
+                let first = args.get(3).map(|a| parse_num(a)).unwrap_or(1);
+                let last = args.get(4).map(|a| parse_num(a)).unwrap_or(::std::u64::MAX - 1);
+                dump(addr, chain, first, last)
+            },
+            ("stuck", 2) => stuck(addr),
+            ("clients", 2) => clients(addr),
+            _ => usage(),
+        }
+    };
+    if let Err(e) = res {
+        eprintln!("admin request failed: {}", e);
+        exit(1)
+    }
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    exit(1)
+}
+
+fn parse_addr(arg: &str) -> SocketAddr {
+    match arg.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Invalid <admin addr> '{}': {}.", arg, e);
+            exit(1)
+        },
+    }
+}
+
+fn parse_num(arg: &str) -> u64 {
+    match arg.parse() {
+        Ok(num) => num,
+        Err(e) => {
+            eprintln!("Invalid number '{}': {}.", arg, e);
+            exit(1)
+        },
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn new(addr: SocketAddr) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let _ = stream.set_nodelay(true);
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    fn request(&mut self, request: Request) -> io::Result<Response> {
+        request.write_to(&mut self.writer)?;
+        self.writer.flush()?;
+        match Response::read_from(&mut self.reader)? {
+            Response::Error(msg) => Err(io::Error::new(io::ErrorKind::Other, msg)),
+            response => Ok(response),
+        }
+    }
+
+    fn chains(&mut self, request: Request) -> io::Result<Vec<ChainInfo>> {
+        match self.request(request)? {
+            Response::Chains(chains) => Ok(chains),
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected response {:?}", response))
+}
+
+fn chains(addr: SocketAddr, request: Request) -> io::Result<()> {
+    let chains = Connection::new(addr)?.chains(request)?;
+    println!("{:>10} {:>12} {:>12} {:>8} {:>8}",
+        "chain", "min_entry", "next_entry", "phase1", "phase2");
+    for c in chains {
+        println!("{:>10} {:>12} {:>12} {:>8} {:>8}",
+            u64::from(c.chain), c.min_entry, c.next_entry, c.phase1, c.waiting_for_flush);
+    }
+    Ok(())
+}
+
+fn dump(addr: SocketAddr, chain: order, first: u64, last: u64) -> io::Result<()> {
+    let mut conn = Connection::new(addr)?;
+    //entries may be missing from a batch, so stop at the tail the server reports instead
+    let next_entry = match conn.chains(Request::ChainInfo(chain))?.first() {
+        Some(info) => info.next_entry,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound,
+            format!("chain {} is not on this server", u64::from(chain)))),
+    };
+    let last = ::std::cmp::min(last, next_entry.saturating_sub(1));
+    let mut next = first;
+    while next <= last {
+        let end = ::std::cmp::min(last.saturating_add(1), next.saturating_add(MAX_DUMP_ENTRIES));
+        let entries = match conn.request(Request::DumpChain{
+            chain, start: next.into(), end: end.into()
+        })? {
+            Response::Entries(entries) => entries,
+            other => return Err(unexpected(other)),
+        };
+        for (index, bytes) in entries.iter() {
+            println!("{:?}: {:?}", (u64::from(chain), u64::from(*index)), bytes_as_entry(bytes));
+        }
+        next = end;
+    }
+    Ok(())
+}
+
+fn stuck(addr: SocketAddr) -> io::Result<()> {
+    match Connection::new(addr)?.request(Request::StuckAppends)? {
+        Response::Stuck(stuck) => {
+            for s in stuck {
+                println!("{} waiting at ({}, timestamp {})", s.id, u64::from(s.chain), s.timestamp);
+            }
+            Ok(())
+        },
+        other => Err(unexpected(other)),
+    }
+}
+
+fn clients(addr: SocketAddr) -> io::Result<()> {
+    match Connection::new(addr)?.request(Request::Clients)? {
+        Response::Clients(clients) => {
+            for c in clients {
+                println!("{} on worker {}", c.id, c.worker);
+            }
+            Ok(())
+        },
+        other => Err(unexpected(other)),
+    }
+}
+
+fn lag(head: SocketAddr, tail: SocketAddr) -> io::Result<()> {
+    let head_chains = Connection::new(head)?.chains(Request::ListChains)?;
+    let tail_chains: HashMap<_, _> = Connection::new(tail)?.chains(Request::ListChains)?
+        .into_iter().map(|c| (c.chain, c.next_entry)).collect();
+    println!("{:>10} {:>12} {:>12} {:>8}", "chain", "head", "tail", "lag");
+    for c in head_chains {
+        let tail_next = tail_chains.get(&c.chain).cloned().unwrap_or(0);
+        println!("{:>10} {:>12} {:>12} {:>8}",
+            u64::from(c.chain), c.next_entry, tail_next, c.next_entry.saturating_sub(tail_next));
+    }
+    Ok(())
+}
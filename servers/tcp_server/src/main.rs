@@ -11,17 +11,28 @@ use std::env;
 use std::net::{SocketAddr, IpAddr, Ipv4Addr};
 use std::sync::atomic::AtomicUsize;
 
+use servers2::config::{Config, GcPolicy, ServerConfig};
+
 pub fn main() {
     let _ = env_logger::init();
-    let Args {port_number, group, num_worker_threads, upstream, downstream}
-        = parse_args();
-    let ip_addr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-    let addr = SocketAddr::new(ip_addr, port_number);
-    let (server_num, group_size) = match group {
-        Group::Singleton | Group::LockServer => (0, 1),
-        Group::InGroup(server_num, group_size) => (server_num, group_size),
-    };
-    let acceptor = mio::tcp::TcpListener::bind(&addr);
+    let (server, group) = server_config(parse_args());
+    let ServerConfig {
+        listen, server_num, group_size, upstream, downstream, workers, ordering_threads, admin, limits, ..
+    } = server;
+    let num_worker_threads = workers.unwrap_or_else(|| num_cpus::get().saturating_sub(2));
+    let acceptor = mio::tcp::TcpListener::bind(&listen);
+    let admin = admin.map(|addr|
+        match std::net::TcpListener::bind(addr) {
+            Ok(admin) => {
+                println!("Serving admin requests at {}", admin.local_addr().unwrap());
+                admin
+            },
+            Err(e) => {
+                error!("Could not start admin listener due to {}.", e);
+                std::process::exit(1)
+            }
+        }
+    );
     let a = AtomicUsize::new(0);
     let replicated = upstream.is_some() || downstream.is_some();
     let print_start = |addr| match group {
@@ -41,12 +52,9 @@ pub fn main() {
             print_start(addr);
             if replicated {
                 println!("upstream {:?}, downstream {:?}", upstream, downstream);
-                servers2::tcp::run_with_replication(accept, server_num, group_size,
-                    upstream, downstream, num_worker_threads, &a)
-            }
-            else {
-                servers2::tcp::run(accept, server_num, group_size, num_worker_threads, &a)
             }
+            servers2::tcp::run_sharded(accept, server_num, group_size,
+                upstream, downstream, num_worker_threads, ordering_threads, admin, limits, &a)
         }
         Err(e) => {
             error!("Could not start server due to {}.", e);
@@ -81,21 +89,38 @@ pub fn main() {
 
 const USAGE: &'static str =
 "Usage:
-\ttcp_server <port number> [-w | --workers <num worker threads>] [-up | --upstream <ip addr>:<port>] [-dwn | --downstream <ip addr>]
-\ttcp_server (-ls | --lock-server) [-w | --workers <num worker threads>] [-up | --upstream <ip addr>:<port>] [-dwn | --downstream <ip addr>]
-\ttcp_server (-ig | --in-group <server num>:<num servers in group>) [--workers <num worker threads>] [-up | --upstream <ip addr>:<port>] [-dwn | --downstream <ip addr>]
+\ttcp_server <port number> [-w | --workers <num worker threads>] [-up | --upstream <ip addr>:<port>] [-dwn | --downstream <ip addr>] [-adm | --admin <admin port>]
+\ttcp_server (-ls | --lock-server) [-w | --workers <num worker threads>] [-up | --upstream <ip addr>:<port>] [-dwn | --downstream <ip addr>] [-adm | --admin <admin port>]
+\ttcp_server (-ig | --in-group <server num>:<num servers in group>) [--workers <num worker threads>] [-up | --upstream <ip addr>:<port>] [-dwn | --downstream <ip addr>] [-adm | --admin <admin port>]
+\ttcp_server (-c | --config <config file>) [<port number>] [<flags>...]
+
+the chains on an unreplicated server can be ordered by several threads with
+	[--ordering-threads <num ordering threads>]
+replication is not split between ordering threads, so a server with an
+upstream or downstream refuses to start with more than one.
+
+flow control can be tuned with
+\t[--log-queue <max requests waiting for the log>] [--max-in-flight <max appends in flight per client>] [--max-batch <max requests ordered together>]
+
+when a config file is given the flags override the values in its [server] section.
 
 can also be run with 'cargo run --release -- <args>...'";
 
 struct Args {
-    port_number: u16,
+    port_number: Option<u16>,
     group: Group,
-    num_worker_threads: usize,
+    num_worker_threads: Option<usize>,
     upstream: Option<SocketAddr>,
     downstream: Option<IpAddr>,
+    admin_port: Option<u16>,
+    config: Option<String>,
+    log_queue_len: Option<usize>,
+    max_in_flight: Option<usize>,
+    max_batch: Option<usize>,
+    ordering_threads: Option<usize>,
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum Group {
     LockServer,
     Singleton,
@@ -108,6 +133,12 @@ enum Flag {
     InGroup,
     Upstream,
     Downstream,
+    Admin,
+    Config,
+    LogQueue,
+    MaxInFlight,
+    MaxBatch,
+    OrderingThreads,
 }
 
 fn parse_args() -> Args {
@@ -117,11 +148,17 @@ fn parse_args() -> Args {
         std::process::exit(1)
     }
     let mut args = Args {
-        port_number: 0,
+        port_number: None,
         group: Group::Singleton,
-        num_worker_threads: num_cpus::get() - 2,
+        num_worker_threads: None,
         upstream: None,
         downstream: None,
+        admin_port: None,
+        config: None,
+        log_queue_len: None,
+        max_in_flight: None,
+        max_batch: None,
+        ordering_threads: None,
     };
     let mut last_flag = Flag::None;
     for arg in env_args.skip(1) {
@@ -149,9 +186,27 @@ fn parse_args() -> Args {
                     "-dwn" | "--downstream" => {
                         last_flag = Flag::Downstream
                     }
+                    "-adm" | "--admin" => {
+                        last_flag = Flag::Admin
+                    }
+                    "-c" | "--config" => {
+                        last_flag = Flag::Config
+                    }
+                    "--log-queue" => {
+                        last_flag = Flag::LogQueue
+                    }
+                    "--max-in-flight" => {
+                        last_flag = Flag::MaxInFlight
+                    }
+                    "--max-batch" => {
+                        last_flag = Flag::MaxBatch
+                    }
+                    "--ordering-threads" => {
+                        last_flag = Flag::OrderingThreads
+                    }
                     port => {
                         match port.parse() {
-                            Ok(port) => args.port_number = port,
+                            Ok(port) => args.port_number = Some(port),
                             Err(e) => {
                                 error!("Invalid flag: {}.", port);
                                 debug!("caused by {}", e);
@@ -166,10 +221,10 @@ fn parse_args() -> Args {
                     Ok(num_workers) => {
                         if num_workers == 0 {
                             println!("WARNING: Number of worker threads must be non-zero, will default to 1");
-                            args.num_worker_threads = 1
+                            args.num_worker_threads = Some(1)
                         }
                         else {
-                            args.num_worker_threads = num_workers
+                            args.num_worker_threads = Some(num_workers)
                         }
                         last_flag = Flag::None
                     }
@@ -201,6 +256,62 @@ fn parse_args() -> Args {
                 }
                 last_flag = Flag::None;
             }
+            Flag::Admin => {
+                match arg.parse() {
+                    Ok(port) => {
+                        args.admin_port = Some(port)
+                    }
+                    Err(e) => {
+                        error!("Invalid <admin port> at '--admin': {}.", e);
+                        std::process::exit(1)
+                    }
+                }
+                last_flag = Flag::None;
+            }
+            Flag::Config => {
+                args.config = Some(arg);
+                last_flag = Flag::None;
+            }
+            Flag::LogQueue => {
+                match arg.parse() {
+                    Ok(len) if len > 0 => args.log_queue_len = Some(len),
+                    _ => {
+                        error!("Invalid <max requests waiting for the log> '{}' at '--log-queue'.", arg);
+                        std::process::exit(1)
+                    }
+                }
+                last_flag = Flag::None;
+            }
+            Flag::MaxInFlight => {
+                match arg.parse() {
+                    Ok(max) if max > 0 => args.max_in_flight = Some(max),
+                    _ => {
+                        error!("Invalid <max appends in flight per client> '{}' at '--max-in-flight'.", arg);
+                        std::process::exit(1)
+                    }
+                }
+                last_flag = Flag::None;
+            }
+            Flag::MaxBatch => {
+                match arg.parse() {
+                    Ok(max) if max > 0 => args.max_batch = Some(max),
+                    _ => {
+                        error!("Invalid <max requests ordered together> '{}' at '--max-batch'.", arg);
+                        std::process::exit(1)
+                    }
+                }
+                last_flag = Flag::None;
+            }
+            Flag::OrderingThreads => {
+                match arg.parse() {
+                    Ok(threads) if threads > 0 => args.ordering_threads = Some(threads),
+                    _ => {
+                        error!("Invalid <num ordering threads> '{}' at '--ordering-threads'.", arg);
+                        std::process::exit(1)
+                    }
+                }
+                last_flag = Flag::None;
+            }
             Flag::InGroup => {
                 let split: Vec<_> = arg.split(':').collect();
                 if split.len() != 2 {
@@ -254,6 +365,112 @@ fn parse_args() -> Args {
             error!("Missing <upstream addr> for '--upstream'");
             std::process::exit(1)
         }
+        Flag::Admin => {
+            error!("Missing <admin port> for '--admin'");
+            std::process::exit(1)
+        }
+        Flag::Config => {
+            error!("Missing <config file> for '--config'");
+            std::process::exit(1)
+        }
+        Flag::LogQueue => {
+            error!("Missing <max requests waiting for the log> for '--log-queue'");
+            std::process::exit(1)
+        }
+        Flag::MaxInFlight => {
+            error!("Missing <max appends in flight per client> for '--max-in-flight'");
+            std::process::exit(1)
+        }
+        Flag::MaxBatch => {
+            error!("Missing <max requests ordered together> for '--max-batch'");
+            std::process::exit(1)
+        }
+        Flag::OrderingThreads => {
+            error!("Missing <num ordering threads> for '--ordering-threads'");
+            std::process::exit(1)
+        }
+    }
+
+}
+
+// Combine the config file, if there is one, with the command line flags,
+// the flags take precedence.
+fn server_config(args: Args) -> (ServerConfig, Group) {
+    let mut server = match args.config {
+        None => ServerConfig {
+            listen: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            ..ServerConfig::default()
+        },
+        Some(ref path) => match Config::from_file(path) {
+            Ok(Config { server: Some(server), .. }) => server,
+            Ok(Config { server: None, .. }) => {
+                warn!("{} has no [server] section, using the defaults.", path);
+                ServerConfig::default()
+            },
+            Err(e) => {
+                error!("Could not load {}: {}.", path, e);
+                std::process::exit(1)
+            },
+        },
+    };
+    let mut group = if server.group_size > 1 {
+        Group::InGroup(server.server_num, server.group_size)
+    } else {
+        Group::Singleton
+    };
+    match args.group {
+        Group::Singleton => {},
+        Group::LockServer => {
+            server.server_num = 0;
+            server.group_size = 1;
+            group = Group::LockServer
+        },
+        Group::InGroup(server_num, group_size) => {
+            server.server_num = server_num;
+            server.group_size = group_size;
+            group = args.group
+        },
+    }
+    if let Some(port) = args.port_number {
+        server.listen.set_port(port)
+    }
+    if args.num_worker_threads.is_some() {
+        server.workers = args.num_worker_threads
+    }
+    if args.upstream.is_some() {
+        server.upstream = args.upstream
+    }
+    if args.downstream.is_some() {
+        server.downstream = args.downstream
+    }
+    if let Some(port) = args.admin_port {
+        server.admin = Some(SocketAddr::new(server.listen.ip(), port))
+    }
+    if let Some(len) = args.log_queue_len {
+        server.limits.log_queue_len = len
+    }
+    if let Some(max) = args.max_in_flight {
+        server.limits.max_in_flight_per_client = max
+    }
+    if let Some(max) = args.max_batch {
+        server.limits.max_batch = max
+    }
+    if let Some(threads) = args.ordering_threads {
+        server.ordering_threads = threads
+    }
+    if let Err(e) = server.validate() {
+        error!("{}.", e);
+        std::process::exit(1)
     }
 
+    if server.storage_dir.is_some() {
+        warn!("storage_dir is set but this server only stores entries in memory.");
+    }
+    if server.gc != GcPolicy::Never {
+        warn!("gc is set but this server does not yet collect garbage.");
+    }
+    if server.metrics.is_some() {
+        warn!("metrics is set but this server does not yet export metrics.");
+    }
+    (server, group)
 }
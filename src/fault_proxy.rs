@@ -0,0 +1,549 @@
+//! A TCP proxy which injects network faults, for tests.
+//!
+//! A `FaultProxy` listens on an ephemeral localhost port and forwards every
+//! connection it accepts to a fixed target, either a server a client should
+//! reach through the proxy, or the upstream replica a downstream replica
+//! should. While forwarding it can delay, drop or duplicate messages, or hold
+//! them back entirely to simulate a partition, independently in each
+//! direction. Faults can be changed at any time or set on a schedule.
+//!
+//! The proxy understands the fuzzy log wire format, so faults are applied to
+//! whole messages rather than arbitrary byte ranges: a dropped append is
+//! lost, not corrupted. The handshake at the start of each connection is only
+//! ever delayed or held back, never dropped or duplicated.
+//!
+//! `TestClusterBuilder::fault_proxies` puts a proxy in front of every server
+//! of a `TestCluster`.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use fuzzy_log::TestCluster;
+//! use fuzzy_log::fault_proxy::{Direction, Faults};
+//!
+//! let cluster = TestCluster::builder().fault_proxies(true).start().unwrap();
+//! let mut handle = cluster.handle::<[u8]>().chains(vec![1.into()]).build();
+//! let head = cluster.client_proxy(0, 0).unwrap();
+//! head.set_faults(Direction::Both, Faults {
+//!     delay: Duration::from_millis(10), ..Default::default()
+//! });
+//! handle.append(1.into(), &b"slow"[..], &[]);
+//! head.partition();
+//! let id = handle.async_append(1.into(), &b"stuck"[..], &[]);
+//! head.heal();
+//! handle.wait_for_a_specific_append(id).unwrap();
+//! ```
+
+use std::{fmt, io, mem, thread};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use packets::EntryContents;
+use packets::Packet::WrapErr;
+use servers2::sim::Rng;
+
+use fuzzy_log_util::socket_addr::Ipv4SocketAddr;
+
+/// Which way through the proxy a fault applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From whoever connected to the proxy towards its target.
+    ToTarget,
+    /// From the target back towards whoever connected.
+    FromTarget,
+    Both,
+}
+
+/// The faults applied to messages travelling in one direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Faults {
+    /// Hold each message this long before forwarding it.
+    pub delay: Duration,
+    /// The chance, out of 100, that a message is silently discarded.
+    pub drop_percent: u32,
+    /// The chance, out of 100, that a message is forwarded twice.
+    pub duplicate_percent: u32,
+    /// Hold every message until the partition is healed, as TCP would
+    /// retransmit across a network partition.
+    pub partitioned: bool,
+}
+
+/// A change to a proxy's faults, see `FaultProxy::run_schedule`.
+#[derive(Debug, Clone)]
+pub enum Action {
+    SetFaults(Direction, Faults),
+    Partition,
+    Heal,
+    Sever,
+}
+
+pub struct FaultProxy {
+    addr: SocketAddr,
+    target: SocketAddr,
+    shared: Arc<Shared>,
+    acceptor: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    stop: AtomicBool,
+}
+
+struct State {
+    // indexed by `Direction::index`
+    faults: [Faults; 2],
+    rng: Rng,
+    // clones of both ends of every live connection, by connection,
+    // so they can be severed
+    connections: Vec<(u64, [TcpStream; 2])>,
+    next_connection: u64,
+}
+
+impl FaultProxy {
+    /// Start a proxy to `target` which initially forwards everything as-is.
+    pub fn start(target: SocketAddr) -> io::Result<Self> {
+        Self::start_seeded(target, 0)
+    }
+
+    /// Like `start`, with `seed` determining which messages are dropped or
+    /// duplicated.
+    pub fn start_seeded(target: SocketAddr, seed: u64) -> io::Result<Self> {
+        let local_host = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 0);
+        let listener = TcpListener::bind(local_host)?;
+        let addr = listener.local_addr()?;
+        // polled so the acceptor notices when the proxy is dropped
+        listener.set_nonblocking(true)?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                faults: Default::default(),
+                rng: Rng::new(seed),
+                connections: vec![],
+                next_connection: 0,
+            }),
+            changed: Condvar::new(),
+            stop: AtomicBool::new(false),
+        });
+        let acceptor = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(format!("fault proxy {} => {}", addr, target))
+                .spawn(move || accept(listener, target, shared))?
+        };
+        Ok(FaultProxy { addr, target, shared, acceptor: Some(acceptor) })
+    }
+
+    /// The address to connect to instead of the target.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// The faults currently applied in `direction`,
+    /// for `Both` those towards the target.
+    pub fn faults(&self, direction: Direction) -> Faults {
+        let state = self.shared.state.lock().unwrap();
+        match direction {
+            Direction::FromTarget => state.faults[1],
+            _ => state.faults[0],
+        }
+    }
+
+    /// Replace the faults for `direction`.
+    /// Messages already delayed keep their original delay.
+    pub fn set_faults(&self, direction: Direction, faults: Faults) {
+        self.update(direction, |f| *f = faults)
+    }
+
+    /// Hold all traffic, in both directions, until `heal` is called.
+    /// Connections stay open and the other faults are unchanged.
+    pub fn partition(&self) {
+        self.update(Direction::Both, |f| f.partitioned = true)
+    }
+
+    /// Release everything held by `partition`, in the order it was sent.
+    pub fn heal(&self) {
+        self.update(Direction::Both, |f| f.partitioned = false)
+    }
+
+    /// Remove every fault.
+    pub fn clear(&self) {
+        self.set_faults(Direction::Both, Faults::default())
+    }
+
+    /// The number of connections currently going through the proxy.
+    /// A connection is gone once both of its directions have closed.
+    pub fn num_connections(&self) -> usize {
+        self.shared.state.lock().unwrap().connections.len()
+    }
+
+    /// Close every connection currently going through the proxy.
+    /// New connections are still accepted.
+    pub fn sever(&self) {
+        self.shared.sever()
+    }
+
+    /// Apply each action once its offset from now has passed,
+    /// on a background thread. Actions are applied in offset order.
+    pub fn run_schedule<I>(&self, schedule: I) -> thread::JoinHandle<()>
+    where I: IntoIterator<Item=(Duration, Action)> {
+        let mut schedule: Vec<_> = schedule.into_iter().collect();
+        schedule.sort_by_key(|&(at, _)| at);
+        let shared = self.shared.clone();
+        let start = Instant::now();
+        thread::spawn(move || {
+            for (at, action) in schedule {
+                let elapsed = start.elapsed();
+                if at > elapsed {
+                    thread::sleep(at - elapsed)
+                }
+                if shared.stop.load(Ordering::Acquire) {
+                    return
+                }
+                trace!("PROXY {:?}", action);
+                match action {
+                    Action::SetFaults(direction, faults) =>
+                        shared.update(direction, |f| *f = faults),
+                    Action::Partition => shared.update(Direction::Both, |f| f.partitioned = true),
+                    Action::Heal => shared.update(Direction::Both, |f| f.partitioned = false),
+                    Action::Sever => shared.sever(),
+                }
+            }
+        })
+    }
+
+    fn update<F>(&self, direction: Direction, f: F)
+    where F: FnMut(&mut Faults) {
+        self.shared.update(direction, f)
+    }
+}
+
+impl fmt::Debug for FaultProxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FaultProxy")
+            .field("addr", &self.addr)
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+impl Drop for FaultProxy {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        self.shared.changed.notify_all();
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        self.shared.sever()
+    }
+}
+
+impl Direction {
+    fn index(self) -> usize {
+        match self {
+            Direction::ToTarget => 0,
+            Direction::FromTarget => 1,
+            Direction::Both => unreachable!(),
+        }
+    }
+}
+
+impl Shared {
+    fn update<F>(&self, direction: Direction, mut f: F)
+    where F: FnMut(&mut Faults) {
+        {
+            let mut state = self.state.lock().unwrap();
+            match direction {
+                Direction::Both => {
+                    f(&mut state.faults[0]);
+                    f(&mut state.faults[1]);
+                },
+                d => f(&mut state.faults[d.index()]),
+            }
+        }
+        self.changed.notify_all()
+    }
+
+    fn sever(&self) {
+        let connections = mem::replace(&mut self.state.lock().unwrap().connections, vec![]);
+        for (_, ends) in connections {
+            for end in &ends {
+                let _ = end.shutdown(Shutdown::Both);
+            }
+        }
+        self.changed.notify_all()
+    }
+
+    /// The time to forward a message read now, and how many copies to send.
+    fn route(&self, direction: Direction, is_handshake: bool) -> (Instant, usize) {
+        let mut state = self.state.lock().unwrap();
+        let faults = state.faults[direction.index()];
+        let copies = if is_handshake {
+            1
+        } else if state.rng.percent(faults.drop_percent) {
+            0
+        } else if state.rng.percent(faults.duplicate_percent) {
+            2
+        } else {
+            1
+        };
+        (Instant::now() + faults.delay, copies)
+    }
+
+    /// Blocks while `direction` is partitioned, returns `false` if the proxy
+    /// stopped in the meantime.
+    fn wait_until_connected(&self, direction: Direction) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while state.faults[direction.index()].partitioned {
+            if self.stop.load(Ordering::Acquire) {
+                return false
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+        !self.stop.load(Ordering::Acquire)
+    }
+}
+
+fn accept(listener: TcpListener, target: SocketAddr, shared: Arc<Shared>) {
+    while !shared.stop.load(Ordering::Acquire) {
+        let client = match listener.accept() {
+            Ok((client, _)) => client,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10));
+                continue
+            },
+            Err(e) => {
+                error!("PROXY accept error {}", e);
+                continue
+            },
+        };
+        let server = match TcpStream::connect(target) {
+            Ok(server) => server,
+            Err(e) => {
+                // the client sees its connection close, as if the target were down
+                trace!("PROXY cannot reach {}: {}", target, e);
+                continue
+            },
+        };
+        if let Err(e) = connect(client, server, &shared) {
+            error!("PROXY cannot forward to {}: {}", target, e)
+        }
+    }
+}
+
+fn connect(client: TcpStream, server: TcpStream, shared: &Arc<Shared>) -> io::Result<()> {
+    client.set_nonblocking(false)?;
+    let _ = client.set_nodelay(true);
+    let _ = server.set_nodelay(true);
+    let connection = {
+        let mut state = shared.state.lock().unwrap();
+        let id = state.next_connection;
+        state.next_connection += 1;
+        state.connections.push((id, [client.try_clone()?, server.try_clone()?]));
+        Arc::new(Connection { shared: shared.clone(), id })
+    };
+    // the message format from the target depends on whether the connection
+    // is from a client or a replica, which is sent in the handshake
+    let (send_kind, recv_kind) = mpsc::channel();
+    spawn_pump(&connection, Direction::ToTarget, client.try_clone()?, server.try_clone()?,
+        Framing::ToTarget(send_kind))?;
+    spawn_pump(&connection, Direction::FromTarget, server, client,
+        Framing::FromTarget(recv_kind))
+}
+
+/// Held by every thread pumping a connection,
+/// which is forgotten once none of them are running.
+struct Connection {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.shared.state.lock().unwrap().connections.retain(|&(c, _)| c != id)
+    }
+}
+
+/// How to split one direction of a connection into messages.
+enum Framing {
+    /// A one byte connection kind and a client id, then packets each followed
+    /// by the sender's id. The kind is passed on to the other direction.
+    ToTarget(mpsc::Sender<u8>),
+    /// A one byte ready signal, and a client id once the kind is known,
+    /// then packets; those to a replica also carry a storage location
+    /// and the id of the client which sent them.
+    FromTarget(mpsc::Receiver<u8>),
+}
+
+// the kind a replica sends in the handshake, clients send 2
+const REPLICA_KIND: u8 = 1;
+
+fn spawn_pump(
+    connection: &Arc<Connection>,
+    direction: Direction,
+    from: TcpStream,
+    to: TcpStream,
+    framing: Framing,
+) -> io::Result<()> {
+    let (send, recv) = mpsc::channel();
+    {
+        let connection = connection.clone();
+        thread::Builder::new()
+            .name(format!("fault proxy {:?} reader", direction))
+            .spawn(move || read_messages(&connection.shared, direction, from, framing, send))?;
+    }
+    let connection = connection.clone();
+    thread::Builder::new()
+        .name(format!("fault proxy {:?} writer", direction))
+        .spawn(move || write_messages(&connection.shared, direction, to, recv))?;
+    Ok(())
+}
+
+fn read_messages(
+    shared: &Shared,
+    direction: Direction,
+    from: TcpStream,
+    framing: Framing,
+    to_writer: mpsc::Sender<(Instant, Vec<u8>)>,
+) {
+    let mut reader = MessageReader { stream: from, buffer: vec![] };
+    let forward = |message: Vec<u8>, is_handshake: bool| {
+        let (at, copies) = shared.route(direction, is_handshake);
+        for _ in 1..copies {
+            let _ = to_writer.send((at, message.clone()));
+        }
+        if copies > 0 {
+            let _ = to_writer.send((at, message));
+        }
+    };
+    let id_size = mem::size_of::<Ipv4SocketAddr>();
+    let trailer = match framing {
+        Framing::ToTarget(send_kind) => {
+            let handshake = match reader.read_exact(1 + id_size) {
+                Some(handshake) => handshake,
+                None => return,
+            };
+            let _ = send_kind.send(handshake[0]);
+            forward(handshake, true);
+            id_size
+        },
+        Framing::FromTarget(recv_kind) => {
+            match reader.read_exact(1) {
+                Some(ready) => forward(ready, true),
+                None => return,
+            }
+            let kind = match recv_kind.recv() {
+                Ok(kind) => kind,
+                Err(..) => return,
+            };
+            match reader.read_exact(id_size) {
+                Some(id) => forward(id, true),
+                None => return,
+            }
+            if kind == REPLICA_KIND {
+                mem::size_of::<u64>() + id_size
+            } else {
+                0
+            }
+        },
+    };
+    loop {
+        match reader.read_packet(trailer) {
+            Ok(Some(message)) => forward(message, false),
+            Ok(None) => return,
+            Err(()) => {
+                // not something we understand, stop applying faults and
+                // pass the rest through untouched
+                error!("PROXY cannot parse message {:?}, forwarding raw bytes", direction);
+                loop {
+                    let rest = mem::replace(&mut reader.buffer, vec![]);
+                    if !rest.is_empty() {
+                        forward(rest, true)
+                    }
+                    if !reader.fill() {
+                        return
+                    }
+                }
+            },
+        }
+    }
+}
+
+fn write_messages(
+    shared: &Shared,
+    direction: Direction,
+    mut to: TcpStream,
+    from_reader: mpsc::Receiver<(Instant, Vec<u8>)>,
+) {
+    for (at, message) in from_reader {
+        let now = Instant::now();
+        if at > now {
+            thread::sleep(at - now)
+        }
+        if !shared.wait_until_connected(direction) {
+            break
+        }
+        if to.write_all(&message).is_err() {
+            break
+        }
+    }
+    let _ = to.shutdown(Shutdown::Write);
+}
+
+struct MessageReader {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl MessageReader {
+    /// Read more bytes onto the end of the buffer,
+    /// returns `false` once the stream is closed.
+    fn fill(&mut self) -> bool {
+        let mut bytes = [0u8; 8192];
+        loop {
+            match self.stream.read(&mut bytes) {
+                Ok(0) => return false,
+                Ok(read) => {
+                    self.buffer.extend_from_slice(&bytes[..read]);
+                    return true
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(..) => return false,
+            }
+        }
+    }
+
+    fn read_exact(&mut self, len: usize) -> Option<Vec<u8>> {
+        while self.buffer.len() < len {
+            if !self.fill() {
+                return None
+            }
+        }
+        let rest = self.buffer.split_off(len);
+        Some(mem::replace(&mut self.buffer, rest))
+    }
+
+    /// The next packet along with `trailer` bytes after it,
+    /// `Ok(None)` once the stream is closed.
+    fn read_packet(&mut self, trailer: usize) -> Result<Option<Vec<u8>>, ()> {
+        loop {
+            let size = unsafe { EntryContents::try_ref(&self.buffer).map(|(c, _)| c.len()) };
+            match size {
+                Ok(size) if self.buffer.len() >= size + trailer => {
+                    return Ok(self.read_exact(size + trailer))
+                },
+                Ok(..) | Err(WrapErr::NotEnoughBytes(..)) => {},
+                Err(..) => return Err(()),
+            }
+            if !self.fill() {
+                return Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Recording what clients did and checking it against the log's guarantees.
+//!
+//! A `History` collects, from any number of `LogHandle`s, the appends each
+//! one had acknowledged, with the locations it was given, and the events it
+//! read, in the order it read them. `History::check` then verifies offline
+//! that
+//!  - every location holds a single entry, and every entry a single location
+//!    in each of its chains, so all clients agree on each chain's order,
+//!  - each client reads the chains it is interested in in order, without
+//!    skipping entries other clients saw,
+//!  - multiappends are seen in all of their chains or none,
+//!  - and no client reads an entry before the entries it happens after.
+//!
+//! ```no_run
+//! use fuzzy_log::TestCluster;
+//! use fuzzy_log::history::History;
+//!
+//! let cluster = TestCluster::builder().start().unwrap();
+//! let history = History::new();
+//! let writer = history.client(&[]);
+//! let mut w = cluster.handle::<[u8]>().chains(vec![1.into(), 2.into()]).build();
+//! writer.multiappend(&mut w, &[1.into(), 2.into()], &b"hello"[..], &[]);
+//!
+//! let reader = history.client(&[1.into(), 2.into()]);
+//! let mut r = cluster.handle::<[u8]>().chains(vec![1.into(), 2.into()]).build();
+//! r.snapshot_colors(&[1.into(), 2.into()]);
+//! while let Ok(_) = reader.get_next(&mut r) {}
+//! history.check().unwrap();
+//! ```
+//!
+//! Histories can be saved with `write_to` and checked later with `read_from`,
+//! one record per line:
+//!
+//! ```text
+//! C <client> <chain>,<chain>,...
+//! A <client> <id> <chain>:<index>,... <dep chain>:<dep index>,...
+//! R <client> <id> <chain>:<index>,... <dep chain>:<dep index>,...
+//! ```
+//!
+//! `C` declares the chains a client reads, `A` is an acknowledged append and
+//! `R` an event a client read; an empty list is written as `-`.
+//! Locations with index `0`, such as the chains of a multiappend a client
+//! does not know the position in, are ignored.
+//! The throughput bench's `ssm` workload records such a file with
+//! `--history <file>`, and its `check_history <file>` command checks one.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use async::fuzzy_log::log_handle::{Event, GetRes, LogHandle};
+use packets::{entry, order, OrderIndex, Uuid};
+use packets::storeables::{Storeable, UnStoreable};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Append,
+    Read,
+}
+
+/// One acknowledged append or read event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub client: usize,
+    pub kind: Kind,
+    pub id: Uuid,
+    pub locs: Vec<OrderIndex>,
+    pub deps: Vec<OrderIndex>,
+}
+
+/// A broken guarantee found by `History::check`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// Two different entries were seen at the same location.
+    Conflict { loc: OrderIndex, ids: (Uuid, Uuid) },
+    /// One entry was seen at two locations in the same chain.
+    Moved { id: Uuid, locs: (OrderIndex, OrderIndex) },
+    /// A client read a chain out of order.
+    OutOfOrder { client: usize, before: OrderIndex, after: OrderIndex },
+    /// A client read past an entry without reading it.
+    Skipped { client: usize, loc: OrderIndex, id: Uuid },
+    /// A client read a multiappend in only some of its chains.
+    Partial { client: usize, id: Uuid, read: Vec<order>, appended: Vec<order> },
+    /// A client read an entry without first reading one it happens after.
+    Causality { client: usize, id: Uuid, dep: OrderIndex },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Violation::*;
+        match *self {
+            Conflict { loc, ids } =>
+                write!(f, "both {} and {} are at {:?}", ids.0, ids.1, loc),
+            Moved { id, locs } =>
+                write!(f, "{} is at both {:?} and {:?}", id, locs.0, locs.1),
+            OutOfOrder { client, before, after } =>
+                write!(f, "client {} read {:?} after {:?}", client, after, before),
+            Skipped { client, loc, id } =>
+                write!(f, "client {} skipped {} at {:?}", client, id, loc),
+            Partial { client, id, ref read, ref appended } =>
+                write!(f, "client {} read {} in {:?}, it was appended to {:?}",
+                    client, id, read, appended),
+            Causality { client, id, dep } =>
+                write!(f, "client {} read {} before its dependency {:?}", client, id, dep),
+        }
+    }
+}
+
+/// Records shared by every client of a test or benchmark.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    // the chains each client reads, indexed by client
+    clients: Vec<Vec<order>>,
+    records: Vec<Record>,
+}
+
+/// Adds the operations of one client to a `History`.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    client: usize,
+    history: History,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new client, reading `chains`.
+    /// Clients which only append can pass no chains.
+    pub fn client(&self, chains: &[order]) -> Recorder {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clients.push(chains.to_vec());
+        Recorder { client: inner.clients.len() - 1, history: self.clone() }
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        self.inner.lock().unwrap().records.clone()
+    }
+
+    fn push(&self, record: Record) {
+        self.inner.lock().unwrap().records.push(record)
+    }
+
+    /// Check every recorded operation, returning all of the violations found.
+    pub fn check(&self) -> Result<(), Vec<Violation>> {
+        let inner = self.inner.lock().unwrap();
+        let violations = check(&inner.clients, &inner.records);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let inner = self.inner.lock().unwrap();
+        for (client, chains) in inner.clients.iter().enumerate() {
+            let chains: Vec<_> = chains.iter().map(|&c| u64::from(c).to_string()).collect();
+            writeln!(out, "C {} {}", client, list(chains))?;
+        }
+        for record in &inner.records {
+            let kind = match record.kind {
+                Kind::Append => "A",
+                Kind::Read => "R",
+            };
+            let locs = |locs: &[OrderIndex]| list(locs.iter().map(|&OrderIndex(o, i)|
+                format!("{}:{}", u64::from(o), u64::from(i))
+            ).collect());
+            writeln!(out, "{} {} {} {} {}",
+                kind, record.client, record.id, locs(&record.locs), locs(&record.deps))?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(input: R) -> io::Result<Self> {
+        let mut inner = Inner::default();
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue
+            }
+            parse_line(line, &mut inner).ok_or_else(||
+                io::Error::new(io::ErrorKind::InvalidData, format!("bad record {:?}", line))
+            )?;
+        }
+        Ok(History { inner: Arc::new(Mutex::new(inner)) })
+    }
+}
+
+impl Recorder {
+    pub fn client_num(&self) -> usize {
+        self.client
+    }
+
+    /// Record an acknowledged append and the locations it was given.
+    pub fn appended(&self, id: Uuid, locs: &[OrderIndex], deps: &[OrderIndex]) {
+        self.history.push(Record {
+            client: self.client,
+            kind: Kind::Append,
+            id: id,
+            locs: locs.to_vec(),
+            deps: deps.to_vec(),
+        })
+    }
+
+    /// Record an event read by this client.
+    pub fn read(&self, id: Uuid, inhabits: &[OrderIndex], happens_after: &[OrderIndex]) {
+        self.history.push(Record {
+            client: self.client,
+            kind: Kind::Read,
+            id: id,
+            locs: inhabits.to_vec(),
+            deps: happens_after.to_vec(),
+        })
+    }
+
+    /// `LogHandle::append`, recording the result.
+    pub fn append<V: ?Sized + Storeable>(
+        &self, handle: &mut LogHandle<V>, chain: order, data: &V, deps: &[OrderIndex]
+    ) -> Vec<OrderIndex> {
+        let id = handle.async_append(chain, data, deps);
+        let locs = handle.wait_for_a_specific_append(id).unwrap();
+        self.appended(id, &locs, deps);
+        locs
+    }
+
+    /// `LogHandle::multiappend`, recording the result.
+    pub fn multiappend<V: ?Sized + Storeable>(
+        &self, handle: &mut LogHandle<V>, chains: &[order], data: &V, deps: &[OrderIndex]
+    ) -> Vec<OrderIndex> {
+        let id = handle.async_multiappend(chains, data, deps);
+        let locs = handle.wait_for_a_specific_append(id).unwrap();
+        self.appended(id, &locs, deps);
+        locs
+    }
+
+    /// `LogHandle::get_next_event`, recording the event read.
+    pub fn get_next<'h, V: ?Sized + UnStoreable + Storeable>(
+        &self, handle: &'h mut LogHandle<V>
+    ) -> Result<Event<'h, V>, GetRes> {
+        let event = handle.get_next_event()?;
+        self.read(*event.id, event.inhabits, event.happens_after);
+        Ok(event)
+    }
+}
+
+fn list(items: Vec<String>) -> String {
+    if items.is_empty() {
+        "-".to_string()
+    } else {
+        items.join(",")
+    }
+}
+
+fn parse_line(line: &str, inner: &mut Inner) -> Option<()> {
+    let parts: Vec<_> = line.split_whitespace().collect();
+    match (parts.get(0).cloned(), parts.len()) {
+        (Some("C"), 3) => {
+            let client: usize = parts[1].parse().ok()?;
+            let chains = parse_list(parts[2], |c| c.parse::<u64>().ok().map(order::from))?;
+            if inner.clients.len() <= client {
+                inner.clients.resize(client + 1, vec![]);
+            }
+            inner.clients[client] = chains;
+        },
+        (Some(kind), 5) => {
+            let kind = match kind {
+                "A" => Kind::Append,
+                "R" => Kind::Read,
+                _ => return None,
+            };
+            let client: usize = parts[1].parse().ok()?;
+            if inner.clients.len() <= client {
+                inner.clients.resize(client + 1, vec![]);
+            }
+            inner.records.push(Record {
+                client: client,
+                kind: kind,
+                id: parts[2].parse().ok()?,
+                locs: parse_list(parts[3], parse_loc)?,
+                deps: parse_list(parts[4], parse_loc)?,
+            });
+        },
+        _ => return None,
+    }
+    Some(())
+}
+
+fn parse_list<T, F>(list: &str, parse: F) -> Option<Vec<T>>
+where F: Fn(&str) -> Option<T> {
+    if list == "-" {
+        return Some(vec![])
+    }
+    list.split(',').map(parse).collect()
+}
+
+fn parse_loc(loc: &str) -> Option<OrderIndex> {
+    let mut parts = loc.splitn(2, ':');
+    let chain: u64 = parts.next()?.parse().ok()?;
+    let index: u64 = parts.next()?.parse().ok()?;
+    Some(OrderIndex(chain.into(), index.into()))
+}
+
+fn is_known(loc: &OrderIndex) -> bool {
+    loc.0 != order::from(0) && loc.1 != entry::from(0)
+}
+
+fn chains_of(locs: &[OrderIndex]) -> Vec<order> {
+    let mut chains: Vec<_> = locs.iter().map(|l| l.0).filter(|&c| c != order::from(0)).collect();
+    chains.sort();
+    chains.dedup();
+    chains
+}
+
+#[derive(Default)]
+struct Violations {
+    found: Vec<Violation>,
+    seen: HashSet<Violation>,
+}
+
+impl Violations {
+    fn report(&mut self, violation: Violation) {
+        if self.seen.insert(violation.clone()) {
+            self.found.push(violation)
+        }
+    }
+}
+
+fn check(clients: &[Vec<order>], records: &[Record]) -> Vec<Violation> {
+    let mut violations = Violations::default();
+
+    // what every record agrees is at each location
+    let mut at: BTreeMap<OrderIndex, Uuid> = BTreeMap::new();
+    let mut position: HashMap<(Uuid, order), OrderIndex> = HashMap::new();
+    let mut appended_to: HashMap<Uuid, Vec<order>> = HashMap::new();
+    for record in records {
+        if record.kind == Kind::Append {
+            appended_to.insert(record.id, chains_of(&record.locs));
+        }
+        for &loc in record.locs.iter().filter(|l| is_known(l)) {
+            let other = *at.entry(loc).or_insert(record.id);
+            if other != record.id {
+                violations.report(Violation::Conflict { loc: loc, ids: (other, record.id) })
+            }
+            let other = *position.entry((record.id, loc.0)).or_insert(loc);
+            if other != loc {
+                violations.report(Violation::Moved { id: record.id, locs: (other, loc) })
+            }
+        }
+    }
+
+    for (client, chains) in clients.iter().enumerate() {
+        let reads = records.iter()
+            .filter(|r| r.client == client && r.kind == Kind::Read);
+        // when this client read each location
+        let mut read_at: HashMap<OrderIndex, usize> = HashMap::new();
+        let mut last: BTreeMap<order, OrderIndex> = BTreeMap::new();
+        for (n, read) in reads.enumerate() {
+            for &loc in read.locs.iter().filter(|l| is_known(l)) {
+                read_at.insert(loc, n);
+                if !chains.contains(&loc.0) {
+                    continue
+                }
+                if let Some(&before) = last.get(&loc.0) {
+                    if before.1 >= loc.1 {
+                        violations.report(Violation::OutOfOrder {
+                            client: client, before: before, after: loc,
+                        })
+                    }
+                }
+                last.insert(loc.0, loc);
+            }
+
+            if let Some(appended) = appended_to.get(&read.id) {
+                let read_chains = chains_of(&read.locs);
+                if read_chains != *appended {
+                    violations.report(Violation::Partial {
+                        client: client,
+                        id: read.id,
+                        read: read_chains,
+                        appended: appended.clone(),
+                    })
+                }
+            }
+
+            for &dep in read.deps.iter().filter(|l| is_known(l)) {
+                // only entries in the chains a client reads must be delivered
+                if !chains.contains(&dep.0) {
+                    continue
+                }
+                match read_at.get(&dep) {
+                    Some(&m) if m < n => {},
+                    _ => violations.report(Violation::Causality {
+                        client: client, id: read.id, dep: dep,
+                    }),
+                }
+            }
+        }
+
+        for (&chain, &end) in &last {
+            let start = OrderIndex(chain, entry::from(1));
+            for (&loc, &id) in at.range(start..end) {
+                if !read_at.contains_key(&loc) {
+                    violations.report(Violation::Skipped { client: client, loc: loc, id: id })
+                }
+            }
+        }
+    }
+
+    violations.found
+}
@@ -10,7 +10,6 @@ This crate contains a combined version of the fuzzy log client and server code.
 
 extern crate byteorder;
 extern crate mio;
-extern crate toml;
 extern crate libc;
 extern crate env_logger;
 
@@ -31,6 +30,11 @@ pub use packets::{order, entry, OrderIndex};
 pub use fuzzy_log_client as async;
 pub use async::fuzzy_log::log_handle::{LogHandle, LogBuilder};
 
+pub mod test_cluster;
+pub use test_cluster::{TestCluster, TestClusterBuilder};
+pub mod fault_proxy;
+pub mod history;
+
 #[cfg(test)] mod tests;
 #[cfg(test)] mod replication_tests;
 
@@ -70,25 +74,30 @@ pub fn run_server(
     )
 }
 
-pub fn start_server_thread(server_ip: &str) {
+/// Start an unreplicated server on a background thread,
+/// the returned handle can be used to stop it.
+pub fn start_server_thread(server_ip: &str) -> servers2::tcp::ShutdownHandle {
+    use std::sync::Arc;
     use std::sync::atomic::{AtomicUsize, Ordering};
-    let server_started = AtomicUsize::new(0);
-    let started = unsafe {
-        //This should be safe since the while loop at the of the function
-        //prevents it from exiting until the server is started and
-        //server_started is no longer used
-        (extend_lifetime(&server_started))
-    };
+    //The server thread outlives this function, so the readiness counter
+    //must be shared rather than borrowed from this stack frame.
+    let server_started = Arc::new(AtomicUsize::new(0));
+    let started = server_started.clone();
 
     let addr = server_ip.parse().unwrap();
+    let shutdown = servers2::tcp::ShutdownHandle::new();
+    let server_shutdown = shutdown.clone();
     ::std::thread::spawn(move || {
-        run_server(addr, 0, 1, None, None, 1, &started)
+        let acceptor = mio::tcp::TcpListener::bind(&addr)
+            .expect("Bind error");
+        servers2::tcp::run_until_shutdown(
+            acceptor, 0, 1, None, None, 1, 1, None, Default::default(), &server_shutdown, &started
+        )
     });
-    while !server_started.load(Ordering::SeqCst) < 1 {}
-
-    unsafe fn extend_lifetime<'a, 'b, T>(r: &'a T) -> &'b T {
-        ::std::mem::transmute(r)
+    while server_started.load(Ordering::SeqCst) < 1 {
+        ::std::thread::yield_now()
     }
+    shutdown
 }
 
 pub mod c_binidings {
@@ -96,31 +105,188 @@ pub mod c_binidings {
     use packets::*;
     use async::fuzzy_log::log_handle::{
         HashMap,
+        Event,
         LogHandle,
         ReadHandle,
         WriteHandle,
         GetRes,
         TryWaitRes
     };
+    use async::fuzzy_log::cut::Cut;
 
     //use std::collections::HashMap;
-    use std::{mem, ptr, slice};
+    use std::{fmt, io, mem, ptr, slice};
 
+    use std::cell::RefCell;
     use std::ffi::{CStr, CString};
     use std::net::SocketAddr;
-    use std::os::raw::{c_char, c_void};
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::str::FromStr;
 
     use std::sync::atomic::{AtomicUsize, Ordering};
 
+    use fuzzy_log_util::config::{ClusterConfig, Config};
+
     use mio;
 
     pub type DAG = LogHandle<[u8]>;
     pub type ColorID = u64;
 
+    /// The result of a fallible FuzzyLog call.
+    /// Whenever a function returns something other than `FUZZYLOG_OK`
+    /// (or its documented failure value) a description of what went wrong
+    /// can be retrieved with `fuzzylog_last_error`.
+    #[repr(C)]
+    #[allow(non_camel_case_types)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum FuzzyLogStatus {
+        FUZZYLOG_OK = 0,
+        /// A NULL handle, malformed address, or otherwise unusable argument.
+        FUZZYLOG_INVALID_ARGUMENT = 1,
+        /// Communication with a server failed.
+        FUZZYLOG_IO_ERROR = 2,
+        /// The handle's timeout passed before the operation finished.
+        FUZZYLOG_TIMEOUT = 3,
+        /// The append was cancelled before it was given a place in the log.
+        FUZZYLOG_CANCELLED = 4,
+        /// The requested entries have been garbage collected by the servers.
+        FUZZYLOG_TRIMMED = 5,
+        /// Nothing is ready yet, try again once the event fd is readable.
+        /// Not an error, `fuzzylog_last_error` is not set.
+        FUZZYLOG_NOTHING_READY = 6,
+        /// Every event in the current sync has been returned.
+        /// Not an error, `fuzzylog_last_error` is not set.
+        FUZZYLOG_DONE = 7,
+    }
+
+    use self::FuzzyLogStatus::*;
+
+    struct Error {
+        status: FuzzyLogStatus,
+        message: String,
+    }
+
+    type Result<T> = ::std::result::Result<T, Error>;
+
+    impl Error {
+        fn new<M: fmt::Display>(status: FuzzyLogStatus, message: M) -> Self {
+            Error { status, message: message.to_string() }
+        }
+
+        fn invalid<M: fmt::Display>(message: M) -> Self {
+            Error::new(FUZZYLOG_INVALID_ARGUMENT, message)
+        }
+    }
+
+    impl From<io::Error> for Error {
+        fn from(e: io::Error) -> Self {
+            Error::new(FUZZYLOG_IO_ERROR, e)
+        }
+    }
+
+    impl From<(io::ErrorKind, usize)> for Error {
+        fn from((kind, server): (io::ErrorKind, usize)) -> Self {
+            Error::new(FUZZYLOG_IO_ERROR, format!("{:?} error on server {}", kind, server))
+        }
+    }
+
+    impl From<TryWaitRes> for Error {
+        fn from(res: TryWaitRes) -> Self {
+            let status = match res {
+                TryWaitRes::IoErr(..) => FUZZYLOG_IO_ERROR,
+                TryWaitRes::Timeout => FUZZYLOG_TIMEOUT,
+                TryWaitRes::Cancelled => FUZZYLOG_CANCELLED,
+                TryWaitRes::NothingReady => FUZZYLOG_NOTHING_READY,
+            };
+            Error::new(status, res)
+        }
+    }
+
+    impl From<GetRes> for Error {
+        fn from(res: GetRes) -> Self {
+            let status = match res {
+                GetRes::IoErr(..) => FUZZYLOG_IO_ERROR,
+                GetRes::Timeout => FUZZYLOG_TIMEOUT,
+                GetRes::AlreadyGCd(..) => FUZZYLOG_TRIMMED,
+                GetRes::NothingReady => FUZZYLOG_NOTHING_READY,
+                GetRes::Done => FUZZYLOG_DONE,
+            };
+            Error::new(status, res)
+        }
+    }
+
+    thread_local! {
+        static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+    }
+
+    fn set_last_error(error: Error) -> FuzzyLogStatus {
+        let Error { status, message } = error;
+        let message = CString::new(message.replace('\0', "\\0"))
+            .unwrap_or_else(|_| CString::default());
+        LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+        status
+    }
+
+    fn to_status(res: Result<()>) -> FuzzyLogStatus {
+        match res {
+            Ok(()) => FUZZYLOG_OK,
+            Err(e) => set_last_error(e),
+        }
+    }
+
+    fn or_report<T>(res: Result<T>, on_err: T) -> T {
+        res.unwrap_or_else(|e| { set_last_error(e); on_err })
+    }
+
+    /// A description of the last error that occurred in a FuzzyLog call made
+    /// on this thread, or NULL if no call on this thread has failed.
+    /// The string is owned by the FuzzyLog and remains valid until the next
+    /// failing call on this thread.
+    #[no_mangle]
+    pub extern "C" fn fuzzylog_last_error() -> *const c_char {
+        LAST_ERROR.with(|last| match *last.borrow() {
+            Some(ref message) => message.as_ptr(),
+            None => ptr::null(),
+        })
+    }
+
+    unsafe fn handle_mut<'a, H>(handle: *mut H) -> Result<&'a mut H> {
+        handle.as_mut().ok_or_else(|| Error::invalid("need to provide a valid DAGHandle"))
+    }
+
+    unsafe fn parse_addr<A>(s: *const c_char) -> Result<A>
+    where A: FromStr, A::Err: fmt::Display {
+        if s.is_null() {
+            return Err(Error::invalid("IP string is NULL"))
+        }
+        let ip_string = CStr::from_ptr(s).to_str()
+            .map_err(|_| Error::invalid("invalid IP string"))?;
+        ip_string.parse()
+            .map_err(|e| Error::invalid(format!("invalid IP addr \"{}\": {}", ip_string, e)))
+    }
+
+    fn check(valid: bool, message: &str) -> Result<()> {
+        if valid { Ok(()) } else { Err(Error::invalid(message)) }
+    }
+
+    fn check_data(data: *const u8, data_size: usize) -> Result<()> {
+        check(
+            !data.is_null() || data_size == 0,
+            "Either data = NULL or data_size > 0",
+        )
+    }
+
+    fn check_no_color_zero(inhabits: &[order]) -> Result<()> {
+        check(
+            !inhabits.contains(&order::from(0)),
+            "color 0 should not be used;it is special cased for legacy reasons.",
+        )
+    }
+
     #[repr(C)]
     pub struct ReaderAndWriter {
-        reader: Box<ReadHandle<[u8]>>,
-        writer: Box<WriteHandle<[u8]>>,
+        reader: *mut ReadHandle<[u8]>,
+        writer: *mut WriteHandle<[u8]>,
     }
 
     #[repr(C)]
@@ -155,15 +321,20 @@ pub mod c_binidings {
 
         fn to_uuid(self) -> Uuid {
             let WriteId {bytes} = self;
-            Uuid::from_bytes(&bytes[..]).unwrap()
+            Uuid::from_bytes(&bytes[..]).unwrap_or(Uuid::nil())
         }
 
-        #[allow(dead_code)]
         fn nil() -> Self {
             WriteId::from_uuid(Uuid::nil())
         }
     }
 
+    impl WriteLocations {
+        fn empty() -> Self {
+            WriteLocations { num_locs: 0, locs: ptr::null_mut() }
+        }
+    }
+
 
     /// A `ColorSpec` describes the layout of a color.
     ///
@@ -177,9 +348,9 @@ pub mod c_binidings {
     ///   though it is not necessary to do so.
     #[repr(C)]
     pub struct ColorSpec {
-        local_chain: u64,
-        num_remote_chains: usize,
-        remote_chains: *mut u64,
+        pub local_chain: u64,
+        pub num_remote_chains: usize,
+        pub remote_chains: *mut u64,
     }
 
     impl ColorSpec {
@@ -229,23 +400,23 @@ pub mod c_binidings {
     ///   snap: a SnapId that the client should start `sync`ing from,
     ///         or NULL if the client should start from the beginning of its
     ///         color
+    ///
+    /// The handle may only be used by one thread at a time,
+    /// use `fuzzylog_split` to read and append from different threads.
+    ///
+    /// returns NULL if the client could not be started.
     #[no_mangle]
     pub unsafe extern "C" fn new_fuzzylog_instance(
         servers: ServerSpec, color: ColorSpec, snap: SnapId) -> FLPtr {
-        assert!(servers.is_valid());
-        assert!(color.is_valid());
-
-        let _ = ::env_logger::init();
+        or_report(new_instance(servers, color, snap), ptr::null_mut())
+    }
 
-        let parse_ip = |&s: &*mut c_char| {
-            let ip_string = CStr::from_ptr(s).to_str().expect("invalid IP string") ;
-            let ip_parse = ip_string.parse();
-            match ip_parse {
-                Err(e) => panic!("invalid IP addr \"{}\": {}", ip_string, e),
-                Ok(addr) => addr,
+    unsafe fn new_instance(servers: ServerSpec, color: ColorSpec, snap: SnapId)
+    -> Result<FLPtr> {
+        check(servers.is_valid(), "invalid ServerSpec")?;
+        check(color.is_valid(), "invalid ColorSpec")?;
 
-            }
-        };
+        let _ = ::env_logger::init();
 
         let remote_chains =
             slice::from_raw_parts(color.remote_chains, color.num_remote_chains);
@@ -258,19 +429,22 @@ pub mod c_binidings {
             });
 
         let ServerSpec{ num_ips, head_ips, tail_ips } = servers;
-        let heads: &[*mut c_char] = slice::from_raw_parts(head_ips, num_ips);
-        let heads = heads.into_iter().map(parse_ip);
+        let heads: Vec<SocketAddr> = slice::from_raw_parts(head_ips, num_ips).iter()
+            .map(|&s| parse_addr(s))
+            .collect::<Result<_>>()?;
 
         let builder = if tail_ips.is_null() {
             LogHandle::unreplicated_with_servers(heads)
         } else {
-            let tails = slice::from_raw_parts(tail_ips, num_ips).into_iter().map(parse_ip);
-            LogHandle::replicated_with_servers(heads.zip(tails))
+            let tails: Vec<SocketAddr> = slice::from_raw_parts(tail_ips, num_ips).iter()
+                .map(|&s| parse_addr(s))
+                .collect::<Result<_>>()?;
+            LogHandle::replicated_with_servers(heads.into_iter().zip(tails))
         };
 
         let mut handle = builder
             .my_colors_chains(chains)
-            .build();
+            .try_build()?;
 
         if !snap.is_null() {
             for (&o, &i) in &*snap {
@@ -278,7 +452,7 @@ pub mod c_binidings {
             }
         }
 
-        Box::into_raw(handle.into())
+        Ok(Box::into_raw(handle.into()))
     }
 
     /// Append a node to the FuzzyLog
@@ -299,25 +473,13 @@ pub mod c_binidings {
         data_size: usize,
         colors: *const ColorSpec,
         num_colors: usize,
-    ) -> i32 {
-        assert!(
-            !data.is_null() || data_size == 0
-            "args: data = {:?}, data_size = {} is not valid\nEither data = NULL or data_size > 0"
-        );
-        assert!(!colors.is_null());
-        assert!((&*colors).is_valid());
-
-        let handle = handle.as_mut().expect("need to provide a valid DAGHandle");
-        let data = slice::from_raw_parts(data as *const u8, data_size);
-        let colors = slice::from_raw_parts(colors, num_colors);
-
-        let mut colors: Vec<_> = (&*colors).iter()
-            .map(|c| order::from(c.local_chain))
-            .collect();
-
-        let id = handle.simpler_causal_append(data, &mut colors);
-        handle.wait_for_a_specific_append(id).unwrap();
-        1
+    ) -> FuzzyLogStatus {
+        to_status(async_append(handle, data, data_size, colors, num_colors).and_then(
+            |id| {
+                handle_mut(handle)?.wait_for_a_specific_append(id)?;
+                Ok(())
+            }
+        ))
     }
 
     /// Asynchronously append a node to the FuzzyLog.
@@ -334,6 +496,8 @@ pub mod c_binidings {
     ///   colors: the colors the new node should inhabit. Note that only
     ///           `local_color` will be read from these colors.
     ///   num_colors: the number of colors in `colors`
+    ///
+    /// returns WriteId{0} if the append could not be sent.
     #[no_mangle]
     pub unsafe extern "C" fn fuzzylog_async_append(
         handle: FLPtr,
@@ -342,23 +506,42 @@ pub mod c_binidings {
         colors: *const ColorSpec,
         num_colors: usize,
     ) -> WriteId {
-        assert!(
-            !data.is_null() || data_size == 0
-            "args: data = {:?}, data_size = {} is not valid\nEither data = NULL or data_size > 0"
-        );
-        assert!(!colors.is_null());
-        assert!((&*colors).is_valid());
+        let id = async_append(handle, data, data_size, colors, num_colors);
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
+    }
+
+    unsafe fn async_append(
+        handle: FLPtr,
+        data: *const c_char,
+        data_size: usize,
+        colors: *const ColorSpec,
+        num_colors: usize,
+    ) -> Result<Uuid> {
+        let handle = handle_mut(handle)?;
+        let (data, mut colors) = append_args(data, data_size, colors, num_colors)?;
+        Ok(handle.simple_async_append(data, &mut colors))
+    }
+
+    unsafe fn append_args<'a>(
+        data: *const c_char,
+        data_size: usize,
+        colors: *const ColorSpec,
+        num_colors: usize,
+    ) -> Result<(&'a [u8], Vec<order>)> {
+        check_data(data as *const u8, data_size)?;
+        check(!colors.is_null() && num_colors > 0, "need to provide at least one color")?;
 
-        let handle = handle.as_mut().expect("need to provide a valid DAGHandle");
         let data = slice::from_raw_parts(data as *const u8, data_size);
         let colors = slice::from_raw_parts(colors, num_colors);
+        check(colors.iter().all(ColorSpec::is_valid), "invalid ColorSpec")?;
 
         let mut colors: Vec<_> = (&*colors).iter()
             .map(|c| order::from(c.local_chain))
             .collect();
-
-        let id = handle.simpler_causal_append(data, &mut colors);
-        WriteId::from_uuid(id)
+        check_no_color_zero(&colors)?;
+        colors.sort();
+        colors.dedup();
+        Ok((data, colors))
     }
 
     /// Sync a local view with the FuzzyLog.
@@ -370,23 +553,27 @@ pub mod c_binidings {
     ///             the events `data_size`
     ///   callback_state: a pointer passed as the first argument to callback.
     ///                   May be `NULL`.
+    ///
+    /// returns NULL if the sync failed.
     #[no_mangle]
     pub unsafe extern "C" fn fuzzylog_sync(
         handle: FLPtr,
         callback: fn(*mut c_void, *const c_char, usize) -> (),
         callback_state: *mut c_void,
     ) -> SnapId {
-        let handle = handle.as_mut().expect("need to provide a valid DAGHandle");
-        let entries_seen =
-            handle.sync(|data, _, _| callback(
+        let entries_seen = handle_mut(handle).and_then(|handle| {
+            let cut = handle.sync(|data, _, _| callback(
                 callback_state,
                 data.as_ptr() as *const i8,
                 data.len()
-            ));
-        match entries_seen {
-            Ok(entries) => Box::into_raw(entries.into()),
-            Err(..) => ptr::null_mut(),
-        }
+            ))?;
+            Ok(cut)
+        });
+        or_report(entries_seen.map(into_snap_id), ptr::null_mut())
+    }
+
+    fn into_snap_id(cut: Cut) -> SnapId {
+        Box::into_raw(Box::new(cut.into()))
     }
 
     #[repr(C)]
@@ -398,6 +585,16 @@ pub mod c_binidings {
         inhabits_len: usize,
     }
 
+    fn to_ffi_event(e: Event<[u8]>) -> FuzzyLogEvent {
+        FuzzyLogEvent {
+            id: e.id as *const Uuid as *const WriteId,
+            data: e.data.as_ptr() as *const c_char,
+            data_size: e.data.len(),
+            inhabits: e.inhabits.as_ptr() as _,
+            inhabits_len: e.inhabits.len(),
+        }
+    }
+
     /// Sync a local view with the FuzzyLog.
     ///
     /// args:
@@ -407,27 +604,21 @@ pub mod c_binidings {
     ///             the events `data_size`
     ///   callback_state: a pointer passed as the first argument to callback.
     ///                   May be `NULL`.
+    ///
+    /// returns NULL if the sync failed.
     #[no_mangle]
     pub unsafe extern "C" fn fuzzylog_sync_events(
         handle: FLPtr,
         callback: fn(*mut c_void, FuzzyLogEvent) -> (),
         callback_state: *mut c_void,
     ) -> SnapId {
-        let handle = handle.as_mut().expect("need to provide a valid DAGHandle");
-        let entries_seen = handle.sync_events(|e| {
-            let event = FuzzyLogEvent {
-                id: e.id as *const Uuid as *const WriteId,
-                data: e.data.as_ptr() as *const c_char,
-                data_size: e.data.len(),
-                inhabits: e.inhabits.as_ptr() as _,
-                inhabits_len: e.inhabits.len(),
-            };
-            callback(callback_state, event);
+        let entries_seen = handle_mut(handle).and_then(|handle| {
+            let cut = handle.sync_events(|e| {
+                callback(callback_state, to_ffi_event(e));
+            })?;
+            Ok(cut)
         });
-        match entries_seen {
-            Ok(entries) => Box::into_raw(entries.into()),
-            Err(..) => ptr::null_mut(),
-        }
+        or_report(entries_seen.map(into_snap_id), ptr::null_mut())
     }
 
     #[no_mangle]
@@ -437,21 +628,13 @@ pub mod c_binidings {
         callback: fn(*mut c_void, FuzzyLogEvent) -> (),
         callback_state: *mut c_void,
     ) -> SnapId {
-        let handle = handle.as_mut().expect("need to provide a valid DAGHandle");
-        let entries_seen = handle.sync_events_for_chain(chain.into(), |e| {
-            let event = FuzzyLogEvent {
-                id: e.id as *const Uuid as *const WriteId,
-                data: e.data.as_ptr() as *const c_char,
-                data_size: e.data.len(),
-                inhabits: e.inhabits.as_ptr() as _,
-                inhabits_len: e.inhabits.len(),
-            };
-            callback(callback_state, event);
+        let entries_seen = handle_mut(handle).and_then(|handle| {
+            let cut = handle.sync_events_for_chain(chain.into(), |e| {
+                callback(callback_state, to_ffi_event(e));
+            })?;
+            Ok(cut)
         });
-        match entries_seen {
-            Ok(entries) => Box::into_raw(entries.into()),
-            Err(..) => ptr::null_mut(),
-        }
+        or_report(entries_seen.map(into_snap_id), ptr::null_mut())
     }
 
     #[no_mangle]
@@ -459,6 +642,7 @@ pub mod c_binidings {
         event: FuzzyLogEvent,
         chain: u64,
     ) -> bool {
+        if event.inhabits.is_null() { return false }
         let inhabits = slice::from_raw_parts(
             event.inhabits as *const OrderIndex, event.inhabits_len
         );
@@ -468,20 +652,76 @@ pub mod c_binidings {
         false
     }
 
+    /// A file descriptor which becomes readable whenever an event or an
+    /// append ack is ready for `handle`, for use with epoll or libuv.
+    /// Once it is readable, first read 8 bytes from it to reset it, then
+    /// drain `fuzzylog_try_next_event` and `fuzzylog_try_wait_for_any_append`
+    /// until neither has anything ready.
+    /// It starts out readable, so results which arrived before it was
+    /// asked for are not missed.
+    /// The descriptor is owned by the handle, and closed by `fuzzylog_close`,
+    /// every call returns the same one.
+    ///
+    /// returns -1 if the descriptor could not be created.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_event_fd(handle: FLPtr) -> c_int {
+        let fd = handle_mut(handle).and_then(|handle| Ok(handle.ready_fd()?));
+        or_report(fd, -1)
+    }
+
+    /// Start syncing a local view with the FuzzyLog without waiting for it.
+    /// The events are then returned by `fuzzylog_try_next_event`.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_start_sync(handle: FLPtr) -> FuzzyLogStatus {
+        to_status(handle_mut(handle).map(|handle| handle.take_snapshot()))
+    }
+
+    /// Get the next event of a sync started with `fuzzylog_start_sync`
+    /// without blocking.
+    /// The event's contents remain valid until the next read on `handle`.
+    ///
+    /// returns FUZZYLOG_OK and fills in `event` if an event was ready,
+    ///         FUZZYLOG_NOTHING_READY if it was not yet,
+    ///         and FUZZYLOG_DONE once the sync has returned every event.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_try_next_event(
+        handle: FLPtr,
+        event: *mut FuzzyLogEvent,
+    ) -> FuzzyLogStatus {
+        let next = check(!event.is_null(), "need to provide a valid FuzzyLogEvent")
+            .and_then(|()| handle_mut(handle));
+        let handle = match next {
+            Ok(handle) => handle,
+            Err(e) => return set_last_error(e),
+        };
+        match handle.try_get_next_event() {
+            Ok(e) => {
+                ptr::write(event, to_ffi_event(e));
+                FUZZYLOG_OK
+            },
+            Err(GetRes::NothingReady) => FUZZYLOG_NOTHING_READY,
+            Err(GetRes::Done) => FUZZYLOG_DONE,
+            Err(e) => set_last_error(e.into()),
+        }
+    }
+
 
     /// Wait for all outstanding appends to be ack'd by the server.
     #[no_mangle]
-    pub unsafe extern "C" fn fuzzylog_wait_for_all_appends(handle: FLPtr) {
+    pub unsafe extern "C" fn fuzzylog_wait_for_all_appends(handle: FLPtr) -> FuzzyLogStatus {
         wait_for_all_appends(handle)
     }
 
     /// Wait for a specific append sent by this client to be ack'd by the server.
     #[no_mangle]
-    pub unsafe extern "C" fn fuzzylog_wait_for_a_specific_append(handle: FLPtr, write_id: WriteId) {
+    pub unsafe extern "C" fn fuzzylog_wait_for_a_specific_append(
+        handle: FLPtr, write_id: WriteId
+    ) -> FuzzyLogStatus {
         wait_for_a_specific_append(handle, write_id)
     }
 
     /// Wait for any append sent by this client to be ack'd by the server.
+    /// return WriteId{0} if no such append exists or the wait failed.
     #[no_mangle]
     pub unsafe extern "C" fn fuzzylog_wait_for_any_append(handle: FLPtr) -> WriteId {
         wait_for_any_append(handle)
@@ -494,80 +734,308 @@ pub mod c_binidings {
         try_wait_for_any_append(handle)
     }
 
+    /// Let the servers free every entry up to a snapshot returned by a sync.
+    /// The freed entries can no longer be read by any handle.
     #[no_mangle]
-    pub unsafe extern "C" fn fuzzylog_trim(handle: FLPtr, snap: SnapId) {
-        unimplemented!()
+    pub unsafe extern "C" fn fuzzylog_trim(handle: FLPtr, snap: SnapId) -> FuzzyLogStatus {
+        to_status(handle_mut(handle).and_then(|handle| {
+            let snap = snap.as_ref()
+                .ok_or_else(|| Error::invalid("need to provide a valid SnapId"))?;
+            handle.trim(&Cut::from(snap.clone()));
+            Ok(())
+        }))
     }
 
+    /// Close a client handle. Closing NULL does nothing.
     #[no_mangle]
     pub unsafe extern "C" fn fuzzylog_close(handle: FLPtr) {
         close_dag_handle(handle)
     }
 
+    /// Free a SnapId returned by a sync. Deleting NULL does nothing.
     #[no_mangle]
     pub unsafe extern "C" fn delete_snap_id(snap: SnapId) {
-        let _ = Box::from_raw(snap);
+        if !snap.is_null() {
+            let _ = Box::from_raw(snap);
+        }
+    }
+
+    ///////////////////////////////////////////////////
+    //          Split reader and writer handles      //
+    ///////////////////////////////////////////////////
+
+    pub type FuzzyLogReader = ReadHandle<[u8]>;
+    pub type FuzzyLogWriter = WriteHandle<[u8]>;
+    pub type FLReader = *mut FuzzyLogReader;
+    pub type FLWriter = *mut FuzzyLogWriter;
+
+    /// The two halves of a client handle split by `fuzzylog_split`.
+    ///
+    /// Neither a `FLPtr`, nor a `FLReader`, nor a `FLWriter` may be used by
+    /// more than one thread at a time. However, the reader and the writer of
+    /// a split handle are independent: one thread may read with the reader
+    /// while another appends with the writer.
+    /// A handle may be moved between threads between calls.
+    #[repr(C)]
+    pub struct FuzzyLogReaderAndWriter {
+        pub reader: FLReader,
+        pub writer: FLWriter,
     }
 
+    /// Split a client handle into a reader, which syncs the handle's colors,
+    /// and a writer, which performs the handle's appends, so that each can
+    /// be used from its own thread.
+    /// This consumes `handle`, which must no longer be used.
+    /// The reader and writer are closed independently, the connection to the
+    /// servers is closed along with the last of them.
+    ///
+    /// returns NULL for both halves if `handle` is NULL.
     #[no_mangle]
-    pub extern "C" fn new_dag_handle(lock_server_ip: *const c_char,
-        num_chain_ips: usize, chain_server_ips: *const *const c_char,
-        color: *const colors) -> Box<DAG> {
-        assert_eq!(mem::size_of::<Box<DAG>>(), mem::size_of::<*mut u8>());
-        //assert_eq!(num_ips, 1, "Multiple servers are not yet supported via the C API");
-        assert!(chain_server_ips != ptr::null());
-        assert!(unsafe {*chain_server_ips != ptr::null()});
-        assert!(num_chain_ips >= 1);
-        assert!(color != ptr::null());
-        assert!(colors_valid(color));
-        let _ = ::env_logger::init();
-        let (lock_server_addr, server_addrs) = unsafe {
-            let addrs = slice::from_raw_parts(chain_server_ips, num_chain_ips)
-                .into_iter().map(|&s|
-                    CStr::from_ptr(s).to_str().expect("invalid IP string")
-                        .parse().expect("invalid IP addr")
-                    ).collect::<Vec<SocketAddr>>();
-            if lock_server_ip != ptr::null() {
-                let lock_server_addr = CStr::from_ptr(lock_server_ip).to_str()
-                    .expect("invalid IP string")
-                    .parse().expect("invalid IP addr");
-                (lock_server_addr, addrs)
-            }
-            else {
-                (addrs[0], addrs)
+    pub unsafe extern "C" fn fuzzylog_split(handle: FLPtr) -> FuzzyLogReaderAndWriter {
+        if handle.is_null() {
+            set_last_error(Error::invalid("need to provide a valid DAGHandle"));
+            return FuzzyLogReaderAndWriter {
+                reader: ptr::null_mut(),
+                writer: ptr::null_mut(),
             }
+        }
+        let (reader, writer) = Box::from_raw(handle).split();
+        FuzzyLogReaderAndWriter {
+            reader: Box::into_raw(Box::new(reader)),
+            writer: Box::into_raw(Box::new(writer)),
+        }
+    }
+
+    /// Like `fuzzylog_sync` but for the reader half of a split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_reader_sync(
+        reader: FLReader,
+        callback: fn(*mut c_void, *const c_char, usize) -> (),
+        callback_state: *mut c_void,
+    ) -> SnapId {
+        let entries_seen = handle_mut(reader).and_then(|reader| {
+            let cut = reader.sync(|data, _, _| callback(
+                callback_state,
+                data.as_ptr() as *const i8,
+                data.len()
+            ))?;
+            Ok(cut)
+        });
+        or_report(entries_seen.map(into_snap_id), ptr::null_mut())
+    }
+
+    /// Like `fuzzylog_sync_events` but for the reader half of a split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_reader_sync_events(
+        reader: FLReader,
+        callback: fn(*mut c_void, FuzzyLogEvent) -> (),
+        callback_state: *mut c_void,
+    ) -> SnapId {
+        let entries_seen = handle_mut(reader).and_then(|reader| {
+            let cut = reader.sync_events(|e| callback(callback_state, to_ffi_event(e)))?;
+            Ok(cut)
+        });
+        or_report(entries_seen.map(into_snap_id), ptr::null_mut())
+    }
+
+    /// Like `fuzzylog_start_sync` but for the reader half of a split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_reader_start_sync(reader: FLReader) -> FuzzyLogStatus {
+        to_status(handle_mut(reader).map(|reader| reader.take_snapshot()))
+    }
+
+    /// Like `fuzzylog_try_next_event` but for the reader half of a split
+    /// handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_reader_try_next_event(
+        reader: FLReader,
+        event: *mut FuzzyLogEvent,
+    ) -> FuzzyLogStatus {
+        let next = check(!event.is_null(), "need to provide a valid FuzzyLogEvent")
+            .and_then(|()| handle_mut(reader));
+        let reader = match next {
+            Ok(reader) => reader,
+            Err(e) => return set_last_error(e),
         };
-        let colors = unsafe {slice::from_raw_parts((*color).mycolors, (*color).numcolors)};
-        Box::new(LogHandle::spawn_tcp_log2(lock_server_addr, server_addrs.into_iter(),
-            colors.into_iter().cloned().map(order::from)))
+        match reader.try_get_next_event() {
+            Ok(e) => {
+                ptr::write(event, to_ffi_event(e));
+                FUZZYLOG_OK
+            },
+            Err(GetRes::NothingReady) => FUZZYLOG_NOTHING_READY,
+            Err(GetRes::Done) => FUZZYLOG_DONE,
+            Err(e) => set_last_error(e.into()),
+        }
+    }
+
+    /// Close the reader half of a split handle. Closing NULL does nothing.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_reader_close(reader: FLReader) {
+        if !reader.is_null() {
+            mem::drop(Box::from_raw(reader));
+        }
+    }
+
+    /// Like `fuzzylog_append` but for the writer half of a split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_append(
+        writer: FLWriter,
+        data: *const c_char,
+        data_size: usize,
+        colors: *const ColorSpec,
+        num_colors: usize,
+    ) -> FuzzyLogStatus {
+        to_status(writer_async_append(writer, data, data_size, colors, num_colors).and_then(
+            |id| {
+                handle_mut(writer)?.wait_for_a_specific_append(id)?;
+                Ok(())
+            }
+        ))
+    }
+
+    /// Like `fuzzylog_async_append` but for the writer half of a split handle.
+    ///
+    /// returns WriteId{0} if the append could not be sent.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_async_append(
+        writer: FLWriter,
+        data: *const c_char,
+        data_size: usize,
+        colors: *const ColorSpec,
+        num_colors: usize,
+    ) -> WriteId {
+        let id = writer_async_append(writer, data, data_size, colors, num_colors);
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
+    }
+
+    unsafe fn writer_async_append(
+        writer: FLWriter,
+        data: *const c_char,
+        data_size: usize,
+        colors: *const ColorSpec,
+        num_colors: usize,
+    ) -> Result<Uuid> {
+        let writer = handle_mut(writer)?;
+        let (data, mut colors) = append_args(data, data_size, colors, num_colors)?;
+        Ok(writer.simple_async_append(data, &mut colors))
+    }
+
+    /// Like `fuzzylog_wait_for_all_appends` but for the writer half of a
+    /// split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_wait_for_all_appends(writer: FLWriter)
+    -> FuzzyLogStatus {
+        to_status(handle_mut(writer).and_then(|writer| Ok(writer.wait_for_all_appends()?)))
+    }
+
+    /// Like `fuzzylog_wait_for_a_specific_append` but for the writer half of
+    /// a split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_wait_for_a_specific_append(
+        writer: FLWriter, write_id: WriteId
+    ) -> FuzzyLogStatus {
+        to_status(handle_mut(writer).and_then(|writer| {
+            writer.wait_for_a_specific_append(write_id.to_uuid())?;
+            Ok(())
+        }))
+    }
+
+    /// Like `fuzzylog_wait_for_any_append` but for the writer half of a
+    /// split handle.
+    /// return WriteId{0} if no such append exists or the wait failed.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_wait_for_any_append(writer: FLWriter) -> WriteId {
+        let id = handle_mut(writer).and_then(|writer| match writer.wait_for_any_append() {
+            Ok((id, _)) => Ok(id),
+            Err(TryWaitRes::NothingReady) => Ok(Uuid::nil()),
+            Err(e) => Err(e.into()),
+        });
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
+    }
+
+    /// Like `fuzzylog_try_wait_for_any_append` but for the writer half of a
+    /// split handle.
+    /// return WriteId{0} if no such append exists.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_try_wait_for_any_append(writer: FLWriter)
+    -> WriteId {
+        let id = handle_mut(writer).and_then(|writer| match writer.try_wait_for_any_append() {
+            Ok((id, _)) => Ok(id),
+            Err(TryWaitRes::NothingReady) => Ok(Uuid::nil()),
+            Err(e) => Err(e.into()),
+        });
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
+    }
+
+    /// Like `fuzzylog_trim` but for the writer half of a split handle.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_trim(writer: FLWriter, snap: SnapId)
+    -> FuzzyLogStatus {
+        to_status(handle_mut(writer).and_then(|writer| {
+            let snap = snap.as_ref()
+                .ok_or_else(|| Error::invalid("need to provide a valid SnapId"))?;
+            writer.trim(&Cut::from(snap.clone()));
+            Ok(())
+        }))
+    }
+
+    /// Close the writer half of a split handle. Closing NULL does nothing.
+    #[no_mangle]
+    pub unsafe extern "C" fn fuzzylog_writer_close(writer: FLWriter) {
+        if !writer.is_null() {
+            mem::drop(Box::from_raw(writer));
+        }
+    }
+
+    unsafe fn parse_addrs(ips: *const *const c_char, num_ips: usize)
+    -> Result<Vec<SocketAddr>> {
+        check(!ips.is_null() && num_ips >= 1, "need to provide at least one server")?;
+        slice::from_raw_parts(ips, num_ips).iter().map(|&s| parse_addr(s)).collect()
+    }
+
+    unsafe fn color_slice<'a>(c: *const colors) -> Result<&'a mut [order]> {
+        check(colors_valid(c), "need to provide valid colors")?;
+        let colors: *mut order = (*c).mycolors as *mut _;
+        Ok(slice::from_raw_parts_mut(colors, (*c).numcolors))
+    }
+
+    unsafe fn optional_color_slice<'a>(c: *const colors) -> Result<&'a mut [order]> {
+        if c.is_null() { Ok(&mut []) } else { color_slice(c) }
+    }
+
+    /// Multiappends no longer go through a lock server, so `lock_server_ip`
+    /// is only checked, if it is not NULL, and the handle is the same as
+    /// the one `new_dag_handle_with_skeens` builds.
+    #[no_mangle]
+    pub unsafe extern "C" fn new_dag_handle(lock_server_ip: *const c_char,
+        num_chain_ips: usize, chain_server_ips: *const *const c_char,
+        color: *const colors) -> *mut DAG {
+        if !lock_server_ip.is_null() {
+            if let Err(e) = parse_addr::<SocketAddr>(lock_server_ip) {
+                set_last_error(e);
+                return ptr::null_mut()
+            }
+        }
+        new_dag_handle_with_skeens(num_chain_ips, chain_server_ips, color)
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn new_dag_handle_with_skeens(
         num_chain_ips: usize, chain_server_ips: *const *const c_char, color: *const colors
-    ) -> Box<DAG> {
-        assert_eq!(mem::size_of::<Box<DAG>>(), mem::size_of::<*mut u8>());
-        //assert_eq!(num_ips, 1, "Multiple servers are not yet supported via the C API");
-        assert!(chain_server_ips != ptr::null());
-        assert!(*chain_server_ips != ptr::null());
-        assert!(num_chain_ips >= 1);
-        assert!(color != ptr::null());
-        assert!(colors_valid(color));
-        let _ = ::env_logger::init();
-        trace!("Lib num chain servers {:?}", num_chain_ips);
-        let server_addrs = slice::from_raw_parts(chain_server_ips, num_chain_ips)
-            .into_iter().map(|&s|
-                CStr::from_ptr(s).to_str().expect("invalid IP string")
-                    .parse().expect("invalid IP addr")
-            ).collect::<Vec<SocketAddr>>();
-        let colors = slice::from_raw_parts((*color).mycolors, (*color).numcolors);
-        let colors: Vec<_> = colors.into_iter().cloned().map(order::from).collect();
-        //Box::new(LogHandle::new_tcp_log(server_addrs.into_iter(), colors))
-        let handle = LogHandle::unreplicated_with_servers(server_addrs)
-            .chains(colors)
-            .reads_my_writes()
-            .build();
-        Box::new(handle)
+    ) -> *mut DAG {
+        let handle = parse_addrs(chain_server_ips, num_chain_ips).and_then(|server_addrs| {
+            let colors = color_slice(color)?;
+            check_no_color_zero(colors)?;
+            let _ = ::env_logger::init();
+            trace!("Lib num chain servers {:?}", num_chain_ips);
+            //Box::new(LogHandle::new_tcp_log(server_addrs.into_iter(), colors))
+            let handle = LogHandle::unreplicated_with_servers(server_addrs)
+                .chains(colors.iter().cloned())
+                .reads_my_writes()
+                .try_build()?;
+            Ok(Box::new(handle))
+        });
+        or_report(handle.map(Box::into_raw), ptr::null_mut())
     }
 
     #[no_mangle]
@@ -576,78 +1044,51 @@ pub mod c_binidings {
         chain_server_head_ips: *const *const c_char,
         chain_server_tail_ips: *const *const c_char,
         color: *const colors
-    ) -> Box<DAG> {
-        assert_eq!(mem::size_of::<Box<DAG>>(), mem::size_of::<*mut u8>());
-
-        assert!(chain_server_head_ips != ptr::null());
-        assert!(chain_server_tail_ips != ptr::null());
-        assert!(*chain_server_head_ips != ptr::null());
-        assert!(*chain_server_tail_ips != ptr::null());
-        assert!(num_chain_ips >= 1);
-
-        assert!(color != ptr::null());
-        assert!(colors_valid(color));
-
-        let _ = ::env_logger::init();
-        trace!("Lib num chain servers {:?}", num_chain_ips);
-        let server_heads = slice::from_raw_parts(chain_server_head_ips, num_chain_ips)
-            .into_iter().map(|&s|
-                CStr::from_ptr(s).to_str().expect("invalid IP string")
-                    .parse().expect("invalid IP addr")
-            );
-        let server_tails = slice::from_raw_parts(chain_server_tail_ips, num_chain_ips)
-            .into_iter().map(|&s|
-                CStr::from_ptr(s).to_str().expect("invalid IP string")
-                    .parse().expect("invalid IP addr")
-            );
-        let colors = slice::from_raw_parts((*color).mycolors, (*color).numcolors);
-        Box::new(LogHandle::new_tcp_log_with_replication(
-            server_heads.zip(server_tails),
-            colors.into_iter().cloned().map(order::from)
-        ))
+    ) -> *mut DAG {
+        let handle = parse_addrs(chain_server_head_ips, num_chain_ips).and_then(|heads| {
+            let tails = parse_addrs(chain_server_tail_ips, num_chain_ips)?;
+            let colors = color_slice(color)?;
+            check_no_color_zero(colors)?;
+            let _ = ::env_logger::init();
+            trace!("Lib num chain servers {:?}", num_chain_ips);
+            let handle = LogHandle::replicated_with_servers(heads.into_iter().zip(tails))
+                .chains(colors.iter().cloned())
+                .fetch_boring_multis()
+                .try_build()?;
+            Ok(Box::new(handle))
+        });
+        or_report(handle.map(Box::into_raw), ptr::null_mut())
     }
 
     #[no_mangle]
     pub extern "C" fn new_dag_handle_from_config(
         config_filename: *const c_char, color: *const colors
-    ) -> Box<DAG> {
-        assert_eq!(mem::size_of::<Box<DAG>>(), mem::size_of::<*mut u8>());
-        assert!(color != ptr::null());
-        assert!(colors_valid(color));
-        let _ = ::env_logger::init();
-        let (_, chain_server_addrs, chain_server_tails) = read_config_file(config_filename);
-        let server_addrs = chain_server_addrs.into_iter()
-            .map(|s| s.parse().expect("Invalid server addr"))
-            .collect::<Vec<SocketAddr>>();
-        let chain_server_tails = chain_server_tails.into_iter()
-            .map(|s| s.parse().expect("Invalid server addr"))
-            .collect::<Vec<SocketAddr>>();
-        let colors = unsafe {slice::from_raw_parts((*color).mycolors, (*color).numcolors)};
-        if chain_server_tails.is_empty() {
-            Box::new(
-                LogHandle::new_tcp_log(
-                    server_addrs.into_iter(),
-                    colors.into_iter().cloned().map(order::from)
-            ))
-        } else {
-            assert_eq!(
-                server_addrs.len(), chain_server_tails.len(),
-                "Must have a tail server for each head server.");
-            Box::new(
-                LogHandle::new_tcp_log_with_replication(
-                    server_addrs.into_iter().zip(chain_server_tails),
-                    colors.into_iter().cloned().map(order::from)
-            ))
-        }
+    ) -> *mut DAG {
+        let handle = unsafe { read_config_file(config_filename) }.and_then(|config| {
+            let colors = unsafe { color_slice(color)? };
+            check_no_color_zero(colors)?;
+            let _ = ::env_logger::init();
+            let handle = LogHandle::with_config(&config)
+                .chains(colors.iter().cloned())
+                .fetch_boring_multis()
+                .try_build()?;
+            Ok(Box::new(handle))
+        });
+        or_report(handle.map(Box::into_raw), ptr::null_mut())
     }
 
+    /// Split a handle into a reader and a writer, consuming it.
+    /// Both halves are NULL if `dag` is NULL.
     #[no_mangle]
     pub extern "C" fn split_dag_handle(dag: *mut DAG) -> ReaderAndWriter {
-        assert!(dag != ptr::null_mut());
+        if dag.is_null() {
+            set_last_error(Error::invalid("need to provide a valid DAGHandle"));
+            return ReaderAndWriter { reader: ptr::null_mut(), writer: ptr::null_mut() }
+        }
         let dag = unsafe { Box::from_raw(dag) };
         let (reader, writer) = dag.split();
-        let reader = Box::new(reader);
-        let writer = Box::new(writer);
+        let reader = Box::into_raw(Box::new(reader));
+        let writer = Box::into_raw(Box::new(writer));
         ReaderAndWriter { reader, writer }
     }
 
@@ -656,34 +1097,22 @@ pub mod c_binidings {
     #[no_mangle]
     pub extern "C" fn do_append(dag: *mut DAG, data: *const u8, data_size: usize,
         inhabits: *mut colors, depends_on: *mut colors, async: u8) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-        assert!(data_size <= 8000);
-
-        let (dag, data, inhabits) = unsafe {
-            let s = slice::from_raw_parts_mut((*inhabits).mycolors, (*inhabits).numcolors);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                mem::transmute(s))
-        };
-        let depends_on: &mut [order] = unsafe {
-            if depends_on != ptr::null_mut() {
-                assert!(colors_valid(depends_on));
-                let s = slice::from_raw_parts_mut((*depends_on).mycolors, (*depends_on).numcolors);
-                mem::transmute(s)
-            }
-            else {
-                &mut []
-            }
+        let id = unsafe {
+            check_data(data, data_size).and_then(|()| {
+                check(data_size <= 8000, "data_size must be at most 8000")?;
+                let dag = handle_mut(dag)?;
+                let inhabits = color_slice(inhabits)?;
+                let depends_on = optional_color_slice(depends_on)?;
+                check(!inhabits.is_empty(), "need to provide at least one color")?;
+                check_no_color_zero(inhabits)?;
+                check_no_color_zero(depends_on)?;
+                let data = slice::from_raw_parts(data, data_size);
+                let (id, error) = dag.color_append(data, inhabits, depends_on, async != 0);
+                error?;
+                Ok(id)
+            })
         };
-        let (id, error) = dag.color_append(data, inhabits, depends_on, async != 0);
-        match error {
-            Ok(()) => {}
-            //TODO set errno?
-            Err(e) => panic!("IO error {:?}", e),
-        }
-        WriteId::from_uuid(id)
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     fn colors_valid(c: *const colors) -> bool {
@@ -691,6 +1120,26 @@ pub mod c_binidings {
             ((*c).numcolors == 0 || (*c).mycolors != ptr::null_mut()) }
     }
 
+    unsafe fn no_remote_append_inner(
+        dag: *mut DAG,
+        data: *const u8,
+        data_size: usize,
+        inhabits: *mut colors,
+        deps: *mut OrderIndex,
+        num_deps: usize,
+        async: bool,
+    ) -> Result<(Uuid, ::std::result::Result<OrderIndex, TryWaitRes>)> {
+        check_data(data, data_size)?;
+        check(data_size <= 8000, "data_size must be at most 8000")?;
+        check(!deps.is_null() || num_deps == 0, "Either deps = NULL or num_deps > 0")?;
+        let dag = handle_mut(dag)?;
+        let inhabits = color_slice(inhabits)?;
+        check_no_color_zero(inhabits)?;
+        let deps = if num_deps == 0 { &mut [] } else { slice::from_raw_parts_mut(deps, num_deps) };
+        let data = slice::from_raw_parts(data, data_size);
+        Ok(dag.color_no_remote_append(data, inhabits, deps, async))
+    }
+
     #[no_mangle]
     pub extern "C" fn async_no_remote_append(
         dag: *mut DAG,
@@ -700,22 +1149,10 @@ pub mod c_binidings {
         deps: *mut OrderIndex,
         num_deps: usize,
     ) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-        assert!(data_size <= 8000);
-
-        let (dag, data, inhabits, deps) = unsafe {
-            let colors: *mut order = (*inhabits).mycolors as *mut _;
-            let s = slice::from_raw_parts_mut(colors, (*inhabits).numcolors);
-            let d = slice::from_raw_parts_mut(deps, num_deps);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                s,
-                d)
+        let id = unsafe {
+            no_remote_append_inner(dag, data, data_size, inhabits, deps, num_deps, true)
         };
-        let id = dag.color_no_remote_append(data, inhabits, deps, true).0;
-        WriteId::from_uuid(id)
+        or_report(id.map(|(id, _)| WriteId::from_uuid(id)), WriteId::nil())
     }
 
     #[no_mangle]
@@ -727,22 +1164,15 @@ pub mod c_binidings {
         deps: *mut OrderIndex,
         num_deps: usize,
     ) -> OrderIndex {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-        assert!(data_size <= 8000);
-
-        let (dag, data, inhabits, deps) = unsafe {
-            let colors: *mut order = (*inhabits).mycolors as *mut _;
-            let s = slice::from_raw_parts_mut(colors, (*inhabits).numcolors);
-            let d = slice::from_raw_parts_mut(deps, num_deps);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                s,
-                d)
+        let loc = unsafe {
+            no_remote_append_inner(dag, data, data_size, inhabits, deps, num_deps, false)
         };
-        let loc = dag.color_no_remote_append(data, inhabits, deps, false).1;
-        loc.unwrap_or(OrderIndex(0.into(), 0.into()))
+        let loc = loc.and_then(|(_, loc)| match loc {
+            Ok(loc) => Ok(loc),
+            Err(TryWaitRes::NothingReady) => Ok(OrderIndex(0.into(), 0.into())),
+            Err(e) => Err(e.into()),
+        });
+        or_report(loc, OrderIndex(0.into(), 0.into()))
     }
 
     #[no_mangle]
@@ -755,32 +1185,27 @@ pub mod c_binidings {
         happens_after: *mut OrderIndex,
         num_happens_after: usize,
     ) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-
-        let (dag, data, inhabits, happens_after) = unsafe {
-            let colors: *mut order = (*inhabits).mycolors as *mut _;
-            let s = slice::from_raw_parts_mut(colors, (*inhabits).numcolors);
-            let h = slice::from_raw_parts_mut(happens_after, num_happens_after);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                s,
-                h)
-        };
-        let depends_on: &mut [order] = unsafe {
-            if depends_on != ptr::null_mut() {
-                assert!(colors_valid(depends_on));
-                let s = slice::from_raw_parts_mut((*depends_on).mycolors, (*depends_on).numcolors);
-                mem::transmute(s)
-            }
-            else {
-                &mut []
-            }
+        let id = unsafe {
+            check_data(data, data_size).and_then(|()| {
+                check(
+                    !happens_after.is_null() || num_happens_after == 0,
+                    "Either happens_after = NULL or num_happens_after > 0",
+                )?;
+                let dag = handle_mut(dag)?;
+                let inhabits = color_slice(inhabits)?;
+                let depends_on = optional_color_slice(depends_on)?;
+                check_no_color_zero(inhabits)?;
+                check_no_color_zero(depends_on)?;
+                let happens_after = if num_happens_after == 0 {
+                    &mut []
+                } else {
+                    slice::from_raw_parts_mut(happens_after, num_happens_after)
+                };
+                let data = slice::from_raw_parts(data, data_size);
+                Ok(dag.causal_color_append(data, inhabits, depends_on, happens_after))
+            })
         };
-
-        let id = dag.causal_color_append(data, inhabits, depends_on, happens_after);
-        WriteId::from_uuid(id)
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
@@ -791,107 +1216,114 @@ pub mod c_binidings {
         inhabits: *mut colors,
         happens_after: *mut colors,
     ) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-
-        let (dag, data, inhabits) = {
-            let colors: *mut order = (*inhabits).mycolors as *mut _;
-            let s = slice::from_raw_parts_mut(colors, (*inhabits).numcolors);
-            (
-                dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                s,
-            )
-        };
-        let happens_after = {
-            if happens_after != ptr::null_mut() {
-                assert!(colors_valid(happens_after));
-                slice::from_raw_parts_mut((*happens_after).mycolors as *mut _, (*happens_after).numcolors)
-            } else {
-                &mut []
-            }
-        };
-
-        let id = dag.simple_causal_append(data, inhabits, happens_after);
-        WriteId::from_uuid(id)
+        let id = check_data(data, data_size).and_then(|()| {
+            let dag = handle_mut(dag)?;
+            let inhabits = color_slice(inhabits)?;
+            let happens_after = optional_color_slice(happens_after)?;
+            check_no_color_zero(inhabits)?;
+            let data = slice::from_raw_parts(data, data_size);
+            Ok(dag.simple_causal_append(data, inhabits, happens_after))
+        });
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn wait_for_all_appends(handle: FLPtr) {
-        let dag = handle.as_mut().expect("need to provide a valid DAGHandle");
-        dag.wait_for_all_appends().unwrap();
+    pub unsafe extern "C" fn wait_for_all_appends(handle: FLPtr) -> FuzzyLogStatus {
+        to_status(handle_mut(handle).and_then(|dag| {
+            dag.wait_for_all_appends()?;
+            Ok(())
+        }))
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn wait_for_a_specific_append(handle: FLPtr, write_id: WriteId) {
-        let dag = handle.as_mut().expect("need to provide a valid DAGHandle");
-        dag.wait_for_a_specific_append(write_id.to_uuid()).unwrap();
+    pub unsafe extern "C" fn wait_for_a_specific_append(handle: FLPtr, write_id: WriteId)
+    -> FuzzyLogStatus {
+        to_status(handle_mut(handle).and_then(|dag| {
+            dag.wait_for_a_specific_append(write_id.to_uuid())?;
+            Ok(())
+        }))
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn wait_for_any_append(handle: FLPtr) -> WriteId {
-        let dag = handle.as_mut().expect("need to provide a valid DAGHandle");
-        let id = dag.wait_for_any_append().map(|t| t.0).unwrap_or(Uuid::nil());
-        WriteId::from_uuid(id)
+        let id = handle_mut(handle).and_then(|dag| match dag.wait_for_any_append() {
+            Ok((id, _)) => Ok(id),
+            Err(TryWaitRes::NothingReady) => Ok(Uuid::nil()),
+            Err(e) => Err(e.into()),
+        });
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn wait_for_a_specific_append_and_locations(
         dag: *mut DAG, write_id: WriteId
     ) -> WriteLocations {
-        let locs = dag.as_mut().expect("need to provide a valid DAGHandle")
-            .wait_for_a_specific_append(write_id.to_uuid()).unwrap();
-        build_write_locs(locs)
+        let locs = handle_mut(dag).and_then(|dag| {
+            let locs = dag.wait_for_a_specific_append(write_id.to_uuid())?;
+            build_write_locs(locs)
+        });
+        or_report(locs, WriteLocations::empty())
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn try_wait_for_any_append(handle: FLPtr) -> WriteId {
-        let dag = handle.as_mut().expect("need to provide a valid DAGHandle");
-        let id = dag.try_wait_for_any_append().map(|t| t.0).unwrap_or(Uuid::nil());
-        WriteId::from_uuid(id)
+        let id = handle_mut(handle).and_then(|dag| match dag.try_wait_for_any_append() {
+            Ok((id, _)) => Ok(id),
+            Err(TryWaitRes::NothingReady) => Ok(Uuid::nil()),
+            Err(e) => Err(e.into()),
+        });
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn try_wait_for_any_append_and_location(dag: *mut DAG)
     -> WriteIdAndLocs {
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
+        let nothing = |num_locs| WriteIdAndLocs {
+            write_id: WriteId::nil(),
+            locs: WriteLocations { num_locs, locs: ptr::null_mut() },
+        };
+        let dag = match handle_mut(dag) {
+            Ok(dag) => dag,
+            Err(e) => { set_last_error(e); return nothing(0) },
+        };
         match dag.try_wait_for_any_append() {
-            Err(TryWaitRes::NothingReady) => WriteIdAndLocs {
-                write_id: WriteId::nil(),
-                locs: WriteLocations { num_locs: 0, locs: ptr::null_mut() },
-            },
-            //TODO what to do with error number?
-            Err(TryWaitRes::IoErr(_kind, server)) => WriteIdAndLocs {
-                write_id: WriteId::nil(),
-                locs: WriteLocations { num_locs: server, locs: ptr::null_mut() },
+            Err(TryWaitRes::NothingReady) => nothing(0),
+            //NOTE the server number is reported in num_locs for legacy reasons
+            Err(TryWaitRes::IoErr(kind, server)) => {
+                set_last_error((kind, server).into());
+                nothing(server)
             },
-            Ok((id, locs)) => WriteIdAndLocs {
-                write_id: WriteId::from_uuid(id),
-                locs: build_write_locs(locs),
+            Err(e) => { set_last_error(e.into()); nothing(0) },
+            Ok((id, locs)) => match build_write_locs(locs) {
+                Ok(locs) => WriteIdAndLocs { write_id: WriteId::from_uuid(id), locs },
+                Err(e) => { set_last_error(e); nothing(0) },
             },
         }
 
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn flush_completed_appends(dag: *mut DAG) {
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        dag.flush_completed_appends().unwrap();
+    pub unsafe extern "C" fn flush_completed_appends(dag: *mut DAG) -> FuzzyLogStatus {
+        to_status(handle_mut(dag).and_then(|dag| {
+            dag.flush_completed_appends()?;
+            Ok(())
+        }))
     }
 
-    unsafe fn build_write_locs(locs: Vec<OrderIndex>) -> WriteLocations {
+    unsafe fn build_write_locs(locs: Vec<OrderIndex>) -> Result<WriteLocations> {
         let num_locs = locs.len();
         let my_locs = ::libc::malloc(mem::size_of::<OrderIndex>() * num_locs) as *mut _;
-        assert!(my_locs != ptr::null_mut());
+        if my_locs == ptr::null_mut() && num_locs > 0 {
+            return Err(Error::new(FUZZYLOG_IO_ERROR, "out of memory"))
+        }
         let s = slice::from_raw_parts_mut(my_locs, num_locs);
         for i in 0..num_locs {
             s[i] = locs[i];
         }
-        WriteLocations {
+        Ok(WriteLocations {
             num_locs: num_locs,
             locs: my_locs,
-        }
+        })
     }
 
 
@@ -900,37 +1332,47 @@ pub mod c_binidings {
     // greater than DELOS_MAX_DATA_SIZE
     #[no_mangle]
     pub extern "C" fn get_next(dag: *mut DAG, data_out: *mut u8, data_read: *mut usize,
-        inhabits_out: *mut colors) {
-        assert!(data_out != ptr::null_mut());
-        assert!(data_read != ptr::null_mut());
-        assert!(inhabits_out != ptr::null_mut());
-
-        let dag = unsafe {dag.as_mut().expect("need to provide a valid DAGHandle")};
-        let data_out = unsafe { slice::from_raw_parts_mut(data_out, 8000)};
-        let data_read = unsafe {data_read.as_mut().expect("must provide valid data_out")};
-        let val = dag.get_next();
-        unsafe {
-            let (mycolors, numcolors) = match val {
-                Ok((data, inhabited_colors)) => {
-                    *data_read = <[u8] as Storeable>::copy_to_mut(data, data_out);
-                    let numcolors = inhabited_colors.len();
-                    let mycolors = ::libc::malloc(mem::size_of::<ColorID>() * numcolors) as *mut _;
-                    let s = slice::from_raw_parts_mut(mycolors, numcolors);
-                    for i in 0..numcolors {
-                        let e: order = inhabited_colors[i].0;
-                        s[i] = e.into();
+        inhabits_out: *mut colors) -> FuzzyLogStatus {
+        if inhabits_out.is_null() {
+            return set_last_error(Error::invalid("must provide valid inhabits_out"))
+        }
+        let next = unsafe {
+            check(!data_out.is_null(), "must provide valid data_out").and_then(|()| {
+                let data_read = data_read.as_mut()
+                    .ok_or_else(|| Error::invalid("must provide valid data_read"))?;
+                let dag = handle_mut(dag)?;
+                let data_out = slice::from_raw_parts_mut(data_out, 8000);
+                match dag.get_next() {
+                    Ok((data, inhabited_colors)) => {
+                        check(data.len() <= data_out.len(), "event larger than data_out")?;
+                        *data_read = <[u8] as Storeable>::copy_to_mut(data, data_out);
+                        let numcolors = inhabited_colors.len();
+                        let mycolors = ::libc::malloc(mem::size_of::<ColorID>() * numcolors)
+                            as *mut ColorID;
+                        if mycolors.is_null() && numcolors > 0 {
+                            return Err(Error::new(FUZZYLOG_IO_ERROR, "out of memory"))
+                        }
+                        let s = slice::from_raw_parts_mut(mycolors, numcolors);
+                        for i in 0..numcolors {
+                            let e: order = inhabited_colors[i].0;
+                            s[i] = e.into();
+                        }
+                        //ptr::copy_nonoverlapping(&inhabited_colors[0], mycolors, numcolors);
+                        Ok((mycolors, numcolors))
                     }
-                    //ptr::copy_nonoverlapping(&inhabited_colors[0], mycolors, numcolors);
-                    (mycolors, numcolors)
-                }
-                Err(GetRes::Done) => {
-                    (ptr::null_mut(), 0)
+                    Err(GetRes::Done) => Ok((ptr::null_mut(), 0)),
+                    Err(e) => Err(e.into()),
                 }
-                _ => unimplemented!(),
-            };
-
+            })
+        };
+        let ((mycolors, numcolors), status) = match next {
+            Ok(colors) => (colors, FUZZYLOG_OK),
+            Err(e) => ((ptr::null_mut(), 0), set_last_error(e)),
+        };
+        unsafe {
             ptr::write(inhabits_out, colors{ numcolors: numcolors, mycolors: mycolors});
         }
+        status
     }
 
     #[repr(C)]
@@ -939,16 +1381,24 @@ pub mod c_binidings {
         locs: *const OrderIndex,
     }
 
-    #[no_mangle]
-    pub unsafe extern "C" fn get_next2(
-        dag: *mut DAG,
+    /// Report a read through the out-params of the `get_next2` family.
+    /// Nothing is reported if the out-params are invalid.
+    unsafe fn write_vals(
+        val: Result<(&[u8], &[OrderIndex])>,
         data_read: *mut usize,
         num_locs: *mut usize,
     ) -> Vals {
-        assert!(data_read != ptr::null_mut());
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        let val = dag.get_next();
-        let (data, locs) = val.unwrap_or((&[], &[]));
+        let val = check(!data_read.is_null() && !num_locs.is_null(), "invalid out-params")
+            .and_then(|()| val);
+        let (data, locs) = match val {
+            Ok(val) => val,
+            Err(e) => {
+                set_last_error(e);
+                if !data_read.is_null() { ptr::write(data_read, 0) }
+                if !num_locs.is_null() { ptr::write(num_locs, 0) }
+                return Vals { data: ptr::null(), locs: ptr::null() }
+            },
+        };
 
         ptr::write(data_read, data.len());
         ptr::write(num_locs, locs.len());
@@ -956,55 +1406,64 @@ pub mod c_binidings {
         Vals { data: data.as_ptr(), locs: locs.as_ptr() }
     }
 
+    /// Blocking read of the next event in the current snapshot.
+    /// Once the snapshot is exhausted both sizes are 0,
+    /// on error both pointers are NULL as well.
+    #[no_mangle]
+    pub unsafe extern "C" fn get_next2(
+        dag: *mut DAG,
+        data_read: *mut usize,
+        num_locs: *mut usize,
+    ) -> Vals {
+        let val = handle_mut(dag).and_then(|dag| match dag.get_next() {
+            Ok(val) => Ok(val),
+            Err(GetRes::Done) => Ok((&[][..], &[][..])),
+            Err(e) => Err(e.into()),
+        });
+        write_vals(val, data_read, num_locs)
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn async_get_next2(
         dag: *mut DAG,
         data_read: *mut usize,
         num_locs: *mut usize,
     ) -> Vals {
-        assert!(data_read != ptr::null_mut());
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        let val = dag.try_get_next();
-        let (data, locs): (&[u8], &[OrderIndex]) = match val {
-            Ok((data, locs)) => (data, locs),
-            Err(GetRes::NothingReady) => (&[], &[]),
+        let dag = match handle_mut(dag) {
+            Ok(dag) => dag,
+            Err(e) => return write_vals(Err(e), data_read, num_locs),
+        };
+        let val: Result<(&[u8], &[OrderIndex])> = match dag.try_get_next() {
+            Ok((data, locs)) => Ok((data, locs)),
+            Err(GetRes::NothingReady) => Ok((&[], &[])),
             Err(GetRes::Done) => {
-                ptr::write(data_read, 0);
-                ptr::write(num_locs, 0);
+                write_vals(Ok((&[], &[])), data_read, num_locs);
                 return Vals { data: ptr::null(), locs: ptr::null() }
             },
-            _ => unimplemented!(),
+            Err(e) => Err(e.into()),
         };
-
-        ptr::write(data_read, data.len());
-        ptr::write(num_locs, locs.len());
-
-        Vals { data: data.as_ptr(), locs: locs.as_ptr() }
+        write_vals(val, data_read, num_locs)
     }
 
     #[no_mangle]
-    pub extern "C" fn snapshot(dag: *mut DAG) {
-        let dag = unsafe {dag.as_mut().expect("need to provide a valid DAGHandle")};
-        dag.take_snapshot();
+    pub extern "C" fn snapshot(dag: *mut DAG) -> FuzzyLogStatus {
+        to_status(unsafe { handle_mut(dag) }.map(|dag| dag.take_snapshot()))
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn snapshot_colors(dag: *mut DAG, colors: *mut colors) {
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        assert!(colors != ptr::null_mut());
-        assert!(colors_valid(colors));
-        let colors = {
-            let num_colors = (*colors).numcolors;
-            let colors: *mut order = (*colors).mycolors as *mut _;
-            slice::from_raw_parts_mut(colors, num_colors)
-        };
-        dag.snapshot_colors(colors);
+    pub unsafe extern "C" fn snapshot_colors(dag: *mut DAG, colors: *mut colors)
+    -> FuzzyLogStatus {
+        to_status(handle_mut(dag).and_then(|dag| {
+            dag.snapshot_colors(color_slice(colors)?);
+            Ok(())
+        }))
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn close_dag_handle(dag: *mut DAG) {
-        assert!(dag != ptr::null_mut());
-        Box::from_raw(dag);
+        if !dag.is_null() {
+            mem::drop(Box::from_raw(dag));
+        }
     }
 
 
@@ -1021,16 +1480,31 @@ pub mod c_binidings {
         // deps: *mut OrderIndex,
         // num_deps: usize,
     ) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(data != ptr::null());
-        let (dag, data, deps) = unsafe {
-            // let d = slice::from_raw_parts_mut(deps, num_deps);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                &mut [])
+        let id = unsafe {
+            check_data(data, data_size).and_then(|()| {
+                check_no_color_zero(&[inhabits.into()])?;
+                // let d = slice::from_raw_parts_mut(deps, num_deps);
+                let dag = handle_mut(dag)?;
+                let data = slice::from_raw_parts(data, data_size);
+                Ok(dag.async_append(inhabits.into(), data, &mut []))
+            })
         };
-        let id = dag.async_append(inhabits.into(), data, deps);
-        WriteId::from_uuid(id)
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
+    }
+
+    unsafe fn multiappend_args<'a>(
+        dag: *mut WriteHandle<[u8]>,
+        data: *const u8,
+        data_size: usize,
+        inhabits: *mut colors,
+    ) -> Result<(&'a mut WriteHandle<[u8]>, &'a [u8], &'a mut [order])> {
+        check_data(data, data_size)?;
+        check(data_size <= 8000, "data_size must be at most 8000")?;
+        let dag = handle_mut(dag)?;
+        let inhabits = color_slice(inhabits)?;
+        inhabits.sort();
+        check_no_color_zero(inhabits)?;
+        Ok((dag, slice::from_raw_parts(data, data_size), inhabits))
     }
 
     #[no_mangle]
@@ -1042,27 +1516,9 @@ pub mod c_binidings {
         // deps: *mut OrderIndex,
         // num_deps: usize,
     ) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-        assert!(data_size <= 8000);
-
-        let (dag, data, inhabits, deps) = unsafe {
-            let colors: *mut order = (*inhabits).mycolors as *mut _;
-            let s = slice::from_raw_parts_mut(colors, (*inhabits).numcolors);
-            // let d = slice::from_raw_parts_mut(deps, num_deps);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                s,
-                &mut [])
-        };
-        inhabits.sort();
-        assert!(
-            inhabits.binary_search(&order::from(0)).is_err(),
-            "color 0 should not be used;it is special cased for legacy reasons."
-        );
-        let id = dag.async_multiappend(inhabits, data, deps);
-        WriteId::from_uuid(id)
+        let id = unsafe { multiappend_args(dag, data, data_size, inhabits) }
+            .map(|(dag, data, inhabits)| dag.async_multiappend(inhabits, data, &mut []));
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
@@ -1074,59 +1530,44 @@ pub mod c_binidings {
         // deps: *mut OrderIndex,
         // num_deps: usize,
     ) -> WriteId {
-        assert!(data_size == 0 || data != ptr::null());
-        assert!(inhabits != ptr::null_mut());
-        assert!(colors_valid(inhabits));
-        assert!(data_size <= 8000);
-
-        let (dag, data, inhabits, deps) = unsafe {
-            let colors: *mut order = (*inhabits).mycolors as *mut _;
-            let s = slice::from_raw_parts_mut(colors, (*inhabits).numcolors);
-            // let d = slice::from_raw_parts_mut(deps, num_deps);
-            (dag.as_mut().expect("need to provide a valid DAGHandle"),
-                slice::from_raw_parts(data, data_size),
-                s,
-                &mut [])
-        };
-        inhabits.sort();
-        assert!(
-            inhabits.binary_search(&order::from(0)).is_err(),
-            "color 0 should not be used;it is special cased for legacy reasons."
-        );
-        let id = dag.async_no_remote_multiappend(inhabits, data, deps);
-        WriteId::from_uuid(id)
+        let id = unsafe { multiappend_args(dag, data, data_size, inhabits) }
+            .map(|(dag, data, inhabits)|
+                dag.async_no_remote_multiappend(inhabits, data, &mut [])
+            );
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn wh_flush_completed_appends(dag: *mut WriteHandle<[u8]>) {
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        dag.flush_completed_appends().unwrap();
+    pub unsafe extern "C" fn wh_flush_completed_appends(dag: *mut WriteHandle<[u8]>)
+    -> FuzzyLogStatus {
+        to_status(handle_mut(dag).and_then(|dag| {
+            dag.flush_completed_appends()?;
+            Ok(())
+        }))
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn wh_wait_for_any_append(dag: *mut WriteHandle<[u8]>) -> WriteId {
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        let id = dag.wait_for_any_append().map(|t| t.0).unwrap_or(Uuid::nil());
-        WriteId::from_uuid(id)
+        let id = handle_mut(dag).and_then(|dag| match dag.wait_for_any_append() {
+            Ok((id, _)) => Ok(id),
+            Err(TryWaitRes::NothingReady) => Ok(Uuid::nil()),
+            Err(e) => Err(e.into()),
+        });
+        or_report(id.map(WriteId::from_uuid), WriteId::nil())
     }
 
     #[no_mangle]
-    pub extern "C" fn rh_snapshot(dag: *mut ReadHandle<[u8]>) {
-        let dag = unsafe {dag.as_mut().expect("need to provide a valid DAGHandle")};
-        dag.take_snapshot();
+    pub extern "C" fn rh_snapshot(dag: *mut ReadHandle<[u8]>) -> FuzzyLogStatus {
+        to_status(unsafe { handle_mut(dag) }.map(|dag| dag.take_snapshot()))
     }
 
     #[no_mangle]
-    pub unsafe extern "C" fn rh_snapshot_colors(dag: *mut ReadHandle<[u8]>, colors: *mut colors) {
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        assert!(colors != ptr::null_mut());
-        assert!(colors_valid(colors));
-        let colors = {
-            let num_colors = (*colors).numcolors;
-            let colors: *mut order = (*colors).mycolors as *mut _;
-            slice::from_raw_parts_mut(colors, num_colors)
-        };
-        dag.snapshot_colors(colors);
+    pub unsafe extern "C" fn rh_snapshot_colors(dag: *mut ReadHandle<[u8]>, colors: *mut colors)
+    -> FuzzyLogStatus {
+        to_status(handle_mut(dag).and_then(|dag| {
+            dag.snapshot_colors(color_slice(colors)?);
+            Ok(())
+        }))
     }
 
     #[no_mangle]
@@ -1135,72 +1576,82 @@ pub mod c_binidings {
         data_read: *mut usize,
         num_locs: *mut usize,
     ) -> Vals {
-        assert!(data_read != ptr::null_mut());
-        let dag = dag.as_mut().expect("need to provide a valid DAGHandle");
-        let val = dag.get_next();
-        let (data, locs) = val.unwrap_or((&[], &[]));
-
-        ptr::write(data_read, data.len());
-        ptr::write(num_locs, locs.len());
-
-        Vals { data: data.as_ptr(), locs: locs.as_ptr() }
+        let val = handle_mut(dag).and_then(|dag| match dag.get_next() {
+            Ok(val) => Ok(val),
+            Err(GetRes::Done) => Ok((&[][..], &[][..])),
+            Err(e) => Err(e.into()),
+        });
+        write_vals(val, data_read, num_locs)
     }
 
     ////////////////////////////////////
     //         Server bindings        //
     ////////////////////////////////////
 
+    /// Run a server on the current thread.
+    /// Only returns if the server could not be started.
     #[no_mangle]
-    pub extern "C" fn start_fuzzy_log_server(server_ip: *const c_char) -> ! {
+    pub extern "C" fn start_fuzzy_log_server(server_ip: *const c_char) -> FuzzyLogStatus {
         start_fuzzy_log_server_for_group(server_ip, 0, 1)
     }
 
     #[no_mangle]
-    pub extern "C" fn start_fuzzy_log_server_thread(server_ip: *const c_char) {
+    pub extern "C" fn start_fuzzy_log_server_thread(server_ip: *const c_char) -> FuzzyLogStatus {
         start_fuzzy_log_server_thread_from_group(server_ip, 0, 1)
     }
 
+    /// Run a server on the current thread.
+    /// Only returns if the server could not be started.
     #[no_mangle]
     pub extern "C" fn start_fuzzy_log_server_for_group(server_ip: *const c_char,
-        server_number: u32, total_servers_in_group: u32) -> ! {
-        assert!(server_ip != ptr::null());
-        let server_ip = unsafe {
-            CStr::from_ptr(server_ip).to_str().expect("invalid IP string")
-        };
-        start_server(server_ip, server_number,
-            total_servers_in_group, &AtomicUsize::new(0));
+        server_number: u32, total_servers_in_group: u32) -> FuzzyLogStatus {
+        let acceptor = unsafe { parse_addr(server_ip) }.and_then(|addr| bind(&addr));
+        match acceptor {
+            Ok(acceptor) => run_server(
+                acceptor, server_number, total_servers_in_group, &AtomicUsize::new(0)
+            ),
+            Err(e) => set_last_error(e),
+        }
     }
 
     #[no_mangle]
     pub extern "C" fn start_fuzzy_log_server_thread_from_group(server_ip: *const c_char,
-        server_number: u32, total_servers_in_group: u32) {
-            assert!(server_ip != ptr::null());
-            let server_started = AtomicUsize::new(0);
-            let started = unsafe {
-                //This should be safe since the while loop at the of the function
-                //prevents it from exiting until the server is started and
-                //server_started is no longer used
-                (extend_lifetime(&server_started))
-            };
-            let server_ip = unsafe {
-                CStr::from_ptr(server_ip).to_str().expect("invalid IP string")
-            };
-            let handle = ::std::thread::spawn(move || {
-                start_server(server_ip, server_number, total_servers_in_group, &started)
-            });
-            while !server_started.load(Ordering::SeqCst) < 1 {}
-            mem::forget(handle);
-            mem::drop(server_ip);
+        server_number: u32, total_servers_in_group: u32) -> FuzzyLogStatus {
+        to_status(unsafe { parse_addr(server_ip) }.and_then(|addr|
+            start_server_thread(&addr, server_number, total_servers_in_group)
+        ))
+    }
 
-            unsafe fn extend_lifetime<'a, 'b, T>(r: &'a T) -> &'b T {
-                ::std::mem::transmute(r)
-            }
+    fn start_server_thread(addr: &SocketAddr,
+        server_num: u32, total_num_servers: u32) -> Result<()> {
+        let acceptor = bind(addr)?;
+        let server_started = AtomicUsize::new(0);
+        let started = unsafe {
+            //This should be safe since the while loop at the of the function
+            //prevents it from exiting until the server is started and
+            //server_started is no longer used
+            (extend_lifetime(&server_started))
+        };
+        let handle = ::std::thread::spawn(move || {
+            run_server(acceptor, server_num, total_num_servers, &started)
+        });
+        while !server_started.load(Ordering::SeqCst) < 1 {}
+        mem::forget(handle);
+        return Ok(());
+
+        unsafe fn extend_lifetime<'a, 'b, T>(r: &'a T) -> &'b T {
+            ::std::mem::transmute(r)
+        }
+    }
+
+    fn bind(addr: &SocketAddr) -> Result<mio::tcp::TcpListener> {
+        mio::tcp::TcpListener::bind(addr).map_err(|e|
+            Error::new(FUZZYLOG_IO_ERROR, format!("cannot start server on {}: {}", addr, e))
+        )
     }
 
-    fn start_server(server_ip: &str,
+    fn run_server(acceptor: mio::tcp::TcpListener,
         server_num: u32, total_num_servers: u32, servers_ready: &AtomicUsize) -> ! {
-        let ip_addr = server_ip.parse().expect("invalid IP addr");
-        let acceptor = mio::tcp::TcpListener::bind(&ip_addr).expect("cannot start server");
         ::servers2::tcp::run(
             acceptor,
             server_num,
@@ -1211,54 +1662,33 @@ pub mod c_binidings {
     }
 
     #[no_mangle]
-    pub extern "C" fn start_servers_from_config(file_name: *const c_char) {
-        assert!(file_name != ptr::null());
-        let (lock_server_addr, chain_server_addrs, _) = read_config_file(file_name);
-        if let Some(addr) = lock_server_addr {
-            let addr = CString::new(addr).unwrap();
-            start_fuzzy_log_server_thread_from_group(addr.into_raw(), 0, 1);
-        }
-        let total_chain_servers = chain_server_addrs.len() as u32;
-        for (i, addr) in chain_server_addrs.into_iter().enumerate() {
-            let addr = CString::new(addr).unwrap();
-            start_fuzzy_log_server_thread_from_group(addr.into_raw(), i as u32, total_chain_servers);
-        }
+    pub extern "C" fn start_servers_from_config(file_name: *const c_char) -> FuzzyLogStatus {
+        to_status(unsafe { read_config_file(file_name) }.and_then(|config| {
+            let ClusterConfig{lock_server, servers, ..} = config;
+            if let Some(addr) = lock_server {
+                start_server_thread(&addr, 0, 1)?;
+            }
+            let total_chain_servers = servers.len() as u32;
+            for (i, addr) in servers.iter().enumerate() {
+                start_server_thread(addr, i as u32, total_chain_servers)?;
+            }
+            Ok(())
+        }))
     }
 
     ////////////////////////////////////
     //           Config I/O           //
     ////////////////////////////////////
 
-    fn read_config_file(file_name: *const c_char)
-    -> (Option<String>, Vec<String>, Vec<String>) {
-        use std::fs::File;
-        use std::io::Read;
-        use toml::{self, Value};
-
-        let file_name = unsafe { CStr::from_ptr(file_name) }
-            .to_str().expect("Can only hanlde utf-8 filenames.");
-        let mut config_string = String::new();
-        {
-            let mut config = File::open(file_name)
-                .expect("Could not open config file.");
-            config.read_to_string(&mut config_string)
-                .expect("Invalid config file encoding.");
-        }
-        let mut vals = toml::Parser::new(&config_string)
-            .parse().expect("Invalid config file format.");
-        let lock_server_str =
-            if let Some(Value::String(s)) = vals.remove("DELOS_LOCK_SERVER") {
-                Some(s)
-            } else { None };
-        let css = vals.remove("DELOS_CHAIN_SERVERS")
-            .expect("Must provide at least one chain server addr.");
-        let chain_server_strings = if let Value::String(s) = css {
-                s.split_whitespace().map(|s| s.to_string()).collect()
-            } else { panic!("Must provide at least one chain server addr.") };
-        let csst = match vals.remove("DELOS_CHAIN_SERVERS_TAILS") {
-            Some(Value::String(s)) => s.split_whitespace().map(|s| s.to_string()).collect(),
-            _ => vec![],
+    unsafe fn read_config_file(file_name: *const c_char) -> Result<ClusterConfig> {
+        check(!file_name.is_null(), "need to provide a config file")?;
+        let file_name = CStr::from_ptr(file_name).to_str()
+            .map_err(|_| Error::invalid("Can only hanlde utf-8 filenames."))?;
+        let config = match Config::from_file(file_name) {
+            Ok(config) => config.cluster,
+            Err(e) => return Err(Error::invalid(format!("Could not load {}: {}", file_name, e))),
         };
-        (lock_server_str, chain_server_strings, csst)
+        check(!config.servers.is_empty(), "Must provide at least one chain server addr.")?;
+        Ok(config)
     }
 }
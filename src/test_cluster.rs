@@ -0,0 +1,315 @@
+//! An in-process fuzzy log cluster for integration tests.
+//!
+//! Unlike `start_server_thread`, which binds a caller chosen address,
+//! a `TestCluster` binds every server to an ephemeral port on localhost,
+//! so any number of clusters can run side-by-side in the same test binary.
+//!
+//! ```no_run
+//! use fuzzy_log::TestCluster;
+//!
+//! let cluster = TestCluster::builder().chain_servers(2).replicas(2).start().unwrap();
+//! let mut handle = cluster.handle::<[u8]>().chains(vec![1.into()]).build();
+//! handle.append(1.into(), &b"hello"[..], &[]);
+//! ```
+//!
+//! The servers are shut down when the cluster is dropped.
+//!
+//! With `fault_proxies` every connection to a server, from clients and from
+//! the server's downstream replica, goes through a `FaultProxy`,
+//! see `client_proxy` and `upstream_proxy`.
+
+use std::{fmt, io};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fault_proxy::FaultProxy;
+use fuzzy_log_util::config::ClusterConfig;
+use packets::storeables::Storeable;
+use servers2::tcp::{Limits, ShutdownHandle};
+
+use mio;
+
+use {LogBuilder, LogHandle};
+
+// How long `start` waits for the servers to start accepting connections.
+const START_TIMEOUT_SECS: u64 = 10;
+
+/// Configures a `TestCluster`.
+#[derive(Debug, Clone)]
+pub struct TestClusterBuilder {
+    chain_servers: u32,
+    replicas: usize,
+    workers: usize,
+    fault_proxies: bool,
+    limits: Limits,
+}
+
+impl Default for TestClusterBuilder {
+    fn default() -> Self {
+        TestClusterBuilder {
+            chain_servers: 1,
+            replicas: 1,
+            workers: 1,
+            fault_proxies: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl TestClusterBuilder {
+    /// The number of chain servers the chains are partitioned between,
+    /// defaults to 1.
+    pub fn chain_servers(mut self, chain_servers: u32) -> Self {
+        self.chain_servers = chain_servers;
+        self
+    }
+
+    /// The length of each chain server's replication chain,
+    /// defaults to 1, i.e. unreplicated.
+    pub fn replicas(mut self, replicas: usize) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    /// The number of worker threads each server runs, defaults to 1.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Route all traffic to the servers through `FaultProxy`s,
+    /// defaults to false.
+    pub fn fault_proxies(mut self, fault_proxies: bool) -> Self {
+        self.fault_proxies = fault_proxies;
+        self
+    }
+
+    /// The flow control every server uses, defaults to `Limits::default()`.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Start every server of the cluster,
+    /// returning once all of them are accepting connections.
+    /// Fails if a server thread panics while starting,
+    /// or if the servers are not all up after 10 seconds.
+    pub fn start(self) -> io::Result<TestCluster> {
+        let TestClusterBuilder { chain_servers, replicas, workers, fault_proxies, limits } = self;
+        if chain_servers == 0 || replicas == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a cluster needs at least one chain server and replica",
+            ))
+        }
+
+        let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let any_port = SocketAddr::new(local_host, 0);
+
+        // bind everything up front so replicas know the addresses of their
+        // upstreams before any server starts
+        let mut acceptors = Vec::with_capacity(chain_servers as usize);
+        for _ in 0..chain_servers {
+            let chain = (0..replicas)
+                .map(|_| {
+                    let acceptor = mio::tcp::TcpListener::bind(&any_port)?;
+                    let addr = acceptor.local_addr()?;
+                    Ok((acceptor, addr))
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            acceptors.push(chain);
+        }
+
+        let mut proxies = vec![];
+        if fault_proxies {
+            for chain in &acceptors {
+                let mut chain_proxies = Vec::with_capacity(replicas);
+                for (i, &(_, addr)) in chain.iter().enumerate() {
+                    let upstream = match i {
+                        0 => None,
+                        i => Some(FaultProxy::start(chain[i - 1].1)?),
+                    };
+                    chain_proxies.push(Proxies { client: FaultProxy::start(addr)?, upstream });
+                }
+                proxies.push(chain_proxies);
+            }
+        }
+        // the address clients connect to for each replica
+        let client_addr = |server_num: usize, i: usize, addr: SocketAddr| {
+            proxies.get(server_num).map_or(addr, |chain: &Vec<Proxies>| chain[i].client.addr())
+        };
+
+        let mut config = ClusterConfig::default();
+        for (server_num, chain) in acceptors.iter().enumerate() {
+            config.servers.push(client_addr(server_num, 0, chain[0].1));
+            if replicas > 1 {
+                config.tails.push(client_addr(server_num, replicas - 1, chain[replicas - 1].1));
+            }
+        }
+
+        let ready = Arc::new(AtomicUsize::new(0));
+        let panicked = Arc::new(AtomicBool::new(false));
+        // in chain order, so shutting them down in order stops each head first
+        let mut servers = Vec::with_capacity(chain_servers as usize * replicas);
+        for (server_num, chain) in acceptors.into_iter().enumerate() {
+            let mut prev_server = None;
+            for (i, (acceptor, addr)) in chain.into_iter().enumerate() {
+                let next_server = if i + 1 < replicas { Some(local_host) } else { None };
+                let ready = ready.clone();
+                let panicked = PanicFlag(panicked.clone());
+                let shutdown = ShutdownHandle::new();
+                let server_shutdown = shutdown.clone();
+                thread::Builder::new()
+                    .name(format!("test server {}.{}", server_num, i))
+                    .spawn(move || {
+                        let _panicked = panicked;
+                        ::servers2::tcp::run_until_shutdown(
+                            acceptor,
+                            server_num as u32,
+                            chain_servers,
+                            prev_server,
+                            next_server,
+                            workers,
+                            1,
+                            None,
+                            limits,
+                            &server_shutdown,
+                            &ready,
+                        )
+                    })?;
+                servers.push(shutdown);
+                // the next replica reaches this one through its own proxy
+                prev_server = match proxies.get(server_num) {
+                    Some(chain) if i + 1 < replicas =>
+                        chain[i + 1].upstream.as_ref().map(|p| p.addr()),
+                    _ => Some(addr),
+                };
+            }
+        }
+
+        let num_servers = chain_servers as usize * replicas;
+        let started = Instant::now();
+        while ready.load(Ordering::Acquire) < num_servers {
+            let failure = if panicked.load(Ordering::Acquire) {
+                Some((io::ErrorKind::Other, "a server panicked while starting"))
+            } else if started.elapsed() > Duration::from_secs(START_TIMEOUT_SECS) {
+                Some((io::ErrorKind::TimedOut, "the servers did not start in time"))
+            } else {
+                None
+            };
+            if let Some((kind, error)) = failure {
+                // a server which panicked would never finish shutting down
+                for server in &servers {
+                    server.request()
+                }
+                return Err(io::Error::new(kind, error))
+            }
+            thread::yield_now()
+        }
+
+        Ok(TestCluster { config, replicas, servers, proxies, panicked })
+    }
+}
+
+// Set if the server thread holding it panics.
+struct PanicFlag(Arc<AtomicBool>);
+
+impl Drop for PanicFlag {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.store(true, Ordering::Release)
+        }
+    }
+}
+
+struct Proxies {
+    client: FaultProxy,
+    upstream: Option<FaultProxy>,
+}
+
+/// A set of fuzzy log servers running on threads of the current process.
+pub struct TestCluster {
+    config: ClusterConfig,
+    replicas: usize,
+    servers: Vec<ShutdownHandle>,
+    // by chain server then replica, empty unless built with `fault_proxies`
+    proxies: Vec<Vec<Proxies>>,
+    // set once any server thread has panicked
+    panicked: Arc<AtomicBool>,
+}
+
+impl TestCluster {
+    pub fn builder() -> TestClusterBuilder {
+        TestClusterBuilder::default()
+    }
+
+    /// The addresses of the cluster's servers,
+    /// for handles which should not be built with `handle`.
+    pub fn config(&self) -> &ClusterConfig {
+        &self.config
+    }
+
+    /// A `LogBuilder` for a handle connected to every server in the cluster.
+    pub fn handle<V: ?Sized + Storeable>(&self) -> LogBuilder<V> {
+        LogHandle::with_config(&self.config)
+    }
+
+    /// The proxy clients go through to reach `replica` of `chain_server`,
+    /// if the cluster was built with `fault_proxies`.
+    /// Replica `0` is the head, which clients send appends to;
+    /// in a replicated cluster reads and acknowledgements come from the tail.
+    pub fn client_proxy(&self, chain_server: usize, replica: usize) -> Option<&FaultProxy> {
+        self.proxies.get(chain_server)
+            .and_then(|chain| chain.get(replica))
+            .map(|proxies| &proxies.client)
+    }
+
+    /// The proxy `replica` of `chain_server` goes through to reach the
+    /// replica before it, if the cluster was built with `fault_proxies`.
+    /// The head has no upstream.
+    pub fn upstream_proxy(&self, chain_server: usize, replica: usize) -> Option<&FaultProxy> {
+        self.proxies.get(chain_server)
+            .and_then(|chain| chain.get(replica))
+            .and_then(|proxies| proxies.upstream.as_ref())
+    }
+
+    /// Shut down `replica` of `chain_server`, returning once it has stopped,
+    /// the rest of the cluster keeps running.
+    /// Shutting down a head lets it replicate what it has already received,
+    /// so those appends can still be read from the tail.
+    pub fn shutdown_server(&self, chain_server: usize, replica: usize) {
+        assert!(replica < self.replicas, "no replica {}", replica);
+        self.servers[chain_server * self.replicas + replica].shutdown()
+    }
+
+    /// Shut down every server in the cluster, returning once all of them
+    /// have stopped. Appends the servers have already received are finished
+    /// first, see `ShutdownHandle`.
+    /// If any server thread has panicked the rest are only asked to stop,
+    /// without waiting for them.
+    pub fn shutdown(mut self) {
+        self.stop()
+    }
+
+    fn stop(&mut self) {
+        let panicked = self.panicked.load(Ordering::Acquire);
+        for server in self.servers.drain(..) {
+            if panicked { server.request() } else { server.shutdown() }
+        }
+    }
+}
+
+impl fmt::Debug for TestCluster {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TestCluster").field("config", &self.config).finish()
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        self.stop()
+    }
+}
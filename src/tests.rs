@@ -242,20 +242,25 @@ macro_rules! async_tests {
 
             let columns = vec![26.into(), 27.into(), 28.into(), 29.into(), 30.into()];
             let mut lh = $new_thread_log::<u64>(columns.clone());
+            let history = ::history::History::new();
+            let writer = history.client(&[]);
             for (i, cols) in columns.windows(2).rev().enumerate() {
                 let i = i as u64;
-                let _ = lh.multiappend(&cols, &((i + 1) * 2), &[]);
+                let _ = writer.multiappend(&mut lh, &cols, &((i + 1) * 2), &[]);
             }
             lh.snapshot(26.into());
-            assert_eq!(lh.get_next(),
-                Ok((&2, &[OrderIndex(29.into(), 1.into()), OrderIndex(30.into(), 1.into())][..])));
-            assert_eq!(lh.get_next(),
-                Ok((&4, &[OrderIndex(28.into(), 1.into()), OrderIndex(29.into(), 2.into())][..])));
-            assert_eq!(lh.get_next(),
-                Ok((&6, &[OrderIndex(27.into(), 1.into()), OrderIndex(28.into(), 2.into())][..])));
-            assert_eq!(lh.get_next(),
-                Ok((&8, &[OrderIndex(26.into(), 1.into()), OrderIndex(27.into(), 2.into())][..])));
-            assert_eq!(lh.get_next(), Err(GetRes::Done));
+            let reader = history.client(&columns);
+            let mut get_next = || reader.get_next(&mut lh).map(|e| (*e.data, e.inhabits.to_vec()));
+            assert_eq!(get_next(),
+                Ok((2, vec![OrderIndex(29.into(), 1.into()), OrderIndex(30.into(), 1.into())])));
+            assert_eq!(get_next(),
+                Ok((4, vec![OrderIndex(28.into(), 1.into()), OrderIndex(29.into(), 2.into())])));
+            assert_eq!(get_next(),
+                Ok((6, vec![OrderIndex(27.into(), 1.into()), OrderIndex(28.into(), 2.into())])));
+            assert_eq!(get_next(),
+                Ok((8, vec![OrderIndex(26.into(), 1.into()), OrderIndex(27.into(), 2.into())])));
+            assert_eq!(get_next(), Err(GetRes::Done));
+            assert_eq!(history.check(), Ok(()));
         }
 
         #[test]
@@ -945,6 +950,114 @@ macro_rules! async_tests {
             }
         }
 
+        #[test]
+        #[inline(never)]
+        pub fn test_session_token() {
+            use async::fuzzy_log::session::SessionToken;
+
+            let _ = env_logger::init();
+            trace!("TEST session token");
+
+            let columns = vec![89.into(), 90.into()];
+            let mut writer = $new_thread_log::<u64>(columns.clone());
+            let _ = writer.append(89.into(), &1, &[]);
+            let _ = writer.multiappend(&columns, &2, &[]);
+            let token = writer.session_token();
+            assert_eq!(token.horizon(89.into()), 2.into());
+            assert_eq!(token.horizon(90.into()), 1.into());
+
+            let token = SessionToken::from_bytes(&token.to_bytes()).unwrap();
+            let mut reader = $new_thread_log::<u64>(columns.clone());
+            reader.snapshot_colors_after(&columns, &token);
+            assert_eq!(reader.get_next().map(|(&v, _)| v), Ok(1));
+            assert_eq!(reader.get_next().map(|(&v, _)| v), Ok(2));
+            assert_eq!(reader.get_next(), Err(GetRes::Done));
+            assert_eq!(reader.session_token(), token);
+        }
+
+        #[test]
+        pub fn test_cut() {
+            use async::fuzzy_log::cut::Cut;
+
+            let _ = env_logger::init();
+            trace!("TEST cut");
+
+            let columns = vec![91.into(), 92.into()];
+            let mut writer = $new_thread_log::<u64>(columns.clone());
+            let _ = writer.append(91.into(), &1, &[]);
+            let _ = writer.append(91.into(), &2, &[]);
+            let _ = writer.multiappend(&columns, &3, &[]);
+
+            let mut reader = $new_thread_log::<u64>(columns.clone());
+            let mut seen = vec![];
+            let cut = reader.sync(|&v, _, _| seen.push(v)).unwrap();
+            assert_eq!(seen, vec![1, 2, 3]);
+            assert_eq!(cut.horizon(91.into()), 3.into());
+            assert_eq!(cut.horizon(92.into()), 1.into());
+            assert!(reader.has_observed(&cut));
+
+            let _ = writer.append(92.into(), &4, &[]);
+            let cut = Cut::from_bytes(&cut.to_bytes()).unwrap();
+            let mut checkpoint = $new_thread_log::<u64>(columns.clone());
+            assert!(!checkpoint.has_observed(&cut));
+            checkpoint.read_until_cut(&cut);
+            assert_eq!(checkpoint.get_next().map(|(&v, _)| v), Ok(1));
+            assert_eq!(checkpoint.get_next().map(|(&v, _)| v), Ok(2));
+            assert_eq!(checkpoint.get_next().map(|(&v, _)| v), Ok(3));
+            assert_eq!(checkpoint.get_next(), Err(GetRes::Done));
+            assert!(checkpoint.has_observed(&cut));
+
+            let later = writer.session_token();
+            assert!(later > cut);
+            assert!(!checkpoint.has_observed(&later));
+        }
+
+        #[test]
+        pub fn test_checkpoint() {
+            use std::{env, fs};
+            use async::fuzzy_log::cut::Checkpoint;
+
+            let _ = env_logger::init();
+            trace!("TEST checkpoint");
+
+            let columns = vec![93.into(), 94.into()];
+            let mut writer = $new_thread_log::<u64>(columns.clone());
+            let _ = writer.append(93.into(), &1, &[]);
+            let _ = writer.multiappend(&columns, &2, &[]);
+
+            let mut view = $new_thread_log::<u64>(columns.clone());
+            let mut sum = 0;
+            view.sync(|&v, _, _| sum += v).unwrap();
+            assert_eq!(sum, 3);
+            let path = env::temp_dir()
+                .join(format!("{}.checkpoint", module_path!().replace("::", "_")));
+            view.checkpoint(sum.to_string().into_bytes()).write_to_file(&path).unwrap();
+            drop(view);
+
+            let _ = writer.append(94.into(), &4, &[]);
+            let _ = writer.append(93.into(), &8, &[]);
+
+            let checkpoint = Checkpoint::read_from_file(&path).unwrap();
+            let _ = fs::remove_file(&path);
+            let mut sum: u64 = String::from_utf8(checkpoint.state().to_vec())
+                .unwrap().parse().unwrap();
+            let mut view = $new_thread_log::<u64>(columns.clone());
+            view.restore(&checkpoint);
+            assert!(view.has_observed(checkpoint.cut()));
+            let mut seen = vec![];
+            view.sync(|&v, _, _| seen.push(v)).unwrap();
+            seen.sort();
+            assert_eq!(seen, vec![4, 8]);
+            sum += seen.iter().sum::<u64>();
+            assert_eq!(sum, 15);
+
+            view.trim(checkpoint.cut());
+            let _ = writer.append(93.into(), &16, &[]);
+            let mut seen = vec![];
+            view.sync(|&v, _, _| seen.push(v)).unwrap();
+            assert_eq!(seen, vec![16]);
+        }
+
         //TODO test append after prefetch but before read
     );
     (tcp) => (
@@ -1103,6 +1216,118 @@ macro_rules! async_tests {
                 assert_eq!(lh.get_next(), Err(GetRes::Done));
             }
 
+            #[test]
+            pub fn test_prefetch_policy() {
+                use std::net::SocketAddr;
+                use async::fuzzy_log::prefetch::PrefetchPolicy;
+
+                let _ = env_logger::init();
+                trace!("TEST prefetch policy");
+
+                start_tcp_servers();
+                let addrs: Vec<SocketAddr> =
+                    addr_strs.into_iter().map(|s| s.parse().unwrap()).collect();
+                let columns: Vec<order> = vec![1_000_04.into(), 1_000_05.into()];
+                let mut writer = LogHandle::<u64>::unreplicated_with_servers(addrs.clone())
+                    .chains(columns.clone())
+                    .build();
+                for i in 0..20 {
+                    let _ = writer.append(columns[i % 2], &(i as u64), &[]);
+                }
+
+                let mut reader = LogHandle::<u64>::unreplicated_with_servers(addrs)
+                    .chains(columns.clone())
+                    .prefetch(PrefetchPolicy::Fixed(2))
+                    .prefetch_for(columns[1], PrefetchPolicy::Adaptive{ min: 1, max: 8 })
+                    .build();
+                let mut seen = vec![];
+                reader.sync(|&v, _, _| seen.push(v)).unwrap();
+                seen.sort();
+                assert_eq!(seen, (0..20).collect::<Vec<_>>());
+                let stats = reader.prefetch_stats();
+                assert_eq!(stats.hits + stats.misses, 20);
+            }
+
+            #[test]
+            pub fn test_ready_fd() {
+                use std::net::SocketAddr;
+                use async::fuzzy_log::log_handle::TryWaitRes;
+
+                let _ = env_logger::init();
+                trace!("TEST ready fd");
+
+                start_tcp_servers();
+                let addrs: Vec<SocketAddr> =
+                    addr_strs.into_iter().map(|s| s.parse().unwrap()).collect();
+                let columns: Vec<order> = vec![1_000_06.into()];
+                let mut writer = LogHandle::<u64>::unreplicated_with_servers(addrs.clone())
+                    .chains(columns.clone())
+                    .build();
+                let fd = writer.ready_fd().unwrap();
+                assert_eq!(writer.ready_fd().unwrap(), fd);
+                let id = writer.async_append(columns[0], &7, &[]);
+                // the fd starts out readable, so the ack may take a second wakeup
+                let acked = loop {
+                    wait_readable(fd);
+                    match writer.try_wait_for_any_append() {
+                        Err(TryWaitRes::NothingReady) => continue,
+                        res => break res.map(|(i, _)| i),
+                    }
+                };
+                assert_eq!(acked, Ok(id));
+
+                let mut reader = LogHandle::<u64>::unreplicated_with_servers(addrs)
+                    .chains(columns.clone())
+                    .build();
+                let fd = reader.ready_fd().unwrap();
+                reader.take_snapshot();
+                let mut seen = vec![];
+                'poll: loop {
+                    wait_readable(fd);
+                    loop {
+                        match reader.try_get_next() {
+                            Ok((&v, _)) => seen.push(v),
+                            Err(GetRes::NothingReady) => break,
+                            Err(GetRes::Done) => break 'poll,
+                            Err(e) => panic!("{:?}", e),
+                        }
+                    }
+                }
+                assert_eq!(seen, vec![7]);
+            }
+
+            #[test]
+            pub fn test_ready_fd_after_results() {
+                use std::net::SocketAddr;
+
+                let _ = env_logger::init();
+                trace!("TEST ready fd after results");
+
+                start_tcp_servers();
+                let addrs: Vec<SocketAddr> =
+                    addr_strs.into_iter().map(|s| s.parse().unwrap()).collect();
+                let columns: Vec<order> = vec![1_000_07.into()];
+                let mut writer = LogHandle::<u64>::unreplicated_with_servers(addrs)
+                    .chains(columns.clone())
+                    .build();
+                let id = writer.async_append(columns[0], &7, &[]);
+                // the ack is queued before anyone asks for the fd
+                ::std::thread::sleep(::std::time::Duration::from_millis(500));
+                let fd = writer.ready_fd().unwrap();
+                wait_readable(fd);
+                assert_eq!(writer.try_wait_for_any_append().map(|(i, _)| i), Ok(id));
+            }
+
+            fn wait_readable(fd: ::std::os::unix::io::RawFd) {
+                let mut poll = ::libc::pollfd { fd, events: ::libc::POLLIN, revents: 0 };
+                assert_eq!(unsafe { ::libc::poll(&mut poll, 1, 10_000) }, 1);
+                let mut count = 0u64;
+                let read = unsafe {
+                    ::libc::read(fd, &mut count as *mut u64 as *mut ::libc::c_void, 8)
+                };
+                assert_eq!(read, 8);
+            }
+
             fn new_thread_log<V>(interesting_chains: Vec<order>) -> LogHandle<V> {
                 start_tcp_servers();
 
@@ -1285,116 +1510,46 @@ macro_rules! async_tests {
     };
     (r3tcp) => {
         mod r3tcp {
-            use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
-            use std::{thread, iter};
-            use std::net::{SocketAddr, Ipv4Addr, IpAddr};
+            use std::sync::{Once, ONCE_INIT};
 
-            use mio;
+            use TestCluster;
 
             async_tests!(test new_thread_log, ntl_with_boring, ntl_with_simple);
 
-            const SERVER1_ADDRS: &'static [&'static str] = &["0.0.0.0:13790", "0.0.0.0:13791", "0.0.0.0:13792"];
-            const SERVER2_ADDRS: &'static [&'static str] = &["0.0.0.0:13890", "0.0.0.0:13891", "0.0.0.0:13892"];
-
             fn new_thread_log<V>(interesting_chains: Vec<order>) -> LogHandle<V> {
-                start_tcp_servers();
-
-                let addrs = iter::once((SERVER1_ADDRS.first().unwrap(), SERVER1_ADDRS.last().unwrap()))
-                    .chain(iter::once((SERVER2_ADDRS.first().unwrap(), SERVER2_ADDRS.last().unwrap())))
-                    .map(|(s1, s2)| (s1.parse().unwrap(), s2.parse().unwrap()));
-
-                LogHandle::replicated_with_servers(addrs)
+                cluster().handle()
                     .chains(interesting_chains)
                     .build()
             }
 
             fn ntl_with_boring<V>(interesting_chains: Vec<order>) -> LogHandle<V> {
-                start_tcp_servers();
-
-                let addrs = iter::once((SERVER1_ADDRS.first().unwrap(), SERVER1_ADDRS.last().unwrap()))
-                    .chain(iter::once((SERVER2_ADDRS.first().unwrap(), SERVER2_ADDRS.last().unwrap())))
-                    .map(|(s1, s2)| (s1.parse().unwrap(), s2.parse().unwrap()));
-
-                LogHandle::replicated_with_servers(addrs)
+                cluster().handle()
                     .chains(interesting_chains)
                     .fetch_boring_multis()
                     .build()
             }
 
             fn ntl_with_simple<V>(interesting_chains: Vec<order>) -> LogHandle<V> {
-                start_tcp_servers();
-
-                let addrs = iter::once((SERVER1_ADDRS.first().unwrap(), SERVER1_ADDRS.last().unwrap()))
-                    .chain(iter::once((SERVER2_ADDRS.first().unwrap(), SERVER2_ADDRS.last().unwrap())))
-                    .map(|(s1, s2)| (s1.parse().unwrap(), s2.parse().unwrap()));
-
-                LogHandle::replicated_with_servers(addrs)
+                cluster().handle()
                     .my_colors_chains(interesting_chains)
                     .build()
             }
 
-
-            fn start_tcp_servers()
-            {
-                static SERVERS_READY: AtomicUsize = ATOMIC_USIZE_INIT;
-                static SERVER_STARTING: AtomicUsize = ATOMIC_USIZE_INIT;
-
-                if SERVER_STARTING.swap(2, Ordering::SeqCst) == 0 {
-
-                    let local_host = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-
-                    let server1s = [
-                        ("0.0.0.0:13790".parse().unwrap(), None, Some(local_host)),
-                        ("0.0.0.0:13791".parse().unwrap(),
-                            Some(SocketAddr::from((local_host, 13790))),
-                            Some(local_host)),
-                        ("0.0.0.0:13792".parse().unwrap(),
-                            Some(SocketAddr::from((local_host, 13791))),
-                            None),
-                    ];
-
-                    let server2s = [
-                        ("0.0.0.0:13890".parse().unwrap(), None, Some(local_host)),
-                        ("0.0.0.0:13891".parse().unwrap(),
-                            Some(SocketAddr::from((local_host, 13890))),
-                            Some(local_host)),
-                        ("0.0.0.0:13892".parse().unwrap(),
-                            Some(SocketAddr::from((local_host, 13891))),
-                            None),
-                    ];
-
-                    for &(addr, prev, next) in server1s.iter() {
-                        let acceptor = mio::tcp::TcpListener::bind(&addr);
-                        let acceptor = acceptor.unwrap();
-                        thread::spawn(move || {
-                            // trace!("starting replica server {:?}", (0, i));
-                            ::servers2::tcp::run_with_replication(
-                                acceptor, 0, 2,
-                                prev,
-                                next,
-                                3,
-                                &SERVERS_READY
-                            )
-                        });
-                    }
-
-                    for &(addr, prev, next) in server2s.iter() {
-                        let acceptor = mio::tcp::TcpListener::bind(&addr);
-                        let acceptor = acceptor.unwrap();
-                        thread::spawn(move || {
-                            // trace!("starting replica server {:?}", (1, i));
-                            ::servers2::tcp::run_with_replication(
-                                acceptor, 1, 2,
-                                prev,
-                                next,
-                                3,
-                                &SERVERS_READY
-                            )
-                        });
-                    }
+            fn cluster() -> &'static TestCluster {
+                static START: Once = ONCE_INIT;
+                static mut CLUSTER: Option<TestCluster> = None;
+                unsafe {
+                    START.call_once(|| {
+                        let cluster = TestCluster::builder()
+                            .chain_servers(2)
+                            .replicas(3)
+                            .workers(3)
+                            .start()
+                            .expect("could not start cluster");
+                        CLUSTER = Some(cluster)
+                    });
+                    CLUSTER.as_ref().unwrap()
                 }
-
-                while SERVERS_READY.load(Ordering::Acquire) < SERVER1_ADDRS.len() + SERVER2_ADDRS.len() {}
             }
         }
     };
@@ -1527,3 +1682,668 @@ macro_rules! async_tests {
 }
 
 async_tests!();
+
+mod timeouts {
+    use std::time::{Duration, Instant};
+
+    use packets::order;
+    use async::fuzzy_log::log_handle::{LogHandle, GetRes, TryWaitRes};
+
+    extern crate env_logger;
+
+    // a store which never answers, standing in for a partitioned server
+    fn unreachable_log() -> (LogHandle<[u8]>, ::mio::channel::Receiver<Vec<u8>>) {
+        let (to_store, from_log) = ::mio::channel::channel();
+        let lh = LogHandle::build_with_store(
+            vec![order::from(5)], false, true, None, |_| to_store
+        );
+        (lh, from_log)
+    }
+
+    #[test]
+    fn test_get_next_timeout() {
+        let _ = env_logger::init();
+        let (mut lh, _store) = unreachable_log();
+        lh.snapshot(5.into());
+        let start = Instant::now();
+        assert_eq!(lh.get_next_timeout(Duration::from_millis(100)), Err(GetRes::Timeout));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        lh.set_timeout(Some(Duration::from_millis(10)));
+        assert_eq!(lh.get_next(), Err(GetRes::Timeout));
+        assert_eq!(lh.sync(|_, _, _| {}).map(|_| ()), Err(GetRes::Timeout));
+    }
+
+    #[test]
+    fn test_wait_for_append_timeout() {
+        let _ = env_logger::init();
+        let (mut lh, _store) = unreachable_log();
+        let id = lh.async_append(5.into(), &[1u8][..], &[]);
+        let start = Instant::now();
+        assert_eq!(
+            lh.wait_for_a_specific_append_timeout(id, Duration::from_millis(100)),
+            Err(TryWaitRes::Timeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        lh.set_timeout(Some(Duration::from_millis(10)));
+        assert_eq!(lh.wait_for_all_appends(), Err(TryWaitRes::Timeout));
+    }
+}
+
+mod shutdown {
+    use std::collections::HashMap;
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+
+    use async::fuzzy_log::log_handle::{GetRes, LogHandle, TryWaitRes};
+    use packets::OrderIndex;
+    use {start_server_thread, TestCluster};
+
+    extern crate env_logger;
+
+    #[test]
+    fn test_shutdown_cluster() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().replicas(2).workers(2).start().unwrap();
+        let config = cluster.config().clone();
+        {
+            let mut lh = cluster.handle::<i32>().chains(vec![7.into()]).build();
+            lh.append(7.into(), &1, &[]);
+            lh.append(7.into(), &2, &[]);
+        }
+        cluster.shutdown();
+        for addr in config.servers.iter().chain(config.tails.iter()) {
+            assert!(TcpStream::connect(addr).is_err(), "{} still accepting", addr);
+        }
+    }
+
+    #[test]
+    fn test_shutdown_drains_in_flight_appends() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().replicas(2).workers(2).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![7.into()]).build();
+        let mut values = HashMap::new();
+        for i in 0..100 {
+            values.insert(lh.async_append(7.into(), &i, &[]), i);
+        }
+        // make sure the head is serving this client before it is stopped
+        let (first, locs) = lh.wait_for_any_append().unwrap();
+        let mut acked = vec![(values[&first], locs[0])];
+        // stopping the head lets it replicate what it has already received,
+        // the tail keeps serving and acknowledging those appends
+        cluster.shutdown_server(0, 0);
+        loop {
+            match lh.wait_for_any_append_timeout(Duration::from_millis(500)) {
+                Ok((id, locs)) => acked.push((values[&id], locs[0])),
+                Err(TryWaitRes::Timeout) | Err(TryWaitRes::NothingReady) => break,
+                Err(_) => {},
+            }
+        }
+
+        lh.snapshot(7.into());
+        let mut read = HashMap::new();
+        loop {
+            match lh.get_next() {
+                Ok((&v, locs)) => { read.insert(locs[0], v); },
+                Err(GetRes::Done) | Err(GetRes::NothingReady) | Err(GetRes::Timeout) => break,
+                Err(_) => {},
+            }
+        }
+        for (v, loc) in acked {
+            assert_eq!(read.get(&loc), Some(&v), "acked append {:?} was lost", loc);
+        }
+    }
+
+    #[test]
+    fn test_shutdown_server_thread() {
+        let _ = env_logger::init();
+        let addr: SocketAddr = "127.0.0.1:13797".parse().unwrap();
+        let shutdown = start_server_thread("127.0.0.1:13797");
+        {
+            let mut lh = LogHandle::<i32>::unreplicated_with_servers(&[addr])
+                .chains(vec![3.into()])
+                .build();
+            lh.append(3.into(), &1, &[]);
+            lh.snapshot(3.into());
+            assert_eq!(lh.get_next(), Ok((&1, &[OrderIndex(3.into(), 1.into())][..])));
+        }
+        shutdown.shutdown();
+        assert!(TcpStream::connect(addr).is_err(), "{} still accepting", addr);
+    }
+}
+
+mod fault_proxy {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use async::fuzzy_log::log_handle::{GetRes, LogHandle, TryWaitRes};
+    use fault_proxy::{Action, Direction, FaultProxy, Faults};
+    use packets::{order, OrderIndex};
+    use TestCluster;
+
+    extern crate env_logger;
+
+    fn read_all(lh: &mut LogHandle<i32>, chains: &[order]) -> Vec<i32> {
+        lh.snapshot_colors(chains);
+        let mut values = vec![];
+        loop {
+            match lh.get_next() {
+                Ok((&v, _)) => values.push(v),
+                Err(GetRes::Done) => {
+                    values.sort();
+                    return values
+                },
+                Err(e) => panic!("cannot read {:?}", e),
+            }
+        }
+    }
+
+    fn wait_until<F: FnMut() -> bool>(mut condition: F) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(10))
+        }
+    }
+
+    #[test]
+    fn test_delayed_appends() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().fault_proxies(true).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![3.into()]).build();
+        cluster.client_proxy(0, 0).unwrap().set_faults(Direction::Both, Faults {
+            delay: Duration::from_millis(20),
+            ..Default::default()
+        });
+        // delayed on the way to the server and again on the way back
+        let start = Instant::now();
+        lh.append(3.into(), &1, &[]);
+        assert!(start.elapsed() >= Duration::from_millis(40), "{:?}", start.elapsed());
+        lh.append(3.into(), &2, &[]);
+        lh.snapshot(3.into());
+        assert_eq!(lh.get_next(), Ok((&1, &[OrderIndex(3.into(), 1.into())][..])));
+        assert_eq!(lh.get_next(), Ok((&2, &[OrderIndex(3.into(), 2.into())][..])));
+    }
+
+    #[test]
+    fn test_duplicated_appends() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().fault_proxies(true).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![4.into(), 5.into()]).build();
+        let head = cluster.client_proxy(0, 0).unwrap();
+        head.set_faults(Direction::ToTarget, Faults {
+            duplicate_percent: 100,
+            ..Default::default()
+        });
+        assert_eq!(lh.append(4.into(), &1, &[]), vec![OrderIndex(4.into(), 1.into())]);
+        assert_eq!(lh.append(4.into(), &2, &[]), vec![OrderIndex(4.into(), 2.into())]);
+        lh.multiappend(&[4.into(), 5.into()], &3, &[]);
+        head.clear();
+        assert_eq!(read_all(&mut lh, &[4.into(), 5.into()]), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_dropped_append() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().fault_proxies(true).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![6.into()]).build();
+        let head = cluster.client_proxy(0, 0).unwrap();
+        head.set_faults(Direction::ToTarget, Faults {
+            drop_percent: 100,
+            ..Default::default()
+        });
+        let lost = lh.async_append(6.into(), &1, &[]);
+        assert_eq!(
+            lh.wait_for_a_specific_append_timeout(lost, Duration::from_millis(100)),
+            Err(TryWaitRes::Timeout)
+        );
+        // the client does not resend it, but later appends are unaffected
+        head.clear();
+        assert_eq!(lh.append(6.into(), &2, &[]), vec![OrderIndex(6.into(), 1.into())]);
+        assert_eq!(read_all(&mut lh, &[6.into()]), vec![2]);
+        assert_eq!(
+            lh.wait_for_a_specific_append_timeout(lost, Duration::from_millis(100)),
+            Err(TryWaitRes::Timeout)
+        );
+    }
+
+    #[test]
+    fn test_closed_connections_are_forgotten() {
+        let _ = env_logger::init();
+        let target = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = FaultProxy::start(target.local_addr().unwrap()).unwrap();
+        let client = TcpStream::connect(proxy.addr()).unwrap();
+        let (server, _) = target.accept().unwrap();
+        wait_until(|| proxy.num_connections() == 1);
+        drop(client);
+        drop(server);
+        wait_until(|| proxy.num_connections() == 0);
+    }
+
+    #[test]
+    fn test_partitioned_head() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().fault_proxies(true).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![5.into()]).build();
+        let head = cluster.client_proxy(0, 0).unwrap();
+        head.partition();
+        let id = lh.async_append(5.into(), &1, &[]);
+        assert_eq!(
+            lh.wait_for_a_specific_append_timeout(id, Duration::from_millis(100)),
+            Err(TryWaitRes::Timeout)
+        );
+        head.heal();
+        assert_eq!(lh.wait_for_a_specific_append(id), Ok(vec![OrderIndex(5.into(), 1.into())]));
+    }
+
+    #[test]
+    fn test_partitioned_replication() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().replicas(2).fault_proxies(true).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![7.into()]).build();
+        let upstream = cluster.upstream_proxy(0, 1).unwrap();
+        // the tail only acknowledges once it has the append,
+        // so the client waits for the schedule to heal the partition
+        upstream.partition();
+        let schedule = upstream.run_schedule(vec![(Duration::from_millis(100), Action::Heal)]);
+        let id = lh.async_append(7.into(), &1, &[]);
+        assert_eq!(
+            lh.wait_for_a_specific_append_timeout(id, Duration::from_millis(50)),
+            Err(TryWaitRes::Timeout)
+        );
+        schedule.join().unwrap();
+        assert_eq!(lh.wait_for_a_specific_append(id), Ok(vec![OrderIndex(7.into(), 1.into())]));
+        lh.snapshot(7.into());
+        assert_eq!(lh.get_next(), Ok((&1, &[OrderIndex(7.into(), 1.into())][..])));
+    }
+}
+
+mod overload {
+    use async::fuzzy_log::log_handle::GetRes;
+    use servers2::tcp::Limits;
+    use TestCluster;
+
+    extern crate env_logger;
+
+    #[test]
+    fn test_retry_refused_appends() {
+        let _ = env_logger::init();
+        // every append after the first the server has not yet ordered is refused
+        let limits = Limits { log_queue_len: 1, max_in_flight_per_client: 1, max_batch: 1 };
+        let cluster = TestCluster::builder().limits(limits).start().unwrap();
+        let mut lh = cluster.handle::<i32>().chains(vec![9.into()]).build();
+        for i in 0..200 {
+            lh.async_append(9.into(), &i, &[]);
+        }
+        lh.wait_for_all_appends().unwrap();
+
+        lh.snapshot(9.into());
+        let mut values = vec![];
+        loop {
+            match lh.get_next() {
+                Ok((&v, _)) => values.push(v),
+                Err(GetRes::Done) => break,
+                Err(e) => panic!("cannot read {:?}", e),
+            }
+        }
+        values.sort();
+        assert_eq!(values, (0..200).collect::<Vec<_>>());
+    }
+}
+
+mod recovery {
+    use std::thread;
+    use std::time::Duration;
+
+    use async::fuzzy_log::log_handle::{GetRes, LogHandle, TryWaitRes};
+    use fault_proxy::{Direction, Faults};
+    use packets::order;
+    use TestCluster;
+
+    extern crate env_logger;
+
+    // chain 1 lives on server 1 and chain 2 on server 0
+    fn start_cluster() -> TestCluster {
+        TestCluster::builder().chain_servers(2).fault_proxies(true).start().unwrap()
+    }
+
+    fn drop_everything() -> Faults {
+        Faults { drop_percent: 100, ..Default::default() }
+    }
+
+    fn read_all(lh: &mut LogHandle<i32>) -> Vec<i32> {
+        read_chains(lh, &[1.into(), 2.into()])
+    }
+
+    fn read_chains(lh: &mut LogHandle<i32>, chains: &[order]) -> Vec<i32> {
+        lh.snapshot_colors(chains);
+        let mut values = vec![];
+        loop {
+            match lh.get_next() {
+                Ok((&v, _)) => values.push(v),
+                Err(GetRes::Done) => return values,
+                Err(e) => panic!("cannot read {:?}", e),
+            }
+        }
+    }
+
+    // An append queued behind the abandoned multiappend only gets through
+    // once some other client notices and recovers it.
+    fn append_past_abandoned(cluster: &TestCluster, chain: order) -> LogHandle<i32> {
+        let mut lh = cluster.handle::<i32>().chains(vec![1.into(), 2.into()]).build();
+        let id = lh.async_append(chain, &2, &[]);
+        let res = lh.wait_for_a_specific_append_timeout(id, Duration::from_secs(10));
+        assert!(res.is_ok(), "append stuck behind abandoned multiappend {:?}", res);
+        lh
+    }
+
+    #[test]
+    fn test_finish_abandoned_multiappend() {
+        let _ = env_logger::init();
+        let cluster = start_cluster();
+        {
+            let mut writer = cluster.handle::<i32>().chains(vec![1.into(), 2.into()]).build();
+            // both chains get phase 1, but the writer never hears back
+            // so it dies without sending any phase 2
+            for server in 0..2 {
+                cluster.client_proxy(server, 0).unwrap()
+                    .set_faults(Direction::FromTarget, drop_everything());
+            }
+            writer.async_multiappend(&[1.into(), 2.into()], &1, &[]);
+            thread::sleep(Duration::from_millis(100));
+        }
+        for server in 0..2 {
+            cluster.client_proxy(server, 0).unwrap().clear();
+        }
+        let mut lh = append_past_abandoned(&cluster, 1.into());
+        let mut values = read_all(&mut lh);
+        values.sort();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_abort_abandoned_multiappend() {
+        let _ = env_logger::init();
+        let cluster = start_cluster();
+        {
+            let mut writer = cluster.handle::<i32>().chains(vec![1.into(), 2.into()]).build();
+            // only chain 2 gets phase 1, so the append can never be finished
+            cluster.client_proxy(1, 0).unwrap()
+                .set_faults(Direction::ToTarget, drop_everything());
+            writer.async_multiappend(&[1.into(), 2.into()], &1, &[]);
+            thread::sleep(Duration::from_millis(100));
+        }
+        cluster.client_proxy(1, 0).unwrap().clear();
+        let mut lh = append_past_abandoned(&cluster, 2.into());
+        assert_eq!(read_all(&mut lh), vec![2]);
+    }
+
+    #[test]
+    fn test_recover_after_many_flushes() {
+        let _ = env_logger::init();
+        let cluster = start_cluster();
+        {
+            let mut writer = cluster.handle::<i32>().chains(vec![1.into(), 2.into()]).build();
+            // chain 1 gets phase 1 slowly, and loses phase 2,
+            // while chain 2 gets both and flushes the append
+            let proxy = cluster.client_proxy(1, 0).unwrap();
+            proxy.set_faults(Direction::ToTarget, Faults {
+                delay: Duration::from_millis(300), ..Default::default()
+            });
+            writer.async_multiappend(&[1.into(), 2.into()], &1, &[]);
+            thread::sleep(Duration::from_millis(150));
+            proxy.set_faults(Direction::ToTarget, drop_everything());
+            thread::sleep(Duration::from_millis(400));
+        }
+        cluster.client_proxy(1, 0).unwrap().clear();
+
+        // chain 2 has to remember the append however much it flushed since
+        let mut lh = cluster.handle::<i32>().chains(vec![2.into(), 3.into()]).build();
+        for _ in 0..2000 {
+            lh.async_multiappend(&[2.into(), 3.into()], &3, &[]);
+        }
+        lh.wait_for_all_appends().unwrap();
+
+        let mut lh = append_past_abandoned(&cluster, 1.into());
+        assert_eq!(read_chains(&mut lh, &[1.into()]), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_cancel_multiappend() {
+        let _ = env_logger::init();
+        let cluster = start_cluster();
+        let mut lh = cluster.handle::<i32>().chains(vec![1.into(), 2.into()]).build();
+        // server 0 never answers phase 1, so phase 2 cannot be sent
+        cluster.client_proxy(0, 0).unwrap()
+            .set_faults(Direction::FromTarget, drop_everything());
+        let id = lh.async_multiappend(&[1.into(), 2.into()], &1, &[]);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(lh.cancel(id), Err(TryWaitRes::Cancelled));
+        cluster.client_proxy(0, 0).unwrap().clear();
+
+        // had either server kept its phase 1 this would be stuck behind it,
+        // or recovered along with it
+        let id = lh.async_multiappend(&[1.into(), 2.into()], &2, &[]);
+        let res = lh.wait_for_a_specific_append_timeout(id, Duration::from_secs(10));
+        assert!(res.is_ok(), "append stuck behind cancelled multiappend {:?}", res);
+        assert_eq!(read_all(&mut lh), vec![2]);
+    }
+}
+
+mod history {
+    use history::{History, Violation};
+    use packets::{OrderIndex, Uuid};
+    use TestCluster;
+
+    extern crate env_logger;
+
+    fn loc(chain: u64, index: u64) -> OrderIndex {
+        OrderIndex(chain.into(), index.into())
+    }
+
+    #[test]
+    fn test_consistent_history() {
+        let history = History::new();
+        let writer = history.client(&[]);
+        let reader = history.client(&[1.into(), 2.into()]);
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        writer.appended(a, &[loc(1, 1)], &[]);
+        writer.appended(b, &[loc(1, 2), loc(2, 1)], &[]);
+        writer.appended(c, &[loc(2, 2)], &[loc(1, 1)]);
+        reader.read(a, &[loc(1, 1)], &[]);
+        reader.read(b, &[loc(1, 2), loc(2, 1)], &[]);
+        reader.read(c, &[loc(2, 2)], &[loc(1, 1)]);
+        assert_eq!(history.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_violations() {
+        let history = History::new();
+        let writer = history.client(&[]);
+        let reader = history.client(&[1.into(), 2.into()]);
+        let (a, b, c, d) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        writer.appended(a, &[loc(1, 1)], &[]);
+        writer.appended(b, &[loc(1, 2), loc(2, 1)], &[]);
+        writer.appended(c, &[loc(2, 2)], &[loc(1, 1)]);
+        writer.appended(d, &[loc(1, 1)], &[]);
+        reader.read(c, &[loc(2, 2)], &[loc(1, 1)]);
+        reader.read(b, &[loc(1, 2)], &[]);
+        let violations = history.check().unwrap_err();
+        assert_eq!(violations, vec![
+            Violation::Conflict { loc: loc(1, 1), ids: (a, d) },
+            Violation::Causality { client: 1, id: c, dep: loc(1, 1) },
+            Violation::Partial {
+                client: 1, id: b, read: vec![1.into()], appended: vec![1.into(), 2.into()]
+            },
+            Violation::Skipped { client: 1, loc: loc(1, 1), id: a },
+            Violation::Skipped { client: 1, loc: loc(2, 1), id: b },
+        ]);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let history = History::new();
+        let reader = history.client(&[3.into()]);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        reader.read(b, &[loc(3, 2)], &[]);
+        reader.read(a, &[loc(3, 1)], &[]);
+        assert_eq!(history.check(), Err(vec![
+            Violation::OutOfOrder { client: 0, before: loc(3, 2), after: loc(3, 1) },
+        ]));
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let history = History::new();
+        let writer = history.client(&[]);
+        let reader = history.client(&[1.into(), 2.into()]);
+        let id = Uuid::new_v4();
+        writer.appended(id, &[loc(1, 1), loc(2, 1)], &[loc(2, 0)]);
+        reader.read(id, &[loc(1, 1), loc(2, 1)], &[]);
+        let mut trace = vec![];
+        history.write_to(&mut trace).unwrap();
+        let read = History::read_from(&trace[..]).unwrap();
+        assert_eq!(read.records(), history.records());
+        assert_eq!(read.check(), Ok(()));
+        assert!(History::read_from(&b"A 0 not-an-id - -"[..]).is_err());
+    }
+
+    #[test]
+    fn test_recorded_cluster() {
+        let _ = env_logger::init();
+        let cluster = TestCluster::builder().chain_servers(2).start().unwrap();
+        let chains = vec![1.into(), 2.into(), 3.into(), 4.into()];
+        let history = History::new();
+
+        let writers: Vec<_> = (0..2).map(|_| history.client(&[])).collect();
+        let mut handles: Vec<_> = (0..2)
+            .map(|_| cluster.handle::<i32>().chains(chains.clone()).build())
+            .collect();
+        for i in 0..10 {
+            for (w, (writer, handle)) in writers.iter().zip(handles.iter_mut()).enumerate() {
+                let data = (i * 2 + w) as i32;
+                let chain = chains[(i + w) % chains.len()];
+                let locs = writer.append(handle, chain, &data, &[]);
+                let other = chains[(i + w + 1) % chains.len()];
+                writer.multiappend(handle, &[chain, other], &data, &locs);
+            }
+        }
+
+        for _ in 0..2 {
+            let reader = history.client(&chains);
+            let mut handle = cluster.handle::<i32>().chains(chains.clone()).build();
+            handle.snapshot_colors(&chains);
+            while reader.get_next(&mut handle).is_ok() {}
+        }
+
+        if let Err(violations) = history.check() {
+            panic!("{} violations: {:?}", violations.len(), violations)
+        }
+    }
+}
+
+mod c_api {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_void};
+    use std::{ptr, slice, thread};
+
+    use c_binidings::*;
+    use c_binidings::FuzzyLogStatus::*;
+    use TestCluster;
+
+    fn last_error() -> String {
+        let error = fuzzylog_last_error();
+        assert!(!error.is_null());
+        unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_invalid_ip_is_reported() {
+        let ip = CString::new("not an ip").unwrap();
+        assert_eq!(start_fuzzy_log_server_thread(ip.as_ptr()), FUZZYLOG_INVALID_ARGUMENT);
+        assert!(last_error().contains("not an ip"), "{}", last_error());
+        assert_eq!(start_fuzzy_log_server_thread(ptr::null()), FUZZYLOG_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn test_null_handle_is_reported() {
+        unsafe {
+            assert_eq!(fuzzylog_wait_for_all_appends(ptr::null_mut()), FUZZYLOG_INVALID_ARGUMENT);
+            assert!(last_error().contains("DAGHandle"));
+            assert_eq!(fuzzylog_trim(ptr::null_mut(), ptr::null_mut()), FUZZYLOG_INVALID_ARGUMENT);
+            assert!(fuzzylog_sync(ptr::null_mut(), |_, _, _| {}, ptr::null_mut()).is_null());
+            fuzzylog_close(ptr::null_mut());
+            delete_snap_id(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_new_dag_handle_lock_server() {
+        let ip = CString::new("not an ip").unwrap();
+        unsafe {
+            assert!(new_dag_handle(ip.as_ptr(), 0, ptr::null(), ptr::null()).is_null());
+            assert!(last_error().contains("not an ip"), "{}", last_error());
+            // without a lock server it builds the same handle as new_dag_handle_with_skeens
+            assert!(new_dag_handle(ptr::null(), 0, ptr::null(), ptr::null()).is_null());
+            assert!(last_error().contains("at least one server"), "{}", last_error());
+        }
+    }
+
+    #[test]
+    fn test_split_null_handle() {
+        unsafe {
+            let FuzzyLogReaderAndWriter { reader, writer } = fuzzylog_split(ptr::null_mut());
+            assert!(reader.is_null() && writer.is_null());
+            assert!(last_error().contains("DAGHandle"));
+            assert_eq!(fuzzylog_reader_start_sync(reader), FUZZYLOG_INVALID_ARGUMENT);
+            assert_eq!(fuzzylog_writer_wait_for_all_appends(writer), FUZZYLOG_INVALID_ARGUMENT);
+            assert!(fuzzylog_reader_sync(reader, |_, _, _| {}, ptr::null_mut()).is_null());
+            fuzzylog_reader_close(reader);
+            fuzzylog_writer_close(writer);
+        }
+    }
+
+    fn collect(state: *mut c_void, data: *const c_char, data_size: usize) {
+        let seen = unsafe { &mut *(state as *mut Vec<u8>) };
+        seen.extend_from_slice(unsafe { slice::from_raw_parts(data as *const u8, data_size) });
+    }
+
+    #[test]
+    fn test_split_across_threads() {
+        let cluster = TestCluster::builder().start().unwrap();
+        let handle = cluster.handle::<[u8]>().my_colors_chains(vec![41.into()]).build();
+        let FuzzyLogReaderAndWriter { reader, writer } =
+            unsafe { fuzzylog_split(Box::into_raw(Box::new(handle))) };
+        // raw pointers are not Send
+        let writer = writer as usize;
+        let appender = thread::spawn(move || unsafe {
+            let writer = writer as FLWriter;
+            let color = ColorSpec {
+                local_chain: 41,
+                num_remote_chains: 0,
+                remote_chains: ptr::null_mut(),
+            };
+            for i in 0..10u8 {
+                let data = &i as *const u8 as *const c_char;
+                let id = fuzzylog_writer_async_append(writer, data, 1, &color, 1);
+                assert_eq!(fuzzylog_writer_wait_for_a_specific_append(writer, id), FUZZYLOG_OK);
+            }
+            let data = &10u8 as *const u8 as *const c_char;
+            assert_eq!(fuzzylog_writer_append(writer, data, 1, &color, 1), FUZZYLOG_OK);
+            fuzzylog_writer_close(writer);
+        });
+
+        let mut seen: Vec<u8> = vec![];
+        while seen.len() < 11 {
+            unsafe {
+                let state = &mut seen as *mut Vec<u8> as *mut c_void;
+                let snap = fuzzylog_reader_sync(reader, collect, state);
+                assert!(!snap.is_null(), "{}", last_error());
+                delete_snap_id(snap);
+            }
+        }
+        appender.join().unwrap();
+        assert_eq!(seen, (0..11).collect::<Vec<u8>>());
+        unsafe { fuzzylog_reader_close(reader) };
+    }
+}